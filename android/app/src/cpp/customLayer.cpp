@@ -1,15 +1,10 @@
 #include <jni.h>
-#include <dlfcn.h>
 #include <cstdio>
 #include "customLayer.hpp"
 
-const CustomLayerHostVtable *
-(*fetchCustomLayer)(int) = reinterpret_cast<const CustomLayerHostVtable *(*)(int)>(dlsym(nullptr,
-                                                                                         "fetchCustomLayerVtable"));
-
-jlong getCustomLayer(JNIEnv *, jobject, jint index) {
-    auto *vtable = fetchCustomLayer(index);
-    auto *impl = new CustomLayerHostImpl(vtable);
+// `layer` comes from createCustomLayer, which allocates a vtable per layer
+jlong wrapCustomLayer(JNIEnv *, jobject, jlong layer) {
+    auto *impl = new CustomLayerHostImpl(reinterpret_cast<CustomLayerHostVtable *>(layer));
     return (jlong) impl;
 }
 
@@ -22,7 +17,7 @@ extern "C" JNIEXPORT jint JNICALL JNI_OnLoad(JavaVM *vm, void *) {
     jclass customLayerClass = env->FindClass("ly/hall/jetlagmobile/CustomLayerShim");
 
     JNINativeMethod methods[] = {
-            {"getCustomLayer", "(I)J", reinterpret_cast<void *>(&getCustomLayer)}};
+            {"wrapCustomLayer", "(J)J", reinterpret_cast<void *>(&wrapCustomLayer)}};
 
     if (env->RegisterNatives(customLayerClass, methods, 1) < 0) {
         env->ExceptionDescribe();
@@ -38,23 +38,28 @@ struct CustomLayerHostVtable {
                  const mbgl::style::CustomLayerRenderParameters *) = nullptr;
   void (*contextLost)(CustomLayerHostVtable *) = nullptr;
   void (*deinitialize)(CustomLayerHostVtable *) = nullptr;
+  // Frees the vtable itself, along with the layer behind it
+  void (*destroy)(CustomLayerHostVtable *) = nullptr;
   void *boxedStruct;
 };
 
 class CustomLayerHostImpl : public mbgl::style::CustomLayerHost {
-  void initialize() override { vtable.initialize(&vtable); }
+  void initialize() override { vtable->initialize(vtable); }
 
   void
   render(const mbgl::style::CustomLayerRenderParameters &parameters) override {
-    vtable.render(&vtable, &parameters);
+    vtable->render(vtable, &parameters);
   }
 
-  void contextLost() override { vtable.contextLost(&vtable); }
+  void contextLost() override { vtable->contextLost(vtable); }
 
-  void deinitialize() override { vtable.deinitialize(&vtable); }
+  void deinitialize() override { vtable->deinitialize(vtable); }
 
 public:
-  CustomLayerHostVtable vtable;
-  explicit CustomLayerHostImpl(const CustomLayerHostVtable *vtable)
-      : vtable(*vtable) {}
+  // Owned; created by createCustomLayer on the Rust side
+  CustomLayerHostVtable *vtable;
+  explicit CustomLayerHostImpl(CustomLayerHostVtable *vtable)
+      : vtable(vtable) {}
+
+  ~CustomLayerHostImpl() override { vtable->destroy(vtable); }
 };
@@ -31,6 +31,7 @@ import org.maplibre.android.style.layers.CustomLayer
 import uniffi.jet_lag_mobile.MapState
 import uniffi.jet_lag_mobile.UniffiLib
 import uniffi.jet_lag_mobile.ViewState
+import uniffi.jet_lag_mobile.createCustomLayer
 import uniffi.jet_lag_mobile.uniffiEnsureInitialized
 
 class GameScreen : ComponentActivity() {
@@ -78,7 +79,8 @@ object CustomLayerShim {
     System.loadLibrary("custom-layer-shim")
   }
 
-  external fun getCustomLayer(kind: Int): Long
+  /** Wrap a layer from [createCustomLayer] for MapLibre, which takes ownership of it */
+  external fun wrapCustomLayer(layer: Long): Long
 }
 
 @Composable
@@ -95,7 +97,9 @@ fun MapLibreMap(modifier: Modifier = Modifier) {
     val m = map ?: return@LaunchedEffect
     val ms = mapState ?: return@LaunchedEffect
 
-    val layer = CustomLayerShim.getCustomLayer(0)
+    val layer = CustomLayerShim.wrapCustomLayer(
+      createCustomLayer("shape-overlay", emptyMap()).toLong()
+    )
 
     m.setStyle(Style.Builder()
       .fromJson(ms.getStyle())
@@ -0,0 +1,84 @@
+//! Resource bundle manifests.
+//!
+//! These match the JSON form of `ResourceBundle` and `ResourceReference` in
+//! jet-lag-core, which is what the server stores and serves at
+//! `/bundles/{id}`.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+/// A named set of resources downloaded together
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub id: String,
+    pub version: u64,
+
+    /// Total size of every resource in bytes
+    pub size: u64,
+
+    /// Resources by name
+    pub resources: HashMap<String, ResourceManifest>,
+}
+
+impl BundleManifest {
+    pub fn new(id: impl Into<String>, version: u64) -> Self {
+        Self {
+            id: id.into(),
+            version,
+            size: 0,
+            resources: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, resource: ResourceManifest) {
+        self.size += resource.size;
+        if let Some(replaced) = self.resources.insert(name.into(), resource) {
+            self.size -= replaced.size;
+        }
+    }
+}
+
+/// A single file, fetched from `/resources/{id}`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceManifest {
+    pub id: String,
+    pub version: u64,
+    pub size: u64,
+
+    /// xxHash3 (64-bit) of the contents
+    pub xxhash: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_core_bundle_json() {
+        // As serialized by jet-lag-core's ResourceBundle
+        let json = r#"{
+            "id": "nyc",
+            "version": 3,
+            "size": 2048,
+            "resources": {
+                "tiles": { "version": 3, "size": 2048, "xxhash": 12345, "id": "nyc-tiles-3" }
+            }
+        }"#;
+
+        let manifest: BundleManifest = serde_json::from_str(json).unwrap();
+
+        let mut expected = BundleManifest::new("nyc", 3);
+        expected.insert(
+            "tiles",
+            ResourceManifest {
+                id: "nyc-tiles-3".into(),
+                version: 3,
+                size: 2048,
+                xxhash: 12345,
+            },
+        );
+
+        assert_eq!(manifest, expected);
+    }
+}
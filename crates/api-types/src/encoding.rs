@@ -0,0 +1,142 @@
+//! The versioned JSON envelope.
+//!
+//! Every text frame is a JSON object with the protocol version it was
+//! encoded in under `v`, alongside the message's own fields:
+//!
+//! ```json
+//! {"v": 1, "type": "join_lobby", "join_code": "ABC234", "player_name": "Sam"}
+//! ```
+//!
+//! The version is checked before the message itself is decoded, so a peer
+//! speaking an unsupported version gets [`DecodeError::UnsupportedVersion`]
+//! rather than a confusing field error.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::version::VersionRange;
+
+/// A message with the protocol version it's encoded in
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Envelope<T> {
+    pub v: u16,
+    #[serde(flatten)]
+    pub message: T,
+}
+
+#[derive(Debug)]
+pub enum DecodeError {
+    Json(serde_json::Error),
+    UnsupportedVersion(u16),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Json(e) => write!(f, "Invalid message: {}", e),
+            DecodeError::UnsupportedVersion(v) => write!(f, "Unsupported protocol version {}", v),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DecodeError::Json(e) => Some(e),
+            DecodeError::UnsupportedVersion(_) => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for DecodeError {
+    fn from(e: serde_json::Error) -> Self {
+        DecodeError::Json(e)
+    }
+}
+
+/// Encode `message` at protocol `version`
+pub fn encode<T: Serialize>(version: u16, message: &T) -> String {
+    let envelope = Envelope {
+        v: version,
+        message,
+    };
+    serde_json::to_string(&envelope).expect("protocol messages always serialize")
+}
+
+/// Decode a message, rejecting versions outside `VersionRange::supported()`
+pub fn decode<T: DeserializeOwned>(text: &str) -> Result<Envelope<T>, DecodeError> {
+    #[derive(Deserialize)]
+    struct Header {
+        v: u16,
+    }
+
+    let Header { v } = serde_json::from_str(text)?;
+    if !VersionRange::supported().contains(v) {
+        return Err(DecodeError::UnsupportedVersion(v));
+    }
+
+    Ok(serde_json::from_str(text)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ErrorCode, EventBody, GameEvent, PROTOCOL_VERSION, PlayerId,
+        lobby::{ClientMessage, ServerMessage},
+    };
+
+    #[test]
+    fn test_round_trip() {
+        let message = ServerMessage::Event {
+            event: GameEvent {
+                seq: 3,
+                player_id: PlayerId::new("p1"),
+                body: EventBody::Answer {
+                    question_seq: 2,
+                    payload: serde_json::json!({ "closer": true }),
+                },
+            },
+        };
+
+        let text = encode(PROTOCOL_VERSION, &message);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&text).unwrap(),
+            serde_json::json!({
+                "v": 1,
+                "type": "event",
+                "event": {
+                    "seq": 3,
+                    "player_id": "p1",
+                    "kind": "answer",
+                    "question_seq": 2,
+                    "payload": { "closer": true },
+                },
+            })
+        );
+
+        let decoded: Envelope<ServerMessage> = decode(&text).unwrap();
+        assert_eq!(decoded.v, PROTOCOL_VERSION);
+        assert_eq!(decoded.message, message);
+    }
+
+    #[test]
+    fn test_version_checked_first() {
+        let text = r#"{"v": 99, "type": "from_the_future"}"#;
+        assert!(matches!(
+            decode::<ClientMessage>(text),
+            Err(DecodeError::UnsupportedVersion(99))
+        ));
+
+        let text = r#"{"v": 1, "type": "error", "code": "from_the_future", "message": ""}"#;
+        let decoded: Envelope<ServerMessage> = decode(text).unwrap();
+        assert!(matches!(
+            decoded.message,
+            ServerMessage::Error {
+                code: ErrorCode::Unknown,
+                ..
+            }
+        ));
+    }
+}
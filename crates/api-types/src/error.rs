@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+/// Why the server rejected a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The message couldn't be decoded or isn't valid here
+    BadRequest,
+    /// No protocol version is supported by both sides
+    UnsupportedVersion,
+    /// The message needs a `hello` first
+    NotNegotiated,
+    LobbyNotFound,
+    InvalidToken,
+    /// The message needs the socket to be in a lobby first
+    NotInLobby,
+    InvalidUpdate,
+    /// The connection fell behind the lobby and must rejoin to resync
+    Lagged,
+    Internal,
+
+    /// A code from a newer protocol version
+    #[serde(other)]
+    Unknown,
+}
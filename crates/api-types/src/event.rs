@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::ids::PlayerId;
+
+/// A question or answer, numbered in the order the server received it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameEvent {
+    pub seq: u64,
+    pub player_id: PlayerId,
+    #[serde(flatten)]
+    pub body: EventBody,
+}
+
+/// What a player did
+///
+/// Payloads are opaque to the server; the client defines the question and
+/// answer formats.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EventBody {
+    Question {
+        payload: Value,
+    },
+    /// Answers the question event numbered `question_seq`
+    Answer {
+        question_seq: u64,
+        payload: Value,
+    },
+}
@@ -1,14 +1,27 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
-}
+//! # api-types
+//!
+//! The protocol shared by the mobile client, the game server and tools.
+//!
+//! - [`ids`]: lobby and player identities
+//! - [`lobby`]: messages exchanged over the game WebSocket
+//! - [`event`]: question and answer events relayed between players
+//! - [`bundle`]: resource bundle manifests served at `/bundles/{id}`
+//! - [`error`]: error codes reported to clients
+//! - [`version`]: protocol versions and their negotiation
+//! - [`encoding`]: the versioned JSON envelope every message travels in
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+pub mod bundle;
+pub mod encoding;
+pub mod error;
+pub mod event;
+pub mod ids;
+pub mod lobby;
+pub mod version;
 
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
-    }
-}
+pub use bundle::{BundleManifest, ResourceManifest};
+pub use encoding::{DecodeError, Envelope, decode, encode};
+pub use error::ErrorCode;
+pub use event::{EventBody, GameEvent};
+pub use ids::{LobbyId, PlayerId, PlayerInfo, SessionToken};
+pub use lobby::{ClientMessage, ServerMessage};
+pub use version::{PROTOCOL_VERSION, VersionRange};
@@ -0,0 +1,86 @@
+//! Messages exchanged over the game WebSocket.
+//!
+//! Text frames carry one [`ClientMessage`] or [`ServerMessage`] each, in an
+//! [`Envelope`](crate::Envelope). A connection starts with a `hello` from
+//! each side to agree on a protocol version, then the client creates, joins
+//! or rejoins a lobby. Binary frames carry yrs (v1) document updates:
+//! clients send their local updates and receive everyone else's, plus the
+//! full document state on joining.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    error::ErrorCode,
+    event::GameEvent,
+    ids::{LobbyId, PlayerId, PlayerInfo, SessionToken},
+    version::VersionRange,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    /// First message on a connection
+    Hello {
+        versions: VersionRange,
+        /// Client name and version, for logs
+        client: String,
+    },
+    /// Open a new lobby and join it as its first player
+    CreateLobby {
+        player_name: String,
+    },
+    JoinLobby {
+        join_code: String,
+        player_name: String,
+    },
+    /// Resume a session after reconnecting
+    Rejoin {
+        lobby_id: LobbyId,
+        token: SessionToken,
+    },
+    Question {
+        payload: Value,
+    },
+    Answer {
+        question_seq: u64,
+        payload: Value,
+    },
+    Leave,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// Reply to the client's hello with the version the rest of the
+    /// connection uses
+    Hello {
+        version: u16,
+        server: String,
+    },
+    /// Sent once the socket is in a lobby, followed by a binary frame with
+    /// the document state
+    Welcome {
+        lobby_id: LobbyId,
+        join_code: String,
+        player_id: PlayerId,
+        /// Pass to [`ClientMessage::Rejoin`] to resume this session
+        token: SessionToken,
+        players: Vec<PlayerInfo>,
+        /// Every event so far, in order
+        events: Vec<GameEvent>,
+    },
+    PlayerJoined {
+        player: PlayerInfo,
+    },
+    PlayerLeft {
+        player_id: PlayerId,
+    },
+    Event {
+        event: GameEvent,
+    },
+    Error {
+        code: ErrorCode,
+        message: String,
+    },
+}
@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+/// The newest protocol version this build speaks
+pub const PROTOCOL_VERSION: u16 = 1;
+
+/// The oldest protocol version this build still speaks
+pub const MIN_PROTOCOL_VERSION: u16 = 1;
+
+/// Protocol versions one side can speak, inclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionRange {
+    pub min: u16,
+    pub max: u16,
+}
+
+impl VersionRange {
+    pub fn new(min: u16, max: u16) -> Self {
+        Self { min, max }
+    }
+
+    /// Versions this build speaks
+    pub fn supported() -> Self {
+        Self::new(MIN_PROTOCOL_VERSION, PROTOCOL_VERSION)
+    }
+
+    pub fn contains(&self, version: u16) -> bool {
+        self.min <= version && version <= self.max
+    }
+
+    /// Newest version both ranges contain
+    pub fn negotiate(&self, other: &VersionRange) -> Option<u16> {
+        let newest = self.max.min(other.max);
+        (newest >= self.min.max(other.min)).then_some(newest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        let server = VersionRange::new(2, 4);
+
+        assert_eq!(server.negotiate(&VersionRange::new(1, 3)), Some(3));
+        assert_eq!(server.negotiate(&VersionRange::new(3, 9)), Some(4));
+        assert_eq!(server.negotiate(&VersionRange::new(1, 1)), None);
+        assert_eq!(server.negotiate(&VersionRange::new(5, 6)), None);
+    }
+}
@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use jet_lag_transit::TransitProvider;
+use jet_lag_transit::{TransitProvider, classify::HighSpeedRailClassifier};
 
 use crate::{
     hide_and_seek::state::GameState,
@@ -25,6 +25,32 @@ pub struct PathSegment {
     pub positions: geo::LineString,
 }
 
+impl PathSegment {
+    /// High-speed rail segments inferred from the transit schedule, for
+    /// contexts that back `high_speed_rail_lines` with transit data.
+    ///
+    /// Each high-speed route contributes one segment per stop-to-stop hop,
+    /// slow hops included, so the whole line is covered. IDs are assigned
+    /// sequentially; they are not OSM way IDs.
+    pub fn high_speed_rail(transit: &dyn TransitProvider) -> Vec<PathSegment> {
+        HighSpeedRailClassifier::default()
+            .classify(transit)
+            .into_iter()
+            .flat_map(|line| {
+                line.segments
+                    .into_iter()
+                    .map(move |segment| (line.name.clone(), segment.geometry))
+            })
+            .enumerate()
+            .map(|(id, (name, positions))| PathSegment {
+                id: id as i64,
+                name: Some(name),
+                positions,
+            })
+            .collect()
+    }
+}
+
 pub trait QuestionContext: Send {
     fn game_state(&self) -> &GameState;
     fn transit_context(&self) -> &dyn TransitProvider;
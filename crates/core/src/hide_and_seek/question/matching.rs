@@ -1,7 +1,7 @@
-use std::sync::Arc;
+use std::{collections::HashSet, sync::Arc};
 
+use chrono::{DateTime, Duration, Utc};
 use strum::EnumDiscriminants;
-use unicode_segmentation::UnicodeSegmentation;
 
 use crate::{
     hide_and_seek::question::{Question, ShapeError, context::QuestionContext},
@@ -10,9 +10,14 @@ use crate::{
         compiler::{Register, SdfCompiler},
         instruction::BoundaryOverlapResolution,
     },
-    transit::StationIdentifier,
+    transit::{ComplexIdentifier, RouteIdentifier, StationIdentifier, TransitProvider},
 };
 
+/// How far either side of the question time a route's trips still count
+/// towards its service pattern, so a question asked between two trips on a
+/// sparse route isn't answered by an empty line
+const TRANSIT_LINE_WINDOW: Duration = Duration::minutes(30);
+
 // TODO: change points to regions!
 //       overlapping region resolution rules:
 //         seeker:
@@ -126,6 +131,35 @@ pub enum MatchingTarget {
     },
 }
 
+impl MatchingTarget {
+    /// "Will {route} stop at your station right now?", with the stations taken
+    /// from the route's trips that are running within
+    /// [`TRANSIT_LINE_WINDOW`] of `at`.
+    pub fn transit_line(
+        transit: &dyn TransitProvider,
+        route_id: &RouteIdentifier,
+        at: DateTime<Utc>,
+    ) -> Self {
+        MatchingTarget::TransitLine {
+            scheduled_stations: transit.stations_served_between(
+                route_id,
+                at - TRANSIT_LINE_WINDOW,
+                at + TRANSIT_LINE_WINDOW,
+            ),
+        }
+    }
+
+    /// "Is the name of your station {} letters long?", counted on the
+    /// station's normalised question name.
+    pub fn stations_name_length(
+        transit: &dyn TransitProvider,
+        station_id: &StationIdentifier,
+    ) -> Option<Self> {
+        let names = transit.station_names(station_id)?;
+        Some(MatchingTarget::StationsNameLength(names.question_length))
+    }
+}
+
 // Is your nearest {category} the same as my nearest {category}?
 pub struct MatchingQuestion {
     pub category: MatchingTarget,
@@ -170,14 +204,29 @@ impl Shape for MatchingQuestionShape {
             }
 
             MatchingTarget::TransitLine { scheduled_stations } => {
-                let all_complexes = self.context.transit_context().all_complexes();
-                let (question_stations, other_stations): (Vec<_>, Vec<_>) = all_complexes
+                let transit = self.context.transit_context();
+                let all_complexes = transit.all_complexes();
+
+                // Complexes linked in-system to a served one count as the same station
+                let mut served_complexes: HashSet<ComplexIdentifier> = all_complexes
                     .iter()
-                    .partition(|c| {
+                    .filter(|c| {
                         c.station_ids()
                             .iter()
                             .any(|station_id| scheduled_stations.contains(station_id))
-                    });
+                    })
+                    .map(|c| c.id().clone())
+                    .collect();
+
+                let linked: Vec<_> = served_complexes
+                    .iter()
+                    .flat_map(|id| transit.linked_complexes(id))
+                    .collect();
+                served_complexes.extend(linked);
+
+                let (question_stations, other_stations): (Vec<_>, Vec<_>) = all_complexes
+                    .iter()
+                    .partition(|c| served_complexes.contains(c.id()));
 
                 let osp = compiler.point_cloud(other_stations.iter().map(|s| s.center()).collect());
                 let qsp =
@@ -195,7 +244,11 @@ impl Shape for MatchingQuestionShape {
 
                 let (question_stations, other_stations): (Vec<_>, Vec<_>) = all_stations
                     .iter()
-                    .partition(|s| s.name().graphemes(true).count() as u32 == *target_length);
+                    .partition(|s| {
+                        transit
+                            .station_names(s.id())
+                            .is_some_and(|names| names.question_length == *target_length)
+                    });
 
                 let osp = compiler.point_cloud(
                     other_stations
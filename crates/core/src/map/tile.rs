@@ -72,6 +72,24 @@ impl Tile {
         ]
     }
 
+    /// The tile one zoom level up that contains this one, if any
+    pub fn parent(&self) -> Option<Tile> {
+        let zoom = self.zoom.checked_sub(1)?;
+        let tile_x = self.tile_x / 2;
+        let tile_y = self.tile_y / 2;
+        let size = (1u64 << zoom) as f64;
+
+        Some(Tile {
+            zoom,
+            tile_x,
+            tile_y,
+            x0: tile_x as f64 / size,
+            y0: tile_y as f64 / size,
+            x1: (tile_x + 1) as f64 / size,
+            y1: (tile_y + 1) as f64 / size,
+        })
+    }
+
     pub fn into_bounds(&self) -> TileBounds {
         use std::f64::consts::PI;
 
@@ -1,5 +1,6 @@
 use std::{collections::HashMap, sync::Arc};
 
+use api_types::BundleManifest;
 use serde::{Deserialize, Serialize};
 
 use crate::resource::reference::ResourceReference;
@@ -53,3 +54,33 @@ impl ResourceBundle {
         Some(resource)
     }
 }
+
+impl From<BundleManifest> for ResourceBundle {
+    fn from(manifest: BundleManifest) -> Self {
+        Self {
+            id: manifest.id.into(),
+            version: manifest.version,
+            size: manifest.size,
+            resources: manifest
+                .resources
+                .into_iter()
+                .map(|(name, resource)| (name, resource.into()))
+                .collect(),
+        }
+    }
+}
+
+impl From<&ResourceBundle> for BundleManifest {
+    fn from(bundle: &ResourceBundle) -> Self {
+        Self {
+            id: bundle.id.to_string(),
+            version: bundle.version,
+            size: bundle.size,
+            resources: bundle
+                .resources
+                .iter()
+                .map(|(name, resource)| (name.clone(), resource.into()))
+                .collect(),
+        }
+    }
+}
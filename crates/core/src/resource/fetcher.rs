@@ -1,3 +1,4 @@
+use api_types::BundleManifest;
 use futures_util::StreamExt;
 
 use crate::resource::{bundle::ResourceBundle, reference::ResourceReference};
@@ -34,12 +35,12 @@ impl ResourceFetcher {
             return Err(FetchError::Network(format!("HTTP {}", response.status())));
         }
 
-        let bundle: ResourceBundle = response
+        let manifest: BundleManifest = response
             .json()
             .await
             .map_err(|e| FetchError::InvalidData(e.to_string()))?;
 
-        Ok(bundle)
+        Ok(manifest.into())
     }
 
     /// Fetch the resource data as a streaming byte stream
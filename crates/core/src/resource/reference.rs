@@ -1,5 +1,6 @@
 use std::{hash::Hasher, io::Read, path::PathBuf, sync::Arc};
 
+use api_types::ResourceManifest;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -54,3 +55,25 @@ impl ResourceReference {
         self.xxhash
     }
 }
+
+impl From<ResourceManifest> for ResourceReference {
+    fn from(manifest: ResourceManifest) -> Self {
+        Self {
+            id: manifest.id.into(),
+            version: manifest.version,
+            size: manifest.size,
+            xxhash: manifest.xxhash,
+        }
+    }
+}
+
+impl From<&ResourceReference> for ResourceManifest {
+    fn from(reference: &ResourceReference) -> Self {
+        Self {
+            id: reference.id.to_string(),
+            version: reference.version,
+            size: reference.size,
+            xxhash: reference.xxhash,
+        }
+    }
+}
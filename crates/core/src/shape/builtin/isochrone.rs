@@ -0,0 +1,162 @@
+use std::collections::BTreeMap;
+
+use jet_lag_transit::routing::Reachability;
+
+use crate::shape::{
+    Shape,
+    compiler::{Register, SdfCompiler},
+    types::Centimeters,
+};
+
+/// Stations whose walking radii fall in the same bucket share one dilated
+/// point cloud. Radii are rounded up, so the shape never under-reports, and
+/// over-reports a station's reach by less than one bucket.
+///
+/// A point cloud is one instruction searched through a BVH, but each distinct
+/// radius needs an instruction of its own, evaluated for every pixel.
+/// Remaining walks are practically never equal, so exact radii would cost an
+/// instruction per reachable station; 50 m is about 35 seconds of walking.
+const MIN_RADIUS_BUCKET: Centimeters = Centimeters(5_000);
+
+/// Most buckets an isochrone is split into. Each costs two instructions and
+/// shader slots index instructions with a `u8`, so long walks widen the
+/// buckets instead.
+const MAX_BUCKETS: i32 = 32;
+
+/// Bucket width for stations walking at most `max_radius`
+fn bucket_size(max_radius: Centimeters) -> i32 {
+    let size = (max_radius.0.max(0) + MAX_BUCKETS - 1) / MAX_BUCKETS;
+    size.max(MIN_RADIUS_BUCKET.0)
+}
+
+/// The dilation used for a station with `radius` of walking left
+fn bucket_radius(radius: Centimeters, bucket_size: i32) -> Centimeters {
+    let buckets = (radius.0.max(0) + bucket_size - 1) / bucket_size;
+    Centimeters(buckets * bucket_size)
+}
+
+/// Everywhere reachable from an origin within a time budget, as the union of
+/// a walking circle around the origin and one around every reachable station.
+pub struct Isochrone {
+    pub origin: geo::Point,
+    pub origin_radius: Centimeters,
+    pub stations: Vec<(geo::Point, Centimeters)>,
+}
+
+impl Isochrone {
+    pub fn from_reachability(reachability: &Reachability) -> Self {
+        Isochrone {
+            origin: reachability.origin,
+            origin_radius: Centimeters::from_meters(reachability.origin_walk_m as f32),
+            stations: reachability
+                .stations
+                .iter()
+                .map(|s| (s.location, Centimeters::from_meters(s.remaining_walk_m as f32)))
+                .collect(),
+        }
+    }
+}
+
+impl Shape for Isochrone {
+    fn build_into(&self, compiler: &mut SdfCompiler) -> Register {
+        let max_radius = self.stations.iter().map(|(_, radius)| radius.0).max();
+        let bucket_size = bucket_size(Centimeters(max_radius.unwrap_or(0)));
+
+        let mut buckets: BTreeMap<i32, Vec<geo::Point>> = BTreeMap::new();
+        for (location, radius) in &self.stations {
+            buckets
+                .entry(bucket_radius(*radius, bucket_size).0)
+                .or_default()
+                .push(*location);
+        }
+
+        let origin = compiler.point(self.origin);
+        let mut parts = vec![compiler.dilate(origin, self.origin_radius)];
+
+        for (radius, points) in buckets {
+            let cloud = compiler.point_cloud(points);
+            parts.push(compiler.dilate(cloud, Centimeters(radius)));
+        }
+
+        compiler.union(parts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::shape::instruction::SdfInstruction;
+
+    fn dilations(isochrone: &Isochrone) -> (usize, Vec<i32>) {
+        let mut compiler = SdfCompiler::new();
+        isochrone.build_into(&mut compiler);
+        let instructions = compiler.finish();
+
+        let dilations = instructions
+            .iter()
+            .filter_map(|instruction| match instruction {
+                SdfInstruction::Dilate { amount, .. } => Some(amount.0),
+                _ => None,
+            })
+            .collect();
+        (instructions.len(), dilations)
+    }
+
+    #[test]
+    fn test_bucket_radius_error_bound() {
+        let size = MIN_RADIUS_BUCKET.0;
+        for m in [0.0, 0.01, 49.99, 50.0, 50.01, 123.45, 1_000.0, 2_345.67] {
+            let cm = Centimeters::from_meters(m).0;
+            let bucketed = bucket_radius(Centimeters(cm), size).0;
+            assert!(bucketed >= cm, "{cm} cm rounded down to {bucketed}");
+            assert!(bucketed - cm < size, "{cm} cm rounded up to {bucketed}");
+        }
+
+        assert_eq!(bucket_radius(Centimeters(-10), size).0, 0);
+    }
+
+    #[test]
+    fn test_stations_share_buckets() {
+        let isochrone = Isochrone {
+            origin: geo::Point::new(-73.99, 40.70),
+            origin_radius: Centimeters::from_meters(420.0),
+            stations: vec![
+                (geo::Point::new(-73.99, 40.71), Centimeters::from_meters(101.0)),
+                (geo::Point::new(-73.99, 40.72), Centimeters::from_meters(149.0)),
+                (geo::Point::new(-73.99, 40.73), Centimeters::from_meters(151.0)),
+            ],
+        };
+
+        // The origin, then one per bucket, each within a bucket of its
+        // stations' exact radii
+        let (_, dilations) = dilations(&isochrone);
+        assert_eq!(dilations, vec![42_000, 15_000, 20_000]);
+    }
+
+    #[test]
+    fn test_long_walks_widen_buckets() {
+        // Two hours at 1.4 m/s leaves up to ~10 km of walking, which 50 m
+        // buckets would split into 200
+        let isochrone = Isochrone {
+            origin: geo::Point::new(-73.99, 40.70),
+            origin_radius: Centimeters::from_meters(10_080.0),
+            stations: (0..2_000)
+                .map(|i| {
+                    let location = geo::Point::new(-73.99, 40.70 + i as f64 * 1e-4);
+                    (location, Centimeters::from_meters(i as f32 * 5.04))
+                })
+                .collect(),
+        };
+
+        let (instructions, dilations) = dilations(&isochrone);
+        assert!(instructions <= 255, "{instructions} instructions");
+        // The origin and at most one more bucket than the limit, for
+        // stations with no walk left
+        assert!(dilations.len() <= MAX_BUCKETS as usize + 2, "{dilations:?}");
+
+        // Every station is still covered
+        let max_radius = Centimeters::from_meters(1_999.0 * 5.04).0;
+        assert!(dilations.last().is_some_and(|&radius| radius >= max_radius));
+    }
+}
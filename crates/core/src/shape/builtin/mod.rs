@@ -1 +1,2 @@
 pub mod circle;
+pub mod isochrone;
@@ -93,6 +93,11 @@ impl CompiledShape {
         self.compilation_id
     }
 
+    /// Key of this shape's module in the [`ShaderCache`]
+    pub fn shader_hash(&self) -> u64 {
+        self.shader.hash()
+    }
+
     pub fn fill_arguments(&self, buffer: &mut Vec<u8>, tile: &Tile) -> Vec<ShaderArgument> {
         let mut shader_arguments = Vec::new();
 
@@ -30,4 +30,9 @@ impl ShaderCache {
 
         shader_module
     }
+
+    /// Drop every module whose hash `keep` rejects, e.g. once no shape uses it
+    pub fn retain(&mut self, mut keep: impl FnMut(u64) -> bool) {
+        self.cache.retain(|hash, _| keep(*hash));
+    }
 }
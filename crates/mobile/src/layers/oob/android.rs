@@ -1,57 +1,127 @@
-use core::{ffi, future::Future, pin::Pin, ptr::null_mut, task::Waker};
-use std::{
-    collections::HashMap,
-    sync::{Once, OnceLock},
+use core::{
+    ffi,
+    future::Future,
+    pin::Pin,
+    ptr::null_mut,
+    task::{Poll, Waker},
 };
+use std::sync::{Arc, OnceLock};
 
 use crate::{
     android::gl::{GlResult, get_egl_instance, get_gl_context},
     layers::{
-        android::CustomLayer,
+        android::{CustomLayer, LayerError, LayerParameters},
         oob::{
-            RENDER_SESSION,
+            RENDER_SESSION, ShapeOverlay,
             culling::{AABB, Frustum},
             traverse_quadtree::{TileAction, traverse_quadtree},
         },
     },
-    render::thread::{RenderThread, RequestTile, StartShapeCompilation},
+    render::{
+        android::WrapBufferRef,
+        cache::{ShapeRevisions, TileCache, TileKey},
+        surface::RequestTile,
+        thread::{RenderThread, ShapeId, TileOutput},
+    },
 };
-use actix::{Addr, dev::Request};
+use actix::dev::Request;
 use eyre::{ContextCompat, OptionExt, WrapErr, bail};
-use geo::Point;
 use glam::{DMat4, DQuat, DVec3, dvec3, dvec4};
 use glow::{HasContext, NativeBuffer, NativeProgram, NativeUniformLocation};
-use jet_lag_core::{
-    map::tile::Tile,
-    shape::{Shape, builtin::circle::Circle, compiler::Register, types::Centimeters},
-};
+use jet_lag_core::map::tile::Tile;
 use ndk::hardware_buffer::HardwareBufferRef;
-use pollster::FutureExt;
-use rand::Rng;
 use wgpu_hal::gles::TextureInner;
 use zerocopy::IntoBytes;
 
 const TILE_SIZE: f64 = 512.0;
 const MAX_ZOOM: u8 = 25;
+/// GPU memory kept for tiles, beyond what the current view needs
+const TILE_CACHE_BUDGET: usize = 64 * 1024 * 1024;
+
+/// Draw the whole texture
+const FULL_UV: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
+/// A tile's buffer as seen by the current GL context
+struct ImportedTile {
+    display: khronos_egl::Display,
+    image: khronos_egl::Image,
+    texture: glow::Texture,
+}
 
 enum TileEntry {
     Loaded {
         hardware_buffer: HardwareBufferRef,
-        texture: glow::Texture,
+        /// Imported on first draw, and again after the context is lost
+        imported: Option<ImportedTile>,
     },
     InProgress {
         request: Pin<Box<Request<RenderThread, RequestTile>>>,
     },
+    /// Left in place until it's evicted, rather than requested every frame
+    Failed,
 }
 
-pub struct OutOfBoundsLayer {
+/// Everything tied to the GL context, rebuilt after it's lost
+struct GlObjects {
     pos_attrib: u32,
     proj_uniform: NativeUniformLocation,
+    uv_uniform: NativeUniformLocation,
     // border_color_uniform: NativeUniformLocation,
     buffer: NativeBuffer,
     program: NativeProgram,
+}
 
-    active_tile_requests: HashMap<(u8, u32, u32), TileEntry>,
+pub struct OutOfBoundsLayer {
+    /// Draw just this shape, or all of them if `None`
+    shape: Option<ShapeId>,
+    gl_objects: Option<GlObjects>,
+
+    tiles: TileCache<TileEntry>,
+    revisions: Arc<ShapeRevisions>,
+}
+
+impl GlObjects {
+    fn new(gl: &glow::Context) -> eyre::Result<Self> {
+        let program =
+            OutOfBoundsLayer::create_program(gl).context("failed to create shader program")?;
+        use glow::*;
+        unsafe {
+            let pos_attrib = gl
+                .get_attrib_location(program, "a_pos")
+                .context("no a_pos attribute")?;
+            // let border_color_uniform = gl
+            // .get_uniform_location(program, "fill_color")
+            // .context("no fill_color uniform")?;
+            let proj_uniform = gl
+                .get_uniform_location(program, "proj")
+                .context("no proj uniform")?;
+            let uv_uniform = gl
+                .get_uniform_location(program, "uv_rect")
+                .context("no uv_rect uniform")?;
+
+            static TILE: [f32; 8] = [0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 1.0];
+
+            let buffer = gl.create_buffer().wrap_gl()?;
+            gl.bind_buffer(ARRAY_BUFFER, Some(buffer));
+            gl.buffer_data_u8_slice(ARRAY_BUFFER, TILE.as_bytes(), STATIC_DRAW);
+
+            Ok(Self {
+                program,
+                pos_attrib,
+                proj_uniform,
+                uv_uniform,
+                // border_color_uniform,
+                buffer,
+            })
+        }
+    }
+
+    fn delete(self, gl: &glow::Context) {
+        unsafe {
+            gl.delete_buffer(self.buffer);
+            gl.delete_program(self.program);
+        }
+    }
 }
 
 impl OutOfBoundsLayer {
@@ -75,11 +145,13 @@ impl OutOfBoundsLayer {
 
                     uniform highp mat4 proj;
                     uniform float zoom_level;
+                    // Part of the texture to draw: offset, then scale
+                    uniform highp vec4 uv_rect;
             
                     layout (location = 0) in vec2 a_pos;
                     layout (location = 0) out vec2 texCoord;
                     void main() {{
-                        texCoord = a_pos;
+                        texCoord = uv_rect.xy + a_pos * uv_rect.zw;
                         gl_Position = proj * vec4(a_pos, 0.0, 1.0);
                     }}"
                 ),
@@ -98,44 +170,9 @@ impl OutOfBoundsLayer {
                 layout (location = 0) in vec2 texCoord;
                 out highp vec4 fragColor;
 
-                int decodeSignedDistance(vec4 texel) {
-                    uint byte0 = uint(texel.r * 255.0 + 0.5);
-                    uint byte1 = uint(texel.g * 255.0 + 0.5);
-                    uint byte2 = uint(texel.b * 255.0 + 0.5);
-                    uint byte3 = uint(texel.a * 255.0 + 0.5);
-                    uint unsignedVal = byte0 + (byte1 * 256u) + (byte2 * 65536u) + (byte3 * 16777216u);
-                    
-                    // bitcast unsignedVal to sint
-                    return int(unsignedVal) - int(unsignedVal & 0x80000000u) * 2;
-                }
-
+                // Already styled, with premultiplied alpha
                 void main() {
-                    ivec2 texSize = textureSize(tile, 0);
-                    vec2 texelCoord = texCoord * vec2(texSize) - 0.5;
-                    ivec2 tc00 = ivec2(floor(texelCoord));
-                    vec2 frac = fract(texelCoord);
-                    
-                    // Fetch 4 corner texels
-                    ivec2 tc10 = tc00 + ivec2(1, 0);
-                    ivec2 tc01 = tc00 + ivec2(0, 1);
-                    ivec2 tc11 = tc00 + ivec2(1, 1);
-                    
-                    // Decode each to signed distance
-                    float d00 = float(decodeSignedDistance(texelFetch(tile, tc00, 0)));
-                    float d10 = float(decodeSignedDistance(texelFetch(tile, tc10, 0)));
-                    float d01 = float(decodeSignedDistance(texelFetch(tile, tc01, 0)));
-                    float d11 = float(decodeSignedDistance(texelFetch(tile, tc11, 0)));
-                    
-                    // Bilinear interpolation
-                    float d0 = mix(d00, d10, frac.x);
-                    float d1 = mix(d01, d11, frac.x);
-                    float signedVal = mix(d0, d1, frac.y);
-                    
-                    if (signedVal > 0.0) {
-                        discard;
-                    } else {
-                        fragColor = vec4((-signedVal / 10000.0), 0.0, 0.0, 1.0);
-                    }
+                    fragColor = texture(tile, texCoord);
                 }",
             );
             gl.compile_shader(fragment_shader);
@@ -168,51 +205,46 @@ impl OutOfBoundsLayer {
 }
 
 impl CustomLayer for OutOfBoundsLayer {
-    fn new() -> eyre::Result<Self> {
-        let gl = get_gl_context();
-        let program = Self::create_program(gl).context("failed to create shader program")?;
-        use glow::*;
-        unsafe {
-            let pos_attrib = gl
-                .get_attrib_location(program, "a_pos")
-                .context("no a_pos attribute")?;
-            // let border_color_uniform = gl
-            // .get_uniform_location(program, "fill_color")
-            // .context("no fill_color uniform")?;
-            let proj_uniform = gl
-                .get_uniform_location(program, "proj")
-                .context("no proj uniform")?;
+    type Config = ShapeOverlay;
 
-            static TILE: [f32; 8] = [0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 1.0];
+    fn config(parameters: &LayerParameters) -> Result<Self::Config, LayerError> {
+        ShapeOverlay::from_parameters(parameters)
+    }
 
-            let buffer = gl.create_buffer().wrap_gl()?;
-            gl.bind_buffer(ARRAY_BUFFER, Some(buffer));
-            gl.buffer_data_u8_slice(ARRAY_BUFFER, TILE.as_bytes(), STATIC_DRAW);
+    fn new(config: &ShapeOverlay) -> eyre::Result<Self> {
+        let session = RENDER_SESSION.lock().unwrap();
+        if let (Some(shape), Some(style)) = (config.shape, &config.style) {
+            session.set_style(shape, style.clone());
+        }
 
-            Ok(Self {
-                program,
-                pos_attrib,
-                proj_uniform,
-                // border_color_uniform,
-                buffer,
+        Ok(Self {
+            shape: config.shape,
+            gl_objects: Some(GlObjects::new(get_gl_context())?),
 
-                active_tile_requests: HashMap::with_capacity(60),
-            })
-        }
+            tiles: TileCache::new(TILE_CACHE_BUDGET),
+            revisions: session.revisions(),
+        })
     }
 
     fn render(&mut self, parameters: &crate::layers::android::Parameters) -> eyre::Result<()> {
         use glow::*;
         let gl = get_gl_context();
+        let objects = match &mut self.gl_objects {
+            Some(objects) => objects,
+            lost @ None => {
+                tracing::info!("recreating GL objects after context loss");
+                lost.insert(GlObjects::new(gl)?)
+            }
+        };
         unsafe {
-            gl.use_program(Some(self.program));
-            gl.bind_buffer(ARRAY_BUFFER, Some(self.buffer));
-            gl.enable_vertex_attrib_array(self.pos_attrib);
-            gl.vertex_attrib_pointer_f32(self.pos_attrib, 2, FLOAT, false, 0, 0);
+            gl.use_program(Some(objects.program));
+            gl.bind_buffer(ARRAY_BUFFER, Some(objects.buffer));
+            gl.enable_vertex_attrib_array(objects.pos_attrib);
+            gl.vertex_attrib_pointer_f32(objects.pos_attrib, 2, FLOAT, false, 0, 0);
             gl.disable(STENCIL_TEST);
             gl.disable(DEPTH_TEST);
             gl.enable(BLEND);
-            gl.blend_func(SRC_ALPHA, ONE_MINUS_SRC_ALPHA);
+            gl.blend_func(ONE, ONE_MINUS_SRC_ALPHA);
 
             // Set line width for the border (adjust thickness as needed)
             gl.line_width(5.0);
@@ -231,19 +263,20 @@ impl CustomLayer for OutOfBoundsLayer {
             );
             gl.active_texture(TEXTURE0);
 
-            let draw_tile_at = |zoom: u8, x: f64, y: f64, texture: glow::Texture| {
-                let pos = (2u32.pow(zoom as u32) as f64).recip();
+            let draw_tile_at = |tile: &Tile, uv: [f32; 4], texture: glow::Texture| {
+                let pos = (2u32.pow(tile.zoom as u32) as f64).recip();
 
                 let mat = world_matrix.mul_mat4(&DMat4::from_scale_rotation_translation(
                     DVec3::new(pos, pos, 1.0),
                     DQuat::IDENTITY,
-                    DVec3::new(x as f64, y as f64, 0.0),
+                    DVec3::new(tile.x0, tile.y0, 0.0),
                 ));
 
                 let mat = mat.to_cols_array().map(|v| v as f32);
 
                 gl.bind_texture(TEXTURE_2D, Some(texture));
-                gl.uniform_matrix_4_f32_slice(Some(&self.proj_uniform), false, &mat);
+                gl.uniform_matrix_4_f32_slice(Some(&objects.proj_uniform), false, &mat);
+                gl.uniform_4_f32_slice(Some(&objects.uv_uniform), &uv);
                 gl.draw_arrays(TRIANGLE_STRIP, 0, 4);
             };
 
@@ -332,173 +365,250 @@ impl CustomLayer for OutOfBoundsLayer {
                 }
             });
 
-            static SHAPE: Once = Once::new();
+            self.tiles.next_frame();
+            for stale in self.tiles.evict_stale(&self.revisions) {
+                release_tile(gl, stale);
+            }
 
-            for tile in tiles {
-                let tile: Tile = tile;
-                let request_params = (tile.zoom, tile.tile_x, tile.tile_y);
-                match self.active_tile_requests.get_mut(&request_params) {
-                    Some(TileEntry::InProgress { request }) => {
-                        match request
-                            .as_mut()
-                            .poll(&mut core::task::Context::from_waker(Waker::noop()))
-                        {
-                            core::task::Poll::Ready(texture) => {
-                                let hardware_buffer: HardwareBufferRef = texture.unwrap().0;
-
-                                let egl = get_egl_instance();
-                                let func: extern "C" fn(
-                                    *const ndk_sys::AHardwareBuffer,
-                                )
-                                    -> *mut ffi::c_void = core::mem::transmute(
-                                    egl.get_proc_address("eglGetNativeClientBufferANDROID"),
-                                );
-                                let client_buffer = func(hardware_buffer.as_ptr());
-                                if client_buffer.is_null() {
-                                    panic!("failed to get client buffer for harwdare buffer");
-                                }
-
-                                use khronos_egl as egl;
-                                let create_image_khr: extern "C" fn(
-                                    egl::Display,
-                                    egl::Context,
-                                    u32,
-                                    *mut ffi::c_void,
-                                    *const i32,
-                                )
-                                    -> egl::Image = std::mem::transmute(
-                                    egl.get_proc_address("eglCreateImageKHR").unwrap(),
-                                );
-
-                                const EGL_NATIVE_BUFFER_ANDROID: u32 = 0x3140;
-                                const EGL_IMAGE_PRESERVED_KHR: i32 = 0x30D2;
-
-                                let attrib_list = [EGL_IMAGE_PRESERVED_KHR, 1, egl::NONE as i32];
-                                let image = create_image_khr(
-                                    egl.get_current_display().unwrap(),
-                                    egl::Context::from_ptr(egl::NO_CONTEXT),
-                                    EGL_NATIVE_BUFFER_ANDROID,
-                                    client_buffer,
-                                    attrib_list.as_ptr(),
-                                );
-                                if image.as_ptr().is_null() {
-                                    panic!("Failed to create EGLImage {:?}", egl.get_error());
-                                }
-
-                                let gl_egl_image_target_texture_2d: extern "C" fn(u32, egl::Image) =
-                                    std::mem::transmute(
-                                        egl.get_proc_address("glEGLImageTargetTexture2DOES"),
-                                    );
-
-                                let texture = gl.create_texture().unwrap();
-                                gl.bind_texture(TEXTURE_2D, Some(texture));
-
-                                gl_egl_image_target_texture_2d(TEXTURE_2D, image);
-                                let error = gl.get_error();
-                                if error != NO_ERROR {
-                                    panic!("got error while bidning texture to image: {error}")
-                                }
-
-                                let _ = self.active_tile_requests.insert(
-                                    request_params,
-                                    TileEntry::Loaded {
+            let mut shapes = self.revisions.ids();
+            if let Some(only) = self.shape {
+                shapes.retain(|&id| id == only);
+            }
+
+            for tile in &tiles {
+                for &shape in &shapes {
+                    // Removed since the frame started
+                    let Some(revision) = self.revisions.get(shape) else {
+                        continue;
+                    };
+
+                    let key = TileKey::new(shape, revision, tile);
+                    let texture = match self.tiles.get_mut(&key) {
+                        Some(entry) => {
+                            if let TileEntry::InProgress { request } = entry
+                                && let Poll::Ready(response) = request
+                                    .as_mut()
+                                    .poll(&mut core::task::Context::from_waker(Waker::noop()))
+                            {
+                                *entry = match response {
+                                    Ok(Ok(WrapBufferRef(hardware_buffer))) => TileEntry::Loaded {
                                         hardware_buffer,
-                                        texture,
+                                        imported: None,
                                     },
-                                );
+                                    Ok(Err(error)) => {
+                                        tracing::error!(?key, "failed to render tile: {error}");
+                                        TileEntry::Failed
+                                    }
+                                    Err(error) => {
+                                        tracing::error!(?key, "failed to request tile: {error}");
+                                        TileEntry::Failed
+                                    }
+                                };
+                            }
+
+                            match entry {
+                                TileEntry::Loaded {
+                                    hardware_buffer,
+                                    imported,
+                                } => Some(
+                                    imported
+                                        .get_or_insert_with(|| import_tile(gl, hardware_buffer))
+                                        .texture,
+                                ),
+                                TileEntry::InProgress { .. } | TileEntry::Failed => None,
                             }
-                            core::task::Poll::Pending => {}
                         }
+                        None => {
+                            let request = RENDER_SESSION
+                                .lock()
+                                .unwrap()
+                                .render_thread
+                                .send(RequestTile::new(*tile, Some(shape), TileOutput::Styled));
+
+                            self.tiles.insert(
+                                key,
+                                TileEntry::InProgress {
+                                    request: Box::pin(request),
+                                },
+                            );
+
+                            None
+                        }
+                    };
+
+                    if let Some(texture) = texture {
+                        draw_tile_at(tile, FULL_UV, texture);
+                        continue;
                     }
-                    Some(TileEntry::Loaded {
-                        hardware_buffer,
-                        texture,
-                    }) => {
-                        draw_tile_at(tile.zoom, tile.x0, tile.y0, *texture);
-                    }
-                    None => {
-                        let mut guard = RENDER_SESSION.lock().unwrap();
-
-                        SHAPE.call_once(|| {
-                            let thread: &Addr<RenderThread> = &guard.render_thread;
-                            struct TestShape;
-                            impl Shape for TestShape {
-                                fn build_into(
-                                    &self,
-                                    compiler: &mut jet_lag_core::shape::compiler::SdfCompiler,
-                                ) -> jet_lag_core::shape::compiler::Register
-                                {
-                                    let mut rng = rand::rng();
-                                    let center_lon: f64 = -73.9805655;
-                                    let center_lat: f64 = 40.7571418;
-
-                                    // 10km in degrees (approx)
-                                    let km_to_lat: f64 = 1.0 / 111.0; // 1 degree ≈ 111km
-                                    let km_to_lon: f64 =
-                                        1.0 / (111.0 * center_lat.to_radians().cos()); // adjust for latitude
-
-                                    let points: Vec<Point> = (0..10_000)
-                                        .map(|_| {
-                                            // Exponential falloff: lambda=0.3 gives mean ~3.3km, most within 10km
-                                            let radius_km = -3.3 * rng.random::<f64>().ln(); // exponential with mean 3.3km
-
-                                            let angle =
-                                                rng.random_range(0.0..std::f64::consts::TAU);
-
-                                            Point::new(
-                                                center_lon + radius_km * km_to_lon * angle.cos(),
-                                                center_lat + radius_km * km_to_lat * angle.sin(),
-                                            )
-                                        })
-                                        .collect();
-
-                                    let pc = compiler.point_cloud(points);
-                                    let pc = compiler.dilate(pc, Centimeters::from_meters(50.0));
-
-                                    let c = compiler.with(&Circle::new(
-                                        geo::Point::new(center_lon - 0.05, center_lat),
-                                        Centimeters::from_meters(500.0),
-                                    ));
-
-                                    compiler.subtract(pc, c)
-                                }
-                            }
-                            thread
-                                .send(StartShapeCompilation {
-                                    shape: Box::new(TestShape),
-                                })
-                                .block_on()
-                                .unwrap();
-                        });
-
-                        let thread: &Addr<RenderThread> = &guard.render_thread;
-
-                        let request = thread.send(RequestTile { tile });
-
-                        let sent_request = request;
-                        self.active_tile_requests.insert(
-                            request_params,
-                            TileEntry::InProgress {
-                                request: Box::pin(sent_request),
-                            },
-                        );
+
+                    // Stand in with the closest loaded ancestor, scaled up
+                    let mut ancestor = tile.parent();
+                    while let Some(parent) = ancestor {
+                        let key = TileKey::new(shape, revision, &parent);
+                        if let Some(TileEntry::Loaded {
+                            hardware_buffer,
+                            imported,
+                        }) = self.tiles.get_mut(&key)
+                        {
+                            let imported =
+                                imported.get_or_insert_with(|| import_tile(gl, hardware_buffer));
+                            draw_tile_at(tile, uv_within(tile, &parent), imported.texture);
+                            break;
+                        }
+                        ancestor = parent.parent();
                     }
                 }
             }
+
+            // Dropping a request before the render thread gets to it cancels it
+            self.tiles
+                .evict_unused(|entry| matches!(entry, TileEntry::InProgress { .. }));
+
+            for evicted in self.tiles.trim() {
+                release_tile(gl, evicted);
+            }
         }
 
         Ok(())
     }
 
     fn context_lost(&mut self) {
-        todo!("handle context loss properly")
+        tracing::warn!("GL context lost");
+
+        // The old handles went with the context, so forget rather than
+        // delete them
+        self.gl_objects = None;
+
+        // Rendered buffers outlive the context and are imported again on the
+        // next draw; pending requests are dropped and made again
+        let cancelled = self.tiles.retain_mut(|entry| match entry {
+            TileEntry::Loaded { imported, .. } => {
+                if let Some(imported) = imported.take() {
+                    unsafe { destroy_image(&imported) };
+                }
+                true
+            }
+            TileEntry::InProgress { .. } | TileEntry::Failed => false,
+        });
+        drop(cancelled);
     }
 
-    fn cleanup(self) {
+    fn cleanup(mut self) {
         let gl = get_gl_context();
         unsafe {
-            gl.delete_buffer(self.buffer);
-            gl.delete_program(self.program);
+            for tile in self.tiles.clear() {
+                release_tile(gl, tile);
+            }
+        }
+        if let Some(objects) = self.gl_objects.take() {
+            objects.delete(gl);
+        }
+    }
+}
+
+/// Where `tile` sits within the texture of its ancestor `outer`
+fn uv_within(tile: &Tile, outer: &Tile) -> [f32; 4] {
+    let width = outer.x1 - outer.x0;
+    let height = outer.y1 - outer.y0;
+
+    [
+        ((tile.x0 - outer.x0) / width) as f32,
+        ((tile.y0 - outer.y0) / height) as f32,
+        ((tile.x1 - tile.x0) / width) as f32,
+        ((tile.y1 - tile.y0) / height) as f32,
+    ]
+}
+
+/// Wrap a rendered tile's buffer in a GL texture
+unsafe fn import_tile(gl: &glow::Context, hardware_buffer: &HardwareBufferRef) -> ImportedTile {
+    use glow::*;
+    use khronos_egl as egl;
+
+    unsafe {
+        let egl = get_egl_instance();
+        let func: extern "C" fn(*const ndk_sys::AHardwareBuffer) -> *mut ffi::c_void =
+            core::mem::transmute(egl.get_proc_address("eglGetNativeClientBufferANDROID"));
+        let client_buffer = func(hardware_buffer.as_ptr());
+        if client_buffer.is_null() {
+            panic!("failed to get client buffer for harwdare buffer");
+        }
+
+        let create_image_khr: extern "C" fn(
+            egl::Display,
+            egl::Context,
+            u32,
+            *mut ffi::c_void,
+            *const i32,
+        ) -> egl::Image = std::mem::transmute(egl.get_proc_address("eglCreateImageKHR").unwrap());
+
+        const EGL_NATIVE_BUFFER_ANDROID: u32 = 0x3140;
+        const EGL_IMAGE_PRESERVED_KHR: i32 = 0x30D2;
+
+        let display = egl.get_current_display().unwrap();
+        let attrib_list = [EGL_IMAGE_PRESERVED_KHR, 1, egl::NONE as i32];
+        let image = create_image_khr(
+            display,
+            egl::Context::from_ptr(egl::NO_CONTEXT),
+            EGL_NATIVE_BUFFER_ANDROID,
+            client_buffer,
+            attrib_list.as_ptr(),
+        );
+        if image.as_ptr().is_null() {
+            panic!("Failed to create EGLImage {:?}", egl.get_error());
         }
+
+        let gl_egl_image_target_texture_2d: extern "C" fn(u32, egl::Image) =
+            std::mem::transmute(egl.get_proc_address("glEGLImageTargetTexture2DOES"));
+
+        let texture = gl.create_texture().unwrap();
+        gl.bind_texture(TEXTURE_2D, Some(texture));
+
+        gl_egl_image_target_texture_2d(TEXTURE_2D, image);
+        let error = gl.get_error();
+        if error != NO_ERROR {
+            panic!("got error while bidning texture to image: {error}")
+        }
+
+        // No mipmaps, so the default filter would leave the texture incomplete
+        gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MIN_FILTER, LINEAR as i32);
+        gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_MAG_FILTER, LINEAR as i32);
+        gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_WRAP_S, CLAMP_TO_EDGE as i32);
+        gl.tex_parameter_i32(TEXTURE_2D, TEXTURE_WRAP_T, CLAMP_TO_EDGE as i32);
+
+        ImportedTile {
+            display,
+            image,
+            texture,
+        }
+    }
+}
+
+/// Free what a tile holds on the GL side; dropping a pending request
+/// cancels it
+unsafe fn release_tile(gl: &glow::Context, entry: TileEntry) {
+    let TileEntry::Loaded {
+        imported: Some(imported),
+        ..
+    } = entry
+    else {
+        return;
+    };
+
+    unsafe {
+        gl.delete_texture(imported.texture);
+        destroy_image(&imported);
+    }
+}
+
+/// The image holds a reference to the hardware buffer until destroyed; it
+/// belongs to the display, so this is safe to call after context loss
+unsafe fn destroy_image(imported: &ImportedTile) {
+    use khronos_egl as egl;
+
+    unsafe {
+        let egl = get_egl_instance();
+        let destroy_image_khr: extern "C" fn(egl::Display, egl::Image) -> egl::Boolean =
+            std::mem::transmute(egl.get_proc_address("eglDestroyImageKHR").unwrap());
+        destroy_image_khr(imported.display, imported.image);
     }
 }
@@ -0,0 +1,29 @@
+use crate::{
+    layers::android::{LayerError, LayerParameters},
+    render::{style::Style, thread::ShapeId},
+};
+
+/// What a `shape-overlay` layer draws
+pub struct ShapeOverlay {
+    /// Draw just this shape, or all of them if `None`
+    pub shape: Option<ShapeId>,
+
+    /// Restyle `shape` when the layer is created, rather than keep the
+    /// style it was added with
+    pub style: Option<Style>,
+}
+
+impl ShapeOverlay {
+    /// `shape`: the one shape to draw; every shape if left out
+    ///
+    /// `style`: how to draw it, as [`Style`] parses it; needs `shape`
+    pub fn from_parameters(parameters: &LayerParameters) -> Result<Self, LayerError> {
+        let shape = parameters.get("shape")?;
+        let style = parameters.get("style")?;
+        if style.is_some() && shape.is_none() {
+            return Err(LayerError::MissingParameter("shape".to_owned()));
+        }
+
+        Ok(Self { shape, style })
+    }
+}
@@ -0,0 +1,437 @@
+use std::{
+    collections::HashMap,
+    panic::{self, AssertUnwindSafe},
+    str::FromStr,
+};
+
+use crate::layers::android::{CustomLayer, Parameters};
+#[cfg(target_os = "android")]
+use crate::layers::{android::setup_logging, oob::OutOfBoundsLayer, test_square::TestSquare};
+
+#[derive(Debug, thiserror::Error, uniffi::Error)]
+#[uniffi(flat_error)]
+pub enum LayerError {
+    #[error("no custom layer is called {0:?}")]
+    UnknownLayer(String),
+    #[error("missing parameter {0:?}")]
+    MissingParameter(String),
+    #[error("parameter {name:?} can't be {value:?}")]
+    InvalidParameter { name: String, value: String },
+}
+
+/// Named parameters a layer is created with
+pub struct LayerParameters(HashMap<String, String>);
+
+impl LayerParameters {
+    pub fn get<T: FromStr>(&self, name: &str) -> Result<Option<T>, LayerError> {
+        let Some(value) = self.0.get(name) else {
+            return Ok(None);
+        };
+
+        value
+            .parse()
+            .map(Some)
+            .map_err(|_| LayerError::InvalidParameter {
+                name: name.to_owned(),
+                value: value.clone(),
+            })
+    }
+
+    pub fn require<T: FromStr>(&self, name: &str) -> Result<T, LayerError> {
+        self.get(name)?
+            .ok_or_else(|| LayerError::MissingParameter(name.to_owned()))
+    }
+}
+
+/// Every layer the host can create, by name
+#[cfg(target_os = "android")]
+const LAYERS: &[(&str, Prepare)] = &[
+    ("shape-overlay", prepare::<OutOfBoundsLayer>),
+    ("test-square", prepare::<TestSquare>),
+];
+
+/// A [`CustomLayer`] with its type erased, so instances of any layer share
+/// one set of callbacks
+trait DynLayer {
+    fn render(&mut self, parameters: &Parameters) -> eyre::Result<()>;
+    fn context_lost(&mut self);
+    fn cleanup(self: Box<Self>);
+}
+
+impl<T: CustomLayer> DynLayer for T {
+    fn render(&mut self, parameters: &Parameters) -> eyre::Result<()> {
+        CustomLayer::render(self, parameters)
+    }
+
+    fn context_lost(&mut self) {
+        CustomLayer::context_lost(self)
+    }
+
+    fn cleanup(self: Box<Self>) {
+        CustomLayer::cleanup(*self)
+    }
+}
+
+type Construct = Box<dyn Fn() -> eyre::Result<Box<dyn DynLayer>>>;
+type Prepare = fn(&LayerParameters) -> Result<Construct, LayerError>;
+
+fn prepare<T: CustomLayer>(parameters: &LayerParameters) -> Result<Construct, LayerError> {
+    let config = T::config(parameters)?;
+    Ok(Box::new(move || {
+        T::new(&config).map(|layer| Box::new(layer) as Box<dyn DynLayer>)
+    }))
+}
+
+/// One layer added to the map
+///
+/// The layer itself only exists between `initialize` and `deinitialize`,
+/// which MapLibre calls on the GL thread.
+struct LayerInstance {
+    name: &'static str,
+    construct: Construct,
+    layer: Option<Box<dyn DynLayer>>,
+}
+
+/// Mirrors `CustomLayerHostVtable` in customLayer.hpp
+#[repr(C)]
+struct CustomLayerVTable {
+    pub initialize: extern "C" fn(*mut CustomLayerVTable),
+    pub render: extern "C" fn(*mut CustomLayerVTable, *const Parameters),
+    pub context_lost: extern "C" fn(*mut CustomLayerVTable),
+    pub deinitialize: extern "C" fn(*mut CustomLayerVTable),
+    /// Called once the host is done with the vtable
+    pub destroy: extern "C" fn(*mut CustomLayerVTable),
+    pub boxed_value: *mut LayerInstance,
+}
+
+/// Run a callback from C; a panic must never unwind across the boundary
+fn catch_panic(callback: &str, f: impl FnOnce()) {
+    // The panic hook has already logged the message
+    if panic::catch_unwind(AssertUnwindSafe(f)).is_err() {
+        tracing::error!("custom layer panicked in {callback}");
+    }
+}
+
+fn instance<'a>(vtable: *mut CustomLayerVTable) -> &'a mut LayerInstance {
+    unsafe { &mut *(*vtable).boxed_value }
+}
+
+extern "C" fn initialize(vtable: *mut CustomLayerVTable) {
+    catch_panic("initialize", || {
+        let instance = instance(vtable);
+        match (instance.construct)() {
+            Ok(layer) => instance.layer = Some(layer),
+            Err(error) => tracing::error!("failed to construct {}: {error:?}", instance.name),
+        }
+    })
+}
+
+extern "C" fn render(vtable: *mut CustomLayerVTable, parameters: *const Parameters) {
+    catch_panic("render", || {
+        let instance = instance(vtable);
+        let Some(layer) = &mut instance.layer else {
+            return;
+        };
+        if let Err(error) = layer.render(unsafe { &*parameters }) {
+            tracing::error!("{} failed to render a frame: {error:?}", instance.name);
+        }
+    })
+}
+
+extern "C" fn context_lost(vtable: *mut CustomLayerVTable) {
+    catch_panic("context_lost", || {
+        if let Some(layer) = &mut instance(vtable).layer {
+            layer.context_lost();
+        }
+    })
+}
+
+extern "C" fn deinitialize(vtable: *mut CustomLayerVTable) {
+    catch_panic("deinitialize", || {
+        if let Some(layer) = instance(vtable).layer.take() {
+            layer.cleanup();
+        }
+    })
+}
+
+extern "C" fn destroy(vtable: *mut CustomLayerVTable) {
+    catch_panic("destroy", || {
+        let vtable = unsafe { Box::from_raw(vtable) };
+        let instance = unsafe { Box::from_raw(vtable.boxed_value) };
+        if let Some(layer) = instance.layer {
+            tracing::warn!("{} destroyed without deinitializing", instance.name);
+            layer.cleanup();
+        }
+    })
+}
+
+/// Create a custom layer, returning a `CustomLayerHostVtable` pointer for
+/// the host shim to wrap
+///
+/// The host owns the result and frees it through its `destroy` callback; a
+/// layer that's never handed to MapLibre leaks.
+#[cfg(target_os = "android")]
+#[uniffi::export]
+pub fn create_custom_layer(
+    name: String,
+    parameters: HashMap<String, String>,
+) -> Result<u64, LayerError> {
+    setup_logging();
+    tracing::info!("creating custom layer {name:?}");
+
+    create_layer(LAYERS, name, parameters).map(|vtable| vtable as u64)
+}
+
+/// Look `name` up in `layers` and box a vtable for a new instance of it
+fn create_layer(
+    layers: &[(&'static str, Prepare)],
+    name: String,
+    parameters: HashMap<String, String>,
+) -> Result<*mut CustomLayerVTable, LayerError> {
+    let Some(&(name, prepare)) = layers.iter().find(|(known, _)| *known == name) else {
+        return Err(LayerError::UnknownLayer(name));
+    };
+
+    let instance = Box::new(LayerInstance {
+        name,
+        construct: prepare(&LayerParameters(parameters))?,
+        layer: None,
+    });
+
+    let vtable = Box::new(CustomLayerVTable {
+        initialize,
+        render,
+        context_lost,
+        deinitialize,
+        destroy,
+        boxed_value: Box::into_raw(instance),
+    });
+
+    Ok(Box::into_raw(vtable))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use glam::DMat4;
+
+    use super::*;
+    use crate::{layers::oob::ShapeOverlay, render::style::Pattern};
+
+    thread_local! {
+        /// What the test layers on this thread have done, in order
+        static EVENTS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn record(event: String) {
+        EVENTS.with_borrow_mut(|events| events.push(event));
+    }
+
+    fn events() -> Vec<String> {
+        EVENTS.with_borrow_mut(std::mem::take)
+    }
+
+    /// Records its lifecycle, tagged with its `id` parameter
+    struct Recorder {
+        id: u32,
+    }
+
+    impl CustomLayer for Recorder {
+        type Config = u32;
+
+        fn config(parameters: &LayerParameters) -> Result<u32, LayerError> {
+            parameters.require("id")
+        }
+
+        fn new(id: &u32) -> eyre::Result<Self> {
+            record(format!("new {id}"));
+            Ok(Self { id: *id })
+        }
+
+        fn render(&mut self, parameters: &Parameters) -> eyre::Result<()> {
+            record(format!("render {} at zoom {}", self.id, parameters.zoom));
+            Ok(())
+        }
+
+        fn context_lost(&mut self) {
+            record(format!("context lost {}", self.id));
+        }
+
+        fn cleanup(self) {
+            record(format!("cleanup {}", self.id));
+        }
+    }
+
+    /// Stands in for `OutOfBoundsLayer`, which needs a GL context, taking
+    /// the same parameters
+    struct ShapeOverlayStub;
+
+    impl CustomLayer for ShapeOverlayStub {
+        type Config = ShapeOverlay;
+
+        fn config(parameters: &LayerParameters) -> Result<ShapeOverlay, LayerError> {
+            ShapeOverlay::from_parameters(parameters)
+        }
+
+        fn new(config: &ShapeOverlay) -> eyre::Result<Self> {
+            let shape = config.shape.map_or("every shape".to_owned(), |id| format!("shape {id}"));
+            let fill = match config.style.as_ref().map(|style| &style.fill) {
+                None => "its own style".to_owned(),
+                Some(Some(Pattern::SolidColor(color))) => {
+                    let color = color.into_format::<u8, u8>();
+                    let [r, g, b, a] = [color.red, color.green, color.blue, color.alpha];
+                    format!("#{r:02x}{g:02x}{b:02x}{a:02x}")
+                }
+                Some(_) => "another fill".to_owned(),
+            };
+            record(format!("overlay {shape} with {fill}"));
+            Ok(Self)
+        }
+
+        fn render(&mut self, _parameters: &Parameters) -> eyre::Result<()> {
+            Ok(())
+        }
+
+        fn context_lost(&mut self) {}
+
+        fn cleanup(self) {}
+    }
+
+    const TEST_LAYERS: &[(&str, Prepare)] = &[
+        ("recorder", prepare::<Recorder>),
+        ("shape-overlay", prepare::<ShapeOverlayStub>),
+    ];
+
+    fn create(
+        name: &str,
+        parameters: &[(&str, &str)],
+    ) -> Result<*mut CustomLayerVTable, LayerError> {
+        let parameters = parameters
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        create_layer(TEST_LAYERS, name.to_owned(), parameters)
+    }
+
+    fn parameters(zoom: f64) -> Parameters {
+        Parameters {
+            width: 512.0,
+            height: 512.0,
+            latitude: 40.75,
+            longitude: -73.98,
+            zoom,
+            bearing: 0.0,
+            pitch: 0.0,
+            field_of_view: 0.6435,
+            projection_matrix: DMat4::IDENTITY,
+        }
+    }
+
+    type Callback = extern "C" fn(*mut CustomLayerVTable);
+
+    /// Call one of the vtable's callbacks the way the host shim does
+    fn call(vtable: *mut CustomLayerVTable, callback: fn(&CustomLayerVTable) -> Callback) {
+        let callback = callback(unsafe { &*vtable });
+        callback(vtable);
+    }
+
+    #[test]
+    fn test_unknown_layer() {
+        assert!(matches!(
+            create("hillshade", &[("id", "1")]),
+            Err(LayerError::UnknownLayer(name)) if name == "hillshade"
+        ));
+        assert!(events().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_parameters() {
+        assert!(matches!(
+            create("recorder", &[]),
+            Err(LayerError::MissingParameter(name)) if name == "id"
+        ));
+        assert!(matches!(
+            create("recorder", &[("id", "first")]),
+            Err(LayerError::InvalidParameter { name, value }) if name == "id" && value == "first"
+        ));
+
+        // Checked up front; nothing is constructed
+        assert!(events().is_empty());
+    }
+
+    #[test]
+    fn test_instances_are_independent() {
+        let first = create("recorder", &[("id", "1")]).unwrap();
+        let second = create("recorder", &[("id", "2")]).unwrap();
+
+        // Layers only exist between initialize and deinitialize
+        assert!(events().is_empty());
+        call(first, |v| v.initialize);
+        call(second, |v| v.initialize);
+
+        (unsafe { &*first }.render)(first, &parameters(12.0));
+        (unsafe { &*second }.render)(second, &parameters(14.0));
+        call(second, |v| v.context_lost);
+        call(first, |v| v.deinitialize);
+
+        // Rendering after deinitialize is ignored, and the layer can come back
+        (unsafe { &*first }.render)(first, &parameters(13.0));
+        call(first, |v| v.initialize);
+
+        call(first, |v| v.deinitialize);
+        call(first, |v| v.destroy);
+        // Destroyed while still initialized: cleaned up anyway
+        call(second, |v| v.destroy);
+
+        assert_eq!(
+            events(),
+            vec![
+                "new 1",
+                "new 2",
+                "render 1 at zoom 12",
+                "render 2 at zoom 14",
+                "context lost 2",
+                "cleanup 1",
+                "new 1",
+                "cleanup 1",
+                "cleanup 2",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shape_overlay_parameters() {
+        let overlays = [
+            create("shape-overlay", &[]).unwrap(),
+            create("shape-overlay", &[("shape", "7")]).unwrap(),
+            create("shape-overlay", &[("shape", "7"), ("style", "#0039a6 2px #ffffff")]).unwrap(),
+        ];
+        for overlay in overlays {
+            call(overlay, |v| v.initialize);
+            call(overlay, |v| v.destroy);
+        }
+
+        assert!(matches!(
+            create("shape-overlay", &[("style", "#0039a6")]),
+            Err(LayerError::MissingParameter(name)) if name == "shape"
+        ));
+        assert!(matches!(
+            create("shape-overlay", &[("shape", "7"), ("style", "blue")]),
+            Err(LayerError::InvalidParameter { name, .. }) if name == "style"
+        ));
+        assert!(matches!(
+            create("shape-overlay", &[("shape", "seven")]),
+            Err(LayerError::InvalidParameter { name, .. }) if name == "shape"
+        ));
+
+        assert_eq!(
+            events(),
+            vec![
+                "overlay every shape with its own style",
+                "overlay shape 7 with its own style",
+                "overlay shape 7 with #0039a6ff",
+            ]
+        );
+    }
+}
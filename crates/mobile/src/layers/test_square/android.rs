@@ -14,7 +14,7 @@ use zerocopy::IntoBytes;
 use crate::{
     android::gl::{GlResult, get_egl_instance},
     layers::{
-        android::{CustomLayer, Parameters},
+        android::{CustomLayer, LayerError, LayerParameters, Parameters},
         test_square::traverse_quadtree::{Tile, TileAction, traverse_quadtree},
     },
 };
@@ -490,7 +490,13 @@ impl TestSquare {
 }
 
 impl CustomLayer for TestSquare {
-    fn new() -> eyre::Result<Self> {
+    type Config = ();
+
+    fn config(_parameters: &LayerParameters) -> Result<(), LayerError> {
+        Ok(())
+    }
+
+    fn new(_config: &()) -> eyre::Result<Self> {
         tracing::info!("setting up context");
         static DYNAMIC: LazyLock<DynamicInstance<EGL1_0>> = LazyLock::new(|| unsafe {
             DynamicInstance::load().expect("failed to obtain egl instance")
@@ -523,18 +529,23 @@ impl CustomLayer for TestSquare {
 
     fn render(&mut self, parameters: &Parameters) -> eyre::Result<()> {
         let gl = &self.gl;
-        let graphics = self
-            .graphics
-            .as_ref()
-            .expect("graphics was removed prematurely");
-
-        graphics.render(gl, parameters).expect("failed to");
+        let graphics = match &mut self.graphics {
+            Some(graphics) => graphics,
+            lost @ None => {
+                info!("recreating graphics after context loss");
+                let program = Self::create_program(gl)?;
+                self.program = Some(program);
+                lost.insert(SimpleGraphics::new(gl, program)?)
+            }
+        };
 
-        Ok(())
+        graphics.render(gl, parameters)
     }
 
     fn context_lost(&mut self) {
+        // The handles went with the context; both are rebuilt on next render
         self.program = None;
+        self.graphics = None;
         error!("context lost...");
     }
 
@@ -0,0 +1,190 @@
+use core::future::Future;
+
+use ash::vk::{self, ExternalMemoryHandleTypeFlags};
+use ndk::{
+    hardware_buffer::{HardwareBufferDesc, HardwareBufferRef, HardwareBufferUsage},
+    hardware_buffer_format::HardwareBufferFormat,
+};
+use tracing::debug;
+use wgpu::{Texture, TextureDescriptor, TextureFormat, TextureUsages, TextureUses};
+use wgpu_hal::{MemoryFlags, vulkan::TextureMemory};
+
+use crate::render::{
+    surface::{TileSurface, TileSurfaceError},
+    thread::{RenderThread, TILE_TEXTURE_EXTENT, TILE_TEXTURE_SIZE},
+};
+
+unsafe fn find_memory_type_index(
+    memory_requirements: &vk::MemoryRequirements,
+    memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    required_properties: vk::MemoryPropertyFlags,
+) -> Option<u32> {
+    for i in 0..memory_properties.memory_type_count {
+        let memory_type = &memory_properties.memory_types[i as usize];
+
+        // Check if this memory type is allowed by the memory requirements
+        let type_supported = (memory_requirements.memory_type_bits & (1 << i)) != 0;
+
+        // Check if this memory type has the required properties
+        let properties_match = memory_type.property_flags.contains(required_properties);
+
+        if type_supported && properties_match {
+            return Some(i);
+        }
+    }
+    None
+}
+
+pub struct WrapBufferRef(pub HardwareBufferRef);
+unsafe impl Send for WrapBufferRef {}
+
+/// A tile drawn straight into an `AHardwareBuffer`, which GL imports
+/// through an `EGLImage`
+pub struct HardwareBufferTile {
+    hardware_buffer: HardwareBufferRef,
+}
+
+impl TileSurface for HardwareBufferTile {
+    type Output = WrapBufferRef;
+
+    fn allocate(thread: &RenderThread) -> Result<(Self, Texture), TileSurfaceError> {
+        let hardware_buffer: HardwareBufferRef =
+            ndk::hardware_buffer::HardwareBuffer::allocate(HardwareBufferDesc {
+                width: TILE_TEXTURE_SIZE,
+                height: TILE_TEXTURE_SIZE,
+                layers: 1,
+                stride: TILE_TEXTURE_SIZE,
+                usage: HardwareBufferUsage::GPU_FRAMEBUFFER
+                    | HardwareBufferUsage::GPU_SAMPLED_IMAGE,
+                format: HardwareBufferFormat::R8G8B8A8_UNORM,
+            })
+            .map_err(|error| TileSurfaceError::Allocate(error.to_string()))?;
+
+        let ext_info = vk::ExternalMemoryImageCreateInfo {
+            handle_types: ExternalMemoryHandleTypeFlags::ANDROID_HARDWARE_BUFFER_ANDROID,
+            ..Default::default()
+        };
+
+        let image_create_info = vk::ImageCreateInfo {
+            p_next: &ext_info as *const _ as *const _,
+            extent: vk::Extent3D {
+                width: TILE_TEXTURE_SIZE,
+                height: TILE_TEXTURE_SIZE,
+                depth: 1,
+            },
+            mip_levels: 1,
+            array_layers: 1,
+            image_type: vk::ImageType::TYPE_2D,
+            format: vk::Format::R8G8B8A8_UNORM,
+            tiling: vk::ImageTiling::LINEAR,
+            initial_layout: vk::ImageLayout::UNDEFINED,
+            usage: vk::ImageUsageFlags::COLOR_ATTACHMENT
+                | vk::ImageUsageFlags::TRANSFER_SRC
+                | vk::ImageUsageFlags::TRANSFER_DST,
+            samples: vk::SampleCountFlags::TYPE_1,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            flags: vk::ImageCreateFlags::MUTABLE_FORMAT,
+            ..Default::default()
+        };
+
+        let hal_device = unsafe { thread.device.as_hal::<wgpu_hal::api::Vulkan>().unwrap() };
+        let device = hal_device.raw_device();
+
+        let texture = unsafe {
+            let image = device
+                .create_image(&image_create_info, None)
+                .map_err(|error| TileSurfaceError::Allocate(error.to_string()))?;
+
+            let dedicated_info = vk::MemoryDedicatedAllocateInfo {
+                image,
+                buffer: vk::Buffer::null(),
+                ..Default::default()
+            };
+
+            let import_memory_info = vk::ImportAndroidHardwareBufferInfoANDROID {
+                p_next: &dedicated_info as *const _ as *const _, // chain dedicated info
+                buffer: hardware_buffer.as_ptr() as _,
+                ..Default::default()
+            };
+
+            let memory_requirements = device.get_image_memory_requirements(image);
+
+            let memory_allocate_info = {
+                let adapter = thread.adapter.as_hal::<wgpu_hal::api::Vulkan>().unwrap();
+                let phy = adapter.raw_physical_device();
+                let memory_properties = adapter
+                    .shared_instance()
+                    .raw_instance()
+                    .get_physical_device_memory_properties(phy);
+                let memory_type_index = find_memory_type_index(
+                    &memory_requirements,
+                    &memory_properties,
+                    vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                )
+                .ok_or_else(|| TileSurfaceError::Allocate("no device-local memory type".into()))?;
+                vk::MemoryAllocateInfo {
+                    p_next: &import_memory_info as *const _ as *const _,
+                    allocation_size: memory_requirements.size,
+                    memory_type_index,
+                    ..Default::default()
+                }
+            };
+
+            let device_memory = device
+                .allocate_memory(&memory_allocate_info, None)
+                .map_err(|error| TileSurfaceError::Allocate(error.to_string()))?;
+
+            let desc = hardware_buffer.describe();
+            debug!(
+                "HW Buffer: {}x{}, stride={}, format={:?}",
+                desc.width, desc.height, desc.stride, desc.format
+            );
+
+            device
+                .bind_image_memory(image, device_memory, 0)
+                .map_err(|error| TileSurfaceError::Allocate(error.to_string()))?;
+
+            let desc = wgpu_hal::TextureDescriptor {
+                label: None,
+                usage: TextureUses::COLOR_TARGET | TextureUses::COPY_SRC | TextureUses::COPY_DST,
+                size: TILE_TEXTURE_EXTENT,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                memory_flags: MemoryFlags::empty(),
+                format: TextureFormat::Rgba8Unorm,
+                view_formats: vec![TextureFormat::Rgba8Unorm],
+            };
+            let texture = hal_device.texture_from_raw(
+                image,
+                &desc,
+                None,
+                TextureMemory::Dedicated(device_memory),
+            );
+
+            thread
+                .device
+                .create_texture_from_hal::<wgpu_hal::api::Vulkan>(
+                    texture,
+                    &TextureDescriptor {
+                        label: None,
+                        size: desc.size,
+                        mip_level_count: desc.mip_level_count,
+                        sample_count: desc.sample_count,
+                        dimension: desc.dimension,
+                        format: desc.format,
+                        usage: TextureUsages::RENDER_ATTACHMENT
+                            | TextureUsages::COPY_SRC
+                            | TextureUsages::COPY_DST,
+                        view_formats: &desc.view_formats,
+                    },
+                )
+        };
+
+        Ok((Self { hardware_buffer }, texture))
+    }
+
+    fn finish(self) -> impl Future<Output = Result<WrapBufferRef, TileSurfaceError>> + 'static {
+        async move { Ok(WrapBufferRef(self.hardware_buffer)) }
+    }
+}
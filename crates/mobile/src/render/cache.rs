@@ -0,0 +1,302 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+};
+
+use jet_lag_core::map::tile::Tile;
+
+use crate::render::thread::{ShapeId, TILE_TEXTURE_SIZE};
+
+#[derive(Default)]
+struct Revisions {
+    /// Revision and insertion order of each shape
+    live: HashMap<ShapeId, (u64, u64)>,
+    next_order: u64,
+}
+
+/// Revision of every live shape, bumped whenever a change makes its tiles
+/// stale
+#[derive(Default)]
+pub struct ShapeRevisions {
+    revisions: Mutex<Revisions>,
+}
+
+impl ShapeRevisions {
+    fn lock(&self) -> MutexGuard<'_, Revisions> {
+        self.revisions.lock().unwrap_or_else(|p| p.into_inner())
+    }
+
+    pub fn get(&self, id: ShapeId) -> Option<u64> {
+        self.lock().live.get(&id).map(|(revision, _)| *revision)
+    }
+
+    /// Every live shape, in the order they were added, which is the order
+    /// styled tiles stack them in
+    pub fn ids(&self) -> Vec<ShapeId> {
+        let revisions = self.lock();
+        let mut ids: Vec<(u64, ShapeId)> = revisions
+            .live
+            .iter()
+            .map(|(id, (_, order))| (*order, *id))
+            .collect();
+        ids.sort();
+        ids.into_iter().map(|(_, id)| id).collect()
+    }
+
+    pub(crate) fn insert(&self, id: ShapeId) {
+        let mut revisions = self.lock();
+        let order = revisions.next_order;
+        revisions.next_order += 1;
+        revisions.live.insert(id, (0, order));
+    }
+
+    pub(crate) fn bump(&self, id: ShapeId) {
+        if let Some((revision, _)) = self.lock().live.get_mut(&id) {
+            *revision += 1;
+        }
+    }
+
+    pub(crate) fn remove(&self, id: ShapeId) {
+        self.lock().live.remove(&id);
+    }
+}
+
+/// GPU memory one cached tile holds
+pub const TILE_BYTES: usize = (TILE_TEXTURE_SIZE * TILE_TEXTURE_SIZE * 4) as usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TileKey {
+    pub shape: ShapeId,
+    /// Revision of the shape the tile is for, covering its style too
+    pub revision: u64,
+    pub zoom: u8,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl TileKey {
+    pub fn new(shape: ShapeId, revision: u64, tile: &Tile) -> Self {
+        Self {
+            shape,
+            revision,
+            zoom: tile.zoom,
+            x: tile.tile_x,
+            y: tile.tile_y,
+        }
+    }
+}
+
+struct CachedTile<T> {
+    /// Frame the tile was last looked up in
+    last_used: u64,
+    value: T,
+}
+
+/// Tiles for every shape, least recently used first out once over budget
+///
+/// Lookups mark a tile as used in the current frame, and tiles used this
+/// frame are never evicted, so a view needing more than the budget still
+/// draws completely.
+pub struct TileCache<T> {
+    entries: HashMap<TileKey, CachedTile<T>>,
+    max_tiles: usize,
+    frame: u64,
+}
+
+impl<T> TileCache<T> {
+    /// A cache holding up to `budget` bytes of tiles
+    pub fn new(budget: usize) -> Self {
+        let max_tiles = (budget / TILE_BYTES).max(1);
+        Self {
+            entries: HashMap::with_capacity(max_tiles),
+            max_tiles,
+            frame: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Start a new frame; tiles not looked up from here on become
+    /// candidates for eviction
+    pub fn next_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    pub fn get_mut(&mut self, key: &TileKey) -> Option<&mut T> {
+        let entry = self.entries.get_mut(key)?;
+        entry.last_used = self.frame;
+        Some(&mut entry.value)
+    }
+
+    pub fn insert(&mut self, key: TileKey, value: T) {
+        self.entries.insert(
+            key,
+            CachedTile {
+                last_used: self.frame,
+                value,
+            },
+        );
+    }
+
+    /// Remove tiles rendered for an older revision of their shape, or for a
+    /// removed shape, and hand them back for cleanup
+    pub fn evict_stale(&mut self, revisions: &ShapeRevisions) -> Vec<T> {
+        let current = revisions.lock();
+        let stale: Vec<TileKey> = self
+            .entries
+            .keys()
+            .filter(|key| {
+                current.live.get(&key.shape).map(|(revision, _)| *revision) != Some(key.revision)
+            })
+            .copied()
+            .collect();
+        drop(current);
+
+        self.remove_all(stale)
+    }
+
+    /// Remove tiles not looked up this frame that match `unused`, e.g.
+    /// requests for tiles that have scrolled out of view
+    pub fn evict_unused(&mut self, mut unused: impl FnMut(&T) -> bool) -> Vec<T> {
+        let keys: Vec<TileKey> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.last_used != self.frame && unused(&entry.value))
+            .map(|(key, _)| *key)
+            .collect();
+
+        self.remove_all(keys)
+    }
+
+    /// Remove the least recently used tiles until the cache is within budget
+    pub fn trim(&mut self) -> Vec<T> {
+        let Some(excess) = self.entries.len().checked_sub(self.max_tiles) else {
+            return Vec::new();
+        };
+
+        let mut candidates: Vec<(u64, TileKey)> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.last_used != self.frame)
+            .map(|(key, entry)| (entry.last_used, *key))
+            .collect();
+        candidates.sort_unstable_by_key(|(last_used, _)| *last_used);
+
+        self.remove_all(candidates.into_iter().take(excess).map(|(_, key)| key))
+    }
+
+    /// Remove tiles for which `keep` returns false, updating the rest in
+    /// place, and hand the removed ones back
+    pub fn retain_mut(&mut self, mut keep: impl FnMut(&mut T) -> bool) -> Vec<T> {
+        let removed: Vec<TileKey> = self
+            .entries
+            .iter_mut()
+            .filter_map(|(key, entry)| (!keep(&mut entry.value)).then_some(*key))
+            .collect();
+
+        self.remove_all(removed)
+    }
+
+    /// Remove every tile
+    pub fn clear(&mut self) -> Vec<T> {
+        self.entries.drain().map(|(_, entry)| entry.value).collect()
+    }
+
+    fn remove_all(&mut self, keys: impl IntoIterator<Item = TileKey>) -> Vec<T> {
+        keys.into_iter()
+            .filter_map(|key| self.entries.remove(&key))
+            .map(|entry| entry.value)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(shape: u64, revision: u64, x: u32) -> TileKey {
+        TileKey {
+            shape: ShapeId(shape),
+            revision,
+            zoom: 12,
+            x,
+            y: 0,
+        }
+    }
+
+    fn sorted(mut values: Vec<u32>) -> Vec<u32> {
+        values.sort();
+        values
+    }
+
+    #[test]
+    fn test_trim_evicts_least_recently_used() {
+        let mut cache = TileCache::new(2 * TILE_BYTES);
+
+        for x in 1..=3 {
+            cache.next_frame();
+            cache.insert(key(1, 0, x), x);
+        }
+
+        // Tile 1 is looked up again, so 2 and 3 are now the oldest
+        cache.next_frame();
+        assert_eq!(cache.get_mut(&key(1, 0, 1)), Some(&mut 1));
+        cache.next_frame();
+        cache.insert(key(1, 0, 4), 4);
+
+        assert_eq!(cache.trim(), vec![2, 3]);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get_mut(&key(1, 0, 1)).is_some());
+        assert!(cache.get_mut(&key(1, 0, 4)).is_some());
+
+        // Within budget
+        assert!(cache.trim().is_empty());
+    }
+
+    #[test]
+    fn test_trim_keeps_tiles_used_this_frame() {
+        let mut cache = TileCache::new(TILE_BYTES);
+
+        for x in 1..=3 {
+            cache.insert(key(1, 0, x), x);
+        }
+
+        // Over budget, but everything is on screen
+        assert!(cache.trim().is_empty());
+        assert_eq!(cache.len(), 3);
+
+        cache.next_frame();
+        cache.get_mut(&key(1, 0, 2));
+        assert_eq!(sorted(cache.trim()), vec![1, 3]);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_evict_stale() {
+        let revisions = ShapeRevisions::default();
+        revisions.insert(ShapeId(1));
+        revisions.insert(ShapeId(2));
+
+        let mut cache = TileCache::new(16 * TILE_BYTES);
+        cache.insert(key(1, 0, 0), 10);
+        cache.insert(key(2, 0, 0), 20);
+        assert!(cache.evict_stale(&revisions).is_empty());
+
+        // A restyle makes the old revision's tiles stale
+        revisions.bump(ShapeId(1));
+        cache.insert(key(1, 1, 0), 11);
+        assert_eq!(cache.evict_stale(&revisions), vec![10]);
+
+        // As does removing the shape
+        revisions.remove(ShapeId(2));
+        assert_eq!(cache.evict_stale(&revisions), vec![20]);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get_mut(&key(1, 1, 0)), Some(&mut 11));
+    }
+}
@@ -0,0 +1,416 @@
+use std::f64::consts::PI;
+
+use jet_lag_core::map::tile::Tile;
+use wgpu::{
+    BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingResource, BindingType, BlendState, BufferBindingType,
+    BufferUsages, ColorTargetState, ColorWrites, CommandEncoder, Device, FragmentState,
+    MultisampleState, Operations, PipelineCompilationOptions, PipelineLayoutDescriptor,
+    PrimitiveState, PrimitiveTopology, RenderPassColorAttachment, RenderPassDescriptor,
+    RenderPipeline, RenderPipelineDescriptor, ShaderModule, ShaderModuleDescriptor, ShaderStages,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages,
+    TextureView, TextureViewDescriptor, TextureViewDimension, VertexState,
+    util::{BufferInitDescriptor, DeviceExt},
+};
+use zerocopy::{Immutable, IntoBytes};
+
+use crate::render::{
+    style::{Pattern, Rotation, Style},
+    thread::TILE_TEXTURE_EXTENT,
+};
+
+/// Equatorial circumference of the earth
+const EARTH_CIRCUMFERENCE_CM: f64 = 4_007_501_668.6;
+
+/// Stripes past this many are dropped
+pub const MAX_STRIPES: usize = 8;
+
+const FILL_NONE: u32 = 0;
+const FILL_SOLID: u32 = 1;
+const FILL_STRIPES: u32 = 2;
+
+/// Ground size of one pixel at the middle of `tile`
+pub fn centimeters_per_pixel(tile: &Tile) -> f64 {
+    // Web Mercator: y = 0 is the north edge
+    let center_y = (tile.y0 + tile.y1) / 2.0;
+    let latitude = (PI - 2.0 * PI * center_y).sinh().atan();
+
+    EARTH_CIRCUMFERENCE_CM * (tile.x1 - tile.x0) * latitude.cos() / TILE_TEXTURE_EXTENT.width as f64
+}
+
+/// `Style`, resolved for one tile; mirrors `StyleUniform` in composite.wgsl
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, IntoBytes, Immutable)]
+struct StyleUniform {
+    fill_color: [f32; 4],
+    border_color: [f32; 4],
+    stripe_colors: [[f32; 4]; MAX_STRIPES],
+    stripe_ends: [[f32; 4]; MAX_STRIPES / 4],
+    stripe_direction: [f32; 2],
+    stripe_phase: f32,
+    stripe_period: f32,
+    fill_kind: u32,
+    stripe_count: u32,
+    follow_field: u32,
+    field_angle: f32,
+    border_half_width: f32,
+    cm_per_pixel: f32,
+    _padding: [f32; 2],
+}
+
+fn rgba(color: &palette::Srgba<f32>) -> [f32; 4] {
+    [color.red, color.green, color.blue, color.alpha]
+}
+
+impl StyleUniform {
+    fn new(style: &Style, tile: &Tile) -> Self {
+        let cm_per_pixel = centimeters_per_pixel(tile);
+
+        let mut uniform = StyleUniform {
+            border_color: rgba(&style.border_color),
+            border_half_width: (style.border_width.to_pixels(cm_per_pixel) / 2.0) as f32,
+            cm_per_pixel: cm_per_pixel as f32,
+            ..Default::default()
+        };
+
+        match &style.fill {
+            None => uniform.fill_kind = FILL_NONE,
+            Some(Pattern::SolidColor(color)) => {
+                uniform.fill_kind = FILL_SOLID;
+                uniform.fill_color = rgba(color);
+            }
+            Some(Pattern::Stripes { stripes, rotation }) => {
+                if stripes.len() > MAX_STRIPES {
+                    tracing::warn!(
+                        stripes = stripes.len(),
+                        "dropping stripes past {MAX_STRIPES}"
+                    );
+                }
+
+                uniform.fill_kind = FILL_STRIPES;
+                let mut end = 0.0;
+                for (i, (width, color)) in stripes.iter().take(MAX_STRIPES).enumerate() {
+                    end += width.to_pixels(cm_per_pixel);
+                    uniform.stripe_colors[i] = rgba(color);
+                    uniform.stripe_ends[i / 4][i % 4] = end as f32;
+                    uniform.stripe_count += 1;
+                }
+                uniform.stripe_period = end as f32;
+
+                match rotation {
+                    Rotation::Fixed(angle) => {
+                        let direction = (*angle as f64).sin_cos();
+                        let direction = [direction.1, direction.0];
+
+                        // Phase of the tile's corner, in f64 so stripes line
+                        // up across tiles even deep into the zoom levels
+                        let world_px = TILE_TEXTURE_EXTENT.width as f64 / (tile.x1 - tile.x0);
+                        let origin = [tile.x0 * world_px, tile.y0 * world_px];
+                        let along = origin[0] * direction[0] + origin[1] * direction[1];
+
+                        uniform.stripe_direction = direction.map(|v| v as f32);
+                        if end > 0.0 {
+                            uniform.stripe_phase = along.rem_euclid(end) as f32;
+                        }
+                    }
+                    Rotation::FieldOffset(angle) => {
+                        uniform.follow_field = 1;
+                        uniform.field_angle = *angle;
+                    }
+                }
+            }
+        }
+
+        uniform
+    }
+}
+
+/// Turns distance tiles into coloured pixels
+///
+/// Each shape's distances are drawn into a scratch texture, then blended
+/// over the output in the shape's style with premultiplied alpha.
+pub(crate) struct Compositor {
+    pipeline: RenderPipeline,
+    bind_group_layout: BindGroupLayout,
+    scratch: TextureView,
+}
+
+impl Compositor {
+    pub(crate) fn new(device: &Device, vertex_shader: &ShaderModule) -> Self {
+        let fragment_shader = device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("composite"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("./composite.wgsl").into()),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("composite"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: false },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: Some("composite"),
+            immediate_size: 0,
+            bind_group_layouts: &[&bind_group_layout],
+        });
+
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: Some("composite"),
+            layout: Some(&pipeline_layout),
+            vertex: VertexState {
+                buffers: &[],
+                compilation_options: PipelineCompilationOptions::default(),
+                entry_point: Some("vtx_main"),
+                module: vertex_shader,
+            },
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: MultisampleState::default(),
+            fragment: Some(FragmentState {
+                module: &fragment_shader,
+                entry_point: Some("composite_main"),
+                compilation_options: PipelineCompilationOptions::default(),
+                targets: &[Some(ColorTargetState {
+                    format: TextureFormat::Rgba8Unorm,
+                    blend: Some(BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: ColorWrites::all(),
+                })],
+            }),
+            multiview_mask: None,
+            cache: None,
+        });
+
+        let scratch = device
+            .create_texture(&TextureDescriptor {
+                label: Some("composite scratch"),
+                size: TILE_TEXTURE_EXTENT,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: TextureFormat::Rgba8Unorm,
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&TextureViewDescriptor::default());
+
+        Self {
+            pipeline,
+            bind_group_layout,
+            scratch,
+        }
+    }
+
+    /// Where a shape's distances go before [`Compositor::blend`]
+    pub(crate) fn scratch(&self) -> &TextureView {
+        &self.scratch
+    }
+
+    /// Clear `target` to transparent
+    pub(crate) fn clear(&self, encoder: &mut CommandEncoder, target: &TextureView) {
+        encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[Some(RenderPassColorAttachment {
+                ops: Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+                view: target,
+                depth_slice: None,
+                resolve_target: None,
+            })],
+            label: Some("composite clear"),
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+            multiview_mask: None,
+        });
+    }
+
+    /// Blend the distances in the scratch texture over `target` in `style`
+    pub(crate) fn blend(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        target: &TextureView,
+        style: &Style,
+        tile: &Tile,
+    ) {
+        let uniform_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: Some("composite style"),
+            contents: StyleUniform::new(style, tile).as_bytes(),
+            usage: BufferUsages::UNIFORM,
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: Some("composite"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&self.scratch),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[Some(RenderPassColorAttachment {
+                ops: Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+                view: target,
+                depth_slice: None,
+                resolve_target: None,
+            })],
+            label: Some("composite"),
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+            multiview_mask: None,
+        });
+
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, Some(&bind_group), &[]);
+        pass.draw(0..4, 0..1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jet_lag_core::shape::types::Centimeters;
+    use palette::Srgba;
+
+    use super::*;
+    use crate::render::style::Size;
+
+    /// The tile at `zoom` containing Web Mercator `(x, y)`
+    fn tile_at(zoom: u8, x: f64, y: f64) -> Tile {
+        let mut tile = Tile::WORLD;
+        for _ in 0..zoom {
+            tile = tile
+                .children()
+                .into_iter()
+                .find(|child| (child.x0..child.x1).contains(&x) && (child.y0..child.y1).contains(&y))
+                .unwrap();
+        }
+        tile
+    }
+
+    /// Times Square
+    fn midtown(zoom: u8) -> Tile {
+        tile_at(zoom, 0.294_43, 0.375_87)
+    }
+
+    fn border_half_width(border: Size, tile: &Tile) -> f32 {
+        let style = Style::solid_color(Srgba::new(1.0, 0.0, 0.0, 1.0))
+            .with_border(border, Srgba::new(0.0, 0.0, 0.0, 1.0));
+        StyleUniform::new(&style, tile).border_half_width
+    }
+
+    #[test]
+    fn test_centimeters_per_pixel() {
+        // The equator, spread over one 256 px tile
+        let world = centimeters_per_pixel(&Tile::WORLD);
+        assert!((world - EARTH_CIRCUMFERENCE_CM / 256.0).abs() < 1.0);
+
+        // About 29 m a pixel over Manhattan at zoom 12
+        let midtown = centimeters_per_pixel(&midtown(12));
+        assert!((2_850.0..2_950.0).contains(&midtown), "{midtown}");
+    }
+
+    #[test]
+    fn test_world_space_border_scales_with_zoom() {
+        let border = || Size::WorldSpace(Centimeters(10_000));
+
+        let widths: Vec<f32> = [10, 12, 14]
+            .into_iter()
+            .map(|zoom| border_half_width(border(), &midtown(zoom)))
+            .collect();
+
+        // 100 m is ~3.5 px across at zoom 12, and four times that per two zooms
+        assert!((1.6..1.9).contains(&widths[1]), "{widths:?}");
+        for pair in widths.windows(2) {
+            assert!((pair[1] / pair[0] - 4.0).abs() < 0.01, "{widths:?}");
+        }
+    }
+
+    #[test]
+    fn test_screen_space_border_is_constant() {
+        for zoom in [0, 6, 12, 18] {
+            let width = border_half_width(Size::ScreenSpace { pixels: 4.0 }, &midtown(zoom));
+            assert_eq!(width, 2.0);
+        }
+    }
+
+    #[test]
+    fn test_maximum_border_switches_with_zoom() {
+        let border = || {
+            Size::Maximum(
+                Box::new(Size::WorldSpace(Centimeters(5_000))),
+                Box::new(Size::ScreenSpace { pixels: 4.0 }),
+            )
+        };
+
+        // Screen space wins zoomed out, world space zoomed in
+        assert_eq!(border_half_width(border(), &midtown(10)), 2.0);
+        let zoomed_in = border_half_width(border(), &midtown(14));
+        assert!((3.2..3.7).contains(&zoomed_in), "{zoomed_in}");
+    }
+
+    #[test]
+    fn test_world_space_stripes_scale_with_zoom() {
+        let style = |rotation| {
+            Style::striped(
+                rotation,
+                vec![
+                    (Size::WorldSpace(Centimeters(20_000)), Srgba::new(1.0, 0.0, 0.0, 1.0)),
+                    (Size::ScreenSpace { pixels: 6.0 }, Srgba::new(0.0, 0.0, 1.0, 1.0)),
+                ],
+            )
+        };
+
+        let at_12 = StyleUniform::new(&style(Rotation::Fixed(0.0)), &midtown(12));
+        let at_13 = StyleUniform::new(&style(Rotation::Fixed(0.0)), &midtown(13));
+
+        assert_eq!(at_12.fill_kind, FILL_STRIPES);
+        assert_eq!(at_12.stripe_count, 2);
+
+        // The world-space stripe doubles, the screen-space one doesn't
+        let world_12 = at_12.stripe_ends[0][0];
+        let world_13 = at_13.stripe_ends[0][0];
+        assert!((world_13 / world_12 - 2.0).abs() < 0.01);
+        assert!((at_12.stripe_ends[0][1] - world_12 - 6.0).abs() < 1e-4);
+        assert!((at_13.stripe_ends[0][1] - world_13 - 6.0).abs() < 1e-4);
+        assert_eq!(at_13.stripe_period, at_13.stripe_ends[0][1]);
+
+        let following = StyleUniform::new(&style(Rotation::FieldOffset(0.5)), &midtown(12));
+        assert_eq!(following.follow_field, 1);
+        assert_eq!(following.field_angle, 0.5);
+    }
+}
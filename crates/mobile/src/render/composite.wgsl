@@ -0,0 +1,98 @@
+const FILL_NONE: u32 = 0u;
+const FILL_SOLID: u32 = 1u;
+const FILL_STRIPES: u32 = 2u;
+
+const MAX_STRIPES: u32 = 8u;
+
+// Every length is in tile pixels
+struct StyleUniform {
+    fill_color: vec4f,
+    border_color: vec4f,
+    stripe_colors: array<vec4f, 8>,
+    // Where each stripe ends within a period, four to a vector
+    stripe_ends: array<vec4f, 2>,
+    stripe_direction: vec2f,
+    stripe_phase: f32,
+    stripe_period: f32,
+    fill_kind: u32,
+    stripe_count: u32,
+    follow_field: u32,
+    field_angle: f32,
+    border_half_width: f32,
+    cm_per_pixel: f32,
+    _padding: vec2f,
+}
+
+@group(0) @binding(0) var distance_tile: texture_2d<f32>;
+@group(0) @binding(1) var<uniform> style: StyleUniform;
+
+// Inverse of the packing in the shape shader's main
+fn decode_distance_cm(texel: vec4f) -> f32 {
+    let bytes = vec4u(round(texel * 255.0));
+    let bits = bytes.x | (bytes.y << 8u) | (bytes.z << 16u) | (bytes.w << 24u);
+    return f32(bitcast<i32>(bits));
+}
+
+fn premultiply(color: vec4f) -> vec4f {
+    return vec4f(color.rgb * color.a, color.a);
+}
+
+fn stripe_end(index: u32) -> f32 {
+    return style.stripe_ends[index / 4u][index % 4u];
+}
+
+fn stripe_coordinate(position: vec2f, distance: f32, gradient: vec2f) -> f32 {
+    if style.follow_field == 1u {
+        let length = max(length(gradient), 1e-6);
+        let tangent = vec2f(-gradient.y, gradient.x) / length;
+        return distance * cos(style.field_angle) + dot(position, tangent) * sin(style.field_angle);
+    }
+
+    return style.stripe_phase + dot(position, style.stripe_direction);
+}
+
+fn stripe_color(coordinate: f32) -> vec4f {
+    let period = style.stripe_period;
+    let offset = coordinate - floor(coordinate / period) * period;
+
+    let count = min(style.stripe_count, MAX_STRIPES);
+    var index = count - 1u;
+    for (var i = 0u; i < count; i++) {
+        if offset < stripe_end(i) {
+            index = i;
+            break;
+        }
+    }
+
+    // Blend into the next stripe over the last half pixel
+    let next = (index + 1u) % count;
+    let edge = clamp(0.5 - (stripe_end(index) - offset), 0.0, 1.0);
+    return mix(style.stripe_colors[index], style.stripe_colors[next], edge);
+}
+
+@fragment
+fn composite_main(@builtin(position) frag_coord: vec4f) -> @location(0) vec4f {
+    let texel = textureLoad(distance_tile, vec2i(frag_coord.xy), 0);
+
+    // Negative inside the shape
+    let distance = decode_distance_cm(texel) / style.cm_per_pixel;
+
+    // Derivatives need uniform control flow, so take them up front
+    let gradient = vec2f(dpdx(distance), dpdy(distance));
+
+    var fill = vec4f(0.0);
+    if style.fill_kind == FILL_SOLID {
+        fill = style.fill_color;
+    } else if style.fill_kind == FILL_STRIPES && style.stripe_count > 0u && style.stripe_period > 0.0 {
+        fill = stripe_color(stripe_coordinate(frag_coord.xy, distance, gradient));
+    }
+
+    let inside = clamp(0.5 - distance, 0.0, 1.0);
+    let fill_layer = premultiply(fill) * inside;
+
+    // The border straddles the outline
+    let on_border = clamp(style.border_half_width + 0.5 - abs(distance), 0.0, 1.0);
+    let border_layer = premultiply(style.border_color) * select(0.0, on_border, style.border_half_width > 0.0);
+
+    return border_layer + fill_layer * (1.0 - border_layer.a);
+}
@@ -0,0 +1,147 @@
+use wgpu::{
+    Adapter, Backends, Device, DeviceDescriptor, FeaturesWGPU, FeaturesWebGPU, Instance,
+    InstanceDescriptor, Limits, PowerPreference, Queue, RequestAdapterError, RequestAdapterOptions,
+    RequestDeviceError,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum RenderContextError {
+    #[error("no suitable adapter: {0}")]
+    Adapter(#[from] RequestAdapterError),
+    #[error("failed to obtain device: {0}")]
+    Device(#[from] RequestDeviceError),
+}
+
+/// Where a headless render thread gets its device from
+///
+/// The defaults pick a software adapter (lavapipe, or llvmpipe through GL)
+/// so tiles render the same on any CI machine.
+#[derive(Debug, Clone, Copy)]
+pub struct HeadlessOptions {
+    pub backends: Backends,
+    pub force_fallback_adapter: bool,
+}
+
+impl Default for HeadlessOptions {
+    fn default() -> Self {
+        Self {
+            backends: Backends::VULKAN | Backends::GL,
+            force_fallback_adapter: true,
+        }
+    }
+}
+
+impl HeadlessOptions {
+    /// Defaults, with the backends overridden by `WGPU_BACKEND` if set
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            backends: Backends::from_env().unwrap_or(default.backends),
+            ..default
+        }
+    }
+
+    pub fn with_backends(mut self, backends: Backends) -> Self {
+        self.backends = backends;
+        self
+    }
+
+    /// Allow a hardware adapter, for desktop tools with a real GPU
+    pub fn with_hardware_adapter(mut self) -> Self {
+        self.force_fallback_adapter = false;
+        self
+    }
+}
+
+/// The device a render thread draws with
+pub struct GpuContext {
+    pub instance: Instance,
+    pub adapter: Adapter,
+    pub device: Device,
+    pub queue: Queue,
+}
+
+impl GpuContext {
+    /// Vulkan, so tiles can be imported into `AHardwareBuffer`s
+    #[cfg(target_os = "android")]
+    pub async fn android() -> Result<Self, RenderContextError> {
+        let instance = Instance::new(&InstanceDescriptor {
+            backends: Backends::VULKAN,
+            ..Default::default()
+        });
+
+        let adapter = instance
+            .request_adapter(&RequestAdapterOptions {
+                ..Default::default()
+            })
+            .await?;
+
+        Self::with_adapter(instance, adapter, Limits::default()).await
+    }
+
+    /// Metal, so tiles can be drawn into `IOSurface`s
+    #[cfg(target_os = "ios")]
+    pub async fn ios() -> Result<Self, RenderContextError> {
+        let instance = Instance::new(&InstanceDescriptor {
+            backends: Backends::METAL,
+            ..Default::default()
+        });
+
+        let adapter = instance
+            .request_adapter(&RequestAdapterOptions {
+                ..Default::default()
+            })
+            .await?;
+
+        Self::with_adapter(instance, adapter, Limits::default()).await
+    }
+
+    /// An adapter with no surface, rendering into ordinary textures
+    pub async fn headless(options: HeadlessOptions) -> Result<Self, RenderContextError> {
+        let instance = Instance::new(&InstanceDescriptor {
+            backends: options.backends,
+            ..Default::default()
+        });
+
+        let adapter = instance
+            .request_adapter(&RequestAdapterOptions {
+                power_preference: PowerPreference::LowPower,
+                force_fallback_adapter: options.force_fallback_adapter,
+                compatible_surface: None,
+            })
+            .await?;
+
+        let info = adapter.get_info();
+        tracing::debug!(adapter = %info.name, backend = ?info.backend, "headless adapter");
+
+        // Software and GL adapters don't reach the WebGPU defaults
+        Self::with_adapter(instance, adapter, Limits::downlevel_defaults()).await
+    }
+
+    async fn with_adapter(
+        instance: Instance,
+        adapter: Adapter,
+        base_limits: Limits,
+    ) -> Result<Self, RenderContextError> {
+        let (device, queue) = adapter
+            .request_device(&DeviceDescriptor {
+                required_features: wgpu::Features {
+                    features_wgpu: FeaturesWGPU::empty(),
+                    features_webgpu: FeaturesWebGPU::empty(),
+                },
+                required_limits: Limits {
+                    max_storage_buffers_per_shader_stage: 4,
+                    ..base_limits
+                },
+                ..Default::default()
+            })
+            .await?;
+
+        Ok(Self {
+            instance,
+            adapter,
+            device,
+            queue,
+        })
+    }
+}
@@ -0,0 +1,120 @@
+use core::future::Future;
+
+use tokio::sync::oneshot;
+use wgpu::{
+    Buffer, BufferDescriptor, BufferUsages, COPY_BYTES_PER_ROW_ALIGNMENT, CommandEncoder, MapMode,
+    TexelCopyBufferInfo, TexelCopyBufferLayout, Texture, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureUsages,
+};
+
+use crate::render::{
+    surface::{RequestTile, TileSurface, TileSurfaceError},
+    thread::{RenderThread, TILE_TEXTURE_EXTENT, TILE_TEXTURE_SIZE},
+};
+
+const BYTES_PER_PIXEL: u32 = 4;
+
+/// A rendered tile, read back from the GPU
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TileImage {
+    pub width: u32,
+    pub height: u32,
+
+    /// RGBA8 pixels, row by row with no padding
+    pub pixels: Vec<u8>,
+}
+
+/// Render a tile into an ordinary texture and read it back
+///
+/// Works with any backend, so tests and desktop tools go through the same
+/// path as the map.
+pub type RenderTileToBytes = RequestTile<ReadbackTile>;
+
+/// A texture copied into a mappable buffer once drawn
+pub struct ReadbackTile {
+    buffer: Buffer,
+}
+
+impl ReadbackTile {
+    /// Buffer rows have to be aligned for the copy
+    fn padded_row_bytes() -> u32 {
+        (TILE_TEXTURE_SIZE * BYTES_PER_PIXEL).next_multiple_of(COPY_BYTES_PER_ROW_ALIGNMENT)
+    }
+}
+
+impl TileSurface for ReadbackTile {
+    type Output = TileImage;
+
+    fn allocate(thread: &RenderThread) -> Result<(Self, Texture), TileSurfaceError> {
+        let texture = thread.device.create_texture(&TextureDescriptor {
+            label: Some("headless tile"),
+            size: TILE_TEXTURE_EXTENT,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rgba8Unorm,
+            usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        let buffer = thread.device.create_buffer(&BufferDescriptor {
+            label: Some("headless tile readback"),
+            size: (Self::padded_row_bytes() * TILE_TEXTURE_SIZE) as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Ok((Self { buffer }, texture))
+    }
+
+    fn after_draw(
+        &mut self,
+        _thread: &RenderThread,
+        texture: &Texture,
+        encoder: &mut CommandEncoder,
+    ) {
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            TexelCopyBufferInfo {
+                buffer: &self.buffer,
+                layout: TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(Self::padded_row_bytes()),
+                    rows_per_image: None,
+                },
+            },
+            TILE_TEXTURE_EXTENT,
+        );
+    }
+
+    fn finish(self) -> impl Future<Output = Result<TileImage, TileSurfaceError>> + 'static {
+        // The actor's poll interval drives the mapping to completion
+        let (sender, receiver) = oneshot::channel();
+        self.buffer
+            .slice(..)
+            .map_async(MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+
+        async move {
+            receiver.await.map_err(|_| TileSurfaceError::Cancelled)??;
+
+            let row_bytes = (TILE_TEXTURE_SIZE * BYTES_PER_PIXEL) as usize;
+            let pixels = self
+                .buffer
+                .slice(..)
+                .get_mapped_range()
+                .chunks_exact(Self::padded_row_bytes() as usize)
+                .flat_map(|row| &row[..row_bytes])
+                .copied()
+                .collect();
+            self.buffer.unmap();
+
+            Ok(TileImage {
+                width: TILE_TEXTURE_SIZE,
+                height: TILE_TEXTURE_SIZE,
+                pixels,
+            })
+        }
+    }
+}
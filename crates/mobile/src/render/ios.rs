@@ -0,0 +1,174 @@
+use core::{ffi::c_void, future::Future};
+
+use core_foundation::{
+    base::{CFRelease, CFRetain, TCFType},
+    dictionary::{CFDictionary, CFDictionaryRef},
+    number::CFNumber,
+    string::{CFString, CFStringRef},
+};
+use foreign_types::ForeignType;
+use metal::{MTLPixelFormat, MTLStorageMode, MTLTextureType, MTLTextureUsage};
+use objc::{msg_send, sel, sel_impl};
+use wgpu::{Texture, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages};
+
+use crate::render::{
+    surface::{TileSurface, TileSurfaceError},
+    thread::{RenderThread, TILE_TEXTURE_EXTENT, TILE_TEXTURE_SIZE},
+};
+
+type IOSurfaceRef = *mut c_void;
+
+#[link(name = "IOSurface", kind = "framework")]
+unsafe extern "C" {
+    fn IOSurfaceCreate(properties: CFDictionaryRef) -> IOSurfaceRef;
+
+    static kIOSurfaceWidth: CFStringRef;
+    static kIOSurfaceHeight: CFStringRef;
+    static kIOSurfaceBytesPerElement: CFStringRef;
+    static kIOSurfacePixelFormat: CFStringRef;
+}
+
+/// `kCVPixelFormatType_32RGBA`, matching `Rgba8Unorm`
+const PIXEL_FORMAT_RGBA: i32 = i32::from_be_bytes(*b"RGBA");
+
+const BYTES_PER_PIXEL: i32 = 4;
+
+/// A retained `IOSurface`, released on drop
+///
+/// MapLibre's Metal device wraps it with
+/// `newTextureWithDescriptor:iosurface:plane:`, the same way GL imports an
+/// `AHardwareBuffer` on Android.
+pub struct SharedIoSurface(IOSurfaceRef);
+
+// IOSurfaces are reference counted and safe to pass between threads
+unsafe impl Send for SharedIoSurface {}
+
+impl SharedIoSurface {
+    pub fn as_ptr(&self) -> IOSurfaceRef {
+        self.0
+    }
+
+    /// Hand a +1 reference to the host, which must `CFRelease` it
+    pub fn into_raw(self) -> IOSurfaceRef {
+        let surface = self.0;
+        core::mem::forget(self);
+        surface
+    }
+}
+
+impl Clone for SharedIoSurface {
+    fn clone(&self) -> Self {
+        unsafe { CFRetain(self.0) };
+        Self(self.0)
+    }
+}
+
+impl Drop for SharedIoSurface {
+    fn drop(&mut self) {
+        unsafe { CFRelease(self.0) };
+    }
+}
+
+/// A tile drawn straight into an `IOSurface` through Metal
+pub struct IoSurfaceTile {
+    surface: SharedIoSurface,
+}
+
+impl IoSurfaceTile {
+    fn create_surface() -> Result<SharedIoSurface, TileSurfaceError> {
+        let size = CFNumber::from(TILE_TEXTURE_SIZE as i32);
+        let properties = unsafe {
+            CFDictionary::from_CFType_pairs(&[
+                (CFString::wrap_under_get_rule(kIOSurfaceWidth), size.clone()),
+                (CFString::wrap_under_get_rule(kIOSurfaceHeight), size),
+                (
+                    CFString::wrap_under_get_rule(kIOSurfaceBytesPerElement),
+                    CFNumber::from(BYTES_PER_PIXEL),
+                ),
+                (
+                    CFString::wrap_under_get_rule(kIOSurfacePixelFormat),
+                    CFNumber::from(PIXEL_FORMAT_RGBA),
+                ),
+            ])
+        };
+
+        let surface = unsafe { IOSurfaceCreate(properties.as_concrete_TypeRef()) };
+        if surface.is_null() {
+            return Err(TileSurfaceError::Allocate("IOSurfaceCreate failed".into()));
+        }
+
+        Ok(SharedIoSurface(surface))
+    }
+}
+
+impl TileSurface for IoSurfaceTile {
+    type Output = SharedIoSurface;
+
+    fn allocate(thread: &RenderThread) -> Result<(Self, Texture), TileSurfaceError> {
+        let surface = Self::create_surface()?;
+
+        let descriptor = metal::TextureDescriptor::new();
+        descriptor.set_texture_type(MTLTextureType::D2);
+        descriptor.set_pixel_format(MTLPixelFormat::RGBA8Unorm);
+        descriptor.set_width(TILE_TEXTURE_SIZE as u64);
+        descriptor.set_height(TILE_TEXTURE_SIZE as u64);
+        descriptor.set_storage_mode(MTLStorageMode::Shared);
+        descriptor.set_usage(MTLTextureUsage::RenderTarget | MTLTextureUsage::ShaderRead);
+
+        let texture = unsafe {
+            let hal_device = thread
+                .device
+                .as_hal::<wgpu_hal::api::Metal>()
+                .ok_or_else(|| TileSurfaceError::Allocate("not a Metal device".into()))?;
+
+            let device: &metal::DeviceRef = hal_device.raw_device();
+            let descriptor: &metal::TextureDescriptorRef = &descriptor;
+            let raw: *mut metal::MTLTexture = msg_send![
+                device,
+                newTextureWithDescriptor: descriptor
+                iosurface: surface.as_ptr()
+                plane: 0usize
+            ];
+            if raw.is_null() {
+                return Err(TileSurfaceError::Allocate(
+                    "failed to wrap the IOSurface in a texture".into(),
+                ));
+            }
+
+            let hal_texture = wgpu_hal::metal::Device::texture_from_raw(
+                metal::Texture::from_ptr(raw),
+                TextureFormat::Rgba8Unorm,
+                MTLTextureType::D2,
+                1,
+                1,
+                wgpu_hal::CopyExtent {
+                    width: TILE_TEXTURE_SIZE,
+                    height: TILE_TEXTURE_SIZE,
+                    depth: 1,
+                },
+            );
+
+            thread
+                .device
+                .create_texture_from_hal::<wgpu_hal::api::Metal>(
+                    hal_texture,
+                    &TextureDescriptor {
+                        label: Some("iosurface tile"),
+                        size: TILE_TEXTURE_EXTENT,
+                        mip_level_count: 1,
+                        sample_count: 1,
+                        dimension: TextureDimension::D2,
+                        format: TextureFormat::Rgba8Unorm,
+                        usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                        view_formats: &[],
+                    },
+                )
+        };
+
+        Ok((Self { surface }, texture))
+    }
+
+    fn finish(self) -> impl Future<Output = Result<SharedIoSurface, TileSurfaceError>> + 'static {
+        async move { Ok(self.surface) }
+    }
+}
@@ -1,35 +1,51 @@
+use std::sync::Arc;
+
 use actix::Addr;
-use geo::Point;
-use jet_lag_core::shape::{
-    Shape,
-    compiler::{Register, SdfCompiler},
-};
+use jet_lag_core::shape::Shape;
 
 use crate::render::{
+    cache::ShapeRevisions,
     style::Style,
-    thread::{RenderThread, StartShapeCompilation, start_render_thread},
+    thread::{
+        RemoveShape, RenderThread, ReplaceShape, ShapeId, StartShapeCompilation, UpdateStyle,
+        start_render_thread,
+    },
 };
 
+#[cfg(target_os = "android")]
+pub mod android;
+pub mod cache;
+pub mod composite;
+pub mod context;
+pub mod headless;
+#[cfg(target_os = "ios")]
+pub mod ios;
 pub mod style;
+pub mod surface;
 pub mod thread;
 
+/// A shape drawn by the render thread
+///
+/// Every change bumps the shape's revision, so tile caches drop what they
+/// rendered for the old version.
 pub struct RenderHandle {
-    id: u64,
+    id: ShapeId,
     style: Style,
+    render_thread: Addr<RenderThread>,
+    revisions: Arc<ShapeRevisions>,
 }
 
 pub struct RenderSession {
     pub render_thread: Addr<RenderThread>,
-    
-
+    revisions: Arc<ShapeRevisions>,
 }
 
 impl RenderSession {
     pub fn new() -> Self {
         let render_thread = start_render_thread();
-        let compiler = SdfCompiler::new();
         Self {
             render_thread,
+            revisions: Arc::default(),
         }
     }
 
@@ -39,22 +55,69 @@ impl RenderSession {
     //     // register
     // }
 
+    /// Live shapes and their revisions
+    pub fn revisions(&self) -> Arc<ShapeRevisions> {
+        self.revisions.clone()
+    }
+
+    /// Restyle a shape added elsewhere, e.g. by the host when creating a
+    /// layer for it
+    pub fn set_style(&self, id: ShapeId, style: Style) {
+        self.render_thread.do_send(UpdateStyle { id, style });
+        self.revisions.bump(id);
+    }
+
     pub async fn append_shape(&mut self, shape: Box<dyn Shape>, style: Style) -> RenderHandle {
-        let id = self.render_thread.send(StartShapeCompilation { shape }).await.expect("render thread shut down unexpectedly");
+        let id = self
+            .render_thread
+            .send(StartShapeCompilation {
+                shape,
+                style: style.clone(),
+            })
+            .await
+            .expect("render thread shut down unexpectedly");
+
+        self.revisions.insert(id);
 
         RenderHandle {
             id,
             style,
+            render_thread: self.render_thread.clone(),
+            revisions: self.revisions.clone(),
         }
     }
 }
 
 impl RenderHandle {
+    pub fn id(&self) -> ShapeId {
+        self.id
+    }
+
+    pub fn style(&self) -> &Style {
+        &self.style
+    }
+
+    // The render thread handles messages in order, so tiles requested after
+    // the revision bump are drawn with the change applied
+
     pub fn update_style(&mut self, style: Style) {
-        todo!()
+        self.render_thread.do_send(UpdateStyle {
+            id: self.id,
+            style: style.clone(),
+        });
+        self.revisions.bump(self.id);
+        self.style = style;
+    }
+
+    /// Swap in a new shape, e.g. when an answer is corrected
+    pub fn replace(&mut self, shape: Box<dyn Shape>) {
+        self.render_thread
+            .do_send(ReplaceShape { id: self.id, shape });
+        self.revisions.bump(self.id);
     }
 
     pub fn remove(self) {
-        todo!()
+        self.render_thread.do_send(RemoveShape { id: self.id });
+        self.revisions.remove(self.id);
     }
 }
@@ -1,28 +1,50 @@
+use std::str::FromStr;
+
 use jet_lag_core::shape::types::Centimeters;
 
+#[derive(Clone)]
 pub enum Size {
     WorldSpace(Centimeters),
     ScreenSpace { pixels: f32 },
     Maximum(Box<Size>, Box<Size>),
 }
 
+impl Size {
+    /// Length in tile pixels, at a scale of `cm_per_pixel`
+    pub fn to_pixels(&self, cm_per_pixel: f64) -> f64 {
+        match self {
+            Size::WorldSpace(centimeters) => centimeters.0 as f64 / cm_per_pixel,
+            Size::ScreenSpace { pixels } => *pixels as f64,
+            Size::Maximum(a, b) => a.to_pixels(cm_per_pixel).max(b.to_pixels(cm_per_pixel)),
+        }
+    }
+}
+
+/// Direction of stripes, in radians
+#[derive(Clone)]
 pub enum Rotation {
+    /// Clockwise from east
     Fixed(f32),
+    /// Relative to the shape's outline: `0.0` draws bands parallel to it
     FieldOffset(f32),
 }
 
+#[derive(Clone)]
 pub enum Pattern {
     SolidColor(palette::Srgba<f32>),
+    /// Repeating bands, each as wide as its size
     Stripes {
         stripes: Vec<(Size, palette::Srgba<f32>)>,
         rotation: Rotation,
     },
 }
 
+#[derive(Clone)]
 pub struct Style {
-    border_color: palette::Srgba<f32>,
-    border_width: Size,
-    fill: Option<Pattern>,
+    pub(crate) border_color: palette::Srgba<f32>,
+    /// Centred on the outline
+    pub(crate) border_width: Size,
+    pub(crate) fill: Option<Pattern>,
 }
 
 impl Style {
@@ -56,3 +78,79 @@ impl Style {
         self
     }
 }
+
+#[derive(Debug)]
+pub struct ParseStyleError;
+
+/// A style as a layer parameter: a fill colour, or `none`, optionally
+/// followed by a border width in screen pixels and its colour, e.g.
+/// `#ff000040 2px #ff0000`. Colours are `#rrggbb` or `#rrggbbaa`.
+impl FromStr for Style {
+    type Err = ParseStyleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let fill = match parts.next().ok_or(ParseStyleError)? {
+            "none" => None,
+            color => Some(Pattern::SolidColor(parse_color(color)?)),
+        };
+        let style = Self {
+            fill,
+            ..Self::transparent()
+        };
+
+        match (parts.next(), parts.next(), parts.next()) {
+            (None, _, _) => Ok(style),
+            (Some(width), Some(color), None) => {
+                let pixels = width
+                    .strip_suffix("px")
+                    .and_then(|pixels| pixels.parse().ok())
+                    .ok_or(ParseStyleError)?;
+                Ok(style.with_border(Size::ScreenSpace { pixels }, parse_color(color)?))
+            }
+            _ => Err(ParseStyleError),
+        }
+    }
+}
+
+fn parse_color(color: &str) -> Result<palette::Srgba<f32>, ParseStyleError> {
+    let hex = color
+        .strip_prefix('#')
+        .filter(|hex| matches!(hex.len(), 6 | 8) && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+        .ok_or(ParseStyleError)?;
+    let value = u32::from_str_radix(hex, 16).map_err(|_| ParseStyleError)?;
+    let [r, g, b, a] = if hex.len() == 6 {
+        (value << 8 | 0xff).to_be_bytes()
+    } else {
+        value.to_be_bytes()
+    };
+
+    Ok(palette::Srgba::new(r, g, b, a).into_format())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_style() {
+        let style: Style = "#ff000080".parse().unwrap();
+        let Some(Pattern::SolidColor(fill)) = style.fill else {
+            panic!("expected a solid fill");
+        };
+        assert_eq!(fill, palette::Srgba::new(255u8, 0, 0, 128).into_format());
+        assert_eq!(style.border_color.alpha, 0.0);
+
+        let style: Style = "none 2.5px #0039a6".parse().unwrap();
+        assert!(style.fill.is_none());
+        assert!(matches!(style.border_width, Size::ScreenSpace { pixels } if pixels == 2.5));
+        assert_eq!(
+            style.border_color,
+            palette::Srgba::new(0x00u8, 0x39, 0xa6, 0xff).into_format()
+        );
+
+        for invalid in ["", "red", "#ff00", "#gg0000", "#ff0000 2px", "#ff0000 2 #000000"] {
+            assert!(invalid.parse::<Style>().is_err(), "{invalid:?}");
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use core::{future::Future, marker::PhantomData};
+
+use actix::{Handler, Message, ResponseFuture};
+use jet_lag_core::map::tile::Tile;
+use tokio::sync::oneshot;
+use wgpu::{BufferAsyncError, CommandEncoder, Texture, TextureViewDescriptor};
+
+use crate::render::thread::{RenderThread, ShapeId, TileOutput};
+
+#[derive(Debug, thiserror::Error)]
+pub enum TileSurfaceError {
+    #[error("failed to allocate tile memory: {0}")]
+    Allocate(String),
+    #[error("failed to map the readback buffer: {0}")]
+    Map(#[from] BufferAsyncError),
+    #[error("render thread stopped before the tile was finished")]
+    Cancelled,
+}
+
+/// Memory a tile is drawn into, then handed to whoever asked for it
+///
+/// Each platform shares tiles with the map its own way: `AHardwareBuffer`s
+/// on Android, `IOSurface`s on iOS. Reading tiles back to the CPU is one
+/// more surface, so the headless backend runs the same request path.
+pub trait TileSurface: Sized + 'static {
+    /// What the requester gets back
+    type Output: Send + 'static;
+
+    /// Allocate one tile, wrapped as a texture the render thread can draw to
+    fn allocate(thread: &RenderThread) -> Result<(Self, Texture), TileSurfaceError>;
+
+    /// Record anything that has to follow the draw, e.g. a copy out of the
+    /// texture
+    fn after_draw(
+        &mut self,
+        _thread: &RenderThread,
+        _texture: &Texture,
+        _encoder: &mut CommandEncoder,
+    ) {
+    }
+
+    /// Hand the tile over once the GPU has finished drawing it
+    fn finish(self) -> impl Future<Output = Result<Self::Output, TileSurfaceError>> + 'static;
+}
+
+/// Where this platform's map wants its tiles
+#[cfg(target_os = "android")]
+pub type PlatformSurface = crate::render::android::HardwareBufferTile;
+#[cfg(target_os = "ios")]
+pub type PlatformSurface = crate::render::ios::IoSurfaceTile;
+#[cfg(not(any(target_os = "android", target_os = "ios")))]
+pub type PlatformSurface = crate::render::headless::ReadbackTile;
+
+/// Draw a tile into a fresh `S`
+pub struct RequestTile<S = PlatformSurface> {
+    pub tile: Tile,
+
+    /// The one shape to draw, or every shape in order if `None`
+    pub shape: Option<ShapeId>,
+
+    pub output: TileOutput,
+
+    surface: PhantomData<fn() -> S>,
+}
+
+impl<S> RequestTile<S> {
+    pub fn new(tile: Tile, shape: Option<ShapeId>, output: TileOutput) -> Self {
+        Self {
+            tile,
+            shape,
+            output,
+            surface: PhantomData,
+        }
+    }
+}
+
+impl<S: TileSurface> Message for RequestTile<S> {
+    type Result = Result<S::Output, TileSurfaceError>;
+}
+
+impl<S: TileSurface> Handler<RequestTile<S>> for RenderThread {
+    type Result = ResponseFuture<Result<S::Output, TileSurfaceError>>;
+
+    fn handle(&mut self, msg: RequestTile<S>, _ctx: &mut Self::Context) -> Self::Result {
+        let (mut surface, texture) = match S::allocate(self) {
+            Ok(allocated) => allocated,
+            Err(error) => return Box::pin(async move { Err(error) }),
+        };
+
+        let view = texture.create_view(&TextureViewDescriptor::default());
+        let mut encoder = self.encode_tile(&view, &msg.tile, msg.shape, msg.output);
+        surface.after_draw(self, &texture, &mut encoder);
+
+        // The actor's poll interval drives the callback
+        let (sender, receiver) = oneshot::channel();
+        encoder.on_submitted_work_done(move || {
+            let _ = sender.send(());
+        });
+        self.queue.submit([encoder.finish()]);
+
+        Box::pin(async move {
+            receiver.await.map_err(|_| TileSurfaceError::Cancelled)?;
+            drop(texture);
+
+            surface.finish().await
+        })
+    }
+}
@@ -1,27 +1,12 @@
 use core::{
-    iter::{self, Iterator},
+    fmt, iter,
     mem::size_of,
-    num::NonZero,
-    option::Option::None,
-};
-use std::{
-    collections::BTreeMap,
-    default::Default,
-    future::pending,
-    pin::Pin,
-    sync::{
-        Arc,
-        atomic::{AtomicBool, Ordering},
-    },
-    task::Poll,
-    time::Duration,
+    num::{NonZero, ParseIntError},
+    str::FromStr,
 };
+use std::{collections::HashSet, default::Default, future::pending, time::Duration};
 
-use actix::{
-    Actor, ActorFuture, Addr, AsyncContext, Context, Handler, Message, WrapFuture,
-    fut::LocalBoxActorFuture,
-};
-use ash::vk::{self, ExternalMemoryHandleTypeFlags, StructureType};
+use actix::{Actor, Addr, AsyncContext, Context, Handler, Message, MessageResponse};
 use jet_lag_core::{
     map::tile::Tile,
     shape::{
@@ -30,44 +15,93 @@ use jet_lag_core::{
             CompiledShape,
             shader::{TileBounds, cache::ShaderCache},
         },
-        instruction::SdfInstruction,
     },
 };
-use ndk::{
-    hardware_buffer::{HardwareBuffer, HardwareBufferDesc, HardwareBufferRef, HardwareBufferUsage},
-    hardware_buffer_format::HardwareBufferFormat,
-};
-use replace_with::replace_with_or_abort;
 use tokio::sync::oneshot;
-use tracing::debug;
 use wgpu::{
-    Backends, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
+    BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
     BufferBinding, BufferUsages, ColorTargetState, ColorWrites, CommandEncoder,
-    CommandEncoderDescriptor, Extent3d, FragmentState, Instance, LoadOpDontCare, MultisampleState,
-    Operations, PipelineCompilationOptions, PipelineLayout, PipelineLayoutDescriptor, PollType,
-    PrimitiveState, PrimitiveTopology, RenderPassColorAttachment, RenderPassDescriptor,
-    RenderPipeline, RenderPipelineDescriptor, ShaderModule, ShaderModuleDescriptor, ShaderStages,
-    Texture, TextureDescriptor, TextureFormat, TextureUsages, TextureUses, TextureViewDescriptor,
-    VertexAttribute, VertexBufferLayout, VertexState, VertexStepMode, naga,
+    CommandEncoderDescriptor, Extent3d, FragmentState, MultisampleState, Operations,
+    PipelineCompilationOptions, PipelineLayoutDescriptor, PollType, PrimitiveState,
+    PrimitiveTopology, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline,
+    RenderPipelineDescriptor, ShaderModule, ShaderModuleDescriptor, ShaderStages, TextureFormat,
+    TextureView, VertexState,
     util::{BufferInitDescriptor, DeviceExt},
-    wgt::ExternalTextureDescriptor,
 };
-use wgpu_hal::{Attachment, AttachmentOps, ColorAttachment, MemoryFlags, vulkan::TextureMemory};
 use zerocopy::IntoBytes;
 
-pub fn start_render_thread() -> Addr<RenderThread> {
-    let (sender, receiver) = oneshot::channel();
+use crate::render::{
+    composite::Compositor,
+    context::{GpuContext, HeadlessOptions, RenderContextError},
+    style::Style,
+};
+
+/// Width and height of a rendered tile, in pixels
+pub const TILE_TEXTURE_SIZE: u32 = 256;
+
+/// What a rendered tile holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileOutput {
+    /// Each pixel's signed distance to the shape in centimetres (negative
+    /// inside), as the bytes of a little-endian `i32`; with several shapes
+    /// the last one wins
+    Distance,
+    /// Shapes drawn in their styles as premultiplied RGBA, later shapes over
+    /// earlier ones
+    Styled,
+}
 
+pub(crate) const TILE_TEXTURE_EXTENT: Extent3d = Extent3d {
+    width: TILE_TEXTURE_SIZE,
+    height: TILE_TEXTURE_SIZE,
+    depth_or_array_layers: 1,
+};
+
+/// Start the render thread for this platform
+///
+/// Android renders through Vulkan into `AHardwareBuffer`s and iOS through
+/// Metal into `IOSurface`s; everywhere else falls back to the headless
+/// backend.
+pub fn start_render_thread() -> Addr<RenderThread> {
     #[cfg(target_os = "android")]
-    let context = { None };
+    let create_context = GpuContext::android;
     #[cfg(target_os = "ios")]
-    let context = None;
+    let create_context = GpuContext::ios;
+    #[cfg(not(any(target_os = "android", target_os = "ios")))]
+    let create_context = || GpuContext::headless(HeadlessOptions::from_env());
+
+    spawn_render_thread(create_context).expect("failed to create render context")
+}
+
+/// Start a render thread that draws into ordinary textures, for tests and
+/// desktop tools; read tiles back with [`RenderTileToBytes`]
+///
+/// [`RenderTileToBytes`]: crate::render::headless::RenderTileToBytes
+pub fn start_headless_render_thread(
+    options: HeadlessOptions,
+) -> Result<Addr<RenderThread>, RenderContextError> {
+    spawn_render_thread(move || GpuContext::headless(options))
+}
+
+fn spawn_render_thread<F, Fut>(create_context: F) -> Result<Addr<RenderThread>, RenderContextError>
+where
+    F: FnOnce() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<GpuContext, RenderContextError>>,
+{
+    let (sender, receiver) = oneshot::channel();
 
     std::thread::spawn(move || {
         actix::run(async move {
-            let thread = RenderThread::new(context).await;
+            let thread = match create_context().await {
+                Ok(context) => RenderThread::new(context),
+                Err(error) => {
+                    let _ = sender.send(Err(error));
+                    return;
+                }
+            };
+
             sender
-                .send(thread.start())
+                .send(Ok(thread.start()))
                 .expect("failed to send addr back");
             pending().await
         })
@@ -78,127 +112,197 @@ pub fn start_render_thread() -> Addr<RenderThread> {
         .expect("failed to receive address from thread")
 }
 
-#[cfg(target_os = "android")]
-unsafe fn find_memory_type_index(
-    memory_requirements: &vk::MemoryRequirements,
-    memory_properties: &vk::PhysicalDeviceMemoryProperties,
-    required_properties: vk::MemoryPropertyFlags,
-) -> Option<u32> {
-    for i in 0..memory_properties.memory_type_count {
-        let memory_type = &memory_properties.memory_types[i as usize];
-
-        // Check if this memory type is allowed by the memory requirements
-        let type_supported = (memory_requirements.memory_type_bits & (1 << i)) != 0;
-
-        // Check if this memory type has the required properties
-        let properties_match = memory_type.property_flags.contains(required_properties);
-
-        if type_supported && properties_match {
-            return Some(i);
-        }
-    }
-    None
-}
-
 pub struct RenderThread {
-    instance: wgpu::Instance,
-    adapter: wgpu::Adapter,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
+    // The device keeps its instance alive; Android also needs the adapter to
+    // import hardware buffers
+    #[cfg(target_os = "android")]
+    pub(crate) adapter: wgpu::Adapter,
+    pub(crate) device: wgpu::Device,
+    pub(crate) queue: wgpu::Queue,
     vertex_shader: ShaderModule,
 
+    compositor: Compositor,
+
     shader_cache: ShaderCache,
     shapes: Vec<ShapeObj>,
 }
 
 impl RenderThread {
-    async fn new(context: Option<usize>) -> Self {
-        #[cfg(target_os = "android")]
-        let (instance, (device, queue), adapter) = {
-            use wgpu::{
-                DeviceDescriptor, FeaturesWGPU, FeaturesWebGPU, Limits, RequestAdapterOptions,
-            };
-
-            let instance = Instance::new(&wgpu::InstanceDescriptor {
-                backends: Backends::VULKAN,
-                ..Default::default()
-            });
-
-            let adapter = instance
-                .request_adapter(&RequestAdapterOptions {
-                    ..Default::default()
-                })
-                .await
-                .unwrap();
-
-            (
-                instance,
-                adapter
-                    .request_device(&DeviceDescriptor {
-                        required_features: wgpu::Features {
-                            features_wgpu: FeaturesWGPU::empty(),
-                            features_webgpu: FeaturesWebGPU::empty(),
-                        },
-                        required_limits: Limits {
-                            max_storage_buffers_per_shader_stage: 4,
-                            ..Default::default()
-                        },
-                        ..Default::default()
-                    })
-                    .await
-                    .expect("failed to obtain device"),
-                adapter,
-            )
-        };
+    fn new(context: GpuContext) -> Self {
+        let GpuContext {
+            #[cfg(target_os = "android")]
+            adapter,
+            device,
+            queue,
+            ..
+        } = context;
 
         let vertex_shader = device.create_shader_module(ShaderModuleDescriptor {
             label: None,
             source: wgpu::ShaderSource::Wgsl(include_str!("./vertex.wgsl").into()),
         });
 
+        let compositor = Compositor::new(&device, &vertex_shader);
+
         Self {
-            instance,
+            #[cfg(target_os = "android")]
             adapter,
             device,
             queue,
             vertex_shader,
+            compositor,
 
             shader_cache: ShaderCache::new(),
             shapes: Vec::new(),
         }
     }
-}
 
-impl Actor for RenderThread {
-    type Context = Context<Self>;
+    /// Record the shapes drawn over `tile` into `view`: just `only`, or
+    /// every shape in the order they were added if `None`
+    pub(crate) fn encode_tile(
+        &self,
+        view: &TextureView,
+        tile: &Tile,
+        only: Option<ShapeId>,
+        output: TileOutput,
+    ) -> CommandEncoder {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor::default());
 
-    fn started(&mut self, ctx: &mut Self::Context) {
-        ctx.run_interval(Duration::from_millis(15), |actor, _| {
-            let _ = actor.device.poll(PollType::Poll);
-        });
-    }
-}
+        let shapes = self
+            .shapes
+            .iter()
+            .filter(|shape| only.is_none_or(|id| shape.id == id));
+
+        match output {
+            TileOutput::Distance => {
+                let mut clear = true;
+                for shape in shapes {
+                    self.encode_distance(&mut encoder, view, tile, shape, clear);
+                    clear = false;
+                }
 
-struct ShapeObj {
-    compiled_shape: CompiledShape,
-    bind_group_layout: BindGroupLayout,
-    no_ellipsoid_low_prec: RenderPipeline,
-    // no_ellipsoid_high_prec: RenderPipeline,
-    // use_ellipsoid_low_prec: RenderPipeline,
-    // use_ellipsoid_high_prec: RenderPipeline,
-}
+                if clear {
+                    // Nothing to draw, but the tile still shouldn't be garbage
+                    encoder.begin_render_pass(&RenderPassDescriptor {
+                        color_attachments: &[Some(RenderPassColorAttachment {
+                            ops: Operations {
+                                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                                store: wgpu::StoreOp::Store,
+                            },
+                            view,
+                            depth_slice: None,
+                            resolve_target: None,
+                        })],
+                        label: None,
+                        depth_stencil_attachment: None,
+                        timestamp_writes: None,
+                        occlusion_query_set: None,
+                        multiview_mask: None,
+                    });
+                }
+            }
+            TileOutput::Styled => {
+                self.compositor.clear(&mut encoder, view);
+
+                for shape in shapes {
+                    let scratch = self.compositor.scratch();
+                    self.encode_distance(&mut encoder, scratch, tile, shape, true);
+                    self.compositor
+                        .blend(&self.device, &mut encoder, view, &shape.style, tile);
+                }
+            }
+        }
 
-#[derive(Message)]
-#[rtype(result = "u64")]
-pub struct StartShapeCompilation {
-    pub shape: Box<dyn Shape>,
-}
+        encoder
+    }
 
-impl Handler<StartShapeCompilation> for RenderThread {
-    type Result = u64;
+    /// Record one shape's distances over `tile` into `view`
+    fn encode_distance(
+        &self,
+        encoder: &mut CommandEncoder,
+        view: &TextureView,
+        tile: &Tile,
+        shape: &ShapeObj,
+        clear: bool,
+    ) {
+        let mut contents: Vec<u8> = vec![];
+        let arguments = shape.compiled_shape.fill_arguments(&mut contents, tile);
+
+        let alignment = self.device.limits().min_storage_buffer_offset_alignment as usize;
+        contents.extend(iter::repeat(0).take(alignment - (contents.len() % alignment)));
+        let arguments_offset: u64 = contents.len() as u64;
+        contents.extend(arguments.as_bytes());
+
+        let storage_buffer = self.device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: &contents,
+            usage: BufferUsages::STORAGE,
+        });
+        let uniform_buffer = self.device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: tile.into_bounds().as_bytes(),
+            usage: BufferUsages::UNIFORM,
+        });
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &shape.bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(BufferBinding {
+                        buffer: &storage_buffer,
+                        offset: arguments_offset as u64,
+                        size: NonZero::new(arguments.as_bytes().len() as u64),
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Buffer(BufferBinding {
+                        buffer: &storage_buffer,
+                        offset: 0,
+                        size: NonZero::new(arguments_offset),
+                    }),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Buffer(BufferBinding {
+                        buffer: &uniform_buffer,
+                        offset: 0,
+                        size: NonZero::new(size_of::<TileBounds>() as u64),
+                    }),
+                },
+            ],
+        });
+        let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            color_attachments: &[Some(RenderPassColorAttachment {
+                ops: Operations {
+                    load: if clear {
+                        wgpu::LoadOp::Clear(wgpu::Color::BLACK)
+                    } else {
+                        wgpu::LoadOp::Load
+                    },
+                    store: wgpu::StoreOp::Store,
+                },
+                view,
+                depth_slice: None,
+                resolve_target: None,
+            })],
+            label: None,
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+            multiview_mask: None,
+        });
 
-    fn handle(&mut self, msg: StartShapeCompilation, _ctx: &mut Self::Context) -> Self::Result {
-        let shape = CompiledShape::compile(&self.device, &mut self.shader_cache, &*msg.shape);
+        pass.set_pipeline(&shape.no_ellipsoid_low_prec);
+        pass.set_bind_group(0, Some(&bind_group), &[]);
+        pass.draw(0..4, 0..1);
+    }
+
+    fn compile_shape(&mut self, shape: &dyn Shape, id: ShapeId, style: Style) -> ShapeObj {
+        let compiled_shape = CompiledShape::compile(&self.device, &mut self.shader_cache, shape);
         let bind_group_layout = self
             .device
             .create_bind_group_layout(&BindGroupLayoutDescriptor {
@@ -268,7 +372,7 @@ impl Handler<StartShapeCompilation> for RenderThread {
                     depth_stencil: None,
                     multisample: MultisampleState::default(),
                     fragment: Some(FragmentState {
-                        module: shape.shader(),
+                        module: compiled_shape.shader(),
                         entry_point: Some("main"),
                         compilation_options: PipelineCompilationOptions {
                             constants: &[
@@ -288,305 +392,162 @@ impl Handler<StartShapeCompilation> for RenderThread {
                 })
         };
 
-        let id = shape.id();
-        self.shapes.push(ShapeObj {
+        ShapeObj {
+            id,
+            style,
             no_ellipsoid_low_prec: create_render_pipeline(false, false),
             // no_ellipsoid_high_prec: create_render_pipeline(false, true),
             // use_ellipsoid_low_prec: create_render_pipeline(true, false),
             // use_ellipsoid_high_prec: create_render_pipeline(true, true),
             bind_group_layout,
-            compiled_shape: shape,
-        });
+            compiled_shape,
+        }
+    }
 
-        id
+    fn shape_mut(&mut self, id: ShapeId) -> Option<&mut ShapeObj> {
+        self.shapes.iter_mut().find(|shape| shape.id == id)
+    }
+
+    /// Drop cached shader modules no remaining shape uses
+    fn release_unused_shaders(&mut self) {
+        let live: HashSet<u64> = self
+            .shapes
+            .iter()
+            .map(|shape| shape.compiled_shape.shader_hash())
+            .collect();
+
+        self.shader_cache.retain(|hash| live.contains(&hash));
     }
 }
 
-pub struct WrapBufferRef(pub HardwareBufferRef);
-unsafe impl Send for WrapBufferRef {}
+impl Actor for RenderThread {
+    type Context = Context<Self>;
 
-type RequestReturnType = WrapBufferRef;
-#[derive(Message)]
-#[rtype(result = "RequestReturnType")]
-pub struct RequestTile {
-    pub tile: Tile,
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(Duration::from_millis(15), |actor, _| {
+            let _ = actor.device.poll(PollType::Poll);
+        });
+    }
 }
 
-impl Handler<RequestTile> for RenderThread {
-    type Result = LocalBoxActorFuture<Self, RequestReturnType>;
-
-    fn handle(&mut self, msg: RequestTile, _ctx: &mut Self::Context) -> Self::Result {
-        let hardware_buffer: HardwareBufferRef =
-            ndk::hardware_buffer::HardwareBuffer::allocate(HardwareBufferDesc {
-                width: 256,
-                height: 256,
-                layers: 1,
-                stride: 256,
-                usage: HardwareBufferUsage::GPU_FRAMEBUFFER
-                    | HardwareBufferUsage::GPU_SAMPLED_IMAGE,
-                format: HardwareBufferFormat::R8G8B8A8_UNORM,
-            })
-            .unwrap();
-
-        let ext_info = vk::ExternalMemoryImageCreateInfo {
-            handle_types: ExternalMemoryHandleTypeFlags::ANDROID_HARDWARE_BUFFER_ANDROID,
-            ..Default::default()
-        };
+struct ShapeObj {
+    id: ShapeId,
+    style: Style,
+    compiled_shape: CompiledShape,
+    bind_group_layout: BindGroupLayout,
+    no_ellipsoid_low_prec: RenderPipeline,
+    // no_ellipsoid_high_prec: RenderPipeline,
+    // use_ellipsoid_low_prec: RenderPipeline,
+    // use_ellipsoid_high_prec: RenderPipeline,
+}
 
-        let image_create_info = vk::ImageCreateInfo {
-            p_next: &ext_info as *const _ as *const _,
-            extent: vk::Extent3D {
-                width: 256,
-                height: 256,
-                depth: 1,
-            },
-            mip_levels: 1,
-            array_layers: 1,
-            image_type: vk::ImageType::TYPE_2D,
-            format: vk::Format::R8G8B8A8_UNORM,
-            tiling: vk::ImageTiling::LINEAR,
-            initial_layout: vk::ImageLayout::UNDEFINED,
-            usage: vk::ImageUsageFlags::COLOR_ATTACHMENT
-                | vk::ImageUsageFlags::TRANSFER_SRC
-                | vk::ImageUsageFlags::TRANSFER_DST,
-            samples: vk::SampleCountFlags::TYPE_1,
-            sharing_mode: vk::SharingMode::EXCLUSIVE,
-            flags: vk::ImageCreateFlags::MUTABLE_FORMAT,
-            ..Default::default()
-        };
+/// A shape on the render thread; stays the same when the shape is replaced
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, MessageResponse)]
+pub struct ShapeId(pub(crate) u64);
 
-        let hal_device = unsafe { self.device.as_hal::<wgpu_hal::api::Vulkan>().unwrap() };
-        let device = hal_device.raw_device();
+// Shapes are named by their id in layer parameters
 
-        let texture = unsafe {
-            let image = device
-                .create_image(&image_create_info, None)
-                .expect("failed to create image");
+impl fmt::Display for ShapeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
 
-            let dedicated_info = vk::MemoryDedicatedAllocateInfo {
-                image,
-                buffer: vk::Buffer::null(),
-                ..Default::default()
-            };
+impl FromStr for ShapeId {
+    type Err = ParseIntError;
 
-            let import_memory_info = vk::ImportAndroidHardwareBufferInfoANDROID {
-                p_next: &dedicated_info as *const _ as *const _, // chain dedicated info
-                buffer: hardware_buffer.as_ptr() as _,
-                ..Default::default()
-            };
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(ShapeId)
+    }
+}
 
-            let memory_requirements = device.get_image_memory_requirements(image);
-
-            let memory_allocate_info = {
-                let adapter = self.adapter.as_hal::<wgpu_hal::api::Vulkan>().unwrap();
-                let phy = adapter.raw_physical_device();
-                let memory_properties = adapter
-                    .shared_instance()
-                    .raw_instance()
-                    .get_physical_device_memory_properties(phy);
-                let memory_type_index = find_memory_type_index(
-                    &memory_requirements,
-                    &memory_properties,
-                    vk::MemoryPropertyFlags::DEVICE_LOCAL,
-                )
-                .unwrap();
-                vk::MemoryAllocateInfo {
-                    p_next: &import_memory_info as *const _ as *const _,
-                    allocation_size: memory_requirements.size,
-                    memory_type_index,
-                    ..Default::default()
-                }
-            };
+#[derive(Message)]
+#[rtype(result = "ShapeId")]
+pub struct StartShapeCompilation {
+    pub shape: Box<dyn Shape>,
+    pub style: Style,
+}
 
-            let device_memory = device.allocate_memory(&memory_allocate_info, None).unwrap();
+impl Handler<StartShapeCompilation> for RenderThread {
+    type Result = ShapeId;
 
-            let desc = hardware_buffer.describe();
-            debug!(
-                "HW Buffer: {}x{}, stride={}, format={:?}",
-                desc.width, desc.height, desc.stride, desc.format
-            );
+    fn handle(&mut self, msg: StartShapeCompilation, _ctx: &mut Self::Context) -> Self::Result {
+        let compiled = self.compile_shape(&*msg.shape, ShapeId(0), msg.style);
+        let id = ShapeId(compiled.compiled_shape.id());
 
-            device.bind_image_memory(image, device_memory, 0).unwrap();
+        self.shapes.push(ShapeObj { id, ..compiled });
+        id
+    }
+}
 
-            let desc = wgpu_hal::TextureDescriptor {
-                label: None,
-                usage: TextureUses::COLOR_TARGET | TextureUses::COPY_SRC | TextureUses::COPY_DST,
-                size: Extent3d {
-                    width: 256,
-                    height: 256,
-                    depth_or_array_layers: 1,
-                },
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                memory_flags: MemoryFlags::empty(),
-                format: TextureFormat::Rgba8Unorm,
-                view_formats: vec![TextureFormat::Rgba8Unorm],
-            };
-            let texture = hal_device.texture_from_raw(
-                image,
-                &desc,
-                None,
-                TextureMemory::Dedicated(device_memory),
-            );
+/// Recompile a shape in place, keeping its id, style and draw order;
+/// returns `false` if there's no such shape
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct ReplaceShape {
+    pub id: ShapeId,
+    pub shape: Box<dyn Shape>,
+}
 
-            self.device
-                .create_texture_from_hal::<wgpu_hal::api::Vulkan>(
-                    texture,
-                    &TextureDescriptor {
-                        label: None,
-                        size: desc.size,
-                        mip_level_count: desc.mip_level_count,
-                        sample_count: desc.sample_count,
-                        dimension: desc.dimension,
-                        format: desc.format,
-                        usage: TextureUsages::RENDER_ATTACHMENT
-                            | TextureUsages::COPY_SRC
-                            | TextureUsages::COPY_DST,
-                        view_formats: &desc.view_formats,
-                    },
-                )
+impl Handler<ReplaceShape> for RenderThread {
+    type Result = bool;
+
+    fn handle(&mut self, msg: ReplaceShape, _ctx: &mut Self::Context) -> Self::Result {
+        let Some(style) = self.shape_mut(msg.id).map(|shape| shape.style.clone()) else {
+            return false;
         };
 
-        let mut encoder = self
-            .device
-            .create_command_encoder(&CommandEncoderDescriptor::default());
+        let replacement = self.compile_shape(&*msg.shape, msg.id, style);
+        if let Some(shape) = self.shape_mut(msg.id) {
+            *shape = replacement;
+        }
 
-        for (i, shape) in self.shapes.iter().enumerate() {
-            let mut contents: Vec<u8> = vec![];
-            let arguments = shape
-                .compiled_shape
-                .fill_arguments(&mut contents, &msg.tile);
+        self.release_unused_shaders();
+        true
+    }
+}
 
-            let alignment = self.device.limits().min_storage_buffer_offset_alignment as usize;
-            contents.extend(iter::repeat(0).take(alignment - (contents.len() % alignment)));
-            let arguments_offset: u64 = contents.len() as u64;
-            contents.extend(arguments.as_bytes());
+/// Returns `false` if there's no such shape
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct UpdateStyle {
+    pub id: ShapeId,
+    pub style: Style,
+}
 
-            let storage_buffer = self.device.create_buffer_init(&BufferInitDescriptor {
-                label: None,
-                contents: &contents,
-                usage: BufferUsages::STORAGE,
-            });
-            let uniform_buffer = self.device.create_buffer_init(&BufferInitDescriptor {
-                label: None,
-                contents: msg.tile.into_bounds().as_bytes(),
-                usage: BufferUsages::UNIFORM,
-            });
-            let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                label: None,
-                layout: &shape.bind_group_layout,
-                entries: &[
-                    BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::Buffer(BufferBinding {
-                            buffer: &storage_buffer,
-                            offset: arguments_offset as u64,
-                            size: NonZero::new(arguments.as_bytes().len() as u64),
-                        }),
-                    },
-                    BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Buffer(BufferBinding {
-                            buffer: &storage_buffer,
-                            offset: 0,
-                            size: NonZero::new(arguments_offset),
-                        }),
-                    },
-                    BindGroupEntry {
-                        binding: 2,
-                        resource: wgpu::BindingResource::Buffer(BufferBinding {
-                            buffer: &uniform_buffer,
-                            offset: 0,
-                            size: NonZero::new(size_of::<TileBounds>() as u64),
-                        }),
-                    },
-                ],
-            });
-            let mut pass = encoder.begin_render_pass(&RenderPassDescriptor {
-                color_attachments: &[Some(RenderPassColorAttachment {
-                    ops: Operations {
-                        load: match i {
-                            0 => wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                            _ => wgpu::LoadOp::Load,
-                        },
-                        store: wgpu::StoreOp::Store,
-                    },
-                    view: &texture.create_view(&TextureViewDescriptor {
-                        ..Default::default()
-                    }),
-                    depth_slice: None,
-                    resolve_target: None,
-                })],
-                label: None,
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-                multiview_mask: None,
-            });
+impl Handler<UpdateStyle> for RenderThread {
+    type Result = bool;
 
-            pass.set_pipeline(&shape.no_ellipsoid_low_prec);
-            pass.set_bind_group(0, Some(&bind_group), &[]);
-            pass.draw(0..4, 0..1);
+    fn handle(&mut self, msg: UpdateStyle, _ctx: &mut Self::Context) -> Self::Result {
+        match self.shape_mut(msg.id) {
+            Some(shape) => {
+                shape.style = msg.style;
+                true
+            }
+            None => false,
         }
+    }
+}
 
-        enum State {
-            ToSubmit(CommandEncoder),
-            Waiting(Arc<AtomicBool>),
-        }
-        #[pin_project::pin_project]
-        struct Impl {
-            return_value: Option<RequestReturnType>,
-            state: State,
-        }
-        impl ActorFuture<RenderThread> for Impl {
-            type Output = RequestReturnType;
-
-            fn poll(
-                self: Pin<&mut Self>,
-                thread: &mut RenderThread,
-                _ctx: &mut <RenderThread as Actor>::Context,
-                task: &mut std::task::Context,
-            ) -> Poll<Self::Output> {
-                let this = self.project();
-                match this.state {
-                    State::ToSubmit(_) => {
-                        let done = Arc::new(AtomicBool::new(false));
-                        let mut encoder_val = None;
-                        replace_with_or_abort(this.state, |state| {
-                            let State::ToSubmit(encoder) = state else {
-                                unreachable!()
-                            };
-
-                            encoder_val = Some(encoder);
-                            State::Waiting(done.clone())
-                        });
-                        let encoder = encoder_val.unwrap();
-                        let waker = task.waker().clone();
-                        encoder.on_submitted_work_done(move || {
-                            done.store(true, Ordering::Release);
-                            waker.wake();
-                        });
-                        let command_buffer = encoder.finish();
-                        thread.queue.submit([command_buffer]);
-                        Poll::Pending
-                    }
-                    State::Waiting(done) => {
-                        if let Some(return_value) =
-                            this.return_value.take_if(|_| done.load(Ordering::Acquire))
-                        {
-                            Poll::Ready(return_value)
-                        } else {
-                            Poll::Pending
-                        }
-                    }
-                }
-            }
+/// Drop a shape and the GPU resources only it used; returns `false` if
+/// there's no such shape
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct RemoveShape {
+    pub id: ShapeId,
+}
+
+impl Handler<RemoveShape> for RenderThread {
+    type Result = bool;
+
+    fn handle(&mut self, msg: RemoveShape, _ctx: &mut Self::Context) -> Self::Result {
+        let before = self.shapes.len();
+        self.shapes.retain(|shape| shape.id != msg.id);
+        if self.shapes.len() == before {
+            return false;
         }
 
-        Box::pin(Impl {
-            state: State::ToSubmit(encoder),
-            return_value: Some(WrapBufferRef(hardware_buffer)),
-        })
+        self.release_unused_shaders();
+        true
     }
 }
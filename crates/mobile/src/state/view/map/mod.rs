@@ -1,36 +1,58 @@
 pub mod source;
 
-use std::path::PathBuf;
+use std::path::Path;
+
+use geo::BoundingRect;
+use pmtiles::TileType;
 
 use crate::{
-    state::view::map::source::MapSource,
-    tile_server::{TileServer, TileServerError},
+    state::view::{MapError, map::source::MapSource},
+    tile_server::{
+        ServedFiles, StyleAssets, TileServer, TileServerError,
+        overlay::{self, mercator_y},
+    },
 };
 
 const DEFAULT_STYLE: &str = include_str!("../../../../assets/libre-theme.json");
 
+/// Highest zoom the initial camera fits a boundary at
+const MAX_FIT_ZOOM: f64 = 18.0;
+
+/// The overlay holding the boundary, which the style masks the map with
+const PLAY_AREA: &str = "playarea";
+
 #[derive(uniffi::Object)]
 pub struct MapState {
     style_json: String,
-    #[allow(dead_code)]
     tile_server: TileServer,
 }
 
 impl MapState {
-    pub async fn new(source: MapSource) -> Result<Self, TileServerError> {
-        let tile_server = TileServer::start(PathBuf::from(source.pmtiles_path))?;
-        let mask_geojson = std::fs::read_to_string(&source.bounds_path).map_err(|e| {
-            std::io::Error::new(e.kind(), format!("{e} (file: {:?})", source.bounds_path))
-        })?;
-        let complexes_geojson = std::fs::read_to_string(&source.complexes_path).map_err(|e| {
-            std::io::Error::new(e.kind(), format!("{e} (file: {:?})", source.complexes_path))
+    pub async fn new(source: &MapSource) -> Result<Self, TileServerError> {
+        let tile_server = TileServer::start(ServedFiles {
+            archives: source
+                .archives
+                .iter()
+                .map(|archive| (archive.name.clone(), archive.path.clone()))
+                .collect(),
+            assets: StyleAssets {
+                glyphs: source.glyphs.clone(),
+                sprites: source.sprites.clone(),
+            },
         })?;
-        let style_json = build_style(
-            DEFAULT_STYLE,
-            tile_server.port(),
-            &mask_geojson,
-            &complexes_geojson,
-        );
+
+        // Overlays are served as vector tiles rather than inlined, so the
+        // map doesn't parse megabytes of GeoJSON along with the style
+        let mask_geojson = read_file(&source.boundary)?;
+        tile_server.set_overlay(PLAY_AREA, &mask_geojson)?;
+
+        let mut overlays = vec![PLAY_AREA];
+        for overlay in &source.overlays {
+            tile_server.set_overlay(&overlay.name, &read_file(&overlay.path)?)?;
+            overlays.push(&overlay.name);
+        }
+
+        let style_json = build_style(DEFAULT_STYLE, &tile_server, &mask_geojson, &overlays);
 
         Ok(Self {
             style_json,
@@ -44,51 +66,126 @@ impl MapState {
     pub fn get_style(&self) -> String {
         self.style_json.clone()
     }
+
+    /// Add or replace an overlay, e.g. to highlight the stations on a
+    /// question's line
+    ///
+    /// Returns the overlay's tile URL, which changes with every update; set
+    /// it as the source's tiles so MapLibre drops what it had cached. New
+    /// overlays need a vector source with that URL and `source-layer` set to
+    /// `name`.
+    pub fn set_overlay(&self, name: String, geojson: String) -> Result<String, MapError> {
+        self.tile_server
+            .set_overlay(&name, &geojson)
+            .map_err(|e| MapError::Overlay {
+                name,
+                reason: e.to_string(),
+            })
+    }
+
+    pub fn remove_overlay(&self, name: String) {
+        self.tile_server.remove_overlay(&name);
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, std::io::Error> {
+    std::fs::read_to_string(path)
+        .map_err(|e| std::io::Error::new(e.kind(), format!("{e} (file: {path:?})")))
 }
 
-fn build_style(base_style: &str, port: u16, mask_geojson: &str, complexes_geojson: &str) -> String {
+/// Where the map opens
+struct Camera {
+    center: [f64; 2],
+    zoom: f64,
+}
+
+impl Camera {
+    /// Centre on the boundary and zoom until it fills a viewport about one
+    /// 512px tile across, roughly a phone screen
+    fn fit(boundary_geojson: &str) -> Option<Self> {
+        let geojson: geojson::GeoJson = boundary_geojson.parse().ok()?;
+        let geometry = geo::Geometry::<f64>::try_from(geojson).ok()?;
+        let bounds = geometry.bounding_rect()?;
+
+        // Fit in Web Mercator, where a zoom level halves the visible span
+        let (west, east) = (bounds.min().x, bounds.max().x);
+        let (north, south) = (mercator_y(bounds.max().y), mercator_y(bounds.min().y));
+        let span = ((east - west) / 360.0).max(south - north);
+
+        Some(Self {
+            center: [
+                (west + east) / 2.0,
+                inverse_mercator_y((north + south) / 2.0),
+            ],
+            zoom: (-span.log2()).clamp(0.0, MAX_FIT_ZOOM),
+        })
+    }
+}
+
+fn inverse_mercator_y(y: f64) -> f64 {
+    (std::f64::consts::PI * (1.0 - 2.0 * y))
+        .sinh()
+        .atan()
+        .to_degrees()
+}
+
+/// Point the style at this play area's archives, overlays and assets
+fn build_style(
+    base_style: &str,
+    tile_server: &TileServer,
+    mask_geojson: &str,
+    overlays: &[&str],
+) -> String {
     let mut style: serde_json::Value = serde_json::from_str(base_style).unwrap();
 
-    // Set initial map center to Central Park, NYC
+    // Open the map over the play area
+    if let (Some(obj), Some(camera)) = (style.as_object_mut(), Camera::fit(mask_geojson)) {
+        obj.insert("center".to_string(), serde_json::json!(camera.center));
+        obj.insert("zoom".to_string(), serde_json::json!(camera.zoom));
+    }
+
+    // Serve fonts and icons locally rather than from a CDN
     if let Some(obj) = style.as_object_mut() {
-        obj.insert(
-            "center".to_string(),
-            serde_json::json!([-73.9805655, 40.7571418]),
-        );
-        obj.insert("zoom".to_string(), serde_json::json!(12));
+        if let Some(glyphs) = tile_server.glyphs_url() {
+            obj.insert("glyphs".to_string(), serde_json::json!(glyphs));
+        }
+        if let Some(sprite) = tile_server.sprite_url() {
+            obj.insert("sprite".to_string(), serde_json::json!(sprite));
+        }
     }
 
     if let Some(sources) = style.get_mut("sources").and_then(|s| s.as_object_mut()) {
-        // Update only the local openmaptiles source, not the world one
-        if let Some(source) = sources.get_mut("openmaptiles") {
-            if let Some(obj) = source.as_object_mut() {
-                obj.insert(
-                    "url".to_string(),
-                    serde_json::json!(format!("http://localhost:{port}/tiles.json")),
-                );
+        // Point local sources at their archive, leaving the world one alone
+        for (name, tile_type) in tile_server.archives() {
+            let url = serde_json::json!(tile_server.tilejson_url(name));
+            match sources.get_mut(name).and_then(|s| s.as_object_mut()) {
+                Some(obj) => {
+                    obj.insert("url".to_string(), url);
+                }
+                None => {
+                    sources.insert(
+                        name.to_string(),
+                        serde_json::json!({
+                            "type": source_type(tile_type),
+                            "url": url
+                        }),
+                    );
+                }
             }
         }
 
-        // Add the complexes source
-        if let Some(complexes_data) = parse_mask_geojson(complexes_geojson) {
-            sources.insert(
-                "complexes".to_string(),
-                serde_json::json!({
-                    "type": "geojson",
-                    "data": complexes_data
-                }),
-            );
-        }
-
-        // Add the play area mask source
-        if let Some(playarea_geojson) = parse_mask_geojson(mask_geojson) {
-            sources.insert(
-                "playarea".to_string(),
-                serde_json::json!({
-                    "type": "geojson",
-                    "data": playarea_geojson
-                }),
-            );
+        // Add the overlay sources, e.g. station complexes and the play area
+        for &name in overlays {
+            if let Some(url) = tile_server.overlay_tiles_url(name) {
+                sources.insert(
+                    name.to_string(),
+                    serde_json::json!({
+                        "type": "vector",
+                        "tiles": [url],
+                        "maxzoom": overlay::MAX_ZOOM
+                    }),
+                );
+            }
         }
     }
 
@@ -97,6 +194,16 @@ fn build_style(base_style: &str, port: u16, mask_geojson: &str, complexes_geojso
 
     // Insert playarea-fill layer after world-water but before local water
     if let Some(layers) = style.get_mut("layers").and_then(|l| l.as_array_mut()) {
+        // Overlay tiles hold one layer, named after the overlay
+        for layer in layers.iter_mut() {
+            let source = layer.get("source").and_then(|s| s.as_str());
+            if let Some(&name) = overlays.iter().find(|&&name| Some(name) == source)
+                && let Some(obj) = layer.as_object_mut()
+            {
+                obj.insert("source-layer".to_string(), serde_json::json!(name));
+            }
+        }
+
         // Add "within" filter to line layers using the local openmaptiles source
         // This clips them to only render within the playarea bounds
         // Note: "within" only supports Point/LineString, not Polygon geometries,
@@ -130,10 +237,12 @@ fn build_style(base_style: &str, port: u16, mask_geojson: &str, complexes_geojso
     serde_json::to_string(&style).unwrap()
 }
 
-/// Parse the mask GeoJSON and convert to a serde_json::Value for use as a MapLibre source
-fn parse_mask_geojson(geojson_str: &str) -> Option<serde_json::Value> {
-    let geojson: geojson::GeoJson = geojson_str.parse().ok()?;
-    Some(serde_json::to_value(&geojson).ok()?)
+/// Style source type for an archive; the style brings its own layers
+fn source_type(tile_type: TileType) -> &'static str {
+    match tile_type {
+        TileType::Mvt => "vector",
+        _ => "raster",
+    }
 }
 
 /// Extract just the geometry from a GeoJSON for use in "within" filter expressions
@@ -156,3 +265,53 @@ fn extract_geometry_for_filter(geojson_str: &str) -> Option<serde_json::Value> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn polygon(west: f64, south: f64, east: f64, north: f64) -> String {
+        serde_json::json!({
+            "type": "Feature",
+            "properties": {},
+            "geometry": {
+                "type": "Polygon",
+                "coordinates": [[
+                    [west, south], [east, south], [east, north], [west, north], [west, south]
+                ]]
+            }
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn test_camera_fits_boundary() {
+        // New York City
+        let camera = Camera::fit(&polygon(-74.26, 40.49, -73.70, 40.92)).unwrap();
+
+        assert!((camera.center[0] - -73.98).abs() < 1e-9);
+        // The Mercator midpoint sits a little north of the midpoint in degrees
+        assert!(camera.center[1] > 40.705 && camera.center[1] < 40.71, "{:?}", camera.center);
+        // ~0.0016 of the world tall, the larger span: 2^-9.3
+        assert!((camera.zoom - 9.3).abs() < 0.05, "{}", camera.zoom);
+    }
+
+    #[test]
+    fn test_camera_zoom_is_clamped() {
+        let camera = Camera::fit(&polygon(-180.0, -85.0, 180.0, 85.0)).unwrap();
+        assert_eq!(camera.zoom, 0.0);
+        assert!(camera.center[1].abs() < 1e-9);
+
+        let point = r#"{ "type": "Point", "coordinates": [-73.9855, 40.758] }"#;
+        let camera = Camera::fit(point).unwrap();
+        assert_eq!(camera.zoom, MAX_FIT_ZOOM);
+        assert!((camera.center[0] - -73.9855).abs() < 1e-9);
+        assert!((camera.center[1] - 40.758).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_camera_needs_geometry() {
+        assert!(Camera::fit("not geojson").is_none());
+        assert!(Camera::fit(r#"{ "type": "FeatureCollection", "features": [] }"#).is_none());
+    }
+}
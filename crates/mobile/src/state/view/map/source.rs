@@ -1,19 +1,192 @@
-use std::ffi::OsString;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+use serde::Deserialize;
+
+use crate::state::view::MapError;
+
+/// A file the map reads, under the name the style refers to it by
+#[derive(Debug, Deserialize)]
+pub(crate) struct NamedFile {
+    pub(crate) name: String,
+    pub(crate) path: PathBuf,
+}
+
+/// A play area: where its tiles, boundary and overlays live
+///
+/// Read from a JSON manifest, with paths relative to the manifest:
+///
+/// ```json
+/// {
+///     "archives": [{ "name": "openmaptiles", "path": "nyc_tiles.pmtiles" }],
+///     "boundary": "nyc_bounds.geojson",
+///     "overlays": [{ "name": "complexes", "path": "complexes.geojson" }],
+///     "glyphs": "fonts",
+///     "sprites": "sprites"
+/// }
+/// ```
+///
+/// Each archive replaces the style source of the same name, or is added as
+/// a vector or raster source; overlays, and the boundary as `playarea`, are
+/// cut into vector tiles. Glyphs and sprites are optional directories served
+/// in place of the style's own. The initial camera fits the boundary.
+#[derive(Debug, Deserialize, uniffi::Object)]
 pub struct MapSource {
-    pub(crate) pmtiles_path: OsString,
-    pub(crate) bounds_path: OsString,
-    pub(crate) complexes_path: OsString,
+    pub(crate) archives: Vec<NamedFile>,
+    pub(crate) boundary: PathBuf,
+    #[serde(default)]
+    pub(crate) overlays: Vec<NamedFile>,
+    #[serde(default)]
+    pub(crate) glyphs: Option<PathBuf>,
+    #[serde(default)]
+    pub(crate) sprites: Option<PathBuf>,
+}
+
+#[uniffi::export]
+impl MapSource {
+    #[uniffi::constructor]
+    pub fn from_manifest(path: String) -> Result<Self, MapError> {
+        let error = |reason: String| MapError::Manifest {
+            path: path.clone(),
+            reason,
+        };
+
+        let manifest = std::fs::read_to_string(&path).map_err(|e| error(e.to_string()))?;
+        let source: MapSource =
+            serde_json::from_str(&manifest).map_err(|e| error(e.to_string()))?;
+        if source.archives.is_empty() {
+            return Err(error("no tile archives".into()));
+        }
+
+        let base = Path::new(&path).parent().unwrap_or(Path::new(""));
+        Ok(source.relative_to(base))
+    }
 }
 
 impl MapSource {
     pub(crate) fn nyc(base_path: String) -> Self {
-        let base = PathBuf::from(&base_path);
         MapSource {
-            pmtiles_path: base.join("nyc_tiles.pmtiles").into_os_string(),
-            bounds_path: base.join("nyc_bounds.geojson").into_os_string(),
-            complexes_path: base.join("complexes.geojson").into_os_string(),
+            archives: vec![NamedFile {
+                name: "openmaptiles".into(),
+                path: "nyc_tiles.pmtiles".into(),
+            }],
+            boundary: "nyc_bounds.geojson".into(),
+            overlays: vec![NamedFile {
+                name: "complexes".into(),
+                path: "complexes.geojson".into(),
+            }],
+            glyphs: None,
+            sprites: None,
+        }
+        .relative_to(Path::new(&base_path))
+    }
+
+    /// Resolve relative paths against `base`; absolute ones are kept
+    fn relative_to(self, base: &Path) -> Self {
+        let resolve = |file: NamedFile| NamedFile {
+            path: base.join(file.path),
+            ..file
+        };
+
+        MapSource {
+            archives: self.archives.into_iter().map(resolve).collect(),
+            boundary: base.join(self.boundary),
+            overlays: self.overlays.into_iter().map(resolve).collect(),
+            glyphs: self.glyphs.map(|glyphs| base.join(glyphs)),
+            sprites: self.sprites.map(|sprites| base.join(sprites)),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A scratch directory holding `manifest.json` with `contents`
+    fn manifest(test: &str, contents: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("jet-lag-{test}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("manifest.json");
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn from_manifest(path: &Path) -> Result<MapSource, MapError> {
+        MapSource::from_manifest(path.to_str().unwrap().to_owned())
+    }
+
+    #[test]
+    fn test_manifest_paths_are_relative_to_it() {
+        let path = manifest(
+            "manifest-paths",
+            r#"{
+                "archives": [
+                    { "name": "openmaptiles", "path": "tiles/nyc.pmtiles" },
+                    { "name": "terrain", "path": "/srv/terrain.pmtiles" }
+                ],
+                "boundary": "nyc_bounds.geojson",
+                "overlays": [{ "name": "complexes", "path": "complexes.geojson" }],
+                "glyphs": "fonts"
+            }"#,
+        );
+        let dir = path.parent().unwrap();
+
+        let source = from_manifest(&path).unwrap();
+
+        let archives: Vec<(&str, &Path)> = source
+            .archives
+            .iter()
+            .map(|archive| (archive.name.as_str(), archive.path.as_path()))
+            .collect();
+        assert_eq!(
+            archives,
+            vec![
+                ("openmaptiles", dir.join("tiles/nyc.pmtiles").as_path()),
+                ("terrain", Path::new("/srv/terrain.pmtiles")),
+            ]
+        );
+        assert_eq!(source.boundary, dir.join("nyc_bounds.geojson"));
+        assert_eq!(source.overlays[0].name, "complexes");
+        assert_eq!(source.overlays[0].path, dir.join("complexes.geojson"));
+        assert_eq!(source.glyphs, Some(dir.join("fonts")));
+        assert_eq!(source.sprites, None);
+    }
+
+    #[test]
+    fn test_manifest_defaults() {
+        let path = manifest(
+            "manifest-defaults",
+            r#"{
+                "archives": [{ "name": "openmaptiles", "path": "nyc.pmtiles" }],
+                "boundary": "nyc_bounds.geojson"
+            }"#,
+        );
+
+        let source = from_manifest(&path).unwrap();
+        assert!(source.overlays.is_empty());
+        assert_eq!(source.glyphs, None);
+        assert_eq!(source.sprites, None);
+    }
+
+    #[test]
+    fn test_manifest_errors() {
+        let reason = |path: &Path| match from_manifest(path) {
+            Err(MapError::Manifest { reason, .. }) => reason,
+            other => panic!("expected a manifest error, got {other:?}"),
+        };
+
+        let no_archives = manifest(
+            "manifest-no-archives",
+            r#"{ "archives": [], "boundary": "nyc_bounds.geojson" }"#,
+        );
+        assert_eq!(reason(&no_archives), "no tile archives");
+
+        let no_boundary = manifest(
+            "manifest-no-boundary",
+            r#"{ "archives": [{ "name": "openmaptiles", "path": "nyc.pmtiles" }] }"#,
+        );
+        assert!(reason(&no_boundary).contains("boundary"));
+
+        let missing = no_archives.with_file_name("missing.json");
+        assert!(!reason(&missing).is_empty());
+    }
+}
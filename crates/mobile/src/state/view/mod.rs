@@ -11,11 +11,15 @@ pub mod map;
 pub enum MapError {
     #[error("{0}")]
     TileServer(String),
+    #[error("failed to load map manifest {path:?}: {reason}")]
+    Manifest { path: String, reason: String },
+    #[error("invalid overlay {name:?}: {reason}")]
+    Overlay { name: String, reason: String },
 }
 
 #[derive(uniffi::Object)]
 pub struct ViewState {
-    base_path: String,
+    source: RwLock<Arc<MapSource>>,
     map: RwLock<Option<Arc<MapState>>>,
 }
 
@@ -24,19 +28,27 @@ impl ViewState {
     #[uniffi::constructor]
     pub fn new(base_path: String) -> Self {
         Self {
-            base_path,
+            source: RwLock::new(Arc::new(MapSource::nyc(base_path))),
             map: RwLock::new(None),
         }
     }
 
+    /// Switch to another play area; the next `get_map_state` loads it
+    pub async fn set_map_source(&self, source: Arc<MapSource>) {
+        let mut guard = self.map.write().await;
+        *self.source.write().await = source;
+        *guard = None;
+    }
+
     pub async fn get_map_state(&self) -> Result<Arc<MapState>, MapError> {
         if let Some(ref map) = *(self.map.read().await) {
             return Ok(Arc::clone(map));
         }
 
         let mut guard = self.map.write().await;
+        let source = Arc::clone(&*self.source.read().await);
         let new_map = Arc::new(
-            MapState::new(MapSource::nyc(self.base_path.clone()))
+            MapState::new(&source)
                 .await
                 .map_err(|e| MapError::TileServer(e.to_string()))?,
         );
@@ -1,26 +1,33 @@
+mod mvt;
+pub mod overlay;
 mod routes;
 
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use pmtiles::MmapBackend;
 use pmtiles::async_reader::AsyncPmTilesReader;
+use pmtiles::{MmapBackend, TileType};
 use tokio::runtime::Runtime;
 use tokio::sync::{RwLock, oneshot};
 
+use crate::tile_server::overlay::{OverlayError, Overlays};
+
 pub type PmTilesReader = AsyncPmTilesReader<MmapBackend>;
 
 #[derive(Debug)]
 pub enum TileServerError {
-    IoError(std::io::Error),
-    PmTilesError(String),
+    Io(std::io::Error),
+    PmTiles(String),
+    Overlay(OverlayError),
 }
 
 impl std::fmt::Display for TileServerError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            TileServerError::IoError(e) => write!(f, "IO error: {e}"),
-            TileServerError::PmTilesError(msg) => write!(f, "PMTiles error: {msg}"),
+            TileServerError::Io(e) => write!(f, "IO error: {e}"),
+            TileServerError::PmTiles(msg) => write!(f, "PMTiles error: {msg}"),
+            TileServerError::Overlay(e) => write!(f, "Overlay error: {e}"),
         }
     }
 }
@@ -29,65 +36,102 @@ impl std::error::Error for TileServerError {}
 
 impl From<std::io::Error> for TileServerError {
     fn from(e: std::io::Error) -> Self {
-        TileServerError::IoError(e)
+        TileServerError::Io(e)
+    }
+}
+
+impl From<OverlayError> for TileServerError {
+    fn from(e: OverlayError) -> Self {
+        TileServerError::Overlay(e)
     }
 }
 
+/// Everything a play area's map loads from the tile server
+#[derive(Debug, Default)]
+pub struct ServedFiles {
+    /// PMTiles archives, vector or raster, by the name their URLs use
+    pub archives: Vec<(String, PathBuf)>,
+    pub assets: StyleAssets,
+}
+
+/// Files the style itself needs, so fonts and icons don't come from a CDN
+#[derive(Debug, Default)]
+pub struct StyleAssets {
+    /// Directory of `{fontstack}/{range}.pbf` glyph files
+    pub glyphs: Option<PathBuf>,
+
+    /// Directory holding `sprite.json`, `sprite.png` and their `@2x`
+    /// variants
+    pub sprites: Option<PathBuf>,
+}
+
 pub struct TileServer {
     #[allow(dead_code)] // Kept alive to keep server running
     runtime: Runtime,
     port: u16,
+    archives: Vec<(String, TileType)>,
+    overlays: Arc<Overlays>,
+    has_glyphs: bool,
+    has_sprites: bool,
     shutdown_tx: Option<oneshot::Sender<()>>,
 }
 
+struct Started {
+    port: u16,
+    archives: Vec<(String, TileType)>,
+}
+
 impl TileServer {
-    pub fn start(pmtiles_path: PathBuf) -> Result<Self, TileServerError> {
+    pub fn start(files: ServedFiles) -> Result<Self, TileServerError> {
         let runtime = Runtime::new()?;
 
+        let has_glyphs = files.assets.glyphs.is_some();
+        let has_sprites = files.assets.sprites.is_some();
+        let overlays = Arc::new(Overlays::default());
+
         let (shutdown_tx, shutdown_rx) = oneshot::channel();
-        let (result_tx, result_rx) = oneshot::channel::<Result<u16, TileServerError>>();
+        let (result_tx, result_rx) = oneshot::channel::<Result<Started, TileServerError>>();
 
+        let server_overlays = overlays.clone();
         runtime.spawn(async move {
-            let result = Self::start_server(pmtiles_path, shutdown_rx).await;
+            let result = Self::start_server(files, server_overlays, shutdown_rx).await;
             let _ = result_tx.send(result);
         });
 
-        let port = runtime.block_on(async {
+        let started = runtime.block_on(async {
             result_rx.await.map_err(|_| {
-                TileServerError::PmTilesError("Server task died unexpectedly".into())
+                TileServerError::PmTiles("Server task died unexpectedly".into())
             })?
         })?;
 
         Ok(Self {
             runtime,
-            port,
+            port: started.port,
+            archives: started.archives,
+            overlays,
+            has_glyphs,
+            has_sprites,
             shutdown_tx: Some(shutdown_tx),
         })
     }
 
     async fn start_server(
-        pmtiles_path: PathBuf,
+        files: ServedFiles,
+        overlays: Arc<Overlays>,
         shutdown_rx: oneshot::Receiver<()>,
-    ) -> Result<u16, TileServerError> {
-        let backend = MmapBackend::try_from(pmtiles_path.as_path())
-            .await
-            .map_err(|e| {
-                TileServerError::PmTilesError(format!(
-                    "Failed to open PMTiles file at {pmtiles_path:?}: {e}"
-                ))
-            })?;
-
-        let reader = AsyncPmTilesReader::try_from_source(backend)
-            .await
-            .map_err(|e| {
-                TileServerError::PmTilesError(format!("Failed to read PMTiles archive: {e}"))
-            })?;
+    ) -> Result<Started, TileServerError> {
+        let mut readers = HashMap::with_capacity(files.archives.len());
+        let mut archives = Vec::with_capacity(files.archives.len());
+        for (name, path) in files.archives {
+            let reader = Self::open_archive(&path).await?;
+            archives.push((name.clone(), reader.get_header().tile_type));
+            readers.insert(name, Arc::new(RwLock::new(reader)));
+        }
 
         let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
         let port = listener.local_addr()?.port();
 
-        let reader = Arc::new(RwLock::new(reader));
-        let app = routes::create_router(reader, port);
+        let app = routes::create_router(readers, overlays, files.assets, port);
 
         tokio::spawn(async move {
             let _ = axum::serve(listener, app)
@@ -97,11 +141,78 @@ impl TileServer {
                 .await;
         });
 
-        Ok(port)
+        Ok(Started { port, archives })
+    }
+
+    async fn open_archive(path: &Path) -> Result<PmTilesReader, TileServerError> {
+        let backend = MmapBackend::try_from(path).await.map_err(|e| {
+            TileServerError::PmTiles(format!("Failed to open PMTiles file at {path:?}: {e}"))
+        })?;
+
+        AsyncPmTilesReader::try_from_source(backend)
+            .await
+            .map_err(|e| {
+                TileServerError::PmTiles(format!(
+                    "Failed to read PMTiles archive at {path:?}: {e}"
+                ))
+            })
+    }
+
+    /// The archives being served, with what kind of tiles each holds
+    pub fn archives(&self) -> impl Iterator<Item = (&str, TileType)> {
+        self.archives
+            .iter()
+            .map(|(name, tile_type)| (name.as_str(), *tile_type))
+    }
+
+    pub fn tilejson_url(&self, archive: &str) -> String {
+        format!(
+            "http://localhost:{}/archives/{archive}/tiles.json",
+            self.port
+        )
+    }
+
+    /// Add or replace a GeoJSON overlay, served as vector tiles with one
+    /// layer named after it
+    ///
+    /// Returns its tile URL template, which changes with every update so
+    /// MapLibre refetches rather than reusing tiles it cached.
+    pub fn set_overlay(&self, name: &str, geojson: &str) -> Result<String, OverlayError> {
+        let revision = self.overlays.set(name, geojson)?;
+        Ok(self.overlay_url(name, revision))
+    }
+
+    pub fn remove_overlay(&self, name: &str) {
+        self.overlays.remove(name);
+    }
+
+    /// Tile URL template for an overlay's current revision
+    pub fn overlay_tiles_url(&self, name: &str) -> Option<String> {
+        let overlay = self.overlays.get(name)?;
+        Some(self.overlay_url(name, overlay.revision()))
+    }
+
+    fn overlay_url(&self, name: &str, revision: u64) -> String {
+        format!(
+            "http://localhost:{}/overlays/{name}/{{z}}/{{x}}/{{y}}?revision={revision}",
+            self.port
+        )
+    }
+
+    /// Glyph URL template for the style, if glyphs are served
+    pub fn glyphs_url(&self) -> Option<String> {
+        self.has_glyphs.then(|| {
+            format!(
+                "http://localhost:{}/fonts/{{fontstack}}/{{range}}.pbf",
+                self.port
+            )
+        })
     }
 
-    pub fn port(&self) -> u16 {
-        self.port
+    /// Sprite URL for the style, if sprites are served
+    pub fn sprite_url(&self) -> Option<String> {
+        self.has_sprites
+            .then(|| format!("http://localhost:{}/sprites/sprite", self.port))
     }
 }
 
@@ -0,0 +1,643 @@
+use std::collections::HashMap;
+
+use geo::{Coord, LineString};
+use serde_json::{Map, Value};
+
+use super::overlay::FeatureGeometry;
+
+/// Tile coordinates run from 0 to `EXTENT` on both axes
+pub const EXTENT: u32 = 4096;
+
+const VERSION: u64 = 2;
+
+// Wire types
+const VARINT: u32 = 0;
+const FIXED64: u32 = 1;
+const LENGTH_DELIMITED: u32 = 2;
+
+// Geometry types and commands
+const POINT: u64 = 1;
+const LINESTRING: u64 = 2;
+const POLYGON: u64 = 3;
+
+const MOVE_TO: u32 = 1;
+const LINE_TO: u32 = 2;
+const CLOSE_PATH: u32 = 7;
+
+/// A protobuf message being written
+#[derive(Default)]
+struct Message(Vec<u8>);
+
+impl Message {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u32) {
+        self.varint(((field << 3) | wire_type) as u64);
+    }
+
+    fn uint(&mut self, field: u32, value: u64) {
+        self.key(field, VARINT);
+        self.varint(value);
+    }
+
+    fn double(&mut self, field: u32, value: f64) {
+        self.key(field, FIXED64);
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn bytes(&mut self, field: u32, bytes: &[u8]) {
+        self.key(field, LENGTH_DELIMITED);
+        self.varint(bytes.len() as u64);
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn packed(&mut self, field: u32, values: &[u32]) {
+        let mut packed = Message::default();
+        for &value in values {
+            packed.varint(value as u64);
+        }
+        self.bytes(field, &packed.0);
+    }
+}
+
+fn zigzag(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+fn command(id: u32, count: usize) -> u32 {
+    id | ((count as u32) << 3)
+}
+
+/// One layer of a Mapbox Vector Tile, with its keys and values deduplicated
+///
+/// Just enough of <https://github.com/mapbox/vector-tile-spec/tree/master/2.1>
+/// to write the overlays.
+pub struct LayerBuilder {
+    name: String,
+    keys: Vec<String>,
+    key_index: HashMap<String, u32>,
+    values: Vec<Vec<u8>>,
+    value_index: HashMap<Vec<u8>, u32>,
+    features: Vec<Vec<u8>>,
+}
+
+impl LayerBuilder {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            keys: Vec::new(),
+            key_index: HashMap::new(),
+            values: Vec::new(),
+            value_index: HashMap::new(),
+            features: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.features.is_empty()
+    }
+
+    /// Add a feature in tile coordinates; geometry that rounds away to
+    /// nothing is skipped
+    pub fn add_feature(
+        &mut self,
+        id: Option<u64>,
+        properties: &Map<String, Value>,
+        geometry: &FeatureGeometry,
+    ) {
+        let mut encoder = GeometryEncoder::default();
+        let geometry_type = match geometry {
+            FeatureGeometry::Points(points) => {
+                encoder.points(points.iter().map(|point| point.0));
+                POINT
+            }
+            FeatureGeometry::Lines(lines) => {
+                for line in lines {
+                    encoder.path(line, false);
+                }
+                LINESTRING
+            }
+            FeatureGeometry::Polygons(polygons) => {
+                for polygon in polygons {
+                    encoder.ring(polygon.exterior(), true);
+                    for interior in polygon.interiors() {
+                        encoder.ring(interior, false);
+                    }
+                }
+                POLYGON
+            }
+        };
+        if encoder.commands.is_empty() {
+            return;
+        }
+
+        let mut tags = Vec::with_capacity(properties.len() * 2);
+        for (key, value) in properties {
+            if let Some(value) = encode_value(value) {
+                tags.push(self.key(key));
+                tags.push(self.value(value));
+            }
+        }
+
+        let mut feature = Message::default();
+        if let Some(id) = id {
+            feature.uint(1, id);
+        }
+        if !tags.is_empty() {
+            feature.packed(2, &tags);
+        }
+        feature.uint(3, geometry_type);
+        feature.packed(4, &encoder.commands);
+        self.features.push(feature.0);
+    }
+
+    fn key(&mut self, key: &str) -> u32 {
+        if let Some(&index) = self.key_index.get(key) {
+            return index;
+        }
+        let index = self.keys.len() as u32;
+        self.keys.push(key.to_owned());
+        self.key_index.insert(key.to_owned(), index);
+        index
+    }
+
+    fn value(&mut self, value: Vec<u8>) -> u32 {
+        if let Some(&index) = self.value_index.get(&value) {
+            return index;
+        }
+        let index = self.values.len() as u32;
+        self.values.push(value.clone());
+        self.value_index.insert(value, index);
+        index
+    }
+
+    /// Encode a tile holding just this layer
+    pub fn into_tile(self) -> Vec<u8> {
+        let mut layer = Message::default();
+        layer.uint(15, VERSION);
+        layer.bytes(1, self.name.as_bytes());
+        for feature in &self.features {
+            layer.bytes(2, feature);
+        }
+        for key in &self.keys {
+            layer.bytes(3, key.as_bytes());
+        }
+        for value in &self.values {
+            layer.bytes(4, value);
+        }
+        layer.uint(5, EXTENT as u64);
+
+        let mut tile = Message::default();
+        tile.bytes(3, &layer.0);
+        tile.0
+    }
+}
+
+/// A property as an encoded `Value` message; nulls are dropped and nested
+/// objects or arrays are kept as JSON strings
+fn encode_value(value: &Value) -> Option<Vec<u8>> {
+    let mut message = Message::default();
+    match value {
+        Value::Null => return None,
+        Value::String(string) => message.bytes(1, string.as_bytes()),
+        Value::Bool(boolean) => message.uint(7, *boolean as u64),
+        Value::Number(number) => {
+            if let Some(uint) = number.as_u64() {
+                message.uint(5, uint);
+            } else if let Some(int) = number.as_i64() {
+                message.uint(6, ((int << 1) ^ (int >> 63)) as u64);
+            } else {
+                message.double(3, number.as_f64()?);
+            }
+        }
+        Value::Array(_) | Value::Object(_) => message.bytes(1, value.to_string().as_bytes()),
+    }
+    Some(message.0)
+}
+
+/// Geometry commands, with every coordinate relative to the last
+#[derive(Default)]
+struct GeometryEncoder {
+    commands: Vec<u32>,
+    cursor: (i32, i32),
+}
+
+impl GeometryEncoder {
+    fn push_point(&mut self, (x, y): (i32, i32)) {
+        self.commands.push(zigzag(x - self.cursor.0));
+        self.commands.push(zigzag(y - self.cursor.1));
+        self.cursor = (x, y);
+    }
+
+    fn points(&mut self, points: impl Iterator<Item = Coord>) {
+        let points: Vec<_> = points.map(round).collect();
+        if points.is_empty() {
+            return;
+        }
+
+        self.commands.push(command(MOVE_TO, points.len()));
+        for point in points {
+            self.push_point(point);
+        }
+    }
+
+    fn path(&mut self, line: &LineString, closed: bool) {
+        let mut points: Vec<_> = line.coords().copied().map(round).collect();
+        points.dedup();
+        if closed && points.len() > 1 && points.first() == points.last() {
+            points.pop();
+        }
+        if points.len() < if closed { 3 } else { 2 } {
+            return;
+        }
+
+        self.commands.push(command(MOVE_TO, 1));
+        self.push_point(points[0]);
+        self.commands.push(command(LINE_TO, points.len() - 1));
+        for &point in &points[1..] {
+            self.push_point(point);
+        }
+        if closed {
+            self.commands.push(command(CLOSE_PATH, 1));
+        }
+    }
+
+    /// Exterior rings must have positive area in tile coordinates, where y
+    /// points down, and interior rings negative
+    fn ring(&mut self, ring: &LineString, exterior: bool) {
+        let area: f64 = ring
+            .lines()
+            .map(|line| line.start.x * line.end.y - line.end.x * line.start.y)
+            .sum();
+
+        if (area > 0.0) == exterior {
+            self.path(ring, true);
+        } else {
+            let mut reversed = ring.clone();
+            reversed.0.reverse();
+            self.path(&reversed, true);
+        }
+    }
+}
+
+fn round(coord: Coord) -> (i32, i32) {
+    (coord.x.round() as i32, coord.y.round() as i32)
+}
+
+/// Just enough of a vector tile reader to check what the overlays write
+#[cfg(test)]
+pub(super) mod decode {
+    use serde_json::Value;
+
+    pub struct Layer {
+        pub name: String,
+        pub extent: u64,
+        pub keys: Vec<String>,
+        pub values: Vec<Value>,
+        pub features: Vec<Feature>,
+    }
+
+    pub struct Feature {
+        pub id: Option<u64>,
+        pub properties: Vec<(String, Value)>,
+        pub geometry_type: u64,
+        /// One entry per `MoveTo`, in absolute tile coordinates; closed
+        /// rings repeat their first point at the end
+        pub parts: Vec<Vec<(i32, i32)>>,
+    }
+
+    struct Reader<'a>(&'a [u8]);
+
+    impl<'a> Reader<'a> {
+        fn varint(&mut self) -> u64 {
+            let mut value = 0;
+            for shift in (0..64).step_by(7) {
+                let (&byte, rest) = self.0.split_first().expect("truncated varint");
+                self.0 = rest;
+                value |= u64::from(byte & 0x7f) << shift;
+                if byte < 0x80 {
+                    break;
+                }
+            }
+            value
+        }
+
+        fn take(&mut self, length: usize) -> &'a [u8] {
+            let (taken, rest) = self.0.split_at(length);
+            self.0 = rest;
+            taken
+        }
+
+        /// Every field as its number and raw varint, 64-bit or bytes value
+        fn fields(mut self) -> Vec<(u64, Field<'a>)> {
+            let mut fields = Vec::new();
+            while !self.0.is_empty() {
+                let key = self.varint();
+                let field = match key & 7 {
+                    0 => Field::Varint(self.varint()),
+                    1 => Field::Fixed64(u64::from_le_bytes(self.take(8).try_into().unwrap())),
+                    2 => {
+                        let length = self.varint() as usize;
+                        Field::Bytes(self.take(length))
+                    }
+                    wire_type => panic!("unexpected wire type {wire_type}"),
+                };
+                fields.push((key >> 3, field));
+            }
+            fields
+        }
+    }
+
+    enum Field<'a> {
+        Varint(u64),
+        Fixed64(u64),
+        Bytes(&'a [u8]),
+    }
+
+    impl<'a> Field<'a> {
+        fn varint(&self) -> u64 {
+            match self {
+                Field::Varint(value) => *value,
+                _ => panic!("expected a varint"),
+            }
+        }
+
+        fn bytes(&self) -> &'a [u8] {
+            match self {
+                Field::Bytes(bytes) => bytes,
+                _ => panic!("expected bytes"),
+            }
+        }
+
+        fn string(&self) -> String {
+            String::from_utf8(self.bytes().to_vec()).unwrap()
+        }
+
+        fn packed(&self) -> Vec<u64> {
+            let mut reader = Reader(self.bytes());
+            let mut values = Vec::new();
+            while !reader.0.is_empty() {
+                values.push(reader.varint());
+            }
+            values
+        }
+    }
+
+    fn unzigzag(value: u64) -> i64 {
+        (value >> 1) as i64 ^ -((value & 1) as i64)
+    }
+
+    /// The single layer of a tile
+    pub fn decode(tile: &[u8]) -> Layer {
+        let layers: Vec<Layer> = Reader(tile)
+            .fields()
+            .into_iter()
+            .filter(|(number, _)| *number == 3)
+            .map(|(_, layer)| decode_layer(layer.bytes()))
+            .collect();
+        assert_eq!(layers.len(), 1);
+        layers.into_iter().next().unwrap()
+    }
+
+    fn decode_layer(bytes: &[u8]) -> Layer {
+        let fields = Reader(bytes).fields();
+        let mut layer = Layer {
+            name: String::new(),
+            extent: 0,
+            keys: Vec::new(),
+            values: Vec::new(),
+            features: Vec::new(),
+        };
+        let mut features = Vec::new();
+
+        for (number, field) in fields {
+            match number {
+                1 => layer.name = field.string(),
+                2 => features.push(field.bytes()),
+                3 => layer.keys.push(field.string()),
+                4 => layer.values.push(decode_value(field.bytes())),
+                5 => layer.extent = field.varint(),
+                15 => assert_eq!(field.varint(), 2),
+                _ => panic!("unexpected layer field {number}"),
+            }
+        }
+
+        layer.features = features
+            .into_iter()
+            .map(|feature| decode_feature(feature, &layer))
+            .collect();
+        layer
+    }
+
+    fn decode_value(bytes: &[u8]) -> Value {
+        let fields = Reader(bytes).fields();
+        assert_eq!(fields.len(), 1);
+        let (number, field) = &fields[0];
+        match (number, field) {
+            (1, _) => Value::String(field.string()),
+            (3, Field::Fixed64(bits)) => f64::from_bits(*bits).into(),
+            (5, _) => field.varint().into(),
+            (6, _) => unzigzag(field.varint()).into(),
+            (7, _) => Value::Bool(field.varint() != 0),
+            _ => panic!("unexpected value field {number}"),
+        }
+    }
+
+    fn decode_feature(bytes: &[u8], layer: &Layer) -> Feature {
+        let mut feature = Feature {
+            id: None,
+            properties: Vec::new(),
+            geometry_type: 0,
+            parts: Vec::new(),
+        };
+
+        for (number, field) in Reader(bytes).fields() {
+            match number {
+                1 => feature.id = Some(field.varint()),
+                2 => {
+                    feature.properties = field
+                        .packed()
+                        .chunks(2)
+                        .map(|tag| {
+                            (
+                                layer.keys[tag[0] as usize].clone(),
+                                layer.values[tag[1] as usize].clone(),
+                            )
+                        })
+                        .collect();
+                }
+                3 => feature.geometry_type = field.varint(),
+                4 => feature.parts = decode_geometry(&field.packed()),
+                _ => panic!("unexpected feature field {number}"),
+            }
+        }
+        feature
+    }
+
+    fn decode_geometry(commands: &[u64]) -> Vec<Vec<(i32, i32)>> {
+        let mut parts: Vec<Vec<(i32, i32)>> = Vec::new();
+        let mut cursor = (0, 0);
+        let mut commands = commands.iter().copied();
+
+        while let Some(command) = commands.next() {
+            let (id, count) = (command & 7, command >> 3);
+            if id == 7 {
+                let part = parts.last_mut().unwrap();
+                part.push(part[0]);
+                continue;
+            }
+            if id == 1 {
+                parts.push(Vec::new());
+            }
+            for _ in 0..count {
+                let dx = unzigzag(commands.next().unwrap()) as i32;
+                let dy = unzigzag(commands.next().unwrap()) as i32;
+                cursor = (cursor.0 + dx, cursor.1 + dy);
+                parts.last_mut().unwrap().push(cursor);
+            }
+        }
+        parts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::{MultiLineString, MultiPoint, MultiPolygon, Polygon};
+    use serde_json::json;
+
+    use super::decode::decode;
+    use super::*;
+
+    fn properties(value: Value) -> Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    /// Twice the signed area; positive is clockwise on screen, with y down
+    fn area(ring: &[(i32, i32)]) -> i64 {
+        ring.windows(2)
+            .map(|w| w[0].0 as i64 * w[1].1 as i64 - w[1].0 as i64 * w[0].1 as i64)
+            .sum()
+    }
+
+    #[test]
+    fn test_properties_round_trip() {
+        let mut layer = LayerBuilder::new("stations");
+        let point = FeatureGeometry::Points(MultiPoint::from(vec![(10.2, 20.7)]));
+
+        layer.add_feature(
+            Some(7),
+            &properties(json!({
+                "name": "Times Sq-42 St",
+                "lines": 11,
+                "level": -2,
+                "ridership": 0.5,
+                "accessible": true,
+                "closed": null,
+                "routes": ["1", "2", "3"],
+            })),
+            &point,
+        );
+        layer.add_feature(
+            None,
+            &properties(json!({ "name": "Grand Central", "lines": 11 })),
+            &point,
+        );
+
+        let layer = decode(&layer.into_tile());
+        assert_eq!(layer.name, "stations");
+        assert_eq!(layer.extent, u64::from(EXTENT));
+
+        // Keys and values shared between features are written once
+        assert_eq!(layer.keys.len(), 6);
+        assert_eq!(layer.values.iter().filter(|v| **v == json!(11)).count(), 1);
+
+        let times_sq = &layer.features[0];
+        assert_eq!(times_sq.id, Some(7));
+        assert_eq!(times_sq.geometry_type, POINT);
+        assert_eq!(times_sq.parts, vec![vec![(10, 21)]]);
+
+        let mut found = times_sq.properties.clone();
+        found.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(
+            found,
+            vec![
+                ("accessible".to_string(), json!(true)),
+                ("level".to_string(), json!(-2)),
+                ("lines".to_string(), json!(11)),
+                ("name".to_string(), json!("Times Sq-42 St")),
+                ("ridership".to_string(), json!(0.5)),
+                // Nested values are kept as JSON text
+                ("routes".to_string(), json!(r#"["1","2","3"]"#)),
+            ]
+        );
+
+        assert_eq!(layer.features[1].id, None);
+        assert_eq!(layer.features[1].properties.len(), 2);
+    }
+
+    #[test]
+    fn test_geometry_round_trip() {
+        let mut layer = LayerBuilder::new("shapes");
+        let none = Map::new();
+
+        layer.add_feature(
+            None,
+            &none,
+            &FeatureGeometry::Lines(MultiLineString::new(vec![
+                LineString::from(vec![(0.0, 0.0), (100.0, 0.0), (100.4, 0.3), (100.0, 50.0)]),
+                // Rounds to a single point, so it's dropped
+                LineString::from(vec![(5.0, 5.0), (5.2, 5.1)]),
+            ])),
+        );
+
+        // Counter-clockwise exterior and clockwise hole on screen: both
+        // need flipping
+        let exterior =
+            LineString::from(vec![(0.0, 0.0), (0.0, 100.0), (100.0, 100.0), (100.0, 0.0)]);
+        let hole = LineString::from(vec![(25.0, 25.0), (75.0, 25.0), (75.0, 75.0), (25.0, 75.0)]);
+        layer.add_feature(
+            None,
+            &none,
+            &FeatureGeometry::Polygons(MultiPolygon::new(vec![Polygon::new(exterior, vec![hole])])),
+        );
+
+        let layer = decode(&layer.into_tile());
+
+        let line = &layer.features[0];
+        assert_eq!(line.geometry_type, LINESTRING);
+        assert_eq!(line.parts, vec![vec![(0, 0), (100, 0), (100, 50)]]);
+
+        let polygon = &layer.features[1];
+        assert_eq!(polygon.geometry_type, POLYGON);
+        assert_eq!(polygon.parts.len(), 2);
+        assert_eq!(polygon.parts[0].len(), 5);
+        assert!(area(&polygon.parts[0]) > 0);
+        assert!(area(&polygon.parts[1]) < 0);
+    }
+
+    #[test]
+    fn test_degenerate_features_are_skipped() {
+        let mut layer = LayerBuilder::new("empty");
+        layer.add_feature(
+            Some(1),
+            &Map::new(),
+            &FeatureGeometry::Lines(MultiLineString::new(vec![LineString::from(vec![
+                (1.0, 1.0),
+                (1.3, 0.8),
+            ])])),
+        );
+        layer.add_feature(
+            Some(2),
+            &Map::new(),
+            &FeatureGeometry::Points(MultiPoint::new(vec![])),
+        );
+
+        assert!(layer.is_empty());
+    }
+}
@@ -0,0 +1,520 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock, RwLock};
+
+use geo::{
+    BooleanOps, BoundingRect, Coord, Geometry, Intersects, LineString, MapCoords, MultiLineString,
+    MultiPoint, MultiPolygon, Point, Polygon, Rect, Simplify,
+};
+use geojson::{Feature, GeoJson, feature::Id};
+use rstar::primitives::{GeomWithData, Rectangle};
+use rstar::{AABB, RTree};
+use serde_json::{Map, Value};
+
+use super::mvt::{EXTENT, LayerBuilder};
+
+/// Overlays are cut up to this zoom; MapLibre overzooms past it
+pub const MAX_ZOOM: u8 = 14;
+
+/// How far geometry reaches past a tile's edges, in tile units, so lines
+/// and outlines don't show seams
+const BUFFER: f64 = 64.0;
+
+/// How far simplified geometry may stray below [`MAX_ZOOM`], in tile units;
+/// less than a unit can't be seen
+const TOLERANCE: f64 = 1.0;
+
+/// Web Mercator's latitude limit, where y reaches 0 and 1
+const MAX_LATITUDE: f64 = 85.051_128_78;
+
+#[derive(Debug, thiserror::Error)]
+pub enum OverlayError {
+    // Boxed: geojson's error carries whole features and would bloat every Result
+    #[error("invalid GeoJSON: {0}")]
+    GeoJson(Box<geojson::Error>),
+}
+
+impl From<geojson::Error> for OverlayError {
+    fn from(error: geojson::Error) -> Self {
+        Self::GeoJson(Box::new(error))
+    }
+}
+
+/// Latitude to Web Mercator y, 0 at the north edge and 1 at the south
+pub(crate) fn mercator_y(latitude: f64) -> f64 {
+    let latitude = latitude.clamp(-MAX_LATITUDE, MAX_LATITUDE).to_radians();
+    (1.0 - latitude.tan().asinh() / std::f64::consts::PI) / 2.0
+}
+
+/// Longitude and latitude to Web Mercator, the world spanning 0 to 1
+fn project(coord: Coord) -> Coord {
+    Coord {
+        x: (coord.x + 180.0) / 360.0,
+        y: mercator_y(coord.y),
+    }
+}
+
+/// GeoJSON overlays by name, replaceable while the server runs
+#[derive(Default)]
+pub struct Overlays {
+    overlays: RwLock<HashMap<String, Arc<Overlay>>>,
+    next_revision: AtomicU64,
+}
+
+impl Overlays {
+    /// Add or replace an overlay, returning its new revision
+    pub fn set(&self, name: &str, geojson: &str) -> Result<u64, OverlayError> {
+        let revision = self.next_revision.fetch_add(1, Ordering::Relaxed);
+        let overlay = Overlay::parse(geojson, revision)?;
+
+        self.overlays
+            .write()
+            .unwrap_or_else(|p| p.into_inner())
+            .insert(name.to_owned(), Arc::new(overlay));
+        Ok(revision)
+    }
+
+    pub fn remove(&self, name: &str) {
+        self.overlays
+            .write()
+            .unwrap_or_else(|p| p.into_inner())
+            .remove(name);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<Overlay>> {
+        self.overlays
+            .read()
+            .unwrap_or_else(|p| p.into_inner())
+            .get(name)
+            .cloned()
+    }
+}
+
+/// Geometry of one kind, as a vector tile feature holds it
+pub enum FeatureGeometry {
+    Points(MultiPoint),
+    Lines(MultiLineString),
+    Polygons(MultiPolygon),
+}
+
+impl FeatureGeometry {
+    fn bounding_rect(&self) -> Option<Rect> {
+        match self {
+            FeatureGeometry::Points(points) => points.bounding_rect(),
+            FeatureGeometry::Lines(lines) => lines.bounding_rect(),
+            FeatureGeometry::Polygons(polygons) => polygons.bounding_rect(),
+        }
+    }
+
+    fn map_coords(&self, f: impl Fn(Coord) -> Coord + Copy) -> Self {
+        match self {
+            FeatureGeometry::Points(points) => FeatureGeometry::Points(points.map_coords(f)),
+            FeatureGeometry::Lines(lines) => FeatureGeometry::Lines(lines.map_coords(f)),
+            FeatureGeometry::Polygons(polygons) => {
+                FeatureGeometry::Polygons(polygons.map_coords(f))
+            }
+        }
+    }
+
+    /// Douglas-Peucker, keeping points as they are
+    fn simplify(&self, epsilon: f64) -> Self {
+        match self {
+            FeatureGeometry::Points(points) => FeatureGeometry::Points(points.clone()),
+            FeatureGeometry::Lines(lines) => FeatureGeometry::Lines(lines.simplify(epsilon)),
+            FeatureGeometry::Polygons(polygons) => {
+                FeatureGeometry::Polygons(polygons.simplify(epsilon))
+            }
+        }
+    }
+
+    fn clip(self, bounds: Rect) -> Self {
+        let clip = || MultiPolygon::new(vec![bounds.to_polygon()]);
+        match self {
+            FeatureGeometry::Points(points) => FeatureGeometry::Points(
+                points
+                    .into_iter()
+                    .filter(|point| bounds.intersects(&point.0))
+                    .collect(),
+            ),
+            FeatureGeometry::Lines(lines) => FeatureGeometry::Lines(clip().clip(&lines, false)),
+            FeatureGeometry::Polygons(polygons) => {
+                FeatureGeometry::Polygons(polygons.intersection(&clip()))
+            }
+        }
+    }
+}
+
+/// Split a geometry by kind, since a vector tile feature holds only one
+#[derive(Default)]
+struct Parts {
+    points: Vec<Point>,
+    lines: Vec<LineString>,
+    polygons: Vec<Polygon>,
+}
+
+impl Parts {
+    fn add(&mut self, geometry: Geometry) {
+        match geometry {
+            Geometry::Point(point) => self.points.push(point),
+            Geometry::MultiPoint(points) => self.points.extend(points),
+            Geometry::Line(line) => self.lines.push(line.into()),
+            Geometry::LineString(line) => self.lines.push(line),
+            Geometry::MultiLineString(lines) => self.lines.extend(lines),
+            Geometry::Polygon(polygon) => self.polygons.push(polygon),
+            Geometry::MultiPolygon(polygons) => self.polygons.extend(polygons),
+            Geometry::Rect(rect) => self.polygons.push(rect.to_polygon()),
+            Geometry::Triangle(triangle) => self.polygons.push(triangle.to_polygon()),
+            Geometry::GeometryCollection(collection) => {
+                for geometry in collection {
+                    self.add(geometry);
+                }
+            }
+        }
+    }
+
+    /// One geometry per kind, any of which may be empty
+    fn into_geometries(self) -> [FeatureGeometry; 3] {
+        [
+            FeatureGeometry::Points(MultiPoint::new(self.points)),
+            FeatureGeometry::Lines(MultiLineString::new(self.lines)),
+            FeatureGeometry::Polygons(MultiPolygon::new(self.polygons)),
+        ]
+    }
+}
+
+struct OverlayFeature {
+    id: Option<u64>,
+    /// Shared by every part and level of detail of a GeoJSON feature
+    properties: Arc<Map<String, Value>>,
+
+    /// Projected to Web Mercator
+    geometry: FeatureGeometry,
+    /// Of the full detail geometry, so they cover every simplification too
+    bounds: Rect,
+}
+
+type IndexEntry = GeomWithData<Rectangle<[f64; 2]>, usize>;
+
+/// An overlay's features at one level of detail, indexed by their bounds
+struct Level {
+    features: Vec<OverlayFeature>,
+    index: RTree<IndexEntry>,
+}
+
+impl Level {
+    fn new(features: Vec<OverlayFeature>) -> Self {
+        let entries = features
+            .iter()
+            .enumerate()
+            .map(|(i, feature)| {
+                let (min, max) = (feature.bounds.min(), feature.bounds.max());
+                GeomWithData::new(Rectangle::from_corners([min.x, min.y], [max.x, max.y]), i)
+            })
+            .collect();
+
+        Self {
+            features,
+            index: RTree::bulk_load(entries),
+        }
+    }
+
+    /// Features whose bounds reach `bounds`, in the order they were added
+    fn features_in(&self, bounds: &Rect) -> impl Iterator<Item = &OverlayFeature> {
+        let (min, max) = (bounds.min(), bounds.max());
+        let envelope = AABB::from_corners([min.x, min.y], [max.x, max.y]);
+
+        let mut found: Vec<usize> = self
+            .index
+            .locate_in_envelope_intersecting(&envelope)
+            .map(|entry| entry.data)
+            .collect();
+        found.sort_unstable();
+        found.into_iter().map(|i| &self.features[i])
+    }
+
+    fn simplify(&self, epsilon: f64) -> Self {
+        Self::new(
+            self.features
+                .iter()
+                .map(|feature| OverlayFeature {
+                    id: feature.id,
+                    properties: feature.properties.clone(),
+                    geometry: feature.geometry.simplify(epsilon),
+                    bounds: feature.bounds,
+                })
+                .collect(),
+        )
+    }
+}
+
+/// A GeoJSON overlay, projected once and cut into vector tiles on request,
+/// the way geojson-vt does in the browser
+pub struct Overlay {
+    revision: u64,
+
+    /// Full detail, cut at [`MAX_ZOOM`] and past it
+    detail: Level,
+    /// Simplified to each lower zoom's resolution, the first time a tile
+    /// at that zoom is cut
+    simplified: [OnceLock<Level>; MAX_ZOOM as usize],
+}
+
+impl Overlay {
+    fn parse(geojson: &str, revision: u64) -> Result<Self, OverlayError> {
+        let features = match geojson.parse::<GeoJson>()? {
+            GeoJson::FeatureCollection(collection) => collection.features,
+            GeoJson::Feature(feature) => vec![feature],
+            GeoJson::Geometry(geometry) => vec![Feature::from(geometry)],
+        };
+
+        let mut parsed = Vec::with_capacity(features.len());
+        for feature in features {
+            let Some(geometry) = feature.geometry else {
+                continue;
+            };
+
+            // Vector tiles only hold unsigned integer ids
+            let id = match feature.id {
+                Some(Id::Number(number)) => number.as_u64(),
+                _ => None,
+            };
+            let properties = Arc::new(feature.properties.unwrap_or_default());
+
+            let mut parts = Parts::default();
+            parts.add(Geometry::try_from(geometry)?.map_coords(project));
+            for geometry in parts.into_geometries() {
+                // Empty geometries have no bounds
+                let Some(bounds) = geometry.bounding_rect() else {
+                    continue;
+                };
+                parsed.push(OverlayFeature {
+                    id,
+                    properties: properties.clone(),
+                    geometry,
+                    bounds,
+                });
+            }
+        }
+
+        Ok(Self {
+            revision,
+            detail: Level::new(parsed),
+            simplified: std::array::from_fn(|_| OnceLock::new()),
+        })
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    fn level(&self, z: u8) -> &Level {
+        let Some(simplified) = self.simplified.get(usize::from(z)) else {
+            return &self.detail;
+        };
+
+        simplified.get_or_init(|| {
+            let epsilon = TOLERANCE / f64::from(EXTENT) / 2f64.powi(z.into());
+            self.detail.simplify(epsilon)
+        })
+    }
+
+    /// Cut one tile as a single layer called `layer`, or `None` if nothing
+    /// reaches it
+    pub fn tile(&self, layer: &str, z: u8, x: u32, y: u32) -> Option<Vec<u8>> {
+        let scale = 2f64.powi(z.into());
+        let (x, y) = (f64::from(x), f64::from(y));
+        let extent = f64::from(EXTENT);
+
+        let buffer = BUFFER / extent / scale;
+        let tile_bounds = Rect::new(
+            Coord {
+                x: x / scale - buffer,
+                y: y / scale - buffer,
+            },
+            Coord {
+                x: (x + 1.0) / scale + buffer,
+                y: (y + 1.0) / scale + buffer,
+            },
+        );
+        let clip_bounds = Rect::new(
+            Coord {
+                x: -BUFFER,
+                y: -BUFFER,
+            },
+            Coord {
+                x: extent + BUFFER,
+                y: extent + BUFFER,
+            },
+        );
+        let to_tile = move |coord: Coord| Coord {
+            x: (coord.x * scale - x) * extent,
+            y: (coord.y * scale - y) * extent,
+        };
+
+        let mut builder = LayerBuilder::new(layer);
+        for feature in self.level(z).features_in(&tile_bounds) {
+            let geometry = feature.geometry.map_coords(to_tile);
+            let geometry = if contains(&tile_bounds, &feature.bounds) {
+                geometry
+            } else {
+                geometry.clip(clip_bounds)
+            };
+            builder.add_feature(feature.id, &feature.properties, &geometry);
+        }
+
+        (!builder.is_empty()).then(|| builder.into_tile())
+    }
+}
+
+fn contains(outer: &Rect, inner: &Rect) -> bool {
+    outer.min().x <= inner.min().x
+        && outer.min().y <= inner.min().y
+        && inner.max().x <= outer.max().x
+        && inner.max().y <= outer.max().y
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::super::mvt::decode::decode;
+    use super::*;
+
+    fn line(coordinates: Value) -> String {
+        json!({
+            "type": "Feature",
+            "id": 42,
+            "properties": { "name": "Northeast Corridor" },
+            "geometry": { "type": "LineString", "coordinates": coordinates }
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn test_lines_are_clipped_to_the_buffer() {
+        // Across most of the northern hemisphere at 45°N
+        let overlay = Overlay::parse(&line(json!([[-170.0, 45.0], [170.0, 45.0]])), 0).unwrap();
+
+        let layer = decode(&overlay.tile("route", 1, 0, 0).unwrap());
+        assert_eq!(layer.name, "route");
+        assert_eq!(layer.features.len(), 1);
+
+        let feature = &layer.features[0];
+        assert_eq!(feature.id, Some(42));
+        assert_eq!(feature.properties, vec![("name".to_string(), json!("Northeast Corridor"))]);
+
+        // Starts inside the tile, 10° in from its west edge, and runs on to
+        // the edge of the buffer
+        let y = ((mercator_y(45.0) * 2.0) * f64::from(EXTENT)).round() as i32;
+        let part = &feature.parts[0];
+        assert_eq!(part.first(), Some(&(228, y)));
+        assert_eq!(part.last(), Some(&(EXTENT as i32 + BUFFER as i32, y)));
+
+        // The eastern tile gets the rest, starting in its buffer
+        let layer = decode(&overlay.tile("route", 1, 1, 0).unwrap());
+        assert_eq!(layer.features[0].parts[0].first(), Some(&(-(BUFFER as i32), y)));
+
+        // Nothing reaches the southern hemisphere
+        assert!(overlay.tile("route", 1, 0, 1).is_none());
+    }
+
+    #[test]
+    fn test_polygons_are_clipped_to_the_buffer() {
+        let geojson = json!({
+            "type": "Polygon",
+            "coordinates": [[
+                [-170.0, -20.0], [170.0, -20.0], [170.0, 80.0], [-170.0, 80.0], [-170.0, -20.0]
+            ]]
+        })
+        .to_string();
+        let overlay = Overlay::parse(&geojson, 0).unwrap();
+
+        let layer = decode(&overlay.tile("area", 2, 1, 1).unwrap());
+        let ring = &layer.features[0].parts[0];
+        let (min, max) = (-(BUFFER as i32), EXTENT as i32 + BUFFER as i32);
+        assert!(ring.iter().all(|&(x, y)| (min..=max).contains(&x) && (min..=max).contains(&y)));
+        // Covers the tile at z2 x1, so clipping leaves just the buffered square
+        assert_eq!(ring.len(), 5);
+        assert!(ring.contains(&(min, min)) && ring.contains(&(max, max)));
+    }
+
+    #[test]
+    fn test_low_zooms_are_simplified() {
+        // A degree of longitude wiggling by ~10 m every ~10 m
+        let coordinates: Vec<[f64; 2]> = (0..10_000)
+            .map(|i| {
+                let wiggle = if i % 2 == 0 { 0.0 } else { 1e-4 };
+                [-74.0 + f64::from(i) * 1e-4, 40.7 + wiggle]
+            })
+            .collect();
+        let overlay = Overlay::parse(&line(json!(coordinates)), 0).unwrap();
+
+        // Far narrower than a tile unit at z0, so just the ends are left
+        let layer = decode(&overlay.tile("route", 0, 0, 0).unwrap());
+        assert_eq!(layer.features[0].parts, vec![vec![(1206, 1540), (1217, 1540)]]);
+
+        // Every wiggle within reach of a tile survives at the highest zoom
+        let (x, y) = (((-73.5 + 180.0) / 360.0 * 16384.0) as u32, 6160);
+        let layer = decode(&overlay.tile("route", MAX_ZOOM, x, y).unwrap());
+        let points: usize = layer.features[0].parts.iter().map(Vec::len).sum();
+        assert!(points > 200, "{points} points");
+    }
+
+    #[test]
+    fn test_geometry_is_split_by_kind() {
+        let geojson = json!({
+            "type": "FeatureCollection",
+            "features": [
+                {
+                    "type": "Feature",
+                    "id": "penn",
+                    "properties": { "station": true },
+                    "geometry": {
+                        "type": "GeometryCollection",
+                        "geometries": [
+                            { "type": "Point", "coordinates": [-73.9935, 40.7506] },
+                            {
+                                "type": "LineString",
+                                "coordinates": [[-73.9935, 40.7506], [-71.0552, 42.3664]]
+                            }
+                        ]
+                    }
+                },
+                { "type": "Feature", "properties": {}, "geometry": null }
+            ]
+        })
+        .to_string();
+        let overlay = Overlay::parse(&geojson, 0).unwrap();
+
+        let layer = decode(&overlay.tile("stations", 0, 0, 0).unwrap());
+        let kinds: Vec<_> = layer.features.iter().map(|f| f.geometry_type).collect();
+        assert_eq!(kinds, vec![1, 2]);
+        // String ids can't be kept in a vector tile
+        assert!(layer.features.iter().all(|f| f.id.is_none()));
+    }
+
+    #[test]
+    fn test_overlays_update_at_runtime() {
+        let overlays = Overlays::default();
+        let tile = |overlays: &Overlays| {
+            decode(&overlays.get("route").unwrap().tile("route", 0, 0, 0).unwrap())
+        };
+
+        let first = overlays.set("route", &line(json!([[-74.0, 40.7], [-71.1, 42.4]]))).unwrap();
+        assert_eq!(overlays.get("route").unwrap().revision(), first);
+        let before = tile(&overlays).features[0].parts.clone();
+
+        // Replacing it serves the new geometry under a new revision
+        let second = overlays.set("route", &line(json!([[-74.0, 40.7], [-77.0, 38.9]]))).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(overlays.get("route").unwrap().revision(), second);
+        assert_ne!(tile(&overlays).features[0].parts, before);
+
+        // Invalid GeoJSON leaves the current overlay in place
+        assert!(matches!(overlays.set("route", "{"), Err(OverlayError::GeoJson(_))));
+        assert_eq!(overlays.get("route").unwrap().revision(), second);
+
+        overlays.remove("route");
+        assert!(overlays.get("route").is_none());
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+use std::path::{Path as FilePath, PathBuf};
 use std::sync::Arc;
 
 use axum::Json;
@@ -10,21 +12,37 @@ use pmtiles::Compression;
 use tokio::sync::RwLock;
 use tower_http::cors::{Any, CorsLayer};
 
-use super::PmTilesReader;
+use super::overlay::Overlays;
+use super::{PmTilesReader, StyleAssets};
 
 type SharedReader = Arc<RwLock<PmTilesReader>>;
 
 #[derive(Clone)]
 pub struct AppState {
-    reader: SharedReader,
+    archives: Arc<HashMap<String, SharedReader>>,
+    overlays: Arc<Overlays>,
+    assets: Arc<StyleAssets>,
     port: u16,
 }
 
-pub fn create_router(reader: SharedReader, port: u16) -> Router {
-    let state = AppState { reader, port };
+pub fn create_router(
+    archives: HashMap<String, SharedReader>,
+    overlays: Arc<Overlays>,
+    assets: StyleAssets,
+    port: u16,
+) -> Router {
+    let state = AppState {
+        archives: Arc::new(archives),
+        overlays,
+        assets: Arc::new(assets),
+        port,
+    };
     Router::new()
-        .route("/tiles/{z}/{x}/{y}/tile.pbf", get(serve_tile))
-        .route("/tiles.json", get(serve_tilejson))
+        .route("/archives/{archive}/{z}/{x}/{y}", get(serve_tile))
+        .route("/archives/{archive}/tiles.json", get(serve_tilejson))
+        .route("/overlays/{overlay}/{z}/{x}/{y}", get(serve_overlay))
+        .route("/fonts/{fontstack}/{range}", get(serve_glyphs))
+        .route("/sprites/{file}", get(serve_sprite))
         .route("/health", get(health))
         .layer(CorsLayer::new().allow_origin(Any))
         .with_state(state)
@@ -32,20 +50,27 @@ pub fn create_router(reader: SharedReader, port: u16) -> Router {
 
 async fn serve_tile(
     State(state): State<AppState>,
-    Path((z, x, y)): Path<(u8, u64, u64)>,
+    Path((archive, z, x, y)): Path<(String, u8, u64, u64)>,
 ) -> Response {
-    let reader = state.reader.read().await;
+    let Some(reader) = state.archives.get(&archive) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let reader = reader.read().await;
+    let tile_header = reader.get_header();
 
     match reader.get_tile(z, x, y).await {
         Ok(Some(tile)) => {
             let mut response = (
                 StatusCode::OK,
-                [(header::CONTENT_TYPE, "application/x-protobuf")],
+                [(
+                    header::CONTENT_TYPE,
+                    tile_header.tile_type.content_type(),
+                )],
                 tile.to_vec(),
             )
                 .into_response();
 
-            if let Some(encoding) = compression_to_encoding(reader.get_header().tile_compression) {
+            if let Some(encoding) = compression_to_encoding(tile_header.tile_compression) {
                 response
                     .headers_mut()
                     .insert(header::CONTENT_ENCODING, encoding);
@@ -67,18 +92,418 @@ fn compression_to_encoding(compression: Compression) -> Option<HeaderValue> {
     }
 }
 
-async fn serve_tilejson(State(state): State<AppState>) -> Json<serde_json::Value> {
-    let reader = state.reader.read().await;
-    let header = reader.get_header();
+/// TileJSON built from the archive's header and metadata, so the map gets
+/// bounds, center, `vector_layers` and attribution without a network round
+/// trip
+async fn serve_tilejson(State(state): State<AppState>, Path(archive): Path<String>) -> Response {
+    let Some(reader) = state.archives.get(&archive) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let reader = reader.read().await;
+
+    let tiles = format!(
+        "http://localhost:{}/archives/{archive}/{{z}}/{{x}}/{{y}}",
+        state.port
+    );
+    match reader.parse_tilejson(vec![tiles]).await {
+        Ok(tilejson) => Json(tilejson).into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Cut an overlay tile on the blocking pool, since clipping a large
+/// overlay can take a while
+async fn serve_overlay(
+    State(state): State<AppState>,
+    Path((name, z, x, y)): Path<(String, u8, u32, u32)>,
+) -> Response {
+    let Some(overlay) = state.overlays.get(&name) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    match tokio::task::spawn_blocking(move || overlay.tile(&name, z, x, y)).await {
+        Ok(Some(tile)) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, "application/x-protobuf")],
+            tile,
+        )
+            .into_response(),
+        Ok(None) => StatusCode::NO_CONTENT.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// One glyph range of a font stack, taken from the first font in the stack
+/// that's available
+async fn serve_glyphs(
+    State(state): State<AppState>,
+    Path((fontstack, range)): Path<(String, String)>,
+) -> Response {
+    let Some(glyphs) = &state.assets.glyphs else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    for font in fontstack.split(',').map(str::trim) {
+        let Some(path) = asset_path(glyphs, &[font, &range]) else {
+            return StatusCode::BAD_REQUEST.into_response();
+        };
 
-    Json(serde_json::json!({
-        "tilejson": "3.0.0",
-        "tiles": [format!("http://localhost:{}/tiles/{{z}}/{{x}}/{{y}}/tile.pbf", state.port)],
-        "minzoom": header.min_zoom,
-        "maxzoom": header.max_zoom
-    }))
+        if let Ok(glyphs) = tokio::fs::read(path).await {
+            return (
+                StatusCode::OK,
+                [(header::CONTENT_TYPE, "application/x-protobuf")],
+                glyphs,
+            )
+                .into_response();
+        }
+    }
+
+    StatusCode::NOT_FOUND.into_response()
+}
+
+async fn serve_sprite(State(state): State<AppState>, Path(file): Path<String>) -> Response {
+    let Some(sprites) = &state.assets.sprites else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let Some(path) = asset_path(sprites, &[&file]) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    let content_type = match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => "application/json",
+        Some("png") => "image/png",
+        _ => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    match tokio::fs::read(path).await {
+        Ok(sprite) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, content_type)],
+            sprite,
+        )
+            .into_response(),
+        Err(_) => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Join URL segments onto an asset directory, refusing any that could
+/// escape it
+fn asset_path(dir: &FilePath, segments: &[&str]) -> Option<PathBuf> {
+    let mut path = dir.to_path_buf();
+    for segment in segments {
+        if segment.is_empty() || segment.starts_with('.') || segment.contains(['/', '\\']) {
+            return None;
+        }
+        path.push(segment);
+    }
+
+    Some(path)
 }
 
 async fn health() -> &'static str {
     "OK"
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::body::{Body, to_bytes};
+    use axum::http::Request;
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    use super::super::TileServer;
+    use super::*;
+
+    const PORT: u16 = 4321;
+
+    /// An empty directory of its own for each test
+    fn scratch(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("jet-lag-{test}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn varint(bytes: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            bytes.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        bytes.push(value as u8);
+    }
+
+    /// Degrees as the header stores them
+    fn e7(bytes: &mut Vec<u8>, degrees: f64) {
+        bytes.extend_from_slice(&((degrees * 1e7).round() as i32).to_le_bytes());
+    }
+
+    /// A PMTiles v3 archive holding one tile at z0, over New York City
+    fn write_archive(
+        test: &str,
+        tile_type: u8,
+        tile_compression: u8,
+        tile: &[u8],
+        metadata: &Value,
+    ) -> PathBuf {
+        // One entry: tile 0, run length 1, at offset 0 (stored plus one)
+        let mut root = Vec::new();
+        for value in [1, 0, 1, tile.len() as u64, 1] {
+            varint(&mut root, value);
+        }
+        let metadata = metadata.to_string().into_bytes();
+
+        let root_offset = 127;
+        let metadata_offset = root_offset + root.len() as u64;
+        let data_offset = metadata_offset + metadata.len() as u64;
+
+        let mut archive = b"PMTiles\x03".to_vec();
+        for value in [
+            root_offset,
+            root.len() as u64,
+            metadata_offset,
+            metadata.len() as u64,
+            // No leaf directories
+            data_offset,
+            0,
+            data_offset,
+            tile.len() as u64,
+            // Addressed tiles, tile entries and tile contents
+            1,
+            1,
+            1,
+        ] {
+            archive.extend_from_slice(&value.to_le_bytes());
+        }
+        // Clustered, uncompressed directories, then the tiles' compression
+        // and type, and zooms 0 to 0
+        archive.extend_from_slice(&[1, 1, tile_compression, tile_type, 0, 0]);
+        for degrees in [-74.26, 40.49, -73.70, 40.92] {
+            e7(&mut archive, degrees);
+        }
+        archive.push(0);
+        e7(&mut archive, -73.98);
+        e7(&mut archive, 40.7);
+        assert_eq!(archive.len(), 127);
+
+        archive.extend(root);
+        archive.extend(metadata);
+        archive.extend_from_slice(tile);
+
+        let path = scratch(test).join(format!("{tile_type}.pmtiles"));
+        std::fs::write(&path, archive).unwrap();
+        path
+    }
+
+    /// A vector archive of gzipped tiles and a raster one of PNGs
+    async fn router(test: &str, overlays: Arc<Overlays>) -> Router {
+        let vector = write_archive(
+            test,
+            1,
+            2,
+            b"gzipped vector tile",
+            &json!({
+                "name": "OpenMapTiles",
+                "attribution": "© OpenStreetMap contributors",
+                "vector_layers": [{ "id": "transportation", "fields": { "class": "String" } }]
+            }),
+        );
+        let raster = write_archive(test, 2, 1, b"\x89PNG raster tile", &json!({}));
+
+        let mut archives = HashMap::new();
+        for (name, path) in [("openmaptiles", vector), ("satellite", raster)] {
+            let reader = TileServer::open_archive(&path).await.unwrap();
+            archives.insert(name.to_string(), Arc::new(RwLock::new(reader)));
+        }
+        create_router(archives, overlays, StyleAssets::default(), PORT)
+    }
+
+    async fn get(router: &Router, uri: &str) -> Response {
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        router.clone().oneshot(request).await.unwrap()
+    }
+
+    async fn body(response: Response) -> Vec<u8> {
+        to_bytes(response.into_body(), usize::MAX).await.unwrap().to_vec()
+    }
+
+    fn header(response: &Response, name: header::HeaderName) -> Option<&str> {
+        response.headers().get(name).map(|v| v.to_str().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_archives_are_routed_by_name() {
+        let router = router("routing", Arc::default()).await;
+
+        let vector = get(&router, "/archives/openmaptiles/0/0/0").await;
+        assert_eq!(vector.status(), StatusCode::OK);
+        assert_eq!(
+            header(&vector, header::CONTENT_TYPE),
+            Some("application/vnd.mapbox-vector-tile")
+        );
+        assert_eq!(header(&vector, header::CONTENT_ENCODING), Some("gzip"));
+        assert_eq!(body(vector).await, b"gzipped vector tile");
+
+        let raster = get(&router, "/archives/satellite/0/0/0").await;
+        assert_eq!(raster.status(), StatusCode::OK);
+        assert_eq!(header(&raster, header::CONTENT_TYPE), Some("image/png"));
+        assert_eq!(header(&raster, header::CONTENT_ENCODING), None);
+        assert_eq!(body(raster).await, b"\x89PNG raster tile");
+
+        for uri in [
+            "/archives/terrain/0/0/0",
+            "/archives/openmaptiles/1/0/0",
+            "/archives/terrain/tiles.json",
+        ] {
+            assert_eq!(get(&router, uri).await.status(), StatusCode::NOT_FOUND, "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tilejson() {
+        let router = router("tilejson", Arc::default()).await;
+
+        let response = get(&router, "/archives/openmaptiles/tiles.json").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let tilejson: Value = serde_json::from_slice(&body(response).await).unwrap();
+
+        assert_eq!(
+            tilejson["tiles"],
+            json!(["http://localhost:4321/archives/openmaptiles/{z}/{x}/{y}"])
+        );
+        assert_eq!(tilejson["name"], "OpenMapTiles");
+        assert_eq!(tilejson["attribution"], "© OpenStreetMap contributors");
+        assert_eq!(tilejson["vector_layers"][0]["id"], "transportation");
+        assert_eq!(tilejson["vector_layers"][0]["fields"]["class"], "String");
+        assert_eq!(tilejson["minzoom"], 0);
+        assert_eq!(tilejson["maxzoom"], 0);
+
+        // TileJSON holds them in single precision
+        let close = |value: &Value, expected: &[f64]| {
+            let found: Vec<f64> = serde_json::from_value(value.clone()).unwrap();
+            found.len() == expected.len()
+                && found.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-5)
+        };
+        let bounds = &tilejson["bounds"];
+        assert!(close(bounds, &[-74.26, 40.49, -73.70, 40.92]), "{bounds}");
+        let center = &tilejson["center"];
+        assert!(close(center, &[-73.98, 40.7, 0.0]), "{center}");
+
+        // Rasters have no layers to describe
+        let response = get(&router, "/archives/satellite/tiles.json").await;
+        let tilejson: Value = serde_json::from_slice(&body(response).await).unwrap();
+        assert!(tilejson.get("vector_layers").is_none_or(Value::is_null));
+    }
+
+    #[tokio::test]
+    async fn test_overlays() {
+        let overlays = Arc::new(Overlays::default());
+        let router = router("overlays", overlays.clone()).await;
+        let point = |lon: f64, lat: f64| {
+            json!({ "type": "Point", "coordinates": [lon, lat] }).to_string()
+        };
+
+        overlays.set("stations", &point(-73.99, 40.75)).unwrap();
+        let response = get(&router, "/overlays/stations/1/0/0").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header(&response, header::CONTENT_TYPE), Some("application/x-protobuf"));
+        let before = body(response).await;
+
+        // Nothing in the southern hemisphere
+        let response = get(&router, "/overlays/stations/1/0/1").await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        // Updates are served straight away
+        overlays.set("stations", &point(-71.06, 42.36)).unwrap();
+        assert_ne!(body(get(&router, "/overlays/stations/1/0/0").await).await, before);
+
+        overlays.remove("stations");
+        let response = get(&router, "/overlays/stations/1/0/0").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    /// Serves just style assets, from directories under a scratch one
+    fn asset_router(test: &str, glyphs: bool, sprites: bool) -> Router {
+        let dir = scratch(test);
+        let fonts = dir.join("fonts");
+        std::fs::create_dir_all(fonts.join("Noto Sans Regular")).unwrap();
+        std::fs::write(fonts.join("Noto Sans Regular/0-255.pbf"), "noto glyphs").unwrap();
+
+        let sprites_dir = dir.join("sprites");
+        std::fs::create_dir_all(&sprites_dir).unwrap();
+        std::fs::write(sprites_dir.join("sprite.json"), "{}").unwrap();
+        std::fs::write(sprites_dir.join("sprite@2x.png"), b"\x89PNG sprite").unwrap();
+        std::fs::write(sprites_dir.join("sprite.txt"), "notes").unwrap();
+
+        let assets = StyleAssets {
+            glyphs: glyphs.then_some(fonts),
+            sprites: sprites.then_some(sprites_dir),
+        };
+        create_router(HashMap::new(), Arc::default(), assets, PORT)
+    }
+
+    #[tokio::test]
+    async fn test_glyphs() {
+        let router = asset_router("glyphs", true, false);
+
+        // The first font in the stack that's available
+        let stack = "Open%20Sans%20Bold,Noto%20Sans%20Regular";
+        let response = get(&router, &format!("/fonts/{stack}/0-255.pbf")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header(&response, header::CONTENT_TYPE), Some("application/x-protobuf"));
+        assert_eq!(body(response).await, b"noto glyphs");
+
+        for (uri, status) in [
+            ("/fonts/Open%20Sans%20Bold/0-255.pbf", StatusCode::NOT_FOUND),
+            ("/fonts/Noto%20Sans%20Regular/256-511.pbf", StatusCode::NOT_FOUND),
+            ("/fonts/../0-255.pbf", StatusCode::BAD_REQUEST),
+            ("/fonts/Noto%20Sans%20Regular,..%2Fsprites/sprite.json", StatusCode::BAD_REQUEST),
+            ("/fonts/Noto%20Sans%20Regular/.0-255.pbf", StatusCode::BAD_REQUEST),
+        ] {
+            assert_eq!(get(&router, uri).await.status(), status, "{uri}");
+        }
+
+        // Sprites weren't configured
+        let response = get(&router, "/sprites/sprite.json").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_sprites() {
+        let router = asset_router("sprites", false, true);
+
+        let response = get(&router, "/sprites/sprite.json").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header(&response, header::CONTENT_TYPE), Some("application/json"));
+        assert_eq!(body(response).await, b"{}");
+
+        let response = get(&router, "/sprites/sprite@2x.png").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header(&response, header::CONTENT_TYPE), Some("image/png"));
+        assert_eq!(body(response).await, b"\x89PNG sprite");
+
+        for (uri, status) in [
+            // Only the sprite sheet and its index are served
+            ("/sprites/sprite.txt", StatusCode::NOT_FOUND),
+            ("/sprites/sprite@3x.png", StatusCode::NOT_FOUND),
+            ("/sprites/.sprite.json", StatusCode::BAD_REQUEST),
+            ("/sprites/..%2Ffonts%2Fsprite.json", StatusCode::BAD_REQUEST),
+        ] {
+            assert_eq!(get(&router, uri).await.status(), status, "{uri}");
+        }
+
+        // Glyphs weren't configured
+        let response = get(&router, "/fonts/Noto%20Sans%20Regular/0-255.pbf").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_asset_paths_stay_in_their_directory() {
+        let dir = FilePath::new("/assets/fonts");
+        assert_eq!(
+            asset_path(dir, &["Noto Sans Regular", "0-255.pbf"]),
+            Some(PathBuf::from("/assets/fonts/Noto Sans Regular/0-255.pbf"))
+        );
+        for segment in ["", "..", ".hidden", "a/b", "a\\b"] {
+            assert_eq!(asset_path(dir, &[segment]), None, "{segment}");
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::f64::consts::PI;
+
+use actix::Addr;
+use geo::Point;
+use jet_lag_core::{
+    map::tile::Tile,
+    shape::{builtin::circle::Circle, types::Centimeters},
+};
+use jet_lag_mobile::render::{
+    context::HeadlessOptions,
+    headless::{RenderTileToBytes, TileImage},
+    style::Style,
+    thread::{
+        RenderThread, ShapeId, StartShapeCompilation, TILE_TEXTURE_SIZE, TileOutput,
+        start_headless_render_thread,
+    },
+};
+use palette::Srgba;
+
+/// A zoom 12 tile over Midtown Manhattan, about 7.4 km across
+fn midtown() -> Tile {
+    let (zoom, tile_x, tile_y) = (12, 1206, 1539);
+    let size = (1u64 << zoom) as f64;
+
+    Tile {
+        zoom,
+        tile_x,
+        tile_y,
+        x0: tile_x as f64 / size,
+        y0: tile_y as f64 / size,
+        x1: (tile_x + 1) as f64 / size,
+        y1: (tile_y + 1) as f64 / size,
+    }
+}
+
+fn center(tile: &Tile) -> Point {
+    let x = (tile.x0 + tile.x1) / 2.0;
+    let y = (tile.y0 + tile.y1) / 2.0;
+    let latitude = (PI - 2.0 * PI * y).sinh().atan();
+
+    Point::new(x * 360.0 - 180.0, latitude.to_degrees())
+}
+
+fn start() -> Addr<RenderThread> {
+    start_headless_render_thread(HeadlessOptions::from_env()).expect("no headless adapter")
+}
+
+async fn add_circle(thread: &Addr<RenderThread>, radius_cm: i32, style: Style) -> ShapeId {
+    let circle = Circle::new(center(&midtown()), Centimeters(radius_cm));
+    thread
+        .send(StartShapeCompilation {
+            shape: Box::new(circle),
+            style,
+        })
+        .await
+        .unwrap()
+}
+
+async fn render(thread: &Addr<RenderThread>, output: TileOutput) -> TileImage {
+    thread
+        .send(RenderTileToBytes::new(midtown(), None, output))
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+fn pixel(image: &TileImage, x: u32, y: u32) -> [u8; 4] {
+    let i = ((y * image.width + x) * 4) as usize;
+    image.pixels[i..i + 4].try_into().unwrap()
+}
+
+fn distance_cm(image: &TileImage, x: u32, y: u32) -> i32 {
+    i32::from_le_bytes(pixel(image, x, y))
+}
+
+const MIDDLE: u32 = TILE_TEXTURE_SIZE / 2;
+
+#[test]
+fn test_circle_tile() {
+    let thread = start();
+    let red = Srgba::new(1.0, 0.0, 0.0, 1.0);
+
+    pollster::block_on(async {
+        add_circle(&thread, 100_000, Style::solid_color(red)).await;
+
+        let distances = render(&thread, TileOutput::Distance).await;
+        assert_eq!((distances.width, distances.height), (256, 256));
+        assert_eq!(distances.pixels.len(), 256 * 256 * 4);
+
+        // A kilometre inside at the centre, give or take a pixel
+        let inside = distance_cm(&distances, MIDDLE, MIDDLE);
+        assert!((inside + 100_000).abs() < 5_000, "{inside}");
+
+        // The corners are over 4 km from the centre
+        for (x, y) in [(0, 0), (255, 0), (0, 255), (255, 255)] {
+            let outside = distance_cm(&distances, x, y);
+            assert!(outside > 300_000, "{outside} at {x},{y}");
+        }
+
+        let styled = render(&thread, TileOutput::Styled).await;
+        assert_eq!((styled.width, styled.height), (256, 256));
+        assert_eq!(pixel(&styled, MIDDLE, MIDDLE), [255, 0, 0, 255]);
+        assert_eq!(pixel(&styled, 0, 0), [0, 0, 0, 0]);
+    });
+}
@@ -0,0 +1,103 @@
+//! Resource bundle hosting.
+//!
+//! Serves the paths `ResourceFetcher` in jet-lag-core requests:
+//! `/bundles/{id}` returns the bundle manifest stored at
+//! `{data_dir}/bundles/{id}.json`, and `/resources/{id}` streams the file at
+//! `{data_dir}/resources/{id}`.
+
+use std::path::{Path, PathBuf};
+
+use axum::{
+    body::Body,
+    extract::{Path as UrlPath, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use tokio_util::io::ReaderStream;
+
+use crate::AppState;
+
+pub struct BundleStore {
+    root: PathBuf,
+}
+
+impl BundleStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Path of a bundle manifest, if `id` is a valid identifier
+    pub fn bundle_path(&self, id: &str) -> Option<PathBuf> {
+        valid_id(id).then(|| self.root.join("bundles").join(format!("{}.json", id)))
+    }
+
+    /// Path of a resource file, if `id` is a valid identifier
+    pub fn resource_path(&self, id: &str) -> Option<PathBuf> {
+        valid_id(id).then(|| self.root.join("resources").join(id))
+    }
+}
+
+/// Identifiers are used as file names, so they can't contain separators or
+/// start with a dot
+fn valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && !id.starts_with('.')
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+fn io_error_response(error: std::io::Error) -> Response {
+    if error.kind() == std::io::ErrorKind::NotFound {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    tracing::error!(%error, "failed to read bundle data");
+    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+}
+
+pub(crate) async fn get_bundle(
+    State(state): State<AppState>,
+    UrlPath(id): UrlPath<String>,
+) -> Response {
+    let Some(path) = state.bundles.bundle_path(&id) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    match tokio::fs::read(&path).await {
+        Ok(manifest) => ([(header::CONTENT_TYPE, "application/json")], manifest).into_response(),
+        Err(error) => io_error_response(error),
+    }
+}
+
+pub(crate) async fn get_resource(
+    State(state): State<AppState>,
+    UrlPath(id): UrlPath<String>,
+) -> Response {
+    let Some(path) = state.bundles.resource_path(&id) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+
+    let file = match tokio::fs::File::open(&path).await {
+        Ok(file) => file,
+        Err(error) => return io_error_response(error),
+    };
+
+    let length = match file.metadata().await {
+        Ok(metadata) => metadata.len(),
+        Err(error) => return io_error_response(error),
+    };
+
+    (
+        [
+            (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+            (header::CONTENT_LENGTH, length.to_string()),
+        ],
+        Body::from_stream(ReaderStream::new(file)),
+    )
+        .into_response()
+}
@@ -0,0 +1,36 @@
+use std::{
+    net::{Ipv4Addr, SocketAddr},
+    path::PathBuf,
+};
+
+/// Where and what the server serves
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub listen: SocketAddr,
+
+    /// Holds `bundles/{id}.json` manifests and `resources/{id}` files
+    pub data_dir: PathBuf,
+
+    /// SQLite database for lobbies, events and documents; in memory if `None`
+    pub database: Option<PathBuf>,
+}
+
+impl ServerConfig {
+    pub fn new(data_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            listen: SocketAddr::from((Ipv4Addr::LOCALHOST, 8080)),
+            data_dir: data_dir.into(),
+            database: None,
+        }
+    }
+
+    pub fn with_listen(mut self, listen: SocketAddr) -> Self {
+        self.listen = listen;
+        self
+    }
+
+    pub fn with_database(mut self, database: impl Into<PathBuf>) -> Self {
+        self.database = Some(database.into());
+        self
+    }
+}
@@ -0,0 +1,45 @@
+use api_types::{ErrorCode, LobbyId};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ServerError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Storage error: {0}")]
+    Storage(#[from] rusqlite::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Server is shutting down")]
+    ShuttingDown,
+
+    #[error("No lobby with join code {0}")]
+    UnknownJoinCode(String),
+
+    #[error("No lobby {0}")]
+    UnknownLobby(LobbyId),
+
+    #[error("Invalid session token")]
+    InvalidToken,
+
+    #[error("Invalid document update: {0}")]
+    InvalidUpdate(String),
+}
+
+impl ServerError {
+    /// The code reported to clients
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            ServerError::Io(_)
+            | ServerError::Storage(_)
+            | ServerError::Json(_)
+            | ServerError::ShuttingDown => ErrorCode::Internal,
+            ServerError::UnknownJoinCode(_) | ServerError::UnknownLobby(_) => {
+                ErrorCode::LobbyNotFound
+            }
+            ServerError::InvalidToken => ErrorCode::InvalidToken,
+            ServerError::InvalidUpdate(_) => ErrorCode::InvalidUpdate,
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! # jet-lag-server
+//!
+//! Hosts games for the mobile client:
+//!
+//! - **Lobbies**: created over the game WebSocket, joined with a short code,
+//!   speaking the protocol defined in `api-types`
+//! - **Relay**: questions, answers and yrs document updates go to every
+//!   player in the lobby
+//! - **Storage**: lobbies, events and documents persist in SQLite
+//! - **Bundles**: resource bundles at the paths `ResourceFetcher` expects
+//!
+//! The server binds wherever it's told, so tests can run it on localhost
+//! with [`Server::spawn`].
+
+use std::{io, net::SocketAddr, sync::Arc};
+
+use axum::{Router, routing::get};
+use tokio::{net::TcpListener, task::JoinHandle};
+
+pub mod bundles;
+pub mod config;
+pub mod error;
+pub mod lobby;
+pub mod storage;
+mod ws;
+
+pub use config::ServerConfig;
+pub use error::ServerError;
+
+use crate::{bundles::BundleStore, lobby::LobbyManager, storage::Storage};
+
+/// Shared state of every request handler
+#[derive(Clone)]
+pub(crate) struct AppState {
+    pub lobbies: Arc<LobbyManager>,
+    pub bundles: Arc<BundleStore>,
+}
+
+pub struct Server {
+    config: ServerConfig,
+    state: AppState,
+}
+
+impl Server {
+    pub fn new(config: ServerConfig) -> Result<Self, ServerError> {
+        let storage = match &config.database {
+            Some(path) => Storage::open(path)?,
+            None => Storage::in_memory()?,
+        };
+
+        let state = AppState {
+            lobbies: Arc::new(LobbyManager::new(storage)),
+            bundles: Arc::new(BundleStore::new(&config.data_dir)),
+        };
+
+        Ok(Self { config, state })
+    }
+
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/health", get(|| async { "ok" }))
+            .route("/bundles/{id}", get(bundles::get_bundle))
+            .route("/resources/{id}", get(bundles::get_resource))
+            .route("/ws", get(ws::upgrade))
+            .with_state(self.state.clone())
+    }
+
+    /// Serve on the configured address until the process ends
+    pub async fn run(self) -> io::Result<()> {
+        let listener = TcpListener::bind(self.config.listen).await?;
+        tracing::info!(addr = %listener.local_addr()?, "listening");
+
+        axum::serve(listener, self.router()).await
+    }
+
+    /// Bind the configured address (port 0 picks a free one) and serve in
+    /// the background, returning the bound address
+    pub async fn spawn(self) -> io::Result<(SocketAddr, JoinHandle<io::Result<()>>)> {
+        let listener = TcpListener::bind(self.config.listen).await?;
+        let addr = listener.local_addr()?;
+        let router = self.router();
+
+        let handle = tokio::spawn(async move { axum::serve(listener, router).await });
+        Ok((addr, handle))
+    }
+}
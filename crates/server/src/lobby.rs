@@ -0,0 +1,319 @@
+//! Lobbies, join codes and player sessions.
+//!
+//! Each active lobby keeps its shared yrs document in memory and fans
+//! messages out to connected players over a broadcast channel. Everything is
+//! written through to [`Storage`] first, so a lobby dropped from memory once
+//! its last player disconnects (or lost in a restart) is rebuilt from
+//! storage on the next join.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use api_types::{EventBody, GameEvent, LobbyId, PlayerId, PlayerInfo, ServerMessage, SessionToken};
+use rand::Rng;
+use tokio::sync::broadcast;
+use yrs::{Doc, ReadTxn, StateVector, Transact, Update, updates::decoder::Decode};
+
+use crate::{
+    error::ServerError,
+    storage::{LobbyRecord, Storage},
+};
+
+/// Join codes avoid characters that are easy to misread (0/O, 1/I)
+const JOIN_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+const JOIN_CODE_LENGTH: usize = 6;
+
+/// Messages a slow player can fall behind by before being disconnected
+const BROADCAST_CAPACITY: usize = 256;
+
+/// Something sent to every player in a lobby
+#[derive(Debug, Clone)]
+pub struct Broadcast {
+    /// Player who shouldn't receive it (usually the one who caused it)
+    pub except: Option<PlayerId>,
+    pub payload: Outbound,
+}
+
+#[derive(Debug, Clone)]
+pub enum Outbound {
+    Message(ServerMessage),
+    /// A yrs v1 update to the lobby's document
+    DocumentUpdate(Arc<[u8]>),
+}
+
+pub struct Lobby {
+    pub id: LobbyId,
+    pub join_code: String,
+    document: Mutex<Doc>,
+
+    /// Sequence number of the last event; held while an event is stored and
+    /// sent so every player sees events in order
+    last_seq: Mutex<u64>,
+
+    sender: broadcast::Sender<Broadcast>,
+}
+
+impl Lobby {
+    pub fn subscribe(&self) -> broadcast::Receiver<Broadcast> {
+        self.sender.subscribe()
+    }
+
+    /// The full document state as a single update
+    pub fn document_state(&self) -> Vec<u8> {
+        let document = self.document.lock().unwrap_or_else(|p| p.into_inner());
+        document
+            .transact()
+            .encode_state_as_update_v1(&StateVector::default())
+    }
+
+    fn broadcast(&self, except: Option<&PlayerId>, payload: Outbound) {
+        // No receivers just means nobody is connected
+        let _ = self.sender.send(Broadcast {
+            except: except.cloned(),
+            payload,
+        });
+    }
+}
+
+/// A player's membership of a lobby
+#[derive(Clone)]
+pub struct Session {
+    pub lobby: Arc<Lobby>,
+    pub player: PlayerInfo,
+    pub token: SessionToken,
+}
+
+pub struct LobbyManager {
+    storage: Storage,
+    lobbies: Mutex<HashMap<LobbyId, Arc<Lobby>>>,
+}
+
+impl LobbyManager {
+    pub fn new(storage: Storage) -> Self {
+        Self {
+            storage,
+            lobbies: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn storage(&self) -> &Storage {
+        &self.storage
+    }
+
+    /// Open a new lobby with a fresh join code and add its first player
+    pub fn create(&self, player_name: &str) -> Result<Session, ServerError> {
+        let record = loop {
+            let record = LobbyRecord {
+                id: LobbyId::new(random_id()),
+                join_code: random_join_code(),
+            };
+
+            if self.storage.create_lobby(&record, now())? {
+                break record;
+            }
+        };
+
+        tracing::info!(lobby = %record.id, join_code = %record.join_code, "lobby created");
+
+        let lobby = self.load(record)?;
+        self.add_player(lobby, player_name)
+    }
+
+    pub fn join(&self, join_code: &str, player_name: &str) -> Result<Session, ServerError> {
+        let join_code = join_code.trim().to_ascii_uppercase();
+        let record = self
+            .storage
+            .lobby_by_join_code(&join_code)?
+            .ok_or(ServerError::UnknownJoinCode(join_code))?;
+
+        let lobby = self.load(record)?;
+        self.add_player(lobby, player_name)
+    }
+
+    pub fn rejoin(&self, lobby_id: &LobbyId, token: &SessionToken) -> Result<Session, ServerError> {
+        let record = self
+            .storage
+            .lobby(lobby_id)?
+            .ok_or_else(|| ServerError::UnknownLobby(lobby_id.clone()))?;
+
+        let player = self
+            .storage
+            .player_by_token(lobby_id, token)?
+            .ok_or(ServerError::InvalidToken)?;
+
+        Ok(Session {
+            lobby: self.load(record)?,
+            player,
+            token: token.clone(),
+        })
+    }
+
+    /// Players and events so far, for a player (re)joining
+    pub fn history(&self, lobby: &Lobby) -> Result<(Vec<PlayerInfo>, Vec<GameEvent>), ServerError> {
+        Ok((
+            self.storage.players(&lobby.id)?,
+            self.storage.events(&lobby.id)?,
+        ))
+    }
+
+    /// Store a question or answer and send it to every player, including
+    /// the sender
+    pub fn post(&self, session: &Session, body: EventBody) -> Result<GameEvent, ServerError> {
+        let lobby = &session.lobby;
+        let mut last_seq = lobby.last_seq.lock().unwrap_or_else(|p| p.into_inner());
+
+        let event = GameEvent {
+            seq: *last_seq + 1,
+            player_id: session.player.id.clone(),
+            body,
+        };
+
+        self.storage.append_event(&lobby.id, &event)?;
+        *last_seq = event.seq;
+
+        lobby.broadcast(
+            None,
+            Outbound::Message(ServerMessage::Event {
+                event: event.clone(),
+            }),
+        );
+
+        Ok(event)
+    }
+
+    /// Merge a player's document update and relay it to everyone else
+    pub fn apply_update(&self, session: &Session, update: &[u8]) -> Result<(), ServerError> {
+        let lobby = &session.lobby;
+
+        // Held until the state is stored, so a slower save can't overwrite a
+        // newer one
+        let document = lobby.document.lock().unwrap_or_else(|p| p.into_inner());
+        let state = {
+            let decoded =
+                Update::decode_v1(update).map_err(|e| ServerError::InvalidUpdate(e.to_string()))?;
+
+            let mut txn = document.transact_mut();
+            txn.apply_update(decoded)
+                .map_err(|e| ServerError::InvalidUpdate(e.to_string()))?;
+
+            txn.encode_state_as_update_v1(&StateVector::default())
+        };
+
+        self.storage.save_document(&lobby.id, &state)?;
+        drop(document);
+
+        lobby.broadcast(
+            Some(&session.player.id),
+            Outbound::DocumentUpdate(update.into()),
+        );
+
+        Ok(())
+    }
+
+    /// Tell the other players someone connected or disconnected
+    pub fn announce(&self, session: &Session, connected: bool) {
+        let message = if connected {
+            ServerMessage::PlayerJoined {
+                player: session.player.clone(),
+            }
+        } else {
+            ServerMessage::PlayerLeft {
+                player_id: session.player.id.clone(),
+            }
+        };
+
+        session
+            .lobby
+            .broadcast(Some(&session.player.id), Outbound::Message(message));
+    }
+
+    /// Drop a disconnected player's session, and its lobby from memory if no
+    /// other session holds it
+    pub fn release(&self, session: Session) {
+        let Session { lobby, .. } = session;
+        let mut lobbies = self.lobbies.lock().unwrap_or_else(|p| p.into_inner());
+
+        // Ours and the map's; lobbies are only handed out under this lock
+        if Arc::strong_count(&lobby) == 2 {
+            lobbies.remove(&lobby.id);
+            tracing::debug!(lobby = %lobby.id, "lobby unloaded");
+        }
+    }
+
+    fn add_player(&self, lobby: Arc<Lobby>, player_name: &str) -> Result<Session, ServerError> {
+        let player = PlayerInfo {
+            id: PlayerId::new(random_id()),
+            name: player_name.trim().to_string(),
+        };
+        let token = SessionToken::new(random_id());
+
+        self.storage.add_player(&lobby.id, &player, &token, now())?;
+
+        Ok(Session {
+            lobby,
+            player,
+            token,
+        })
+    }
+
+    /// The in-memory lobby for `record`, restoring it from storage if needed
+    fn load(&self, record: LobbyRecord) -> Result<Arc<Lobby>, ServerError> {
+        let mut lobbies = self.lobbies.lock().unwrap_or_else(|p| p.into_inner());
+
+        // Sessions dropped without being released (e.g. a socket that closed
+        // mid-handshake) leave lobbies nobody holds
+        lobbies.retain(|_, lobby| Arc::strong_count(lobby) > 1);
+
+        if let Some(lobby) = lobbies.get(&record.id) {
+            return Ok(lobby.clone());
+        }
+
+        let document = Doc::new();
+        if let Some(state) = self.storage.load_document(&record.id)? {
+            let update =
+                Update::decode_v1(&state).map_err(|e| ServerError::InvalidUpdate(e.to_string()))?;
+            document
+                .transact_mut()
+                .apply_update(update)
+                .map_err(|e| ServerError::InvalidUpdate(e.to_string()))?;
+        }
+
+        let last_seq = self
+            .storage
+            .events(&record.id)?
+            .last()
+            .map_or(0, |event| event.seq);
+
+        let (sender, _) = broadcast::channel(BROADCAST_CAPACITY);
+        let lobby = Arc::new(Lobby {
+            id: record.id.clone(),
+            join_code: record.join_code,
+            document: Mutex::new(document),
+            last_seq: Mutex::new(last_seq),
+            sender,
+        });
+
+        lobbies.insert(record.id, lobby.clone());
+        Ok(lobby)
+    }
+}
+
+fn random_id() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+fn random_join_code() -> String {
+    let mut rng = rand::rng();
+    (0..JOIN_CODE_LENGTH)
+        .map(|_| JOIN_CODE_ALPHABET[rng.random_range(0..JOIN_CODE_ALPHABET.len())] as char)
+        .collect()
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64)
+}
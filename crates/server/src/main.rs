@@ -1,3 +1,73 @@
-fn main() {
-    println!("Hello, world!");
+use std::{net::SocketAddr, path::PathBuf, process::ExitCode};
+
+use jet_lag_server::{Server, ServerConfig};
+
+const USAGE: &str =
+    "usage: jet-lag-server [--listen <addr>] [--data-dir <dir>] [--database <file>]";
+
+fn parse_args() -> Result<ServerConfig, String> {
+    let mut listen: Option<SocketAddr> = None;
+    let mut data_dir = PathBuf::from("data");
+    let mut database: Option<PathBuf> = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+
+        match arg.as_str() {
+            "--listen" => {
+                let value = value()?;
+                listen = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid --listen: {}", value))?,
+                );
+            }
+            "--data-dir" => data_dir = PathBuf::from(value()?),
+            "--database" => database = Some(PathBuf::from(value()?)),
+            _ => return Err(USAGE.into()),
+        }
+    }
+
+    let mut config = ServerConfig::new(&data_dir)
+        .with_database(database.unwrap_or_else(|| data_dir.join("games.sqlite3")));
+    if let Some(listen) = listen {
+        config = config.with_listen(listen);
+    }
+
+    Ok(config)
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    tracing_subscriber::fmt::init();
+
+    let config = match parse_args() {
+        Ok(config) => config,
+        Err(message) => {
+            tracing::error!("{}", message);
+            return ExitCode::from(64);
+        }
+    };
+
+    if let Err(error) = std::fs::create_dir_all(&config.data_dir) {
+        tracing::error!("failed to create {}: {}", config.data_dir.display(), error);
+        return ExitCode::FAILURE;
+    }
+
+    let server = match Server::new(config) {
+        Ok(server) => server,
+        Err(error) => {
+            tracing::error!("failed to start: {}", error);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match server.run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            tracing::error!("server error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
 }
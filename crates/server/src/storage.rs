@@ -0,0 +1,252 @@
+//! Persistent game storage.
+//!
+//! Lobbies, their players, every question/answer event and the latest state
+//! of each lobby's shared document live in SQLite, so games survive a server
+//! restart and players can rejoin with their session token.
+
+use std::{path::Path, sync::Mutex};
+
+use api_types::{EventBody, GameEvent, LobbyId, PlayerId, PlayerInfo, SessionToken};
+use rusqlite::{Connection, OptionalExtension, Row, params};
+
+use crate::error::ServerError;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS lobbies (
+    id TEXT PRIMARY KEY,
+    join_code TEXT NOT NULL UNIQUE,
+    created_at INTEGER NOT NULL,
+    document BLOB
+);
+
+CREATE TABLE IF NOT EXISTS players (
+    id TEXT PRIMARY KEY,
+    lobby_id TEXT NOT NULL REFERENCES lobbies(id),
+    name TEXT NOT NULL,
+    token TEXT NOT NULL,
+    joined_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS events (
+    lobby_id TEXT NOT NULL REFERENCES lobbies(id),
+    seq INTEGER NOT NULL,
+    player_id TEXT NOT NULL REFERENCES players(id),
+    body TEXT NOT NULL,
+    PRIMARY KEY (lobby_id, seq)
+);
+";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LobbyRecord {
+    pub id: LobbyId,
+    pub join_code: String,
+}
+
+fn lobby_from_row(row: &Row<'_>) -> rusqlite::Result<LobbyRecord> {
+    Ok(LobbyRecord {
+        id: LobbyId::new(row.get::<_, String>(0)?),
+        join_code: row.get(1)?,
+    })
+}
+
+fn player_from_row(row: &Row<'_>) -> rusqlite::Result<PlayerInfo> {
+    Ok(PlayerInfo {
+        id: PlayerId::new(row.get::<_, String>(0)?),
+        name: row.get(1)?,
+    })
+}
+
+pub struct Storage {
+    conn: Mutex<Connection>,
+}
+
+impl Storage {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, ServerError> {
+        Self::init(Connection::open(path)?)
+    }
+
+    pub fn in_memory() -> Result<Self, ServerError> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self, ServerError> {
+        // journal_mode reports the mode it ended up in ("memory" in memory)
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+        conn.pragma_update(None, "foreign_keys", "ON")?;
+        conn.execute_batch(SCHEMA)?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn conn(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.conn
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // ---- Lobbies ----
+
+    /// Insert a lobby; returns `false` if the join code is already taken
+    pub fn create_lobby(&self, lobby: &LobbyRecord, created_at: i64) -> Result<bool, ServerError> {
+        let inserted = self.conn().execute(
+            "INSERT OR IGNORE INTO lobbies (id, join_code, created_at) VALUES (?1, ?2, ?3)",
+            params![lobby.id.as_str(), lobby.join_code, created_at],
+        )?;
+
+        Ok(inserted == 1)
+    }
+
+    pub fn lobby(&self, id: &LobbyId) -> Result<Option<LobbyRecord>, ServerError> {
+        let lobby = self
+            .conn()
+            .query_row(
+                "SELECT id, join_code FROM lobbies WHERE id = ?1",
+                params![id.as_str()],
+                lobby_from_row,
+            )
+            .optional()?;
+
+        Ok(lobby)
+    }
+
+    pub fn lobby_by_join_code(&self, join_code: &str) -> Result<Option<LobbyRecord>, ServerError> {
+        let lobby = self
+            .conn()
+            .query_row(
+                "SELECT id, join_code FROM lobbies WHERE join_code = ?1",
+                params![join_code],
+                lobby_from_row,
+            )
+            .optional()?;
+
+        Ok(lobby)
+    }
+
+    // ---- Players ----
+
+    pub fn add_player(
+        &self,
+        lobby_id: &LobbyId,
+        player: &PlayerInfo,
+        token: &SessionToken,
+        joined_at: i64,
+    ) -> Result<(), ServerError> {
+        self.conn().execute(
+            "INSERT INTO players (id, lobby_id, name, token, joined_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                player.id.as_str(),
+                lobby_id.as_str(),
+                player.name,
+                token.as_str(),
+                joined_at
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Players of a lobby, in the order they joined
+    pub fn players(&self, lobby_id: &LobbyId) -> Result<Vec<PlayerInfo>, ServerError> {
+        let conn = self.conn();
+        let mut statement = conn.prepare(
+            "SELECT id, name FROM players WHERE lobby_id = ?1 ORDER BY joined_at, rowid",
+        )?;
+
+        let players = statement
+            .query_map(params![lobby_id.as_str()], player_from_row)?
+            .collect::<Result<_, _>>()?;
+
+        Ok(players)
+    }
+
+    /// The player holding `token` in a lobby
+    pub fn player_by_token(
+        &self,
+        lobby_id: &LobbyId,
+        token: &SessionToken,
+    ) -> Result<Option<PlayerInfo>, ServerError> {
+        let player = self
+            .conn()
+            .query_row(
+                "SELECT id, name FROM players WHERE lobby_id = ?1 AND token = ?2",
+                params![lobby_id.as_str(), token.as_str()],
+                player_from_row,
+            )
+            .optional()?;
+
+        Ok(player)
+    }
+
+    // ---- Events ----
+
+    pub fn append_event(&self, lobby_id: &LobbyId, event: &GameEvent) -> Result<(), ServerError> {
+        let body = serde_json::to_string(&event.body)?;
+
+        self.conn().execute(
+            "INSERT INTO events (lobby_id, seq, player_id, body) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                lobby_id.as_str(),
+                event.seq as i64,
+                event.player_id.as_str(),
+                body
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Every event of a lobby, in order
+    pub fn events(&self, lobby_id: &LobbyId) -> Result<Vec<GameEvent>, ServerError> {
+        let conn = self.conn();
+        let mut statement = conn
+            .prepare("SELECT seq, player_id, body FROM events WHERE lobby_id = ?1 ORDER BY seq")?;
+
+        let rows = statement
+            .query_map(params![lobby_id.as_str()], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        rows.into_iter()
+            .map(|(seq, player_id, body)| {
+                Ok(GameEvent {
+                    seq: seq as u64,
+                    player_id: PlayerId::new(player_id),
+                    body: serde_json::from_str::<EventBody>(&body)?,
+                })
+            })
+            .collect()
+    }
+
+    // ---- Documents ----
+
+    /// Replace the stored state of a lobby's document
+    pub fn save_document(&self, lobby_id: &LobbyId, state: &[u8]) -> Result<(), ServerError> {
+        self.conn().execute(
+            "UPDATE lobbies SET document = ?2 WHERE id = ?1",
+            params![lobby_id.as_str(), state],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn load_document(&self, lobby_id: &LobbyId) -> Result<Option<Vec<u8>>, ServerError> {
+        let state = self
+            .conn()
+            .query_row(
+                "SELECT document FROM lobbies WHERE id = ?1",
+                params![lobby_id.as_str()],
+                |row| row.get::<_, Option<Vec<u8>>>(0),
+            )
+            .optional()?
+            .flatten();
+
+        Ok(state)
+    }
+}
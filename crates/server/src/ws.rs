@@ -0,0 +1,408 @@
+//! The game WebSocket.
+//!
+//! A socket first says `hello` with the protocol versions it speaks; every
+//! frame after that is encoded in the negotiated version. It then starts
+//! outside any lobby and must create, join or rejoin one before anything
+//! else. From then on text frames carry questions and
+//! answers, binary frames carry document updates, and everything other
+//! players do is relayed back.
+//!
+//! Lobby calls that touch storage run on the blocking pool, so a slow SQLite
+//! write never holds up other sockets on the same worker.
+
+use std::sync::Arc;
+
+use api_types::{
+    ClientMessage, DecodeError, Envelope, ErrorCode, EventBody, PROTOCOL_VERSION, ServerMessage,
+    VersionRange,
+};
+use axum::{
+    body::Bytes,
+    extract::{
+        State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    response::Response,
+};
+use tokio::{
+    sync::broadcast::{Receiver, error::RecvError},
+    task,
+};
+
+use crate::{
+    AppState,
+    error::ServerError,
+    lobby::{Broadcast, LobbyManager, Outbound, Session},
+};
+
+const SERVER_NAME: &str = concat!("jet-lag-server/", env!("CARGO_PKG_VERSION"));
+
+pub(crate) async fn upgrade(State(state): State<AppState>, ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(move |socket| run_session(socket, state.lobbies))
+}
+
+/// A socket and the protocol version it speaks
+struct Client {
+    socket: WebSocket,
+    version: u16,
+}
+
+impl Client {
+    async fn recv(&mut self) -> Option<Result<Message, axum::Error>> {
+        self.socket.recv().await
+    }
+
+    async fn send(&mut self, message: &ServerMessage) -> Result<(), axum::Error> {
+        let text = api_types::encode(self.version, message);
+        self.socket.send(Message::Text(text.into())).await
+    }
+
+    async fn send_binary(&mut self, bytes: Bytes) -> Result<(), axum::Error> {
+        self.socket.send(Message::Binary(bytes)).await
+    }
+
+    async fn send_error(
+        &mut self,
+        code: ErrorCode,
+        message: impl Into<String>,
+    ) -> Result<(), axum::Error> {
+        let message = ServerMessage::Error {
+            code,
+            message: message.into(),
+        };
+        self.send(&message).await
+    }
+
+    async fn send_server_error(&mut self, error: &ServerError) -> Result<(), axum::Error> {
+        self.send_error(error.code(), error.to_string()).await
+    }
+
+    /// Decode a text frame in the negotiated version, or the error code and
+    /// message to reply with
+    fn decode(&self, text: &str) -> Result<ClientMessage, (ErrorCode, String)> {
+        let Envelope { v, message } = Self::decode_any(text)?;
+        if v != self.version {
+            let message = format!("Negotiated version {}, got {}", self.version, v);
+            return Err((ErrorCode::UnsupportedVersion, message));
+        }
+
+        Ok(message)
+    }
+
+    /// Decode a text frame in any supported version, as `hello` may be
+    fn decode_any(text: &str) -> Result<Envelope<ClientMessage>, (ErrorCode, String)> {
+        match api_types::decode::<ClientMessage>(text) {
+            Ok(envelope) => Ok(envelope),
+            Err(error @ DecodeError::UnsupportedVersion(_)) => {
+                Err((ErrorCode::UnsupportedVersion, error.to_string()))
+            }
+            Err(error) => Err((ErrorCode::BadRequest, error.to_string())),
+        }
+    }
+}
+
+async fn run_session(socket: WebSocket, lobbies: Arc<LobbyManager>) {
+    let Some(mut client) = negotiate(socket).await else {
+        return;
+    };
+
+    let Some((session, broadcasts, last_seq)) = handshake(&mut client, &lobbies).await else {
+        return;
+    };
+
+    let player_id = session.player.id.clone();
+    tracing::info!(lobby = %session.lobby.id, player = %player_id, "player connected");
+
+    lobbies.announce(&session, true);
+    if let Err(error) = relay(&mut client, &lobbies, &session, broadcasts, last_seq).await {
+        tracing::debug!(%error, player = %player_id, "socket closed with error");
+    }
+    lobbies.announce(&session, false);
+
+    tracing::info!(lobby = %session.lobby.id, player = %player_id, "player disconnected");
+    lobbies.release(session);
+}
+
+/// Wait for the socket's `hello` and agree on a protocol version
+///
+/// A client that shares no version with the server is told so and dropped.
+/// The `hello` itself should be encoded in the client's oldest version.
+async fn negotiate(socket: WebSocket) -> Option<Client> {
+    // Errors before agreeing on a version are sent in the newest one
+    let mut pending = Client {
+        socket,
+        version: PROTOCOL_VERSION,
+    };
+
+    while let Some(Ok(message)) = pending.recv().await {
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Close(_) => return None,
+            Message::Binary(_) => {
+                pending
+                    .send_error(ErrorCode::NotNegotiated, "Say hello first")
+                    .await
+                    .ok()?;
+                continue;
+            }
+            _ => continue,
+        };
+
+        let (versions, client) = match Client::decode_any(&text).map(|envelope| envelope.message) {
+            Ok(ClientMessage::Hello { versions, client }) => (versions, client),
+            Ok(_) => {
+                pending
+                    .send_error(ErrorCode::NotNegotiated, "Say hello first")
+                    .await
+                    .ok()?;
+                continue;
+            }
+            Err((ErrorCode::UnsupportedVersion, message)) => {
+                pending
+                    .send_error(ErrorCode::UnsupportedVersion, message)
+                    .await
+                    .ok()?;
+                return None;
+            }
+            Err((code, message)) => {
+                pending.send_error(code, message).await.ok()?;
+                continue;
+            }
+        };
+
+        let supported = VersionRange::supported();
+        let Some(version) = supported.negotiate(&versions) else {
+            tracing::debug!(%client, ?versions, "no common protocol version");
+            let message = format!(
+                "Server speaks versions {} to {}",
+                supported.min, supported.max
+            );
+            pending
+                .send_error(ErrorCode::UnsupportedVersion, message)
+                .await
+                .ok()?;
+            return None;
+        };
+
+        tracing::debug!(%client, version, "negotiated protocol version");
+        pending.version = version;
+        pending
+            .send(&ServerMessage::Hello {
+                version,
+                server: SERVER_NAME.to_string(),
+            })
+            .await
+            .ok()?;
+
+        return Some(pending);
+    }
+
+    None
+}
+
+/// How a socket enters a lobby: by creating, joining or rejoining it
+type Entry = dyn FnOnce(&LobbyManager) -> Result<Session, ServerError> + Send;
+
+/// Wait for the socket to enter a lobby, then send it the lobby's history
+/// and document
+///
+/// Also returns the lobby's broadcasts, subscribed before the history was
+/// read so nothing is missed, and the last event in that history.
+async fn handshake(
+    client: &mut Client,
+    lobbies: &Arc<LobbyManager>,
+) -> Option<(Session, Receiver<Broadcast>, u64)> {
+    while let Some(Ok(message)) = client.recv().await {
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Close(_) => return None,
+            Message::Binary(_) => {
+                client
+                    .send_error(ErrorCode::NotInLobby, "Join a lobby first")
+                    .await
+                    .ok()?;
+                continue;
+            }
+            _ => continue,
+        };
+
+        let enter: Box<Entry> = match client.decode(&text) {
+            Ok(ClientMessage::CreateLobby { player_name }) => {
+                Box::new(move |lobbies| lobbies.create(&player_name))
+            }
+            Ok(ClientMessage::JoinLobby {
+                join_code,
+                player_name,
+            }) => Box::new(move |lobbies| lobbies.join(&join_code, &player_name)),
+            Ok(ClientMessage::Rejoin { lobby_id, token }) => {
+                Box::new(move |lobbies| lobbies.rejoin(&lobby_id, &token))
+            }
+            Ok(ClientMessage::Leave) => return None,
+            Ok(_) => {
+                client
+                    .send_error(ErrorCode::NotInLobby, "Join a lobby first")
+                    .await
+                    .ok()?;
+                continue;
+            }
+            Err((code, message)) => {
+                client.send_error(code, message).await.ok()?;
+                continue;
+            }
+        };
+
+        let welcome = blocking(lobbies, move |lobbies| {
+            let session = enter(lobbies)?;
+            let broadcasts = session.lobby.subscribe();
+            let (players, events) = lobbies.history(&session.lobby)?;
+            Ok((session, broadcasts, players, events))
+        })
+        .await;
+
+        let (session, broadcasts, players, events) = match welcome {
+            Ok(welcome) => welcome,
+            Err(error) => {
+                client.send_server_error(&error).await.ok()?;
+                continue;
+            }
+        };
+
+        let last_seq = events.last().map_or(0, |event| event.seq);
+        let welcome = ServerMessage::Welcome {
+            lobby_id: session.lobby.id.clone(),
+            join_code: session.lobby.join_code.clone(),
+            player_id: session.player.id.clone(),
+            token: session.token.clone(),
+            players,
+            events,
+        };
+
+        client.send(&welcome).await.ok()?;
+        client
+            .send_binary(Bytes::from(session.lobby.document_state()))
+            .await
+            .ok()?;
+
+        return Some((session, broadcasts, last_seq));
+    }
+
+    None
+}
+
+async fn relay(
+    client: &mut Client,
+    lobbies: &Arc<LobbyManager>,
+    session: &Session,
+    mut broadcasts: Receiver<Broadcast>,
+    last_seq: u64,
+) -> Result<(), axum::Error> {
+    loop {
+        tokio::select! {
+            incoming = client.recv() => {
+                let message = match incoming {
+                    Some(Ok(message)) => message,
+                    Some(Err(error)) => return Err(error),
+                    None => return Ok(()),
+                };
+
+                match message {
+                    Message::Text(text) if !handle_text(client, lobbies, session, &text).await? => {
+                        return Ok(());
+                    }
+                    Message::Binary(update) => {
+                        let session = session.clone();
+                        let applied = blocking(lobbies, move |lobbies| {
+                            lobbies.apply_update(&session, &update)
+                        })
+                        .await;
+
+                        if let Err(error) = applied {
+                            client.send_server_error(&error).await?;
+                        }
+                    }
+                    Message::Close(_) => return Ok(()),
+                    _ => {}
+                }
+            }
+
+            outgoing = broadcasts.recv() => {
+                let broadcast = match outgoing {
+                    Ok(broadcast) => broadcast,
+                    Err(RecvError::Lagged(_)) => {
+                        // Missed messages can't be replayed; the client
+                        // catches up by rejoining
+                        client.send_error(ErrorCode::Lagged, "Fell behind; rejoin to resync").await?;
+                        return Ok(());
+                    }
+                    Err(RecvError::Closed) => return Ok(()),
+                };
+
+                if broadcast.except.as_ref() == Some(&session.player.id) {
+                    continue;
+                }
+
+                match broadcast.payload {
+                    // Already sent in the welcome
+                    Outbound::Message(ServerMessage::Event { event }) if event.seq <= last_seq => {}
+                    Outbound::Message(message) => client.send(&message).await?,
+                    Outbound::DocumentUpdate(update) => {
+                        client.send_binary(Bytes::copy_from_slice(&update)).await?
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Handle a text frame from a player in a lobby; returns `false` once the
+/// player leaves
+async fn handle_text(
+    client: &mut Client,
+    lobbies: &Arc<LobbyManager>,
+    session: &Session,
+    text: &str,
+) -> Result<bool, axum::Error> {
+    let body = match client.decode(text) {
+        Ok(ClientMessage::Question { payload }) => EventBody::Question { payload },
+        Ok(ClientMessage::Answer {
+            question_seq,
+            payload,
+        }) => EventBody::Answer {
+            question_seq,
+            payload,
+        },
+        Ok(ClientMessage::Leave) => return Ok(false),
+        Ok(_) => {
+            client
+                .send_error(ErrorCode::BadRequest, "Already in a lobby")
+                .await?;
+            return Ok(true);
+        }
+        Err((code, message)) => {
+            client.send_error(code, message).await?;
+            return Ok(true);
+        }
+    };
+
+    let session = session.clone();
+    if let Err(error) = blocking(lobbies, move |lobbies| lobbies.post(&session, body)).await {
+        client.send_server_error(&error).await?;
+    }
+
+    Ok(true)
+}
+
+/// Run a lobby call that reads or writes storage on the blocking pool
+async fn blocking<T, F>(lobbies: &Arc<LobbyManager>, f: F) -> Result<T, ServerError>
+where
+    T: Send + 'static,
+    F: FnOnce(&LobbyManager) -> Result<T, ServerError> + Send + 'static,
+{
+    let lobbies = lobbies.clone();
+    match task::spawn_blocking(move || f(&lobbies)).await {
+        Ok(result) => result,
+        Err(error) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+        // The runtime is shutting down
+        Err(_) => Err(ServerError::ShuttingDown),
+    }
+}
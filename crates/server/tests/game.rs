@@ -0,0 +1,232 @@
+use std::{net::SocketAddr, path::PathBuf};
+
+use api_types::{
+    ClientMessage, ErrorCode, EventBody, PROTOCOL_VERSION, ServerMessage, VersionRange,
+};
+use futures_util::{SinkExt, StreamExt};
+use jet_lag_server::{Server, ServerConfig};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async, tungstenite::Message};
+use yrs::{Doc, GetString, ReadTxn, StateVector, Text, Transact, Update, updates::decoder::Decode};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+fn data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("jet-lag-server-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("bundles")).unwrap();
+    std::fs::create_dir_all(dir.join("resources")).unwrap();
+    dir
+}
+
+async fn start(data_dir: PathBuf) -> SocketAddr {
+    let config = ServerConfig::new(data_dir).with_listen(SocketAddr::from(([127, 0, 0, 1], 0)));
+    let (addr, _) = Server::new(config).unwrap().spawn().await.unwrap();
+    addr
+}
+
+fn hello(versions: VersionRange) -> ClientMessage {
+    ClientMessage::Hello {
+        versions,
+        client: "game-test".into(),
+    }
+}
+
+/// Open a socket and negotiate the current protocol version
+async fn connect(addr: SocketAddr) -> Socket {
+    let mut socket = connect_async(format!("ws://{}/ws", addr)).await.unwrap().0;
+    send(&mut socket, &hello(VersionRange::supported())).await;
+    assert!(matches!(
+        recv(&mut socket).await,
+        ServerMessage::Hello {
+            version: PROTOCOL_VERSION,
+            ..
+        }
+    ));
+    socket
+}
+
+async fn send(socket: &mut Socket, message: &ClientMessage) {
+    let text = api_types::encode(PROTOCOL_VERSION, message);
+    socket.send(Message::text(text)).await.unwrap();
+}
+
+async fn recv(socket: &mut Socket) -> ServerMessage {
+    loop {
+        match socket.next().await.unwrap().unwrap() {
+            Message::Text(text) => return api_types::decode(&text).unwrap().message,
+            Message::Ping(_) | Message::Pong(_) => continue,
+            other => panic!("expected a text frame, got {:?}", other),
+        }
+    }
+}
+
+async fn recv_update(socket: &mut Socket) -> Vec<u8> {
+    match socket.next().await.unwrap().unwrap() {
+        Message::Binary(update) => update.to_vec(),
+        other => panic!("expected a binary frame, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_serves_bundles() {
+    let dir = data_dir("bundles");
+    std::fs::write(dir.join("bundles/nyc.json"), br#"{"id":"nyc"}"#).unwrap();
+    std::fs::write(dir.join("resources/abc123"), b"tile data").unwrap();
+    let addr = start(dir).await;
+
+    let get = |path: &str| reqwest::get(format!("http://{}{}", addr, path));
+
+    let bundle = get("/bundles/nyc").await.unwrap();
+    assert_eq!(bundle.status(), 200);
+    assert_eq!(bundle.text().await.unwrap(), r#"{"id":"nyc"}"#);
+
+    let resource = get("/resources/abc123").await.unwrap();
+    assert_eq!(resource.bytes().await.unwrap().as_ref(), b"tile data");
+
+    assert_eq!(get("/bundles/missing").await.unwrap().status(), 404);
+    assert_eq!(
+        get("/resources/..%2Fgames.sqlite3").await.unwrap().status(),
+        404
+    );
+}
+
+#[tokio::test]
+async fn test_lobby_relay() {
+    let addr = start(data_dir("lobby")).await;
+
+    // Hider opens a lobby
+    let mut hider = connect(addr).await;
+    send(
+        &mut hider,
+        &ClientMessage::CreateLobby {
+            player_name: "Hider".into(),
+        },
+    )
+    .await;
+    let ServerMessage::Welcome {
+        join_code,
+        lobby_id,
+        token,
+        ..
+    } = recv(&mut hider).await
+    else {
+        panic!("expected a welcome");
+    };
+    recv_update(&mut hider).await;
+
+    // Seeker joins with the code, in lower case
+    let mut seeker = connect(addr).await;
+    send(
+        &mut seeker,
+        &ClientMessage::JoinLobby {
+            join_code: join_code.to_lowercase(),
+            player_name: "Seeker".into(),
+        },
+    )
+    .await;
+    let ServerMessage::Welcome { players, .. } = recv(&mut seeker).await else {
+        panic!("expected a welcome");
+    };
+    assert_eq!(players.len(), 2);
+    recv_update(&mut seeker).await;
+
+    assert!(
+        matches!(recv(&mut hider).await, ServerMessage::PlayerJoined { player } if player.name == "Seeker")
+    );
+
+    // A question reaches both players, numbered
+    let payload = serde_json::json!({ "radar": 500 });
+    send(
+        &mut seeker,
+        &ClientMessage::Question {
+            payload: payload.clone(),
+        },
+    )
+    .await;
+    for socket in [&mut hider, &mut seeker] {
+        let ServerMessage::Event { event } = recv(socket).await else {
+            panic!("expected an event");
+        };
+        assert_eq!(event.seq, 1);
+        assert_eq!(
+            event.body,
+            EventBody::Question {
+                payload: payload.clone()
+            }
+        );
+    }
+
+    // Document updates go to the other player only
+    let doc = Doc::new();
+    let text = doc.get_or_insert_text("notes");
+    text.insert(&mut doc.transact_mut(), 0, "hello");
+    let update = doc
+        .transact()
+        .encode_state_as_update_v1(&StateVector::default());
+    seeker.send(Message::binary(update.clone())).await.unwrap();
+
+    let relayed = recv_update(&mut hider).await;
+    let remote = Doc::new();
+    let notes = remote.get_or_insert_text("notes");
+    remote
+        .transact_mut()
+        .apply_update(Update::decode_v1(&relayed).unwrap())
+        .unwrap();
+    assert_eq!(notes.get_string(&remote.transact()), "hello");
+
+    // Reconnecting with the token replays the event and the document
+    drop(hider);
+    let mut hider = connect(addr).await;
+    send(&mut hider, &ClientMessage::Rejoin { lobby_id, token }).await;
+    let ServerMessage::Welcome { events, .. } = recv(&mut hider).await else {
+        panic!("expected a welcome");
+    };
+    assert_eq!(events.len(), 1);
+
+    let state = recv_update(&mut hider).await;
+    let restored = Doc::new();
+    let notes = restored.get_or_insert_text("notes");
+    restored
+        .transact_mut()
+        .apply_update(Update::decode_v1(&state).unwrap())
+        .unwrap();
+    assert_eq!(notes.get_string(&restored.transact()), "hello");
+}
+
+#[tokio::test]
+async fn test_version_negotiation() {
+    let addr = start(data_dir("version")).await;
+    let mut socket = connect_async(format!("ws://{}/ws", addr)).await.unwrap().0;
+
+    // Nothing is accepted before hello
+    send(
+        &mut socket,
+        &ClientMessage::CreateLobby {
+            player_name: "Early".into(),
+        },
+    )
+    .await;
+    assert!(matches!(
+        recv(&mut socket).await,
+        ServerMessage::Error {
+            code: ErrorCode::NotNegotiated,
+            ..
+        }
+    ));
+
+    // A client that only speaks future versions is turned away
+    let future = PROTOCOL_VERSION + 1;
+    send(&mut socket, &hello(VersionRange::new(future, future))).await;
+    assert!(matches!(
+        recv(&mut socket).await,
+        ServerMessage::Error {
+            code: ErrorCode::UnsupportedVersion,
+            ..
+        }
+    ));
+    assert!(matches!(
+        socket.next().await,
+        None | Some(Ok(Message::Close(_))) | Some(Err(_))
+    ));
+}
@@ -0,0 +1,109 @@
+//! Validate a new GTFS feed and diff it against the one currently in use.
+//!
+//! ```text
+//! transit-diff <old-gtfs> <new-gtfs> [--move-threshold <metres>] [--names <rules>]
+//! ```
+//!
+//! `--names` picks the city's station name rules (`default`, `none` or
+//! `new-york`), which decide whether a rename changes a question-name length.
+//!
+//! Exits with 2 if the new feed has validation errors, 1 if the diff has
+//! changes that need review, 3 if a feed can't be loaded, and 0 otherwise.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use jet_lag_transit::compiler::load_gtfs;
+use jet_lag_transit::naming::NameRules;
+use jet_lag_transit::report::{diff_providers, validate, DiffOptions, Severity};
+
+const USAGE: &str = "usage: transit-diff <old-gtfs> <new-gtfs> [--move-threshold <metres>] \
+                     [--names default|none|new-york]";
+
+struct Args {
+    old: PathBuf,
+    new: PathBuf,
+    options: DiffOptions,
+    names: NameRules,
+}
+
+fn name_rules(name: &str) -> Option<NameRules> {
+    match name {
+        "default" => Some(NameRules::default()),
+        "none" => Some(NameRules::none()),
+        "new-york" => Some(NameRules::new_york()),
+        _ => None,
+    }
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut paths = Vec::new();
+    let mut options = DiffOptions::default();
+    let mut names = NameRules::default();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--move-threshold" => {
+                let value = args.next().ok_or("--move-threshold needs a value")?;
+                let metres = value
+                    .parse()
+                    .map_err(|_| format!("invalid --move-threshold: {}", value))?;
+                options = options.with_move_threshold(metres);
+            }
+            "--names" => {
+                let value = args.next().ok_or("--names needs a value")?;
+                names = name_rules(&value).ok_or_else(|| format!("unknown --names: {}", value))?;
+            }
+            "-h" | "--help" => return Err(USAGE.into()),
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    let [old, new]: [PathBuf; 2] = paths.try_into().map_err(|_| USAGE.to_string())?;
+    Ok(Args {
+        old,
+        new,
+        options,
+        names,
+    })
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(message) => {
+            eprintln!("{}", message);
+            return ExitCode::from(64);
+        }
+    };
+
+    let load = |path: &PathBuf| {
+        load_gtfs(path)
+            .map(|provider| provider.with_name_rules(&args.names))
+            .map_err(|e| eprintln!("failed to load {}: {}", path.display(), e))
+    };
+    let (Ok(old), Ok(new)) = (load(&args.old), load(&args.new)) else {
+        return ExitCode::from(3);
+    };
+
+    let issues = validate(&new);
+    if !issues.is_empty() {
+        println!("Validation ({})", issues.len());
+        for issue in &issues {
+            println!("  {}", issue);
+        }
+        println!();
+    }
+
+    let diff = diff_providers(&old, &new, &args.options);
+    print!("{}", diff);
+
+    if issues.iter().any(|i| i.severity() == Severity::Error) {
+        ExitCode::from(2)
+    } else if diff.needs_review() {
+        ExitCode::from(1)
+    } else {
+        ExitCode::SUCCESS
+    }
+}
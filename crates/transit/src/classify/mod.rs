@@ -0,0 +1,21 @@
+//! Route classification from schedule data.
+//!
+//! Feeds rarely say which services are high-speed, so we infer it: the
+//! speed of every stop-to-stop hop is computed from scheduled times and the
+//! distance travelled (along the trip's shape when there is one), and rail
+//! routes whose fastest hop clears a threshold are classified as high-speed,
+//! all of their hops included.
+
+pub mod speed;
+
+pub use speed::{hop_speeds, HighSpeedRailClassifier, HighSpeedRailLine, HopSpeed};
+
+/// Default minimum average hop speed for high-speed rail
+///
+/// Lines upgraded for high speed reach 200 km/h (UIC); average speeds
+/// between stops are lower than top speed, so this is deliberately strict.
+pub const DEFAULT_HIGH_SPEED_MIN_KPH: f64 = 200.0;
+
+/// Default shortest hop considered; schedules are rounded to the minute,
+/// which makes very short hops unreliable
+pub const DEFAULT_MIN_HOP_SECS: u32 = 120;
@@ -0,0 +1,392 @@
+//! Hop speeds and high-speed rail classification.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use geo::{Coord, HaversineLength, LineString, Point};
+
+use crate::classify::{DEFAULT_HIGH_SPEED_MIN_KPH, DEFAULT_MIN_HOP_SECS};
+use crate::identifiers::*;
+use crate::models::traits::{Route, TransitProvider};
+use crate::models::types::RouteType;
+use crate::shapes::reconstruct_shape;
+use crate::spatial::queries::haversine_distance;
+
+// ============================================================================
+// Hop Speeds
+// ============================================================================
+
+/// The fastest scheduled run between two consecutive stops of a route
+#[derive(Clone, Debug)]
+pub struct HopSpeed {
+    pub route_id: RouteIdentifier,
+    pub from: StationIdentifier,
+    pub to: StationIdentifier,
+
+    /// Path between the stops: a slice of the trip's shape if available,
+    /// otherwise a great-circle arc
+    pub geometry: LineString,
+    pub distance_m: f64,
+    pub duration_secs: u32,
+}
+
+impl HopSpeed {
+    pub fn speed_kph(&self) -> f64 {
+        self.distance_m / self.duration_secs.max(1) as f64 * 3.6
+    }
+}
+
+/// Fastest hop between each pair of consecutive stops of a route
+///
+/// Both directions between two stations are merged into one hop. Hops
+/// shorter than `min_hop_secs` are skipped.
+pub fn hop_speeds<P: TransitProvider + ?Sized>(
+    provider: &P,
+    route: &dyn Route,
+    min_hop_secs: u32,
+) -> Vec<HopSpeed> {
+    let mut fastest: HashMap<(StationIdentifier, StationIdentifier), HopSpeed> = HashMap::new();
+    let mut order = Vec::new();
+
+    for trip in route.trips() {
+        let shape = provider.trip_shape(trip.id());
+
+        for hop in trip.stop_events().windows(2) {
+            let duration_secs = hop[1].arrival.saturating_sub(hop[0].departure);
+
+            if duration_secs < min_hop_secs.max(1) {
+                continue;
+            }
+
+            let (Some(from), Some(to)) = (
+                provider.get_station(&hop[0].station_id),
+                provider.get_station(&hop[1].station_id),
+            ) else {
+                continue;
+            };
+
+            let key = if from.id().as_str() <= to.id().as_str() {
+                (from.id().clone(), to.id().clone())
+            } else {
+                (to.id().clone(), from.id().clone())
+            };
+
+            let geometry = shape
+                .as_deref()
+                .and_then(|shape| slice_shape(shape, from.location(), to.location()))
+                .unwrap_or_else(|| reconstruct_shape(&[from.location(), to.location()], None));
+
+            let candidate = HopSpeed {
+                route_id: route.id().clone(),
+                from: from.id().clone(),
+                to: to.id().clone(),
+                distance_m: geometry.haversine_length(),
+                geometry,
+                duration_secs,
+            };
+
+            match fastest.get_mut(&key) {
+                Some(existing) => {
+                    if candidate.speed_kph() > existing.speed_kph() {
+                        *existing = candidate;
+                    }
+                }
+                None => {
+                    order.push(key.clone());
+                    fastest.insert(key, candidate);
+                }
+            }
+        }
+    }
+
+    order
+        .into_iter()
+        .filter_map(|key| fastest.remove(&key))
+        .collect()
+}
+
+/// The part of `shape` between the vertices nearest `from` and `to`, with
+/// the stops themselves as endpoints
+///
+/// Returns `None` if the shape runs the other way or doesn't pass near the
+/// stops (more than twice the stop-to-stop distance away).
+fn slice_shape(shape: &LineString, from: Point, to: Point) -> Option<LineString> {
+    let nearest_from = |point: Point, start: usize| {
+        shape.0[start..]
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (start + i, haversine_distance(point, Point::from(*c))))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    };
+
+    let limit = 2.0 * haversine_distance(from, to);
+
+    let (start, start_distance) = nearest_from(from, 0)?;
+    let (end, end_distance) = nearest_from(to, start)?;
+
+    if start_distance > limit || end_distance > limit || end <= start {
+        return None;
+    }
+
+    let mut coords: Vec<Coord> = vec![from.0];
+    coords.extend_from_slice(&shape.0[start..=end]);
+    coords.push(to.0);
+    coords.dedup();
+
+    Some(LineString::new(coords))
+}
+
+// ============================================================================
+// High-Speed Rail
+// ============================================================================
+
+/// A rail route with at least one hop at high speed
+#[derive(Clone, Debug)]
+pub struct HighSpeedRailLine {
+    pub route_id: RouteIdentifier,
+    pub name: Arc<str>,
+    pub max_speed_kph: f64,
+
+    /// Every hop of the route, including slow ones
+    pub segments: Vec<HopSpeed>,
+}
+
+/// Classifies `RouteType::Rail` routes by their fastest scheduled hops
+///
+/// The decision is per route: a high-speed service is still a high-speed
+/// line on its slow approaches into city terminals (the Acela crawling out
+/// of New York, say), so a route that qualifies keeps all of its hops.
+#[derive(Clone, Copy, Debug)]
+pub struct HighSpeedRailClassifier {
+    pub min_speed_kph: f64,
+    pub min_hop_secs: u32,
+}
+
+impl Default for HighSpeedRailClassifier {
+    fn default() -> Self {
+        Self {
+            min_speed_kph: DEFAULT_HIGH_SPEED_MIN_KPH,
+            min_hop_secs: DEFAULT_MIN_HOP_SECS,
+        }
+    }
+}
+
+impl HighSpeedRailClassifier {
+    pub fn with_min_speed(mut self, min_speed_kph: f64) -> Self {
+        self.min_speed_kph = min_speed_kph;
+        self
+    }
+
+    pub fn with_min_hop_secs(mut self, min_hop_secs: u32) -> Self {
+        self.min_hop_secs = min_hop_secs;
+        self
+    }
+
+    /// Classify a single route; `None` if it isn't high-speed rail
+    pub fn classify_route<P: TransitProvider + ?Sized>(
+        &self,
+        provider: &P,
+        route: &dyn Route,
+    ) -> Option<HighSpeedRailLine> {
+        if route.route_type() != RouteType::Rail {
+            return None;
+        }
+
+        // Short hops are too coarsely timed to judge speed, but still part
+        // of the line
+        let segments = hop_speeds(provider, route, 0);
+
+        let max_speed_kph = segments
+            .iter()
+            .filter(|hop| hop.duration_secs >= self.min_hop_secs)
+            .map(HopSpeed::speed_kph)
+            .max_by(f64::total_cmp)
+            .filter(|speed| *speed >= self.min_speed_kph)?;
+
+        let name = if route.long_name().is_empty() {
+            route.short_name()
+        } else {
+            route.long_name()
+        };
+
+        Some(HighSpeedRailLine {
+            route_id: route.id().clone(),
+            name: name.into(),
+            max_speed_kph,
+            segments,
+        })
+    }
+
+    /// Every high-speed rail line of a network
+    pub fn classify<P: TransitProvider + ?Sized>(&self, provider: &P) -> Vec<HighSpeedRailLine> {
+        provider
+            .all_routes()
+            .iter()
+            .filter_map(|route| self.classify_route(provider, route.as_ref()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono_tz::Tz;
+
+    use crate::models::traits::Trip;
+    use crate::models::types::StopEvent;
+    use crate::provider::{RouteImpl, StaticTransitProvider};
+    use crate::test_support::{self, daily, station};
+
+    fn route(
+        id: &str,
+        route_type: RouteType,
+        timezone: Tz,
+        trips: Vec<(&str, Vec<(&str, u32)>)>,
+    ) -> RouteImpl {
+        let calendar = Arc::new(daily(timezone));
+
+        let trips = trips
+            .into_iter()
+            .map(|(trip_id, stops)| {
+                let stop_events = stops
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, (s, t))| StopEvent::new(StationIdentifier::new(s), t, t, i as u32 + 1))
+                    .collect();
+
+                Arc::new(test_support::trip(trip_id, id, &calendar, stop_events)) as Arc<dyn Trip>
+            })
+            .collect();
+
+        RouteImpl {
+            long_name: format!("Line {id}").into(),
+            ..test_support::route(id, route_type, trips)
+        }
+    }
+
+    fn provider() -> StaticTransitProvider {
+        // Paris → Lyon is ~390km; Lyon → a suburb ~10km
+        let stations = vec![
+            station("paris", 2.3735, 48.8448),
+            station("lyon", 4.8594, 45.7605),
+            station("suburb", 4.9, 45.85),
+        ];
+
+        let routes = vec![
+            route(
+                "tgv",
+                RouteType::Rail,
+                chrono_tz::Europe::Paris,
+                vec![
+                    // 2h → ~195 km/h, just below the threshold
+                    ("slow", vec![("paris", 0), ("lyon", 7200), ("suburb", 7800)]),
+                    // 1h56 in the other direction → ~202 km/h
+                    ("fast", vec![("lyon", 0), ("paris", 6960)]),
+                ],
+            ),
+            route(
+                "ter",
+                RouteType::Rail,
+                chrono_tz::Europe::Paris,
+                vec![("t", vec![("lyon", 0), ("suburb", 900)])],
+            ),
+            // Fast enough, but not rail
+            route(
+                "plane",
+                RouteType::Bus,
+                chrono_tz::Europe::Paris,
+                vec![("p", vec![("paris", 0), ("lyon", 3600)])],
+            ),
+        ];
+
+        StaticTransitProvider::from_data(stations, vec![], routes)
+    }
+
+    #[test]
+    fn test_hop_speeds_merge_directions() {
+        let provider = provider();
+        let route = provider.get_route(&RouteIdentifier::new("tgv")).unwrap();
+
+        let hops = hop_speeds(&provider, route.as_ref(), DEFAULT_MIN_HOP_SECS);
+        assert_eq!(hops.len(), 2);
+
+        let main = &hops[0];
+        assert_eq!(main.duration_secs, 6960);
+        assert!((main.distance_m - 392_000.0).abs() < 5_000.0);
+        assert!(main.geometry.0.len() > 300);
+    }
+
+    #[test]
+    fn test_classify() {
+        let provider = provider();
+        let lines = HighSpeedRailClassifier::default().classify(&provider);
+
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].route_id, RouteIdentifier::new("tgv"));
+        assert_eq!(lines[0].name.as_ref(), "Line tgv");
+        // The slow hop out to the suburb is still part of the line
+        assert_eq!(lines[0].segments.len(), 2);
+        assert!(lines[0].max_speed_kph > 200.0 && lines[0].max_speed_kph < 205.0);
+
+        let lenient = HighSpeedRailClassifier::default().with_min_speed(30.0);
+        assert_eq!(lenient.classify(&provider).len(), 2);
+    }
+
+    #[test]
+    fn test_slow_terminal_approaches_are_kept() {
+        // Acela-like: crawls out of New York, only fast in New England
+        let stations = vec![
+            station("new_york", -73.9935, 40.7506),
+            station("new_haven", -72.9267, 41.2977),
+            station("providence", -71.4134, 41.8290),
+            station("boston", -71.0552, 42.3473),
+        ];
+        let acela = route(
+            "acela",
+            RouteType::Rail,
+            chrono_tz::America::New_York,
+            vec![(
+                "2150",
+                vec![
+                    ("new_york", 0),
+                    ("new_haven", 5400),
+                    ("providence", 7680),
+                    ("boston", 9780),
+                ],
+            )],
+        );
+        let provider = StaticTransitProvider::from_data(stations, vec![], vec![acela]);
+
+        let lines = HighSpeedRailClassifier::default().classify(&provider);
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].max_speed_kph > 200.0);
+
+        let hops: Vec<(&str, &str)> = lines[0]
+            .segments
+            .iter()
+            .map(|hop| (hop.from.as_str(), hop.to.as_str()))
+            .collect();
+        assert_eq!(
+            hops,
+            vec![
+                ("new_york", "new_haven"),
+                ("new_haven", "providence"),
+                ("providence", "boston"),
+            ]
+        );
+        assert!(lines[0].segments[0].speed_kph() < 100.0);
+    }
+
+    #[test]
+    fn test_slice_shape() {
+        let shape = LineString::from(vec![(0.0, 0.0), (0.0, 0.5), (0.1, 1.0), (0.0, 1.5), (0.0, 2.0)]);
+
+        let slice = slice_shape(&shape, Point::new(0.001, 0.49), Point::new(0.0, 1.5)).unwrap();
+        assert_eq!(slice.0.len(), 4);
+        assert_eq!(slice.0[2], Coord { x: 0.1, y: 1.0 });
+
+        // Shape runs the other way
+        assert!(slice_shape(&shape, Point::new(0.0, 1.5), Point::new(0.0, 0.5)).is_none());
+    }
+}
@@ -0,0 +1,494 @@
+//! GTFS → in-memory provider conversion.
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+use geo::{LineString, Point};
+use gtfs_structures::{Exception, Gtfs, LocationType, PathwayDirectionType, TransferType};
+
+use crate::identifiers::*;
+use crate::models::calendar::{ServiceCalendar, WeekdayFlags};
+use crate::models::traits::Trip;
+use crate::models::types::*;
+use crate::provider::{ComplexImpl, RouteImpl, StaticTransitProvider, StationImpl, TripImpl};
+use crate::transfers::{
+    collapse_pathways, FeedTransfer, FeedTransferType, PathwayEdge, TransferConfig, TransferGraph,
+};
+
+/// Read a GTFS feed (directory or zip) and build a provider from it
+pub fn load_gtfs(path: &Path) -> Result<StaticTransitProvider> {
+    let gtfs = Gtfs::from_path(path)
+        .map_err(|e| TransitError::InvalidData(format!("{}: {}", path.display(), e)))?;
+
+    provider_from_gtfs(&gtfs)
+}
+
+/// Build a provider from a parsed GTFS feed
+///
+/// Boarding stops (`location_type` 0) become stations; their parent
+/// stations become complexes. Stops without a parent get a complex of their
+/// own. Shapes from shapes.txt are attached to trips, and transfers.txt and
+/// pathways.txt feed the transfer graph.
+pub fn provider_from_gtfs(gtfs: &Gtfs) -> Result<StaticTransitProvider> {
+    let timezone = agency_timezone(gtfs)?;
+
+    // ---- Stations and complexes ----
+
+    let mut stations = Vec::new();
+    let mut complex_members: HashMap<String, Vec<StationIdentifier>> = HashMap::new();
+
+    for stop in gtfs.stops.values() {
+        if stop.location_type != LocationType::StopPoint {
+            continue;
+        }
+
+        let (Some(lon), Some(lat)) = (stop.longitude, stop.latitude) else {
+            continue;
+        };
+
+        let complex_id = stop.parent_station.clone().unwrap_or_else(|| stop.id.clone());
+        complex_members
+            .entry(complex_id.clone())
+            .or_default()
+            .push(StationIdentifier::new(&stop.id));
+
+        stations.push(StationImpl {
+            id: StationIdentifier::new(&stop.id),
+            name: stop.name.as_deref().unwrap_or(&stop.id).into(),
+            location: Point::new(lon, lat),
+            complex_id: ComplexIdentifier::new(complex_id),
+        });
+    }
+
+    let transfer_graph = TransferGraph::builder()
+        .with_feed_transfers(feed_transfers(gtfs, &complex_members))
+        .with_pathways(collapse_pathways(pathway_edges(gtfs), |node| {
+            boarding_station(gtfs, node)
+        }));
+
+    let locations: HashMap<&StationIdentifier, Point> =
+        stations.iter().map(|s| (&s.id, s.location)).collect();
+
+    let complexes = complex_members
+        .into_iter()
+        .map(|(complex_id, station_ids)| {
+            let parent = gtfs.stops.get(&complex_id);
+
+            let center = parent
+                .and_then(|p| Some(Point::new(p.longitude?, p.latitude?)))
+                .unwrap_or_else(|| {
+                    let n = station_ids.len() as f64;
+                    let (x, y) = station_ids
+                        .iter()
+                        .filter_map(|id| locations.get(id))
+                        .fold((0.0, 0.0), |(x, y), p| (x + p.x(), y + p.y()));
+                    Point::new(x / n, y / n)
+                });
+
+            let name = parent
+                .and_then(|p| p.name.clone())
+                .unwrap_or_else(|| complex_id.clone());
+
+            ComplexImpl {
+                id: ComplexIdentifier::new(&complex_id),
+                name: name.into(),
+                station_ids,
+                center,
+            }
+        })
+        .collect();
+
+    // ---- Calendars ----
+
+    let mut service_ids: HashSet<&String> = gtfs.calendar.keys().collect();
+    service_ids.extend(gtfs.calendar_dates.keys());
+
+    let calendars: HashMap<&String, Arc<ServiceCalendar>> = service_ids
+        .into_iter()
+        .map(|service_id| {
+            let mut added = HashSet::new();
+            let mut removed = HashSet::new();
+
+            for date in gtfs.calendar_dates.get(service_id).into_iter().flatten() {
+                match date.exception_type {
+                    Exception::Added => added.insert(date.date),
+                    Exception::Deleted => removed.insert(date.date),
+                };
+            }
+
+            let calendar = match gtfs.calendar.get(service_id) {
+                Some(c) => ServiceCalendar {
+                    service_id: ServiceIdentifier::new(service_id),
+                    start_date: c.start_date,
+                    end_date: c.end_date,
+                    weekdays: WeekdayFlags::from_bools(
+                        c.monday, c.tuesday, c.wednesday, c.thursday, c.friday, c.saturday,
+                        c.sunday,
+                    ),
+                    added_dates: Arc::new(added),
+                    removed_dates: Arc::new(removed),
+                    timezone,
+                },
+                // calendar_dates.txt only: no regular service
+                None => ServiceCalendar {
+                    service_id: ServiceIdentifier::new(service_id),
+                    start_date: added.iter().min().copied().unwrap_or(NaiveDate::MIN),
+                    end_date: added.iter().max().copied().unwrap_or(NaiveDate::MIN),
+                    weekdays: WeekdayFlags::new(),
+                    added_dates: Arc::new(added),
+                    removed_dates: Arc::new(removed),
+                    timezone,
+                },
+            };
+
+            (service_id, Arc::new(calendar))
+        })
+        .collect();
+
+    // ---- Trips ----
+
+    let mut trips_by_route: HashMap<&str, Vec<Arc<dyn Trip>>> = HashMap::new();
+
+    for trip in gtfs.trips.values() {
+        let Some(calendar) = calendars.get(&trip.service_id) else {
+            continue;
+        };
+
+        let times = interpolate_stop_times(
+            trip.stop_times
+                .iter()
+                .map(|st| (st.arrival_time, st.departure_time, st.shape_dist_traveled)),
+        );
+
+        let stop_events = trip
+            .stop_times
+            .iter()
+            .zip(times)
+            .filter_map(|(st, times)| {
+                let (arrival, departure) = times?;
+
+                Some(StopEvent::new(
+                    StationIdentifier::new(&st.stop.id),
+                    arrival,
+                    departure,
+                    st.stop_sequence.into(),
+                ))
+            })
+            .collect();
+
+        let frequencies = trip
+            .frequencies
+            .iter()
+            .map(|f| {
+                Frequency::new(
+                    f.start_time,
+                    f.end_time,
+                    f.headway_secs,
+                    matches!(f.exact_times, Some(gtfs_structures::ExactTimes::ScheduleBased)),
+                )
+            })
+            .collect();
+
+        let direction_id = match trip.direction_id {
+            Some(gtfs_structures::DirectionType::Inbound) => DirectionId::Inbound,
+            _ => DirectionId::Outbound,
+        };
+
+        trips_by_route
+            .entry(trip.route_id.as_str())
+            .or_default()
+            .push(Arc::new(TripImpl {
+                id: TripIdentifier::new(&trip.id),
+                route_id: RouteIdentifier::new(&trip.route_id),
+                stop_events,
+                frequencies,
+                service_calendar: calendar.clone(),
+                direction_id,
+                headsign: trip.trip_headsign.as_deref().unwrap_or("").into(),
+                shape_id: trip.shape_id.as_deref().map(ShapeIdentifier::new),
+            }));
+    }
+
+    // ---- Routes ----
+
+    let routes = gtfs
+        .routes
+        .values()
+        .filter_map(|route| {
+            let route_type = route_type(route.route_type)?;
+
+            Some(RouteImpl {
+                id: RouteIdentifier::new(&route.id),
+                route_type,
+                short_name: route.short_name.as_deref().unwrap_or("").into(),
+                long_name: route.long_name.as_deref().unwrap_or("").into(),
+                color: Some(hex(route.color.r, route.color.g, route.color.b).into()),
+                text_color: Some(
+                    hex(route.text_color.r, route.text_color.g, route.text_color.b).into(),
+                ),
+                geometry: None,
+                trips: trips_by_route.remove(route.id.as_str()).unwrap_or_default(),
+            })
+        })
+        .collect();
+
+    // ---- Shapes ----
+
+    let shapes = gtfs.shapes.iter().map(|(shape_id, points)| {
+        let mut points = points.clone();
+        points.sort_by_key(|p| p.sequence);
+
+        let line: LineString = points.iter().map(|p| (p.longitude, p.latitude)).collect();
+        (ShapeIdentifier::new(shape_id), line)
+    });
+
+    Ok(StaticTransitProvider::from_data(stations, complexes, routes)
+        .with_shapes(shapes)
+        .with_transfers(transfer_graph))
+}
+
+/// Arrival and departure times for a trip's stop_times rows, filling in
+/// rows without times (non-timepoints) between the timed rows around them
+///
+/// Untimed rows are spaced by `shape_dist_traveled` when every row in the
+/// gap has it, and evenly by stop count otherwise. Rows before the
+/// first or after the last timed row have nothing to interpolate from and
+/// stay `None`.
+fn interpolate_stop_times(
+    rows: impl Iterator<Item = (Option<u32>, Option<u32>, Option<f32>)>,
+) -> Vec<Option<(u32, u32)>> {
+    let rows: Vec<_> = rows.collect();
+
+    let mut times: Vec<Option<(u32, u32)>> = rows
+        .iter()
+        .map(|&(arrival, departure, _)| Some((arrival.or(departure)?, departure.or(arrival)?)))
+        .collect();
+
+    let timed: Vec<usize> = (0..rows.len()).filter(|&i| times[i].is_some()).collect();
+
+    for pair in timed.windows(2) {
+        let (start, end) = (pair[0], pair[1]);
+        if end - start < 2 {
+            continue;
+        }
+
+        let from = times[start].map_or(0, |(_, departure)| departure);
+        let to = times[end].map_or(0, |(arrival, _)| arrival);
+
+        let distances = (start..=end)
+            .map(|i| rows[i].2)
+            .collect::<Option<Vec<f32>>>()
+            .filter(|d| d[d.len() - 1] > d[0]);
+
+        for i in start + 1..end {
+            let fraction = match &distances {
+                Some(d) => f64::from(d[i - start] - d[0]) / f64::from(d[d.len() - 1] - d[0]),
+                None => (i - start) as f64 / (end - start) as f64,
+            };
+
+            let time = from + (f64::from(to.saturating_sub(from)) * fraction).round() as u32;
+            times[i] = Some((time, time));
+        }
+    }
+
+    times
+}
+
+/// Rows of transfers.txt, with transfers between parent stations applied to
+/// each of their platforms
+///
+/// Trip- and route-specific rules (in-seat transfers) don't describe
+/// stations and are skipped.
+fn feed_transfers(
+    gtfs: &Gtfs,
+    complex_members: &HashMap<String, Vec<StationIdentifier>>,
+) -> Vec<FeedTransfer> {
+    let platforms = |stop_id: &str| match complex_members.get(stop_id) {
+        Some(members) => members.clone(),
+        None => vec![StationIdentifier::new(stop_id)],
+    };
+
+    let mut transfers = Vec::new();
+
+    for stop in gtfs.stops.values() {
+        for transfer in &stop.transfers {
+            let transfer_type = match transfer.transfer_type {
+                TransferType::Recommended => FeedTransferType::Recommended,
+                TransferType::Timed => FeedTransferType::Timed,
+                TransferType::MinTime => {
+                    FeedTransferType::MinimumTime(transfer.min_transfer_time.unwrap_or(0))
+                }
+                TransferType::Impossible => FeedTransferType::NotPossible,
+                _ => continue,
+            };
+
+            for from in platforms(&stop.id) {
+                for to in platforms(&transfer.to_stop_id) {
+                    transfers.push(FeedTransfer {
+                        from: from.clone(),
+                        to,
+                        transfer_type,
+                    });
+                }
+            }
+        }
+    }
+
+    transfers
+}
+
+/// Rows of pathways.txt; a pathway without a traversal time is timed from
+/// its length at walking pace, or skipped if it has neither
+fn pathway_edges(gtfs: &Gtfs) -> impl Iterator<Item = PathwayEdge<'_>> {
+    let config = TransferConfig::default();
+
+    gtfs.stops
+        .values()
+        .flat_map(|stop| stop.pathways.iter().map(move |pathway| (stop, pathway)))
+        .filter_map(move |(stop, pathway)| {
+            let traversal_secs = pathway
+                .traversal_time
+                .or_else(|| pathway.length.map(|m| config.walking_secs(m.into())))?;
+
+            let bidirectional =
+                matches!(pathway.is_bidirectional, PathwayDirectionType::Bidirectional);
+
+            Some(PathwayEdge {
+                from: &stop.id,
+                to: &pathway.to_stop_id,
+                traversal_secs,
+                bidirectional,
+            })
+        })
+}
+
+/// The station a pathway node boards: a platform itself, or a boarding
+/// area's platform
+fn boarding_station(gtfs: &Gtfs, node: &str) -> Option<StationIdentifier> {
+    let stop = gtfs.stops.get(node)?;
+
+    match stop.location_type {
+        LocationType::StopPoint => Some(StationIdentifier::new(&stop.id)),
+        LocationType::BoardingArea => stop.parent_station.as_deref().map(StationIdentifier::new),
+        _ => None,
+    }
+}
+
+fn agency_timezone(gtfs: &Gtfs) -> Result<Tz> {
+    let Some(agency) = gtfs.agencies.first() else {
+        return Err(TransitError::InvalidData("Feed has no agency".to_string()));
+    };
+
+    agency.timezone.parse().map_err(|_| {
+        TransitError::InvalidData(format!("Unknown agency timezone: {}", agency.timezone))
+    })
+}
+
+fn route_type(route_type: gtfs_structures::RouteType) -> Option<RouteType> {
+    use gtfs_structures::RouteType as Gtfs;
+
+    match route_type {
+        Gtfs::Tramway => Some(RouteType::Tram),
+        Gtfs::Subway => Some(RouteType::Subway),
+        Gtfs::Rail => Some(RouteType::Rail),
+        Gtfs::Bus | Gtfs::Coach => Some(RouteType::Bus),
+        Gtfs::Ferry => Some(RouteType::Ferry),
+        Gtfs::CableCar => Some(RouteType::CableTram),
+        Gtfs::Gondola => Some(RouteType::AerialLift),
+        Gtfs::Funicular => Some(RouteType::Funicular),
+        Gtfs::Other(value) => u16::try_from(value).ok().and_then(RouteType::from_gtfs),
+        _ => None,
+    }
+}
+
+fn hex(r: u8, g: u8, b: u8) -> String {
+    format!("{:02X}{:02X}{:02X}", r, g, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::models::traits::TransitProvider;
+
+    fn id(s: &str) -> StationIdentifier {
+        StationIdentifier::new(s)
+    }
+
+    #[test]
+    fn test_interpolate_stop_times() {
+        // Evenly spaced without distances
+        let times = interpolate_stop_times(
+            [
+                (Some(100), Some(160), None),
+                (None, None, None),
+                (None, None, None),
+                (Some(460), Some(460), None),
+            ]
+            .into_iter(),
+        );
+        assert_eq!(
+            times,
+            vec![
+                Some((100, 160)),
+                Some((260, 260)),
+                Some((360, 360)),
+                Some((460, 460)),
+            ]
+        );
+
+        // Spaced by distance, and untimed ends stay unknown
+        let times = interpolate_stop_times(
+            [
+                (None, None, Some(0.0)),
+                (Some(0), Some(0), Some(0.0)),
+                (None, None, Some(900.0)),
+                (Some(100), None, Some(1000.0)),
+                (None, None, Some(1200.0)),
+            ]
+            .into_iter(),
+        );
+        assert_eq!(
+            times,
+            vec![None, Some((0, 0)), Some((90, 90)), Some((100, 100)), None]
+        );
+    }
+
+    #[test]
+    fn test_feed_transfers_and_pathways() {
+        let feed = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/transfers_feed");
+        let provider = load_gtfs(&feed).unwrap();
+        let graph = provider.transfer_graph();
+
+        // Through the mezzanine, the quickest way each direction
+        assert_eq!(graph.min_transfer_secs(&id("ts_7"), &id("ts_123")), Some(90));
+        assert_eq!(graph.min_transfer_secs(&id("ts_123"), &id("ts_7")), Some(150));
+
+        // Same-station minimum from transfers.txt
+        assert_eq!(graph.min_transfer_secs(&id("ts_7"), &id("ts_7")), Some(60));
+
+        // Forbidden to every platform of the parent station, but walking the
+        // other way is still generated
+        assert_eq!(graph.min_transfer_secs(&id("bryant_park"), &id("ts_7")), None);
+        assert_eq!(graph.min_transfer_secs(&id("bryant_park"), &id("ts_123")), None);
+        assert!(provider.min_transfer_secs(&id("ts_7"), &id("bryant_park")).is_some());
+    }
+
+    #[test]
+    fn test_feed_frequencies() {
+        let feed = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/frequencies_feed");
+        let provider = load_gtfs(&feed).unwrap();
+        let trip = provider.get_trip(&TripIdentifier::new("gs_shuttle")).unwrap();
+
+        assert_eq!(
+            trip.frequencies(),
+            &[
+                Frequency::new(6 * 3600, 7 * 3600, 600, false),
+                Frequency::new(7 * 3600, 9 * 3600, 300, true),
+            ]
+        );
+    }
+}
@@ -0,0 +1,10 @@
+//! Feed compilation (server-side).
+//!
+//! Turns GTFS feeds into a [`StaticTransitProvider`]. Only built with the
+//! `compiler` feature.
+//!
+//! [`StaticTransitProvider`]: crate::provider::StaticTransitProvider
+
+pub mod gtfs;
+
+pub use gtfs::{load_gtfs, provider_from_gtfs};
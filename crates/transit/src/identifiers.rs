@@ -60,6 +60,7 @@ impl_identifier!(RouteIdentifier);
 impl_identifier!(TripIdentifier);
 impl_identifier!(ComplexIdentifier);
 impl_identifier!(ServiceIdentifier);
+impl_identifier!(ShapeIdentifier);
 
 #[cfg(test)]
 mod tests {
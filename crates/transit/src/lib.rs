@@ -39,12 +39,24 @@
 //! assert_eq!(nearby.len(), 1);
 //! ```
 
+pub mod classify;
+#[cfg(feature = "compiler")]
+pub mod compiler;
 pub mod identifiers;
 pub mod models;
+pub mod naming;
 pub mod provider;
+pub mod report;
+pub mod routing;
+pub mod schedule;
+pub mod shapes;
 pub mod spatial;
+pub mod transfers;
 pub mod network;
 
+#[cfg(test)]
+mod test_support;
+
 // Re-exports for convenience
 pub mod prelude {
     pub use crate::identifiers::*;
@@ -1,8 +1,16 @@
 //! Service calendar for determining when trips run.
 //!
 //! Implements GTFS calendar.txt and calendar_dates.txt logic.
+//!
+//! ## Service Time
+//!
+//! GTFS stop times are measured from "noon minus 12h" in the agency
+//! timezone on the service date. This is midnight on most days, but on DST
+//! transition days it is an hour off from local midnight, which keeps stop
+//! times evenly spaced in real time.
 
-use chrono::{Datelike, NaiveDate, Weekday};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
 use std::collections::HashSet;
 use std::sync::Arc;
 
@@ -21,10 +29,13 @@ pub struct ServiceCalendar {
     // Exception dates
     pub added_dates: Arc<HashSet<NaiveDate>>,   // Service runs on these dates
     pub removed_dates: Arc<HashSet<NaiveDate>>, // Service does not run on these dates
+
+    /// Agency timezone that stop times are expressed in
+    pub timezone: Tz,
 }
 
 /// Compact representation of which weekdays a service runs
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct WeekdayFlags {
     pub(crate) flags: u8,
 }
@@ -79,6 +90,40 @@ impl ServiceCalendar {
 
         self.weekdays.contains(date.weekday())
     }
+
+    /// The instant that service time `0` refers to on `date` (noon minus 12h)
+    pub fn service_day_origin(&self, date: NaiveDate) -> DateTime<Utc> {
+        let noon = date.and_time(NaiveTime::from_hms_opt(12, 0, 0).expect("noon is always valid"));
+
+        // Noon is never skipped or repeated by real-world transitions, but
+        // fall back to the earliest mapping if a zone ever does so.
+        let noon = self
+            .timezone
+            .from_local_datetime(&noon)
+            .earliest()
+            .unwrap_or_else(|| self.timezone.from_utc_datetime(&noon));
+
+        noon.with_timezone(&Utc) - Duration::hours(12)
+    }
+
+    /// Convert a service time (seconds since the service day origin) to an instant
+    pub fn to_instant(&self, date: NaiveDate, seconds: u32) -> DateTime<Utc> {
+        self.service_day_origin(date) + Duration::seconds(seconds as i64)
+    }
+
+    /// Convert an instant to a service time on `date`
+    ///
+    /// Returns `None` if the instant is before the service day origin or too
+    /// far after it to be represented.
+    pub fn to_service_time(&self, date: NaiveDate, instant: DateTime<Utc>) -> Option<u32> {
+        let seconds = (instant - self.service_day_origin(date)).num_seconds();
+        u32::try_from(seconds).ok()
+    }
+
+    /// Local calendar date in the agency timezone at `instant`
+    pub fn local_date(&self, instant: DateTime<Utc>) -> NaiveDate {
+        instant.with_timezone(&self.timezone).date_naive()
+    }
 }
 
 #[cfg(test)]
@@ -86,6 +131,8 @@ mod tests {
     use super::*;
     use chrono::NaiveDate;
 
+    use crate::test_support::{daily, weekdays};
+
     #[test]
     fn test_weekday_flags() {
         let mut flags = WeekdayFlags::new();
@@ -101,16 +148,13 @@ mod tests {
     #[test]
     fn test_service_calendar() {
         let calendar = ServiceCalendar {
-            service_id: ServiceIdentifier::new("weekday"),
-            start_date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
-            end_date: NaiveDate::from_ymd_opt(2024, 12, 31).unwrap(),
-            weekdays: WeekdayFlags::from_bools(true, true, true, true, true, false, false),
             added_dates: Arc::new(HashSet::from([
                 NaiveDate::from_ymd_opt(2024, 7, 4).unwrap(), // Add July 4th (Thursday)
             ])),
             removed_dates: Arc::new(HashSet::from([
                 NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(), // Remove New Year's Day (Monday)
             ])),
+            ..weekdays(chrono_tz::America::New_York)
         };
 
         // Regular weekday
@@ -128,4 +172,78 @@ mod tests {
         // Out of range
         assert!(!calendar.runs_on(NaiveDate::from_ymd_opt(2025, 1, 1).unwrap()));
     }
+
+    fn new_york() -> ServiceCalendar {
+        daily(chrono_tz::America::New_York)
+    }
+
+    fn utc(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap()
+    }
+
+    #[test]
+    fn test_service_time_regular_day() {
+        let calendar = new_york();
+        let date = NaiveDate::from_ymd_opt(2024, 3, 4).unwrap();
+
+        // EST is UTC-5: the origin is local midnight
+        assert_eq!(calendar.service_day_origin(date), utc(2024, 3, 4, 5, 0));
+        assert_eq!(calendar.to_instant(date, 8 * 3600), utc(2024, 3, 4, 13, 0));
+
+        // 25:30 is 01:30 local the next morning
+        assert_eq!(calendar.to_instant(date, 25 * 3600 + 1800), utc(2024, 3, 5, 6, 30));
+    }
+
+    #[test]
+    fn test_service_time_spring_forward() {
+        let calendar = new_york();
+
+        // 2024-03-10: clocks jump from 02:00 EST to 03:00 EDT. Noon is EDT
+        // (16:00 UTC), so the origin is 04:00 UTC = 23:00 EST the day before.
+        let date = NaiveDate::from_ymd_opt(2024, 3, 10).unwrap();
+        assert_eq!(calendar.service_day_origin(date), utc(2024, 3, 10, 4, 0));
+
+        // "01:00" is 00:00 local wall time, "03:00" is 03:00 EDT
+        assert_eq!(calendar.to_instant(date, 3600), utc(2024, 3, 10, 5, 0));
+        assert_eq!(calendar.to_instant(date, 3 * 3600), utc(2024, 3, 10, 7, 0));
+
+        // "08:00" is 08:00 EDT
+        assert_eq!(calendar.to_instant(date, 8 * 3600), utc(2024, 3, 10, 12, 0));
+    }
+
+    #[test]
+    fn test_service_time_fall_back() {
+        let calendar = new_york();
+
+        // 2024-11-03: clocks fall back from 02:00 EDT to 01:00 EST. Noon is
+        // EST (17:00 UTC), so the origin is 05:00 UTC = 01:00 EDT.
+        let date = NaiveDate::from_ymd_opt(2024, 11, 3).unwrap();
+        assert_eq!(calendar.service_day_origin(date), utc(2024, 11, 3, 5, 0));
+
+        // "00:00" is 01:00 EDT; "02:00" is 01:00 EST, an hour later
+        assert_eq!(calendar.to_instant(date, 0), utc(2024, 11, 3, 5, 0));
+        assert_eq!(calendar.to_instant(date, 2 * 3600), utc(2024, 11, 3, 7, 0));
+
+        // "08:00" is 08:00 EST
+        assert_eq!(calendar.to_instant(date, 8 * 3600), utc(2024, 11, 3, 13, 0));
+    }
+
+    #[test]
+    fn test_service_time_round_trip() {
+        let calendar = new_york();
+
+        for date in [
+            NaiveDate::from_ymd_opt(2024, 3, 9).unwrap(),
+            NaiveDate::from_ymd_opt(2024, 3, 10).unwrap(),
+            NaiveDate::from_ymd_opt(2024, 11, 3).unwrap(),
+        ] {
+            for seconds in (0..30 * 3600).step_by(1234) {
+                let instant = calendar.to_instant(date, seconds);
+                assert_eq!(calendar.to_service_time(date, instant), Some(seconds));
+            }
+
+            let before = calendar.service_day_origin(date) - Duration::seconds(1);
+            assert_eq!(calendar.to_service_time(date, before), None);
+        }
+    }
 }
@@ -7,4 +7,4 @@ pub mod types;
 // Re-exports for convenience
 pub use calendar::{ServiceCalendar, WeekdayFlags};
 pub use traits::{Route, TransitComplex, TransitProvider, TransitStation, Trip};
-pub use types::{DirectionId, RouteType, StopEvent, TransitError, Result};
+pub use types::{DirectionId, Frequency, RouteType, StopEvent, TransitError, Result};
@@ -3,13 +3,17 @@
 //! These traits define the public interface for transit data.
 //! Implementations can be in-memory, database-backed, or remote.
 
-use chrono::NaiveDate;
-use geo::{LineString, Point};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use geo::{LineString, Point, Rect};
 use std::sync::Arc;
 
 use crate::identifiers::*;
 use crate::models::types::*;
 use crate::models::calendar::ServiceCalendar;
+use crate::naming::{NameRules, StationNames};
+use crate::schedule::{self, ActiveTrip, ScheduledStop, TripDelays, TripRun, VehiclePosition};
+use crate::shapes::{self, RoutePattern};
+use crate::transfers::{self, Transfer, TransferConfig};
 
 // ============================================================================
 // Core Entity Traits
@@ -21,8 +25,15 @@ pub trait Trip: Send + Sync {
     fn route_id(&self) -> &RouteIdentifier;
 
     /// Ordered stop events for this trip
+    ///
+    /// For frequency-based trips these are a template, shifted per run.
     fn stop_events(&self) -> &[StopEvent];
 
+    /// Headway windows, if this is a frequency-based trip
+    fn frequencies(&self) -> &[Frequency] {
+        &[]
+    }
+
     /// Which days does this trip run?
     fn runs_on(&self, date: NaiveDate) -> bool;
 
@@ -32,6 +43,11 @@ pub trait Trip: Send + Sync {
     /// Display name (e.g., "Downtown", "To City Center")
     fn headsign(&self) -> &str;
 
+    /// Physical path of this trip, if the feed specifies one
+    fn shape_id(&self) -> Option<&ShapeIdentifier> {
+        None
+    }
+
     /// Service calendar (for querying availability)
     fn service_calendar(&self) -> &ServiceCalendar;
 }
@@ -59,8 +75,9 @@ pub trait Route: Send + Sync {
         None
     }
 
-    /// Physical path the route takes (for spatial queries)
-    /// May be empty if geometry unavailable
+    /// Representative path of the route (for spatial queries)
+    /// May be empty if geometry unavailable; see
+    /// [`TransitProvider::route_shapes`] for per-pattern paths
     fn geometry(&self) -> Option<&LineString>;
 
     /// All trips on this route
@@ -119,4 +136,173 @@ pub trait TransitProvider: Send + Sync {
 
     /// Find the N nearest stations to a point
     fn nearest_stations(&self, point: Point, n: usize) -> Vec<Arc<dyn TransitStation>>;
+
+    // ---- Names ----
+
+    /// Normalised display and question names of a station
+    ///
+    /// Defaults to [`NameRules::default`]; providers configured for a city
+    /// should override this.
+    fn station_names(&self, station_id: &StationIdentifier) -> Option<StationNames> {
+        self.get_station(station_id)
+            .map(|station| NameRules::default().names(station.name()))
+    }
+
+    /// Stations whose question name has `length` letters and digits
+    fn stations_with_name_length(&self, length: u32) -> Vec<StationIdentifier> {
+        self.all_stations()
+            .iter()
+            .filter(|station| {
+                self.station_names(station.id())
+                    .is_some_and(|names| names.question_length == length)
+            })
+            .map(|station| station.id().clone())
+            .collect()
+    }
+
+    // ---- Shapes ----
+
+    /// Look up a shape by ID
+    fn get_shape(&self, _id: &ShapeIdentifier) -> Option<Arc<LineString>> {
+        None
+    }
+
+    /// Physical path of a trip
+    fn trip_shape(&self, trip_id: &TripIdentifier) -> Option<Arc<LineString>> {
+        let trip = self.get_trip(trip_id)?;
+        self.get_shape(trip.shape_id()?)
+    }
+
+    /// Distinct stop patterns of a route, most common first
+    fn route_patterns(&self, route_id: &RouteIdentifier) -> Vec<RoutePattern> {
+        self.get_route(route_id)
+            .map(|route| shapes::patterns_of(route.as_ref()))
+            .unwrap_or_default()
+    }
+
+    /// Every distinct path a route's trips take
+    ///
+    /// Falls back to [`Route::geometry`] when no trip has a shape.
+    fn route_shapes(&self, route_id: &RouteIdentifier) -> Vec<Arc<LineString>> {
+        let mut shapes: Vec<Arc<LineString>> = Vec::new();
+
+        for pattern in self.route_patterns(route_id) {
+            let Some(shape) = pattern.trip_ids.first().and_then(|id| self.trip_shape(id)) else {
+                continue;
+            };
+
+            if !shapes.iter().any(|s| Arc::ptr_eq(s, &shape) || s == &shape) {
+                shapes.push(shape);
+            }
+        }
+
+        if shapes.is_empty() {
+            if let Some(geometry) = self.get_route(route_id).and_then(|r| r.geometry().cloned()) {
+                shapes.push(Arc::new(geometry));
+            }
+        }
+
+        shapes
+    }
+
+    // ---- Transfers ----
+
+    /// Transfers leaving a station
+    ///
+    /// Defaults to walking and in-complex links generated from station
+    /// locations; providers with feed data should override this.
+    fn transfers_from(&self, station_id: &StationIdentifier) -> Vec<Transfer> {
+        transfers::generate_transfers_from(self, station_id, &TransferConfig::default())
+    }
+
+    /// Minimum seconds to transfer between two stations, or `None` if there
+    /// is no permitted transfer
+    fn min_transfer_secs(&self, from: &StationIdentifier, to: &StationIdentifier) -> Option<u32> {
+        let transfer = self.transfers_from(from).into_iter().find(|t| &t.to == to);
+
+        match transfer {
+            Some(transfer) => Some(transfer.min_transfer_secs),
+            None if from == to => Some(0),
+            None => None,
+        }
+    }
+
+    /// Complexes joined to a complex by in-system links (not including itself)
+    fn linked_complexes(&self, _complex_id: &ComplexIdentifier) -> Vec<ComplexIdentifier> {
+        Vec::new()
+    }
+
+    /// Do two stations count as the same station, i.e. are they in the same
+    /// complex or in complexes linked inside the system?
+    fn is_same_station(&self, a: &StationIdentifier, b: &StationIdentifier) -> bool {
+        if a == b {
+            return true;
+        }
+
+        let (Some(a), Some(b)) = (self.get_station(a), self.get_station(b)) else {
+            return false;
+        };
+
+        a.complex_id() == b.complex_id()
+            || self.linked_complexes(a.complex_id()).contains(b.complex_id())
+    }
+
+    // ---- Schedule queries ----
+
+    /// Departures from a station within `[at, at + window]`, sorted by time
+    fn departures_from(
+        &self,
+        station_id: &StationIdentifier,
+        at: DateTime<Utc>,
+        window: Duration,
+    ) -> Vec<ScheduledStop> {
+        schedule::departures_from(self, station_id, at, window)
+    }
+
+    /// Trips that are underway at a given time
+    fn trips_active_at(&self, at: DateTime<Utc>) -> Vec<ActiveTrip> {
+        schedule::trips_active_at(self, at)
+    }
+
+    /// The next (or current) stop of a trip that is underway
+    fn next_stop_of(&self, trip_id: &TripIdentifier, at: DateTime<Utc>) -> Option<ScheduledStop> {
+        schedule::next_stop_of(self, trip_id, at)
+    }
+
+    /// Stations served by a route's trips that are underway at a given time
+    fn stations_served_by(
+        &self,
+        route_id: &RouteIdentifier,
+        at: DateTime<Utc>,
+    ) -> Vec<StationIdentifier> {
+        schedule::stations_served_by(self, route_id, at)
+    }
+
+    /// Stations served by a route's trips that are underway at some point
+    /// within `[from, until]`
+    fn stations_served_between(
+        &self,
+        route_id: &RouteIdentifier,
+        from: DateTime<Utc>,
+        until: DateTime<Utc>,
+    ) -> Vec<StationIdentifier> {
+        schedule::stations_served_between(self, route_id, from, until)
+    }
+
+    /// Where a trip's vehicle is at a given time, interpolated along its shape
+    fn vehicle_position(&self, trip_id: &TripIdentifier, at: DateTime<Utc>) -> Option<VehiclePosition> {
+        schedule::vehicle_position(self, trip_id, at)
+    }
+
+    /// Every vehicle inside a bounding box at a given time
+    fn vehicles_in(&self, bounds: Rect, at: DateTime<Utc>) -> Vec<VehiclePosition> {
+        schedule::vehicles_in(self, bounds, at)
+    }
+
+    // ---- Realtime ----
+
+    /// Realtime delays for one run of a trip, if any have been received
+    fn trip_delays(&self, _trip_id: &TripIdentifier, _run: &TripRun) -> Option<TripDelays> {
+        None
+    }
 }
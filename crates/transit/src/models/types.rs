@@ -1,6 +1,9 @@
 //! Core data types and enums for transit data.
 
+use chrono::{DateTime, NaiveDate, Utc};
+
 use crate::identifiers::*;
+use crate::models::calendar::ServiceCalendar;
 
 // ============================================================================
 // Enums
@@ -50,7 +53,8 @@ pub enum DirectionId {
 
 /// A single stop event in a trip (arrival/departure at a station)
 ///
-/// Times are stored as seconds since midnight of the service day.
+/// Times are stored as seconds since the service day origin (local noon minus
+/// 12h, i.e. midnight except on DST transition days; see [`ServiceCalendar`]).
 /// Per GTFS spec, times can exceed 24 hours for trips past midnight
 /// (e.g., 25:30:00 = 91800 seconds for 1:30am the next day).
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -76,6 +80,48 @@ impl StopEvent {
         }
     }
 
+    /// Build a stop event from absolute arrival/departure instants
+    ///
+    /// Returns `Err` if either instant can't be expressed as a service time on
+    /// `service_date`, or if departure is before arrival.
+    pub fn from_instants(
+        station_id: StationIdentifier,
+        calendar: &ServiceCalendar,
+        service_date: NaiveDate,
+        arrival: DateTime<Utc>,
+        departure: DateTime<Utc>,
+        stop_sequence: u32,
+    ) -> Result<Self> {
+        let to_service_time = |instant: DateTime<Utc>| {
+            calendar.to_service_time(service_date, instant).ok_or_else(|| {
+                TransitError::InvalidData(format!(
+                    "{} is outside service day {}", instant, service_date
+                ))
+            })
+        };
+
+        let arrival = to_service_time(arrival)?;
+        let departure = to_service_time(departure)?;
+
+        if departure < arrival {
+            return Err(TransitError::InvalidData(
+                format!("Departure ({}) before arrival ({})", departure, arrival)
+            ));
+        }
+
+        Ok(Self::new(station_id, arrival, departure, stop_sequence))
+    }
+
+    /// Absolute arrival instant when run on `service_date`
+    pub fn arrival_instant(&self, calendar: &ServiceCalendar, service_date: NaiveDate) -> DateTime<Utc> {
+        calendar.to_instant(service_date, self.arrival)
+    }
+
+    /// Absolute departure instant when run on `service_date`
+    pub fn departure_instant(&self, calendar: &ServiceCalendar, service_date: NaiveDate) -> DateTime<Utc> {
+        calendar.to_instant(service_date, self.departure)
+    }
+
     /// Apply a delay in seconds (for realtime updates)
     ///
     /// Returns `Err` if the delay would cause the departure time to be before the arrival time.
@@ -98,6 +144,51 @@ impl StopEvent {
     }
 }
 
+/// A headway-based service window for a trip (GTFS frequencies.txt)
+///
+/// The trip's stop events act as a template: each run shifts them so the
+/// first stop's arrival lands on a run start time. Times are seconds since
+/// the service day origin, like [`StopEvent`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Frequency {
+    pub start_time: u32,
+    pub end_time: u32, // Exclusive: no run starts at or after this time
+    pub headway_secs: u32,
+
+    /// Runs depart exactly on the headway grid. When `false`, the feed only
+    /// promises the headway, and the grid is our best estimate; runs carry
+    /// this through as [`TripRun::exact`](crate::schedule::TripRun::exact).
+    pub exact_times: bool,
+}
+
+impl Frequency {
+    pub fn new(start_time: u32, end_time: u32, headway_secs: u32, exact_times: bool) -> Self {
+        Self {
+            start_time,
+            end_time,
+            headway_secs,
+            exact_times,
+        }
+    }
+
+    /// Start time of the `index`th run, if it starts within the window
+    pub fn run_start(&self, index: u32) -> Option<u32> {
+        if self.headway_secs == 0 {
+            return (index == 0 && self.start_time < self.end_time).then_some(self.start_time);
+        }
+
+        let start = self
+            .start_time
+            .checked_add(index.checked_mul(self.headway_secs)?)?;
+        (start < self.end_time).then_some(start)
+    }
+
+    /// Start times of every run in the window, in order
+    pub fn run_starts(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..).map_while(|index| self.run_start(index))
+    }
+}
+
 // ============================================================================
 // Errors
 // ============================================================================
@@ -121,6 +212,22 @@ pub enum TransitError {
 
     #[error("Serialization error: {0}")]
     SerializationError(String),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// The remote could not be reached (e.g. the device is offline)
+    #[error("Network error: {0}")]
+    Network(String),
+
+    #[error("HTTP {status} from {url}")]
+    HttpStatus { status: u16, url: String },
+
+    #[error("Checksum mismatch: expected {expected}, got {actual}")]
+    ChecksumMismatch { expected: String, actual: String },
+
+    #[error("Unsupported bundle format version {found} (supported up to {supported})")]
+    UnsupportedVersion { found: u32, supported: u32 },
 }
 
 pub type Result<T> = std::result::Result<T, TransitError>;
@@ -162,6 +269,61 @@ mod tests {
         assert!(event2.with_delay(-1100).is_ok());
     }
 
+    #[test]
+    fn test_stop_event_instants() {
+        use crate::test_support::daily;
+        use chrono::TimeZone;
+
+        let calendar = daily(chrono_tz::Europe::London);
+
+        // 2024-03-31: London springs forward at 01:00 GMT. The origin is
+        // 23:00 GMT on the 30th, so "01:30" is 00:30 GMT.
+        let date = NaiveDate::from_ymd_opt(2024, 3, 31).unwrap();
+        let event = StopEvent::new(StationIdentifier::new("kings_cross"), 5400, 5460, 1);
+
+        let arrival = event.arrival_instant(&calendar, date);
+        let departure = event.departure_instant(&calendar, date);
+        assert_eq!(arrival, Utc.with_ymd_and_hms(2024, 3, 31, 0, 30, 0).unwrap());
+
+        let rebuilt = StopEvent::from_instants(
+            StationIdentifier::new("kings_cross"),
+            &calendar,
+            date,
+            arrival,
+            departure,
+            1,
+        )
+        .unwrap();
+        assert_eq!(rebuilt, event);
+
+        // Departing before arriving is rejected
+        assert!(StopEvent::from_instants(
+            StationIdentifier::new("kings_cross"),
+            &calendar,
+            date,
+            departure,
+            arrival,
+            1,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_frequency_run_starts() {
+        // Every 10 minutes from 06:00 until (not including) 07:00
+        let frequency = Frequency::new(6 * 3600, 7 * 3600, 600, true);
+        let starts: Vec<_> = frequency.run_starts().collect();
+
+        assert_eq!(starts.len(), 6);
+        assert_eq!(starts[0], 6 * 3600);
+        assert_eq!(starts[5], 6 * 3600 + 3000);
+        assert_eq!(frequency.run_start(6), None);
+
+        // A zero headway is treated as a single run rather than looping
+        let degenerate = Frequency::new(100, 200, 0, false);
+        assert_eq!(degenerate.run_starts().collect::<Vec<_>>(), vec![100]);
+    }
+
     #[test]
     fn test_route_type_from_gtfs() {
         assert_eq!(RouteType::from_gtfs(1), Some(RouteType::Subway));
@@ -0,0 +1,92 @@
+//! Lookup of stations by normalised name length.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::identifiers::*;
+use crate::models::traits::TransitProvider;
+use crate::naming::{NameRules, StationNames};
+
+/// Normalised names of every station, indexed by question-name length
+#[derive(Clone, Debug, Default)]
+pub struct NameIndex {
+    names: HashMap<StationIdentifier, StationNames>,
+    by_length: BTreeMap<u32, Vec<StationIdentifier>>,
+}
+
+impl NameIndex {
+    pub fn build<P: TransitProvider + ?Sized>(provider: &P, rules: &NameRules) -> Self {
+        let mut index = Self::default();
+
+        for station in provider.all_stations() {
+            let names = rules.names(station.name());
+
+            index
+                .by_length
+                .entry(names.question_length)
+                .or_default()
+                .push(station.id().clone());
+            index.names.insert(station.id().clone(), names);
+        }
+
+        for stations in index.by_length.values_mut() {
+            stations.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        }
+
+        index
+    }
+
+    pub fn names(&self, station_id: &StationIdentifier) -> Option<&StationNames> {
+        self.names.get(station_id)
+    }
+
+    /// Stations whose question name has `length` letters and digits
+    pub fn stations_with_length(&self, length: u32) -> &[StationIdentifier] {
+        self.by_length
+            .get(&length)
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    /// Every length that occurs, ascending
+    pub fn lengths(&self) -> impl Iterator<Item = u32> + '_ {
+        self.by_length.keys().copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::Point;
+
+    use crate::provider::{StaticTransitProvider, StationImpl};
+
+    #[test]
+    fn test_length_index() {
+        let stations = ["Times Sq-42 St", "Times Square - 42 Street", "St George", "Penn Station"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| StationImpl {
+                id: StationIdentifier::new(format!("s{i}")),
+                name: (*name).into(),
+                location: Point::new(-74.0, 40.7),
+                complex_id: ComplexIdentifier::new(format!("c{i}")),
+            })
+            .collect();
+
+        let provider = StaticTransitProvider::from_data(stations, vec![], vec![])
+            .with_name_rules(&NameRules::new_york());
+
+        assert_eq!(
+            provider.stations_with_name_length(19),
+            vec![StationIdentifier::new("s0"), StationIdentifier::new("s1")]
+        );
+        assert_eq!(provider.stations_with_name_length(4), vec![StationIdentifier::new("s3")]);
+
+        let names = provider.station_names(&StationIdentifier::new("s0")).unwrap();
+        assert_eq!(names.display.as_ref(), "Times Sq-42 St");
+        assert_eq!(names.question.as_ref(), "Times Square-42 Street");
+
+        let lengths: Vec<u32> = provider.name_index().lengths().collect();
+        assert_eq!(lengths, vec![4, 8, 19]);
+    }
+}
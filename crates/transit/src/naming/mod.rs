@@ -0,0 +1,31 @@
+//! Station name normalisation.
+//!
+//! Feed names are written for signage, not for questions: "Times Sq-42 St",
+//! "Jamaica Station (LIRR)", "Central - Platform 4". This module derives two
+//! names per station with configurable, per-city [`NameRules`]:
+//!
+//! - **Display name**: the feed name with platform annotations removed and
+//!   whitespace and punctuation tidied
+//! - **Question name**: the display name with suffixes stripped and
+//!   abbreviations expanded, used for name-length questions
+//!
+//! The question length counts letters and digits only, so spaces, hyphens
+//! and apostrophes never decide an answer.
+
+pub mod index;
+pub mod rules;
+
+pub use index::NameIndex;
+pub use rules::{question_length, NameRules, Replacement};
+
+use std::sync::Arc;
+
+/// Normalised names of a station
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StationNames {
+    pub display: Arc<str>,
+    pub question: Arc<str>,
+
+    /// Letters and digits (as grapheme clusters) in the question name
+    pub question_length: u32,
+}
@@ -0,0 +1,352 @@
+//! Configurable name normalisation rules.
+
+use std::sync::Arc;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::naming::StationNames;
+
+/// Words introducing a platform annotation, e.g. "Platform 4", "Track 2"
+const PLATFORM_WORDS: &[&str] = &[
+    "platform", "platforms", "track", "tracks", "bay", "stand", "quai", "voie", "gleis",
+];
+
+/// Direction annotations, e.g. "Broadway - Northbound"
+const DIRECTION_WORDS: &[&str] = &[
+    "northbound", "southbound", "eastbound", "westbound", "uptown", "downtown", "inbound",
+    "outbound", "nb", "sb", "eb", "wb",
+];
+
+/// Count the letters and digits in a name, as grapheme clusters
+pub fn question_length(name: &str) -> u32 {
+    name.graphemes(true)
+        .filter(|g| g.chars().next().is_some_and(char::is_alphanumeric))
+        .count() as u32
+}
+
+/// A whole-word replacement, e.g. "Sq" → "Square"
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Replacement {
+    pub from: Arc<str>,
+    pub to: Arc<str>,
+
+    /// Only replace when the previous word is a number ("42 St" but not
+    /// "St George")
+    pub after_number_only: bool,
+}
+
+impl Replacement {
+    pub fn new(from: &str, to: &str) -> Self {
+        Self {
+            from: from.into(),
+            to: to.into(),
+            after_number_only: false,
+        }
+    }
+
+    pub fn after_number(mut self) -> Self {
+        self.after_number_only = true;
+        self
+    }
+}
+
+/// Per-city rules turning feed names into display and question names
+#[derive(Clone, Debug)]
+pub struct NameRules {
+    /// Trailing words removed from question names, matched case-insensitively
+    /// (e.g. "Station")
+    pub strip_suffixes: Vec<Arc<str>>,
+
+    /// Remove "(…)" and "[…]" from both names
+    pub strip_parentheticals: bool,
+
+    /// Remove trailing platform and direction annotations from both names
+    pub strip_platform_annotations: bool,
+
+    /// Whole-word replacements applied to question names, matched
+    /// case-insensitively
+    pub replacements: Vec<Replacement>,
+}
+
+impl Default for NameRules {
+    fn default() -> Self {
+        Self {
+            strip_suffixes: vec!["Station".into(), "Stn".into(), "Sta".into()],
+            strip_parentheticals: true,
+            strip_platform_annotations: true,
+            replacements: Vec::new(),
+        }
+    }
+}
+
+impl NameRules {
+    /// Rules that only tidy whitespace and punctuation
+    pub fn none() -> Self {
+        Self {
+            strip_suffixes: Vec::new(),
+            strip_parentheticals: false,
+            strip_platform_annotations: false,
+            replacements: Vec::new(),
+        }
+    }
+
+    /// MTA-style abbreviations ("Times Sq-42 St", "Nostrand Av")
+    pub fn new_york() -> Self {
+        Self::default()
+            .with_replacement(Replacement::new("St", "Street").after_number())
+            .with_replacement(Replacement::new("Av", "Avenue"))
+            .with_replacement(Replacement::new("Ave", "Avenue"))
+            .with_replacement(Replacement::new("Sq", "Square"))
+            .with_replacement(Replacement::new("Pkwy", "Parkway"))
+            .with_replacement(Replacement::new("Blvd", "Boulevard"))
+            .with_replacement(Replacement::new("Hts", "Heights"))
+            .with_replacement(Replacement::new("Jct", "Junction"))
+            .with_replacement(Replacement::new("Ctr", "Center"))
+            .with_replacement(Replacement::new("Rd", "Road"))
+            .with_replacement(Replacement::new("Pl", "Place"))
+            .with_replacement(Replacement::new("Tpke", "Turnpike"))
+    }
+
+    pub fn with_suffix(mut self, suffix: &str) -> Self {
+        self.strip_suffixes.push(suffix.into());
+        self
+    }
+
+    pub fn with_replacement(mut self, replacement: Replacement) -> Self {
+        self.replacements.push(replacement);
+        self
+    }
+
+    pub fn with_parentheticals_stripped(mut self, strip: bool) -> Self {
+        self.strip_parentheticals = strip;
+        self
+    }
+
+    pub fn with_platform_annotations_stripped(mut self, strip: bool) -> Self {
+        self.strip_platform_annotations = strip;
+        self
+    }
+
+    /// Display and question names for a feed name
+    pub fn names(&self, raw: &str) -> StationNames {
+        let display = self.display_name(raw);
+        let question = self.question_name_from_display(&display);
+
+        StationNames {
+            question_length: question_length(&question),
+            display: display.into(),
+            question: question.into(),
+        }
+    }
+
+    pub fn display_name(&self, raw: &str) -> String {
+        let mut name = normalize_punctuation(raw);
+
+        if self.strip_parentheticals {
+            name = remove_bracketed(&name);
+        }
+
+        if self.strip_platform_annotations {
+            name = remove_trailing_annotations(&tidy(&name));
+        }
+
+        tidy(&name)
+    }
+
+    pub fn question_name(&self, raw: &str) -> String {
+        self.question_name_from_display(&self.display_name(raw))
+    }
+
+    fn question_name_from_display(&self, display: &str) -> String {
+        let mut name = display.replace('.', "");
+
+        // Strip suffixes repeatedly ("Central Station Stn"), but never down to nothing
+        'strip: loop {
+            for suffix in &self.strip_suffixes {
+                if let Some(stripped) = strip_word_suffix(&name, suffix) {
+                    name = tidy(stripped);
+                    continue 'strip;
+                }
+            }
+            break;
+        }
+
+        tidy(&self.replace_words(&name))
+    }
+
+    fn replace_words(&self, name: &str) -> String {
+        if self.replacements.is_empty() {
+            return name.to_string();
+        }
+
+        let mut result = String::with_capacity(name.len());
+        let mut previous_word: Option<&str> = None;
+
+        for word in name.split_word_bounds() {
+            let is_word = word.chars().next().is_some_and(char::is_alphanumeric);
+
+            if !is_word {
+                result.push_str(word);
+                continue;
+            }
+
+            let after_number =
+                previous_word.is_some_and(|w| w.chars().next().is_some_and(|c| c.is_ascii_digit()));
+
+            let replacement = self.replacements.iter().find(|r| {
+                r.from.eq_ignore_ascii_case(word) && (!r.after_number_only || after_number)
+            });
+
+            match replacement {
+                Some(replacement) => result.push_str(&replacement.to),
+                None => result.push_str(word),
+            }
+
+            previous_word = Some(word);
+        }
+
+        result
+    }
+}
+
+/// Map dash, quote and space variants to their ASCII forms
+fn normalize_punctuation(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '\u{2010}'..='\u{2015}' | '\u{2212}' => '-',
+            '\u{2018}' | '\u{2019}' | '\u{02BC}' => '\'',
+            '\u{201C}' | '\u{201D}' => '"',
+            c if c.is_whitespace() => ' ',
+            c => c,
+        })
+        .collect()
+}
+
+/// Remove "(…)" and "[…]" groups, including nested ones
+fn remove_bracketed(name: &str) -> String {
+    let mut depth = 0usize;
+    let mut result = String::with_capacity(name.len());
+
+    for c in name.chars() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' if depth > 0 => depth -= 1,
+            _ if depth == 0 => result.push(c),
+            _ => {}
+        }
+    }
+
+    result
+}
+
+/// Collapse whitespace, close up space around hyphens and slashes, and trim
+/// separators from the ends
+fn tidy(name: &str) -> String {
+    let collapsed = name.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    collapsed
+        .replace(" -", "-")
+        .replace("- ", "-")
+        .replace(" /", "/")
+        .replace("/ ", "/")
+        .trim_matches(|c: char| c == '-' || c == '/' || c == ',' || c.is_whitespace())
+        .to_string()
+}
+
+/// Remove "… Platform 4", "…-Track B" or "…-Northbound" from the end of a
+/// tidied name
+fn remove_trailing_annotations(name: &str) -> String {
+    let mut name = name.to_string();
+
+    loop {
+        let words: Vec<&str> = name.split([' ', '-', ',']).filter(|w| !w.is_empty()).collect();
+
+        let cut = match words.as_slice() {
+            [.., keyword, label]
+                if words.len() > 2
+                    && PLATFORM_WORDS.contains(&keyword.to_lowercase().as_str())
+                    && label.chars().count() <= 3
+                    && label.chars().all(char::is_alphanumeric) =>
+            {
+                Some(name.rfind(keyword).expect("word comes from the name"))
+            }
+            [.., direction]
+                if words.len() > 1 && DIRECTION_WORDS.contains(&direction.to_lowercase().as_str()) =>
+            {
+                Some(name.rfind(direction).expect("word comes from the name"))
+            }
+            _ => None,
+        };
+
+        match cut {
+            Some(index) => name = tidy(&name[..index]),
+            None => return name,
+        }
+    }
+}
+
+/// `name` without a trailing whole-word `suffix`, unless that would leave
+/// nothing
+fn strip_word_suffix<'a>(name: &'a str, suffix: &str) -> Option<&'a str> {
+    let split = name.len().checked_sub(suffix.len())?;
+
+    if split == 0 || !name.is_char_boundary(split) {
+        return None;
+    }
+
+    let (rest, tail) = name.split_at(split);
+    let at_word_boundary = rest.ends_with([' ', '-', '/']);
+
+    (tail.eq_ignore_ascii_case(suffix) && at_word_boundary).then_some(rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_names() {
+        let rules = NameRules::default();
+
+        assert_eq!(rules.display_name("  Jamaica   Station (LIRR) "), "Jamaica Station");
+        assert_eq!(rules.display_name("Times Sq – 42 St"), "Times Sq-42 St");
+        assert_eq!(rules.display_name("Central - Platform 4"), "Central");
+        assert_eq!(rules.display_name("Broadway Junction-Track B"), "Broadway Junction");
+        assert_eq!(rules.display_name("Canal St - Uptown"), "Canal St");
+
+        // Not an annotation when it's the whole name
+        assert_eq!(rules.display_name("Uptown"), "Uptown");
+        assert_eq!(rules.display_name("Track 7"), "Track 7");
+    }
+
+    #[test]
+    fn test_question_names() {
+        let rules = NameRules::default();
+        assert_eq!(rules.question_name("Jamaica Station (LIRR)"), "Jamaica");
+        assert_eq!(rules.question_name("Station"), "Station");
+        assert_eq!(rules.question_name("Penn Stn."), "Penn");
+        assert_eq!(rules.question_name("Eastation"), "Eastation");
+
+        let nyc = NameRules::new_york();
+        assert_eq!(nyc.question_name("Times Sq-42 St"), "Times Square-42 Street");
+        assert_eq!(nyc.question_name("Times Sq - 42 St."), "Times Square-42 Street");
+        assert_eq!(nyc.question_name("St George"), "St George");
+        assert_eq!(nyc.question_name("Nostrand Av"), "Nostrand Avenue");
+    }
+
+    #[test]
+    fn test_question_length() {
+        assert_eq!(question_length("Times Square-42 Street"), 19);
+        assert_eq!(question_length("Prince's Bay"), 10);
+
+        // Combining accents count once with their letter
+        assert_eq!(question_length("Ope\u{301}ra"), 5);
+        assert_eq!(question_length("Opéra"), 5);
+
+        let nyc = NameRules::new_york();
+        assert_eq!(
+            nyc.names("Times Sq-42 St").question_length,
+            nyc.names("Times Square - 42 Street").question_length
+        );
+    }
+}
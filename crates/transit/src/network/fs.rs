@@ -0,0 +1,166 @@
+//! Local filesystem storage.
+
+use std::future::Future;
+use std::path::{Component, Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use tokio::io::AsyncWriteExt;
+
+use crate::models::types::{Result, TransitError};
+use crate::network::traits::StorageLoader;
+
+/// Stores files under a root directory
+///
+/// Paths passed to the loader are relative to the root and may not escape
+/// it. Writes are atomic: data goes to a temporary file in the same
+/// directory, is flushed to disk, then renamed over the destination, so a
+/// crash mid-write never leaves a truncated bundle behind.
+pub struct FsStorageLoader {
+    root: PathBuf,
+    temp_counter: AtomicU64,
+}
+
+impl FsStorageLoader {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            temp_counter: AtomicU64::new(0),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn resolve(&self, path: &str) -> Result<PathBuf> {
+        let relative = Path::new(path);
+
+        let is_contained = relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+
+        if path.is_empty() || !is_contained {
+            return Err(TransitError::InvalidData(format!(
+                "Storage path must be relative and stay within the root: {:?}",
+                path
+            )));
+        }
+
+        Ok(self.root.join(relative))
+    }
+
+    async fn write_atomic(&self, destination: &Path, data: &[u8]) -> Result<()> {
+        let parent = destination
+            .parent()
+            .expect("resolved paths always have a parent");
+        tokio::fs::create_dir_all(parent).await?;
+
+        let file_name = destination
+            .file_name()
+            .expect("resolved paths always have a file name")
+            .to_string_lossy();
+        let temp = parent.join(format!(
+            ".{}.{}-{}.tmp",
+            file_name,
+            std::process::id(),
+            self.temp_counter.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let result = async {
+            let mut file = tokio::fs::File::create(&temp).await?;
+            file.write_all(data).await?;
+            file.sync_all().await?;
+            drop(file);
+
+            tokio::fs::rename(&temp, destination).await
+        }
+        .await;
+
+        if result.is_err() {
+            let _ = tokio::fs::remove_file(&temp).await;
+        }
+
+        Ok(result?)
+    }
+}
+
+impl StorageLoader for FsStorageLoader {
+    fn load<'a>(
+        &'a self,
+        path: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>>> + Send + 'a>> {
+        Box::pin(async move {
+            let path = self.resolve(path)?;
+            Ok(tokio::fs::read(path).await?)
+        })
+    }
+
+    fn save<'a>(
+        &'a self,
+        path: &'a str,
+        data: &'a [u8],
+    ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+        Box::pin(async move {
+            let path = self.resolve(path)?;
+            self.write_atomic(&path, data).await
+        })
+    }
+
+    fn exists<'a>(
+        &'a self,
+        path: &'a str,
+    ) -> Pin<Box<dyn Future<Output = bool> + Send + 'a>> {
+        Box::pin(async move {
+            match self.resolve(path) {
+                Ok(path) => tokio::fs::try_exists(path).await.unwrap_or(false),
+                Err(_) => false,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "jet-lag-transit-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_save_and_load() {
+        let dir = scratch_dir("fs-roundtrip");
+        let storage = FsStorageLoader::new(&dir);
+
+        assert!(!storage.exists("bundles/nyc.bundle").await);
+
+        storage.save("bundles/nyc.bundle", b"first").await.unwrap();
+        storage.save("bundles/nyc.bundle", b"second").await.unwrap();
+
+        assert!(storage.exists("bundles/nyc.bundle").await);
+        assert_eq!(storage.load("bundles/nyc.bundle").await.unwrap(), b"second");
+
+        // No temporary files are left behind
+        let entries: Vec<_> = std::fs::read_dir(dir.join("bundles")).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rejects_escaping_paths() {
+        let storage = FsStorageLoader::new(scratch_dir("fs-escape"));
+
+        assert!(storage.save("../outside", b"nope").await.is_err());
+        assert!(storage.save("/etc/passwd", b"nope").await.is_err());
+        assert!(storage.load("a/../../b").await.is_err());
+        assert!(!storage.exists("").await);
+    }
+}
@@ -0,0 +1,220 @@
+//! HTTP data fetching.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use reqwest::header::{HeaderMap, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
+
+use crate::models::types::{Result, TransitError};
+use crate::network::traits::{
+    CacheValidators, DataFetcher, FetchResponse, ResponseMetadata, BUNDLE_SHA256_HEADER,
+    BUNDLE_VERSION_HEADER,
+};
+
+/// Fetches over HTTP(S) with `reqwest`
+///
+/// Supports conditional requests via `If-None-Match` / `If-Modified-Since`.
+/// HTTPS requires the `http-tls` feature (or TLS enabled on `reqwest`
+/// elsewhere in the dependency graph).
+#[derive(Clone, Default)]
+pub struct HttpFetcher {
+    client: reqwest::Client,
+}
+
+impl HttpFetcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_client(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+
+    async fn get(&self, url: &str, validators: &CacheValidators) -> Result<reqwest::Response> {
+        let mut request = self.client.get(url);
+
+        if let Some(etag) = &validators.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| TransitError::Network(e.to_string()))?;
+
+        let status = response.status();
+        if !status.is_success() && status != StatusCode::NOT_MODIFIED {
+            return Err(TransitError::HttpStatus {
+                status: status.as_u16(),
+                url: url.to_string(),
+            });
+        }
+
+        Ok(response)
+    }
+}
+
+fn header_string(headers: &HeaderMap, name: impl reqwest::header::AsHeaderName) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+fn response_metadata(headers: &HeaderMap) -> Result<ResponseMetadata> {
+    let version = header_string(headers, BUNDLE_VERSION_HEADER)
+        .map(|v| {
+            v.trim().parse::<u32>().map_err(|_| {
+                TransitError::InvalidData(format!("Invalid {} header: {:?}", BUNDLE_VERSION_HEADER, v))
+            })
+        })
+        .transpose()?;
+
+    Ok(ResponseMetadata {
+        validators: CacheValidators {
+            etag: header_string(headers, ETAG),
+            last_modified: header_string(headers, LAST_MODIFIED),
+        },
+        version,
+        sha256: header_string(headers, BUNDLE_SHA256_HEADER).map(|s| s.trim().to_ascii_lowercase()),
+    })
+}
+
+impl DataFetcher for HttpFetcher {
+    fn fetch<'a>(
+        &'a self,
+        url: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>>> + Send + 'a>> {
+        Box::pin(async move {
+            let response = self.get(url, &CacheValidators::default()).await?;
+            let body = response
+                .bytes()
+                .await
+                .map_err(|e| TransitError::Network(e.to_string()))?;
+
+            Ok(body.to_vec())
+        })
+    }
+
+    fn fetch_conditional<'a>(
+        &'a self,
+        url: &'a str,
+        validators: &'a CacheValidators,
+    ) -> Pin<Box<dyn Future<Output = Result<FetchResponse>> + Send + 'a>> {
+        Box::pin(async move {
+            let response = self.get(url, validators).await?;
+
+            if response.status() == StatusCode::NOT_MODIFIED {
+                return Ok(FetchResponse::NotModified);
+            }
+
+            let metadata = response_metadata(response.headers())?;
+            let data = response
+                .bytes()
+                .await
+                .map_err(|e| TransitError::Network(e.to_string()))?
+                .to_vec();
+
+            Ok(FetchResponse::Modified { data, metadata })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    /// Serves canned responses to `count` requests, reporting each request's
+    /// headers back to the test
+    fn stub_server(responses: Vec<&'static str>) -> (String, mpsc::Receiver<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut request = Vec::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end().to_string();
+                    if line.is_empty() {
+                        break;
+                    }
+                    request.push(line);
+                }
+
+                sender.send(request).unwrap();
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        (address, receiver)
+    }
+
+    #[tokio::test]
+    async fn test_fetch_and_conditional_fetch() {
+        let (address, requests) = stub_server(vec![
+            "HTTP/1.1 200 OK\r\n\
+             Content-Length: 5\r\n\
+             ETag: \"v1\"\r\n\
+             Last-Modified: Mon, 04 Mar 2024 12:00:00 GMT\r\n\
+             X-Bundle-Version: 3\r\n\
+             X-Bundle-Sha256: ABCDEF\r\n\
+             Connection: close\r\n\r\nhello",
+            "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ]);
+        let fetcher = HttpFetcher::new();
+        let url = format!("{}/transit/nyc/bundle", address);
+
+        let FetchResponse::Modified { data, metadata } = fetcher
+            .fetch_conditional(&url, &CacheValidators::default())
+            .await
+            .unwrap()
+        else {
+            panic!("expected a full response");
+        };
+        assert_eq!(data, b"hello");
+        assert_eq!(metadata.validators.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(metadata.version, Some(3));
+        assert_eq!(metadata.sha256.as_deref(), Some("abcdef"));
+        requests.recv().unwrap();
+
+        let response = fetcher
+            .fetch_conditional(&url, &metadata.validators)
+            .await
+            .unwrap();
+        assert!(matches!(response, FetchResponse::NotModified));
+
+        let request = requests.recv().unwrap().join("\n").to_ascii_lowercase();
+        assert!(request.contains("if-none-match: \"v1\""));
+        assert!(request.contains("if-modified-since: mon, 04 mar 2024 12:00:00 gmt"));
+
+        let error = fetcher.fetch(&url).await.unwrap_err();
+        assert!(matches!(error, TransitError::HttpStatus { status: 404, .. }));
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_is_network_error() {
+        // Bind then drop to get a port nothing is listening on
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+
+        let error = HttpFetcher::new()
+            .fetch(&format!("http://127.0.0.1:{}/", port))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, TransitError::Network(_)));
+    }
+}
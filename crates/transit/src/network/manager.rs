@@ -0,0 +1,590 @@
+//! Default bundle manager.
+//!
+//! Combines a [`DataFetcher`] and a [`StorageLoader`] into a
+//! [`BundleManager`] that keeps a verified local copy of each network's
+//! bundle, revalidating it with conditional requests and falling back to it
+//! when the network is unreachable.
+//!
+//! ## Layout
+//!
+//! For each network, storage holds `bundles/{network_id}.bundle` and a small
+//! `bundles/{network_id}.meta` sidecar with the cache validators, format
+//! version and checksum of the stored copy.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use sha2::{Digest, Sha256};
+
+use crate::models::types::{Result, TransitError};
+use crate::network::traits::{
+    BundleManager, CacheValidators, DataFetcher, FetchResponse, StorageLoader,
+};
+
+/// Newest bundle format this crate can read
+pub const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+/// What to do when the bundle server can't be reached
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OfflinePolicy {
+    /// Serve the stored bundle, if there is one
+    #[default]
+    UseCached,
+
+    /// Surface the network error
+    Fail,
+}
+
+// ============================================================================
+// Stored Metadata
+// ============================================================================
+
+/// Sidecar metadata describing a stored bundle
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BundleMetadata {
+    pub validators: CacheValidators,
+    pub version: Option<u32>,
+    pub sha256: String,
+}
+
+impl BundleMetadata {
+    /// Encode as `key=value` lines
+    pub fn encode(&self) -> String {
+        let mut out = String::new();
+
+        if let Some(etag) = &self.validators.etag {
+            out.push_str(&format!("etag={}\n", etag));
+        }
+        if let Some(last_modified) = &self.validators.last_modified {
+            out.push_str(&format!("last_modified={}\n", last_modified));
+        }
+        if let Some(version) = self.version {
+            out.push_str(&format!("version={}\n", version));
+        }
+        out.push_str(&format!("sha256={}\n", self.sha256));
+
+        out
+    }
+
+    /// Decode from `key=value` lines, ignoring unknown keys
+    pub fn decode(text: &str) -> Result<Self> {
+        let mut metadata = Self::default();
+
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| TransitError::InvalidData(format!("Malformed metadata line: {:?}", line)))?;
+
+            match key {
+                "etag" => metadata.validators.etag = Some(value.to_string()),
+                "last_modified" => metadata.validators.last_modified = Some(value.to_string()),
+                "version" => {
+                    metadata.version = Some(value.parse().map_err(|_| {
+                        TransitError::InvalidData(format!("Invalid metadata version: {:?}", value))
+                    })?)
+                }
+                "sha256" => metadata.sha256 = value.to_string(),
+                _ => {}
+            }
+        }
+
+        if metadata.sha256.is_empty() {
+            return Err(TransitError::InvalidData("Metadata is missing a checksum".into()));
+        }
+
+        Ok(metadata)
+    }
+}
+
+/// Hex-encoded SHA-256 of `data`
+pub fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+fn verify_checksum(data: &[u8], expected: &str) -> Result<()> {
+    let actual = sha256_hex(data);
+
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(TransitError::ChecksumMismatch {
+            expected: expected.to_string(),
+            actual,
+        });
+    }
+
+    Ok(())
+}
+
+fn check_version(version: Option<u32>) -> Result<()> {
+    match version {
+        Some(found) if found > BUNDLE_FORMAT_VERSION => Err(TransitError::UnsupportedVersion {
+            found,
+            supported: BUNDLE_FORMAT_VERSION,
+        }),
+        _ => Ok(()),
+    }
+}
+
+// ============================================================================
+// Manager
+// ============================================================================
+
+/// [`BundleManager`] backed by any fetcher and storage
+///
+/// Bundles are fetched from `{base_url}/transit/{network_id}/bundle` and
+/// realtime feeds from `{base_url}/transit/{network_id}/realtime`.
+pub struct DefaultBundleManager<F, S> {
+    fetcher: F,
+    storage: S,
+    base_url: String,
+    offline_policy: OfflinePolicy,
+}
+
+impl<F: DataFetcher, S: StorageLoader> DefaultBundleManager<F, S> {
+    pub fn new(fetcher: F, storage: S, base_url: impl Into<String>) -> Self {
+        Self {
+            fetcher,
+            storage,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            offline_policy: OfflinePolicy::default(),
+        }
+    }
+
+    pub fn with_offline_policy(mut self, offline_policy: OfflinePolicy) -> Self {
+        self.offline_policy = offline_policy;
+        self
+    }
+
+    pub fn fetcher(&self) -> &F {
+        &self.fetcher
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    fn bundle_url(&self, network_id: &str) -> String {
+        format!("{}/transit/{}/bundle", self.base_url, network_id)
+    }
+
+    fn realtime_url(&self, network_id: &str) -> String {
+        format!("{}/transit/{}/realtime", self.base_url, network_id)
+    }
+
+    fn bundle_path(network_id: &str) -> Result<String> {
+        validate_network_id(network_id)?;
+        Ok(format!("bundles/{}.bundle", network_id))
+    }
+
+    fn metadata_path(network_id: &str) -> Result<String> {
+        validate_network_id(network_id)?;
+        Ok(format!("bundles/{}.meta", network_id))
+    }
+
+    /// Metadata for the stored copy, if there is a readable one
+    pub async fn stored_metadata(&self, network_id: &str) -> Option<BundleMetadata> {
+        let path = Self::metadata_path(network_id).ok()?;
+        let bytes = self.storage.load(&path).await.ok()?;
+
+        BundleMetadata::decode(&String::from_utf8_lossy(&bytes)).ok()
+    }
+
+    async fn store(&self, network_id: &str, data: &[u8], metadata: &BundleMetadata) -> Result<()> {
+        // Bundle first: if we crash between the two writes the old metadata
+        // no longer matches, and the checksum check rejects the pair.
+        self.storage.save(&Self::bundle_path(network_id)?, data).await?;
+        self.storage
+            .save(&Self::metadata_path(network_id)?, metadata.encode().as_bytes())
+            .await
+    }
+
+    async fn download(&self, network_id: &str) -> Result<Vec<u8>> {
+        let stored = match self.has_bundle(network_id).await {
+            true => self.stored_metadata(network_id).await,
+            false => None,
+        };
+        let validators = stored
+            .as_ref()
+            .map(|m| m.validators.clone())
+            .unwrap_or_default();
+
+        let url = self.bundle_url(network_id);
+
+        let response = match self.fetcher.fetch_conditional(&url, &validators).await? {
+            // Our copy is current, as long as it's intact; if not, fetch again
+            // without validators.
+            FetchResponse::NotModified => match self.load_bundle(network_id).await {
+                Ok(data) => return Ok(data),
+                Err(_) => {
+                    self.fetcher
+                        .fetch_conditional(&url, &CacheValidators::default())
+                        .await?
+                }
+            },
+            response => response,
+        };
+
+        match response {
+            FetchResponse::NotModified => Err(TransitError::InvalidData(format!(
+                "Server reported {} unchanged without validators",
+                url
+            ))),
+
+            FetchResponse::Modified { data, metadata } => {
+                check_version(metadata.version)?;
+
+                if let Some(expected) = &metadata.sha256 {
+                    verify_checksum(&data, expected)?;
+                }
+
+                let stored = BundleMetadata {
+                    validators: metadata.validators,
+                    version: metadata.version,
+                    sha256: sha256_hex(&data),
+                };
+                self.store(network_id, &data, &stored).await?;
+
+                Ok(data)
+            }
+        }
+    }
+}
+
+/// Network IDs become file names, so keep them to a safe character set
+fn validate_network_id(network_id: &str) -> Result<()> {
+    let valid = !network_id.is_empty()
+        && !network_id.starts_with('.')
+        && network_id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+
+    if !valid {
+        return Err(TransitError::InvalidData(format!("Invalid network id: {:?}", network_id)));
+    }
+
+    Ok(())
+}
+
+impl<F: DataFetcher, S: StorageLoader> BundleManager for DefaultBundleManager<F, S> {
+    /// Download (or revalidate) a bundle, falling back to the stored copy
+    /// when offline if the policy allows it
+    fn download_bundle<'a>(
+        &'a self,
+        network_id: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>>> + Send + 'a>> {
+        Box::pin(async move {
+            match self.download(network_id).await {
+                Err(TransitError::Network(reason))
+                    if self.offline_policy == OfflinePolicy::UseCached
+                        && self.has_bundle(network_id).await =>
+                {
+                    self.load_bundle(network_id).await.map_err(|_| TransitError::Network(reason))
+                }
+                result => result,
+            }
+        })
+    }
+
+    /// Load the stored bundle, verifying it against its recorded checksum
+    ///
+    /// A bundle without readable metadata can't be verified, so it's treated
+    /// as damaged and must be fetched again.
+    fn load_bundle<'a>(
+        &'a self,
+        network_id: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>>> + Send + 'a>> {
+        Box::pin(async move {
+            let data = self.storage.load(&Self::bundle_path(network_id)?).await?;
+
+            let Some(metadata) = self.stored_metadata(network_id).await else {
+                return Err(TransitError::InvalidData(format!(
+                    "Stored bundle {} has no readable metadata",
+                    network_id
+                )));
+            };
+            check_version(metadata.version)?;
+            verify_checksum(&data, &metadata.sha256)?;
+
+            Ok(data)
+        })
+    }
+
+    /// Store a bundle obtained out of band (e.g. shipped with the app)
+    fn save_bundle<'a>(
+        &'a self,
+        network_id: &'a str,
+        data: &'a [u8],
+    ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+        Box::pin(async move {
+            let metadata = BundleMetadata {
+                sha256: sha256_hex(data),
+                ..Default::default()
+            };
+
+            self.store(network_id, data, &metadata).await
+        })
+    }
+
+    fn has_bundle<'a>(
+        &'a self,
+        network_id: &'a str,
+    ) -> Pin<Box<dyn Future<Output = bool> + Send + 'a>> {
+        Box::pin(async move {
+            match Self::bundle_path(network_id) {
+                Ok(path) => self.storage.exists(&path).await,
+                Err(_) => false,
+            }
+        })
+    }
+
+    fn fetch_realtime<'a>(
+        &'a self,
+        network_id: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>>> + Send + 'a>> {
+        Box::pin(async move {
+            validate_network_id(network_id)?;
+            self.fetcher.fetch(&self.realtime_url(network_id)).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use crate::network::traits::ResponseMetadata;
+
+    #[derive(Default)]
+    struct MemoryStorage {
+        files: Mutex<HashMap<String, Vec<u8>>>,
+    }
+
+    impl StorageLoader for MemoryStorage {
+        fn load<'a>(
+            &'a self,
+            path: &'a str,
+        ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>>> + Send + 'a>> {
+            Box::pin(async move {
+                self.files
+                    .lock()
+                    .unwrap()
+                    .get(path)
+                    .cloned()
+                    .ok_or_else(|| TransitError::InvalidData(format!("missing {}", path)))
+            })
+        }
+
+        fn save<'a>(
+            &'a self,
+            path: &'a str,
+            data: &'a [u8],
+        ) -> Pin<Box<dyn Future<Output = Result<()>> + Send + 'a>> {
+            Box::pin(async move {
+                self.files.lock().unwrap().insert(path.to_string(), data.to_vec());
+                Ok(())
+            })
+        }
+
+        fn exists<'a>(
+            &'a self,
+            path: &'a str,
+        ) -> Pin<Box<dyn Future<Output = bool> + Send + 'a>> {
+            Box::pin(async move { self.files.lock().unwrap().contains_key(path) })
+        }
+    }
+
+    /// Replays scripted responses and records the validators it was sent
+    #[derive(Default)]
+    struct ScriptedFetcher {
+        responses: Mutex<Vec<Result<FetchResponse>>>,
+        seen: Mutex<Vec<(String, CacheValidators)>>,
+    }
+
+    impl ScriptedFetcher {
+        fn push(&self, response: Result<FetchResponse>) {
+            self.responses.lock().unwrap().insert(0, response);
+        }
+    }
+
+    impl DataFetcher for ScriptedFetcher {
+        fn fetch<'a>(
+            &'a self,
+            url: &'a str,
+        ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>>> + Send + 'a>> {
+            Box::pin(async move {
+                match self.fetch_conditional(url, &CacheValidators::default()).await? {
+                    FetchResponse::Modified { data, .. } => Ok(data),
+                    FetchResponse::NotModified => unreachable!(),
+                }
+            })
+        }
+
+        fn fetch_conditional<'a>(
+            &'a self,
+            url: &'a str,
+            validators: &'a CacheValidators,
+        ) -> Pin<Box<dyn Future<Output = Result<FetchResponse>> + Send + 'a>> {
+            Box::pin(async move {
+                self.seen.lock().unwrap().push((url.to_string(), validators.clone()));
+                self.responses.lock().unwrap().pop().expect("unexpected request")
+            })
+        }
+    }
+
+    fn modified(data: &[u8], etag: &str, version: u32) -> Result<FetchResponse> {
+        Ok(FetchResponse::Modified {
+            data: data.to_vec(),
+            metadata: ResponseMetadata {
+                validators: CacheValidators {
+                    etag: Some(etag.to_string()),
+                    last_modified: None,
+                },
+                version: Some(version),
+                sha256: Some(sha256_hex(data)),
+            },
+        })
+    }
+
+    fn manager() -> DefaultBundleManager<ScriptedFetcher, MemoryStorage> {
+        DefaultBundleManager::new(ScriptedFetcher::default(), MemoryStorage::default(), "https://example.com/")
+    }
+
+    #[tokio::test]
+    async fn test_download_then_revalidate() {
+        let manager = manager();
+
+        manager.fetcher().push(modified(b"bundle-v1", "\"a\"", 1));
+        assert_eq!(manager.download_bundle("nyc").await.unwrap(), b"bundle-v1");
+        assert!(manager.has_bundle("nyc").await);
+
+        manager.fetcher().push(Ok(FetchResponse::NotModified));
+        assert_eq!(manager.download_bundle("nyc").await.unwrap(), b"bundle-v1");
+
+        let seen = manager.fetcher().seen.lock().unwrap().clone();
+        assert_eq!(seen[0].0, "https://example.com/transit/nyc/bundle");
+        assert!(seen[0].1.is_empty());
+        assert_eq!(seen[1].1.etag.as_deref(), Some("\"a\""));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_bad_checksum_and_newer_version() {
+        let manager = manager();
+
+        manager.fetcher().push(Ok(FetchResponse::Modified {
+            data: b"corrupted".to_vec(),
+            metadata: ResponseMetadata {
+                sha256: Some(sha256_hex(b"original")),
+                ..Default::default()
+            },
+        }));
+        let error = manager.download_bundle("nyc").await.unwrap_err();
+        assert!(matches!(error, TransitError::ChecksumMismatch { .. }));
+
+        manager.fetcher().push(modified(b"future", "\"b\"", BUNDLE_FORMAT_VERSION + 1));
+        let error = manager.download_bundle("nyc").await.unwrap_err();
+        assert!(matches!(error, TransitError::UnsupportedVersion { .. }));
+
+        // Nothing was stored
+        assert!(!manager.has_bundle("nyc").await);
+    }
+
+    #[tokio::test]
+    async fn test_offline_policy() {
+        let manager = manager();
+
+        // No cached copy: the network error surfaces
+        manager.fetcher().push(Err(TransitError::Network("offline".into())));
+        assert!(matches!(
+            manager.download_bundle("nyc").await,
+            Err(TransitError::Network(_))
+        ));
+
+        manager.fetcher().push(modified(b"bundle-v1", "\"a\"", 1));
+        manager.download_bundle("nyc").await.unwrap();
+
+        manager.fetcher().push(Err(TransitError::Network("offline".into())));
+        assert_eq!(manager.download_bundle("nyc").await.unwrap(), b"bundle-v1");
+
+        // HTTP errors are not "offline"
+        manager.fetcher().push(Err(TransitError::HttpStatus { status: 500, url: String::new() }));
+        assert!(manager.download_bundle("nyc").await.is_err());
+
+        let manager = manager.with_offline_policy(OfflinePolicy::Fail);
+        manager.fetcher().push(Err(TransitError::Network("offline".into())));
+        assert!(manager.download_bundle("nyc").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_refetches_when_cached_copy_is_damaged() {
+        let manager = manager();
+
+        manager.fetcher().push(modified(b"bundle-v1", "\"a\"", 1));
+        manager.download_bundle("nyc").await.unwrap();
+        manager.storage().save("bundles/nyc.bundle", b"truncat").await.unwrap();
+
+        manager.fetcher().push(Ok(FetchResponse::NotModified));
+        manager.fetcher().push(modified(b"bundle-v1", "\"a\"", 1));
+        assert_eq!(manager.download_bundle("nyc").await.unwrap(), b"bundle-v1");
+
+        let seen = manager.fetcher().seen.lock().unwrap().clone();
+        assert!(seen[2].1.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_load_detects_tampering() {
+        let manager = manager();
+        manager.save_bundle("nyc", b"bundle").await.unwrap();
+        assert_eq!(manager.load_bundle("nyc").await.unwrap(), b"bundle");
+
+        manager.storage().save("bundles/nyc.bundle", b"tampered").await.unwrap();
+        assert!(matches!(
+            manager.load_bundle("nyc").await,
+            Err(TransitError::ChecksumMismatch { .. })
+        ));
+
+        assert!(manager.load_bundle("../etc").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_refetches_without_metadata() {
+        let manager = manager();
+
+        manager.fetcher().push(modified(b"bundle-v1", "\"a\"", 1));
+        manager.download_bundle("nyc").await.unwrap();
+
+        // Missing sidecar
+        manager.storage().files.lock().unwrap().remove("bundles/nyc.meta");
+        assert!(manager.load_bundle("nyc").await.is_err());
+
+        manager.fetcher().push(modified(b"bundle-v1", "\"a\"", 1));
+        assert_eq!(manager.download_bundle("nyc").await.unwrap(), b"bundle-v1");
+        assert_eq!(manager.load_bundle("nyc").await.unwrap(), b"bundle-v1");
+
+        // Corrupt sidecar
+        manager.storage().save("bundles/nyc.meta", b"etag=x\n").await.unwrap();
+        assert!(manager.load_bundle("nyc").await.is_err());
+
+        // Offline, nothing trustworthy to fall back on
+        manager.fetcher().push(Err(TransitError::Network("offline".into())));
+        assert!(manager.download_bundle("nyc").await.is_err());
+    }
+
+    #[test]
+    fn test_metadata_round_trip() {
+        let metadata = BundleMetadata {
+            validators: CacheValidators {
+                etag: Some("W/\"x=1\"".into()),
+                last_modified: Some("Mon, 04 Mar 2024 12:00:00 GMT".into()),
+            },
+            version: Some(1),
+            sha256: sha256_hex(b"data"),
+        };
+
+        assert_eq!(BundleMetadata::decode(&metadata.encode()).unwrap(), metadata);
+        assert!(BundleMetadata::decode("etag=x\n").is_err());
+    }
+}
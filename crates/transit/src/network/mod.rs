@@ -1,5 +1,17 @@
 //! Network and IO abstractions.
 
+pub mod manager;
 pub mod traits;
 
+#[cfg(feature = "fs")]
+pub mod fs;
+#[cfg(feature = "http")]
+pub mod http;
+
+pub use manager::{DefaultBundleManager, OfflinePolicy};
 pub use traits::{BundleManager, DataFetcher, StorageLoader};
+
+#[cfg(feature = "fs")]
+pub use fs::FsStorageLoader;
+#[cfg(feature = "http")]
+pub use http::HttpFetcher;
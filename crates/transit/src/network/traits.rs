@@ -7,12 +7,78 @@ use std::pin::Pin;
 
 use crate::models::types::Result;
 
+// ============================================================================
+// Conditional Requests
+// ============================================================================
+
+/// Response header carrying the bundle format version
+pub const BUNDLE_VERSION_HEADER: &str = "x-bundle-version";
+
+/// Response header carrying the hex-encoded SHA-256 of the payload
+pub const BUNDLE_SHA256_HEADER: &str = "x-bundle-sha256";
+
+/// Validators from a previous response, sent back to skip unchanged downloads
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheValidators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl CacheValidators {
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// Metadata describing a fetched payload
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ResponseMetadata {
+    pub validators: CacheValidators,
+
+    /// Bundle format version, if the server reported one
+    pub version: Option<u32>,
+
+    /// Hex-encoded SHA-256 of the payload, if the server reported one
+    pub sha256: Option<String>,
+}
+
+/// Result of a conditional fetch
+#[derive(Clone, Debug)]
+pub enum FetchResponse {
+    /// The remote copy matches the validators that were sent
+    NotModified,
+    Modified {
+        data: Vec<u8>,
+        metadata: ResponseMetadata,
+    },
+}
+
+// ============================================================================
+// Traits
+// ============================================================================
+
 /// Fetch raw bytes from a URL
 pub trait DataFetcher: Send + Sync {
     fn fetch<'a>(
         &'a self,
         url: &'a str,
     ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>>> + Send + 'a>>;
+
+    /// Fetch unless the remote copy still matches `validators`
+    ///
+    /// Fetchers without conditional request support always download.
+    fn fetch_conditional<'a>(
+        &'a self,
+        url: &'a str,
+        _validators: &'a CacheValidators,
+    ) -> Pin<Box<dyn Future<Output = Result<FetchResponse>> + Send + 'a>> {
+        Box::pin(async move {
+            Ok(FetchResponse::Modified {
+                data: self.fetch(url).await?,
+                metadata: ResponseMetadata::default(),
+            })
+        })
+    }
 }
 
 /// Load bytes from local storage
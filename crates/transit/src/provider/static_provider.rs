@@ -3,7 +3,7 @@
 //! This is the core implementation that stores all transit data in memory
 //! with spatial indices for fast queries.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use geo::{Point, LineString};
@@ -12,7 +12,11 @@ use rstar::RTree;
 
 use crate::identifiers::*;
 use crate::models::{types::*, traits::*, calendar::ServiceCalendar};
-use crate::spatial::index::{StationNode, RouteSegmentNode};
+use crate::naming::{NameIndex, NameRules, StationNames};
+use crate::schedule::{TripDelays, TripRun};
+use crate::shapes::{self, RailNetwork, RoutePattern};
+use crate::spatial::index::{chord_for_distance, to_ecef, RouteSegmentNode, StationNode};
+use crate::transfers::{Transfer, TransferGraph, TransferGraphBuilder};
 
 // ============================================================================
 // Concrete Implementations of Traits
@@ -75,9 +79,11 @@ pub struct TripImpl {
     pub id: TripIdentifier,
     pub route_id: RouteIdentifier,
     pub stop_events: Vec<StopEvent>,
+    pub frequencies: Vec<Frequency>,
     pub service_calendar: Arc<ServiceCalendar>,
     pub direction_id: DirectionId,
     pub headsign: Arc<str>,
+    pub shape_id: Option<ShapeIdentifier>,
 }
 
 impl Trip for TripImpl {
@@ -93,6 +99,10 @@ impl Trip for TripImpl {
         &self.stop_events
     }
 
+    fn frequencies(&self) -> &[Frequency] {
+        &self.frequencies
+    }
+
     fn runs_on(&self, date: NaiveDate) -> bool {
         self.service_calendar.runs_on(date)
     }
@@ -108,6 +118,10 @@ impl Trip for TripImpl {
     fn service_calendar(&self) -> &ServiceCalendar {
         &self.service_calendar
     }
+
+    fn shape_id(&self) -> Option<&ShapeIdentifier> {
+        self.shape_id.as_ref()
+    }
 }
 
 #[derive(Clone)]
@@ -176,9 +190,22 @@ pub struct StaticTransitProvider {
     route_map: HashMap<RouteIdentifier, Arc<RouteImpl>>,
     trip_map: HashMap<TripIdentifier, Arc<dyn Trip>>,
 
+    // Shapes, and reconstructed shapes for trips the feed gave none
+    shapes: HashMap<ShapeIdentifier, Arc<LineString>>,
+    derived_trip_shapes: HashMap<TripIdentifier, ShapeIdentifier>,
+
     // Spatial indices
     station_tree: RTree<StationNode>,
     route_tree: RTree<RouteSegmentNode>,
+
+    // Transfers
+    transfers: Arc<TransferGraph>,
+
+    // Normalised station names
+    names: Arc<NameIndex>,
+
+    // Latest realtime delays
+    delays: Arc<HashMap<(TripIdentifier, TripRun), TripDelays>>,
 }
 
 impl StaticTransitProvider {
@@ -192,8 +219,13 @@ impl StaticTransitProvider {
             complex_map: HashMap::new(),
             route_map: HashMap::new(),
             trip_map: HashMap::new(),
+            shapes: HashMap::new(),
+            derived_trip_shapes: HashMap::new(),
             station_tree: RTree::new(),
             route_tree: RTree::new(),
+            transfers: Arc::new(TransferGraph::default()),
+            names: Arc::new(NameIndex::default()),
+            delays: Arc::new(HashMap::new()),
         }
     }
 
@@ -239,17 +271,7 @@ impl StaticTransitProvider {
                 .collect(),
         );
 
-        let mut route_segments = Vec::new();
-        for route in &routes {
-            if let Some(geom) = &route.geometry {
-                for segment in geom.lines() {
-                    route_segments.push(RouteSegmentNode::new(segment, route.clone()));
-                }
-            }
-        }
-        let route_tree = RTree::bulk_load(route_segments);
-
-        Self {
+        let mut provider = Self {
             stations,
             complexes,
             routes,
@@ -257,9 +279,145 @@ impl StaticTransitProvider {
             complex_map,
             route_map,
             trip_map,
+            shapes: HashMap::new(),
+            derived_trip_shapes: HashMap::new(),
             station_tree,
-            route_tree,
+            route_tree: RTree::new(),
+            transfers: Arc::new(TransferGraph::default()),
+            names: Arc::new(NameIndex::default()),
+            delays: Arc::new(HashMap::new()),
+        };
+        provider.rebuild_route_tree();
+
+        provider
+            .with_transfers(TransferGraph::builder())
+            .with_name_rules(&NameRules::default())
+    }
+
+    /// Rebuild the transfer graph from feed data (transfers, pathways,
+    /// complex links) on top of the generated walking links
+    pub fn with_transfers(mut self, builder: TransferGraphBuilder) -> Self {
+        self.transfers = Arc::new(builder.build(&self));
+        self
+    }
+
+    pub fn transfer_graph(&self) -> &TransferGraph {
+        &self.transfers
+    }
+
+    /// Re-normalise station names with a city's rules
+    pub fn with_name_rules(mut self, rules: &NameRules) -> Self {
+        self.names = Arc::new(NameIndex::build(&self, rules));
+        self
+    }
+
+    pub fn name_index(&self) -> &NameIndex {
+        &self.names
+    }
+
+    /// Replace realtime delays with a new snapshot (e.g. a GTFS-RT feed)
+    pub fn with_trip_delays(
+        mut self,
+        delays: impl IntoIterator<Item = (TripIdentifier, TripRun, TripDelays)>,
+    ) -> Self {
+        self.delays = Arc::new(
+            delays
+                .into_iter()
+                .map(|(trip_id, run, delays)| ((trip_id, run), delays))
+                .collect(),
+        );
+        self
+    }
+
+    /// Add shapes (e.g. from shapes.txt) referenced by trips' `shape_id`
+    pub fn with_shapes(
+        mut self,
+        shapes: impl IntoIterator<Item = (ShapeIdentifier, LineString)>,
+    ) -> Self {
+        self.shapes
+            .extend(shapes.into_iter().map(|(id, shape)| (id, Arc::new(shape))));
+        self.rebuild_route_tree();
+        self
+    }
+
+    /// Reconstruct shapes for every pattern whose trips have none, snapping
+    /// to `network` where possible and falling back to great-circle arcs
+    ///
+    /// Reconstructed shapes get IDs of the form `{route_id}:pattern:{n}`.
+    pub fn with_reconstructed_shapes(mut self, network: Option<&dyn RailNetwork>) -> Self {
+        for route in &self.routes {
+            let patterns = shapes::patterns_of(route.as_ref());
+
+            for (n, pattern) in patterns.iter().enumerate() {
+                let has_shape = pattern
+                    .shape_id
+                    .as_ref()
+                    .is_some_and(|id| self.shapes.contains_key(id));
+
+                if has_shape {
+                    continue;
+                }
+
+                let stops: Vec<Point> = pattern
+                    .station_ids
+                    .iter()
+                    .filter_map(|id| self.station_map.get(id).map(|s| s.location))
+                    .collect();
+
+                if stops.len() < 2 {
+                    continue;
+                }
+
+                let shape_id = ShapeIdentifier::new(format!("{}:pattern:{}", route.id, n));
+                let shape = shapes::reconstruct_shape(&stops, network);
+
+                self.shapes.insert(shape_id.clone(), Arc::new(shape));
+                for trip_id in &pattern.trip_ids {
+                    self.derived_trip_shapes
+                        .insert(trip_id.clone(), shape_id.clone());
+                }
+            }
         }
+
+        self.rebuild_route_tree();
+        self
+    }
+
+    fn trip_shape_id<'a>(&'a self, trip: &'a dyn Trip) -> Option<&'a ShapeIdentifier> {
+        trip.shape_id()
+            .filter(|id| self.shapes.contains_key(*id))
+            .or_else(|| self.derived_trip_shapes.get(trip.id()))
+    }
+
+    /// Index every distinct shape of every route, plus route-level geometry
+    fn rebuild_route_tree(&mut self) {
+        let mut route_segments = Vec::new();
+
+        for route in &self.routes {
+            let mut indexed: HashSet<&ShapeIdentifier> = HashSet::new();
+
+            for trip in route.trips() {
+                let Some(shape_id) = self.trip_shape_id(trip.as_ref()) else {
+                    continue;
+                };
+
+                if !indexed.insert(shape_id) {
+                    continue;
+                }
+
+                for segment in self.shapes[shape_id].lines() {
+                    route_segments.push(RouteSegmentNode::new(segment, route.clone()));
+                }
+            }
+
+            if let Some(geom) = &route.geometry {
+                for segment in geom.lines() {
+                    route_segments.push(RouteSegmentNode::new(segment, route.clone()));
+                }
+            }
+        }
+
+        self.route_tree = RTree::bulk_load(route_segments);
     }
 }
 
@@ -315,8 +473,10 @@ impl TransitProvider for StaticTransitProvider {
             return Vec::new();
         }
 
+        let chord = chord_for_distance(radius_m);
+
         self.station_tree
-            .locate_within_distance([point.x(), point.y()], radius_m)
+            .locate_within_distance(to_ecef(point), chord * chord)
             .filter(|node| {
                 haversine_distance(point, node.station.location) <= radius_m
             })
@@ -332,9 +492,11 @@ impl TransitProvider for StaticTransitProvider {
             return Vec::new();
         }
 
+        let chord = chord_for_distance(radius_m);
+
         let mut seen = std::collections::HashSet::new();
         self.route_tree
-            .locate_within_distance([point.x(), point.y()], radius_m)
+            .locate_within_distance(to_ecef(point), chord * chord)
             .filter(|node| {
                 haversine_distance_to_line(point, node.segment) <= radius_m
             })
@@ -345,11 +507,53 @@ impl TransitProvider for StaticTransitProvider {
 
     fn nearest_stations(&self, point: Point, n: usize) -> Vec<Arc<dyn TransitStation>> {
         self.station_tree
-            .nearest_neighbor_iter(&[point.x(), point.y()])
+            .nearest_neighbor_iter(&to_ecef(point))
             .take(n)
             .map(|node| node.station.clone() as Arc<dyn TransitStation>)
             .collect()
     }
+
+    fn station_names(&self, station_id: &StationIdentifier) -> Option<StationNames> {
+        self.names.names(station_id).cloned()
+    }
+
+    fn stations_with_name_length(&self, length: u32) -> Vec<StationIdentifier> {
+        self.names.stations_with_length(length).to_vec()
+    }
+
+    fn get_shape(&self, id: &ShapeIdentifier) -> Option<Arc<LineString>> {
+        self.shapes.get(id).cloned()
+    }
+
+    fn trip_shape(&self, trip_id: &TripIdentifier) -> Option<Arc<LineString>> {
+        let trip = self.trip_map.get(trip_id)?;
+        let shape_id = self.trip_shape_id(trip.as_ref())?;
+
+        self.shapes.get(shape_id).cloned()
+    }
+
+    fn route_patterns(&self, route_id: &RouteIdentifier) -> Vec<RoutePattern> {
+        self.route_map
+            .get(route_id)
+            .map(|route| shapes::patterns_of(route.as_ref()))
+            .unwrap_or_default()
+    }
+
+    fn transfers_from(&self, station_id: &StationIdentifier) -> Vec<Transfer> {
+        self.transfers.transfers_from(station_id).to_vec()
+    }
+
+    fn min_transfer_secs(&self, from: &StationIdentifier, to: &StationIdentifier) -> Option<u32> {
+        self.transfers.min_transfer_secs(from, to)
+    }
+
+    fn linked_complexes(&self, complex_id: &ComplexIdentifier) -> Vec<ComplexIdentifier> {
+        self.transfers.linked_complexes(complex_id).to_vec()
+    }
+
+    fn trip_delays(&self, trip_id: &TripIdentifier, run: &TripRun) -> Option<TripDelays> {
+        self.delays.get(&(trip_id.clone(), *run)).cloned()
+    }
 }
 
 #[cfg(test)]
@@ -384,4 +588,238 @@ mod tests {
         assert!(provider.get_station(&StationIdentifier::new("s1")).is_some());
         assert!(provider.get_complex(&ComplexIdentifier::new("c1")).is_some());
     }
+
+    /// Small deterministic xorshift generator so property tests are reproducible
+    struct Rng(u64);
+
+    impl Rng {
+        fn next_f64(&mut self) -> f64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 >> 11) as f64 / (1u64 << 53) as f64
+        }
+
+        fn range(&mut self, lo: f64, hi: f64) -> f64 {
+            lo + (hi - lo) * self.next_f64()
+        }
+
+        /// Mostly points around NYC, with some spread across the globe
+        fn point(&mut self) -> Point {
+            if self.next_f64() < 0.7 {
+                Point::new(self.range(-74.3, -73.7), self.range(40.5, 40.9))
+            } else {
+                Point::new(self.range(-180.0, 180.0), self.range(-85.0, 85.0))
+            }
+        }
+    }
+
+    fn random_stations(rng: &mut Rng, n: usize) -> Vec<StationImpl> {
+        (0..n)
+            .map(|i| StationImpl {
+                id: StationIdentifier::new(format!("s{i}")),
+                name: format!("Station {i}").into(),
+                location: rng.point(),
+                complex_id: ComplexIdentifier::new(format!("c{i}")),
+            })
+            .collect()
+    }
+
+    const RADII_M: [f64; 6] = [50.0, 400.0, 1_500.0, 10_000.0, 250_000.0, 5_000_000.0];
+
+    #[test]
+    fn test_stations_near_matches_brute_force() {
+        use crate::spatial::queries::haversine_distance;
+
+        let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+        let stations = random_stations(&mut rng, 400);
+        let provider = StaticTransitProvider::from_data(stations.clone(), vec![], vec![]);
+
+        for _ in 0..100 {
+            let query = rng.point();
+
+            for radius in RADII_M {
+                let mut expected: Vec<&str> = stations
+                    .iter()
+                    .filter(|s| haversine_distance(query, s.location) <= radius)
+                    .map(|s| s.id.as_str())
+                    .collect();
+                expected.sort();
+
+                let found = provider.stations_near(query, radius);
+                let mut found: Vec<&str> = found.iter().map(|s| s.id().as_str()).collect();
+                found.sort();
+
+                assert_eq!(found, expected, "query {query:?} radius {radius}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_nearest_stations_matches_brute_force() {
+        use crate::spatial::queries::haversine_distance;
+
+        let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+        let stations = random_stations(&mut rng, 400);
+        let provider = StaticTransitProvider::from_data(stations.clone(), vec![], vec![]);
+
+        for _ in 0..100 {
+            let query = rng.point();
+
+            let mut expected: Vec<f64> = stations
+                .iter()
+                .map(|s| haversine_distance(query, s.location))
+                .collect();
+            expected.sort_by(f64::total_cmp);
+
+            let found: Vec<f64> = provider
+                .nearest_stations(query, 10)
+                .iter()
+                .map(|s| haversine_distance(query, s.location()))
+                .collect();
+
+            assert_eq!(found.len(), 10);
+            for (found, expected) in found.iter().zip(&expected) {
+                assert!((found - expected).abs() < 1e-3, "query {query:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_routes_near_matches_brute_force() {
+        use crate::spatial::queries::haversine_distance_to_line;
+
+        let mut rng = Rng(0xD1B5_4A32_D192_ED03);
+        let routes: Vec<RouteImpl> = (0..60)
+            .map(|i| {
+                let mut coords = vec![rng.point()];
+                for _ in 0..4 {
+                    let last = *coords.last().unwrap();
+                    let step = if rng.next_f64() < 0.8 { 0.02 } else { 2.0 };
+                    coords.push(Point::new(
+                        last.x() + rng.range(-step, step),
+                        (last.y() + rng.range(-step, step)).clamp(-85.0, 85.0),
+                    ));
+                }
+
+                RouteImpl {
+                    id: RouteIdentifier::new(format!("r{i}")),
+                    route_type: RouteType::Subway,
+                    short_name: format!("{i}").into(),
+                    long_name: format!("Route {i}").into(),
+                    color: None,
+                    text_color: None,
+                    geometry: Some(LineString::from(coords)),
+                    trips: vec![],
+                }
+            })
+            .collect();
+
+        let expected_for = |query: Point, radius: f64| {
+            let mut ids: Vec<String> = routes
+                .iter()
+                .filter(|r| {
+                    r.geometry
+                        .as_ref()
+                        .unwrap()
+                        .lines()
+                        .any(|l| haversine_distance_to_line(query, l) <= radius)
+                })
+                .map(|r| r.id.as_str().to_string())
+                .collect();
+            ids.sort();
+            ids
+        };
+
+        let provider = StaticTransitProvider::from_data(vec![], vec![], routes.clone());
+
+        for _ in 0..100 {
+            let query = rng.point();
+
+            for radius in RADII_M {
+                let mut found: Vec<String> = provider
+                    .routes_near(query, radius)
+                    .iter()
+                    .map(|r| r.id().as_str().to_string())
+                    .collect();
+                found.sort();
+
+                assert_eq!(found, expected_for(query, radius), "query {query:?} radius {radius}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_patterns_and_reconstructed_shapes() {
+        use crate::test_support::{self, daily, route, station};
+        use geo::Coord;
+
+        // Local stops at a, b, c; express skips b and has its own shape
+        let stations: Vec<StationImpl> = [("a", 40.70), ("b", 40.72), ("c", 40.74)]
+            .into_iter()
+            .map(|(id, lat)| station(id, -74.0, lat))
+            .collect();
+
+        let calendar = Arc::new(daily(chrono_tz::America::New_York));
+
+        let trip = |id: &str, stops: &[&str], shape_id: Option<&str>| -> Arc<dyn Trip> {
+            let stop_events = stops
+                .iter()
+                .enumerate()
+                .map(|(i, s)| {
+                    let t = 8 * 3600 + i as u32 * 120;
+                    StopEvent::new(StationIdentifier::new(*s), t, t, i as u32 + 1)
+                })
+                .collect();
+            Arc::new(TripImpl {
+                headsign: "Uptown".into(),
+                shape_id: shape_id.map(ShapeIdentifier::new),
+                ..test_support::trip(id, "1", &calendar, stop_events)
+            })
+        };
+
+        let route = RouteImpl {
+            long_name: "Test Line".into(),
+            ..route(
+                "1",
+                RouteType::Subway,
+                vec![
+                    trip("express", &["a", "c"], Some("express_shape")),
+                    trip("local_1", &["a", "b", "c"], None),
+                    trip("local_2", &["a", "b", "c"], None),
+                ],
+            )
+        };
+
+        // Express shape bows ~1.7km west of the local line
+        let express_shape = LineString::from(vec![(-74.0, 40.70), (-74.02, 40.72), (-74.0, 40.74)]);
+
+        let provider = StaticTransitProvider::from_data(stations, vec![], vec![route])
+            .with_shapes([(ShapeIdentifier::new("express_shape"), express_shape)]);
+
+        let route_id = RouteIdentifier::new("1");
+        let patterns = provider.route_patterns(&route_id);
+        assert_eq!(patterns.len(), 2);
+        assert_eq!(patterns[0].trip_ids.len(), 2);
+        assert_eq!(patterns[0].station_ids.len(), 3);
+        assert_eq!(patterns[1].shape_id, Some(ShapeIdentifier::new("express_shape")));
+
+        // Only the express has geometry so far
+        let east_of_line = Point::new(-73.999, 40.72);
+        assert!(provider.routes_near(east_of_line, 200.0).is_empty());
+        assert!(provider.trip_shape(&TripIdentifier::new("local_1")).is_none());
+        assert_eq!(provider.route_shapes(&route_id).len(), 1);
+
+        let provider = provider.with_reconstructed_shapes(None);
+
+        let local = provider.trip_shape(&TripIdentifier::new("local_2")).unwrap();
+        assert_eq!(local.0.first(), Some(&Coord { x: -74.0, y: 40.70 }));
+        assert_eq!(local.0.last(), Some(&Coord { x: -74.0, y: 40.74 }));
+        assert!(provider
+            .get_shape(&ShapeIdentifier::new("1:pattern:0"))
+            .is_some());
+
+        assert_eq!(provider.routes_near(east_of_line, 200.0).len(), 1);
+        assert_eq!(provider.route_shapes(&route_id).len(), 2);
+    }
 }
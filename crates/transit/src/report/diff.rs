@@ -0,0 +1,690 @@
+//! Comparison of two providers.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+
+use chrono::NaiveDate;
+
+use crate::identifiers::*;
+use crate::models::calendar::ServiceCalendar;
+use crate::models::traits::{Route, TransitProvider, TransitStation};
+use crate::spatial::queries::haversine_distance;
+
+/// Default distance a station must move to be reported
+pub const DEFAULT_MOVE_THRESHOLD_M: f64 = 25.0;
+
+/// Parameters for [`diff_providers`]
+#[derive(Clone, Copy, Debug)]
+pub struct DiffOptions {
+    pub move_threshold_m: f64,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self {
+            move_threshold_m: DEFAULT_MOVE_THRESHOLD_M,
+        }
+    }
+}
+
+impl DiffOptions {
+    pub fn with_move_threshold(mut self, move_threshold_m: f64) -> Self {
+        self.move_threshold_m = move_threshold_m;
+        self
+    }
+}
+
+// ============================================================================
+// Changes
+// ============================================================================
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StationChange {
+    Added {
+        id: StationIdentifier,
+        name: Arc<str>,
+    },
+    Removed {
+        id: StationIdentifier,
+        name: Arc<str>,
+    },
+    Renamed {
+        id: StationIdentifier,
+        from: Arc<str>,
+        to: Arc<str>,
+        /// Whether the question-name length changed
+        length_changed: bool,
+    },
+    Moved {
+        id: StationIdentifier,
+        distance_m: f64,
+    },
+    ComplexChanged {
+        id: StationIdentifier,
+        from: ComplexIdentifier,
+        to: ComplexIdentifier,
+    },
+}
+
+impl StationChange {
+    /// Could this change alter a question's answer?
+    pub fn needs_review(&self) -> bool {
+        match self {
+            StationChange::Renamed { length_changed, .. } => *length_changed,
+            _ => true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RouteChange {
+    Added {
+        id: RouteIdentifier,
+        name: Arc<str>,
+    },
+    Removed {
+        id: RouteIdentifier,
+        name: Arc<str>,
+    },
+    Renamed {
+        id: RouteIdentifier,
+        from: Arc<str>,
+        to: Arc<str>,
+    },
+    /// Stations served by the route's trips changed
+    StopsChanged {
+        id: RouteIdentifier,
+        added: Vec<StationIdentifier>,
+        removed: Vec<StationIdentifier>,
+    },
+    TripCountChanged {
+        id: RouteIdentifier,
+        from: usize,
+        to: usize,
+    },
+}
+
+impl RouteChange {
+    /// Could this change alter a question's answer?
+    pub fn needs_review(&self) -> bool {
+        !matches!(
+            self,
+            RouteChange::Renamed { .. } | RouteChange::TripCountChanged { .. }
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CalendarChange {
+    Added {
+        service_id: ServiceIdentifier,
+    },
+    Removed {
+        service_id: ServiceIdentifier,
+    },
+    RangeChanged {
+        service_id: ServiceIdentifier,
+        from: (NaiveDate, NaiveDate),
+        to: (NaiveDate, NaiveDate),
+    },
+    WeekdaysChanged {
+        service_id: ServiceIdentifier,
+    },
+    ExceptionsChanged {
+        service_id: ServiceIdentifier,
+        /// Dates whose added/removed status differs between the feeds
+        dates: Vec<NaiveDate>,
+    },
+}
+
+impl CalendarChange {
+    /// Could this change alter a question's answer?
+    ///
+    /// Every calendar change moves which trips run, so all of them do.
+    pub fn needs_review(&self) -> bool {
+        true
+    }
+}
+
+/// Everything that changed between two providers
+#[derive(Clone, Debug, Default)]
+pub struct TransitDiff {
+    pub stations: Vec<StationChange>,
+    pub routes: Vec<RouteChange>,
+    pub calendars: Vec<CalendarChange>,
+}
+
+impl TransitDiff {
+    pub fn is_empty(&self) -> bool {
+        self.stations.is_empty() && self.routes.is_empty() && self.calendars.is_empty()
+    }
+
+    /// Does any change need review before the new data ships?
+    pub fn needs_review(&self) -> bool {
+        self.stations.iter().any(StationChange::needs_review)
+            || self.routes.iter().any(RouteChange::needs_review)
+            || self.calendars.iter().any(CalendarChange::needs_review)
+    }
+}
+
+// ============================================================================
+// Diffing
+// ============================================================================
+
+/// Compare `old` against `new`
+///
+/// Results are sorted by identifier so reports are stable between runs.
+pub fn diff_providers(
+    old: &dyn TransitProvider,
+    new: &dyn TransitProvider,
+    options: &DiffOptions,
+) -> TransitDiff {
+    TransitDiff {
+        stations: diff_stations(old, new, options),
+        routes: diff_routes(old, new),
+        calendars: diff_calendars(old, new),
+    }
+}
+
+fn by_id<K, V>(items: Vec<V>, id: impl Fn(&V) -> K) -> BTreeMap<String, V>
+where
+    K: fmt::Display,
+{
+    items.into_iter().map(|v| (id(&v).to_string(), v)).collect()
+}
+
+fn diff_stations(
+    old: &dyn TransitProvider,
+    new: &dyn TransitProvider,
+    options: &DiffOptions,
+) -> Vec<StationChange> {
+    let old_stations = by_id(old.all_stations(), |s: &Arc<dyn TransitStation>| {
+        s.id().clone()
+    });
+    let new_stations = by_id(new.all_stations(), |s: &Arc<dyn TransitStation>| {
+        s.id().clone()
+    });
+
+    let mut changes = Vec::new();
+
+    for (key, before) in &old_stations {
+        let Some(after) = new_stations.get(key) else {
+            changes.push(StationChange::Removed {
+                id: before.id().clone(),
+                name: before.name().into(),
+            });
+            continue;
+        };
+
+        let id = before.id().clone();
+
+        if before.name() != after.name() {
+            let length = |provider: &dyn TransitProvider| {
+                provider.station_names(&id).map(|n| n.question_length)
+            };
+
+            changes.push(StationChange::Renamed {
+                id: id.clone(),
+                from: before.name().into(),
+                to: after.name().into(),
+                length_changed: length(old) != length(new),
+            });
+        }
+
+        let distance_m = haversine_distance(before.location(), after.location());
+        if distance_m > options.move_threshold_m {
+            changes.push(StationChange::Moved {
+                id: id.clone(),
+                distance_m,
+            });
+        }
+
+        if before.complex_id() != after.complex_id() {
+            changes.push(StationChange::ComplexChanged {
+                id,
+                from: before.complex_id().clone(),
+                to: after.complex_id().clone(),
+            });
+        }
+    }
+
+    for (key, after) in &new_stations {
+        if !old_stations.contains_key(key) {
+            changes.push(StationChange::Added {
+                id: after.id().clone(),
+                name: after.name().into(),
+            });
+        }
+    }
+
+    changes
+}
+
+fn route_name(route: &dyn Route) -> Arc<str> {
+    match (route.short_name(), route.long_name()) {
+        ("", long) => long.into(),
+        (short, "") => short.into(),
+        (short, long) => format!("{} ({})", short, long).into(),
+    }
+}
+
+fn served_stations(route: &dyn Route) -> HashSet<StationIdentifier> {
+    route
+        .trips()
+        .iter()
+        .flat_map(|trip| trip.stop_events().iter().map(|e| e.station_id.clone()))
+        .collect()
+}
+
+fn sorted(ids: impl Iterator<Item = StationIdentifier>) -> Vec<StationIdentifier> {
+    let mut ids: Vec<_> = ids.collect();
+    ids.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+    ids
+}
+
+fn diff_routes(old: &dyn TransitProvider, new: &dyn TransitProvider) -> Vec<RouteChange> {
+    let old_routes = by_id(old.all_routes(), |r: &Arc<dyn Route>| r.id().clone());
+    let new_routes = by_id(new.all_routes(), |r: &Arc<dyn Route>| r.id().clone());
+
+    let mut changes = Vec::new();
+
+    for (key, before) in &old_routes {
+        let Some(after) = new_routes.get(key) else {
+            changes.push(RouteChange::Removed {
+                id: before.id().clone(),
+                name: route_name(before.as_ref()),
+            });
+            continue;
+        };
+
+        let id = before.id().clone();
+
+        let (from, to) = (route_name(before.as_ref()), route_name(after.as_ref()));
+        if from != to {
+            changes.push(RouteChange::Renamed {
+                id: id.clone(),
+                from,
+                to,
+            });
+        }
+
+        let before_stops = served_stations(before.as_ref());
+        let after_stops = served_stations(after.as_ref());
+        if before_stops != after_stops {
+            changes.push(RouteChange::StopsChanged {
+                id: id.clone(),
+                added: sorted(after_stops.difference(&before_stops).cloned()),
+                removed: sorted(before_stops.difference(&after_stops).cloned()),
+            });
+        }
+
+        let (from, to) = (before.trips().len(), after.trips().len());
+        if from != to {
+            changes.push(RouteChange::TripCountChanged { id, from, to });
+        }
+    }
+
+    for (key, after) in &new_routes {
+        if !old_routes.contains_key(key) {
+            changes.push(RouteChange::Added {
+                id: after.id().clone(),
+                name: route_name(after.as_ref()),
+            });
+        }
+    }
+
+    changes
+}
+
+/// Every distinct service calendar referenced by a provider's trips
+fn calendars(provider: &dyn TransitProvider) -> BTreeMap<String, ServiceCalendar> {
+    let mut calendars = BTreeMap::new();
+
+    for route in provider.all_routes() {
+        for trip in route.trips() {
+            let calendar = trip.service_calendar();
+            calendars
+                .entry(calendar.service_id.to_string())
+                .or_insert_with(|| calendar.clone());
+        }
+    }
+
+    calendars
+}
+
+fn exception_status(calendar: &ServiceCalendar) -> HashMap<NaiveDate, bool> {
+    let added = calendar.added_dates.iter().map(|d| (*d, true));
+    let removed = calendar.removed_dates.iter().map(|d| (*d, false));
+
+    added.chain(removed).collect()
+}
+
+fn diff_calendars(old: &dyn TransitProvider, new: &dyn TransitProvider) -> Vec<CalendarChange> {
+    let old_calendars = calendars(old);
+    let new_calendars = calendars(new);
+
+    let mut changes = Vec::new();
+
+    for (key, before) in &old_calendars {
+        let service_id = before.service_id.clone();
+
+        let Some(after) = new_calendars.get(key) else {
+            changes.push(CalendarChange::Removed { service_id });
+            continue;
+        };
+
+        let from = (before.start_date, before.end_date);
+        let to = (after.start_date, after.end_date);
+        if from != to {
+            changes.push(CalendarChange::RangeChanged {
+                service_id: service_id.clone(),
+                from,
+                to,
+            });
+        }
+
+        if before.weekdays != after.weekdays {
+            changes.push(CalendarChange::WeekdaysChanged {
+                service_id: service_id.clone(),
+            });
+        }
+
+        let before_exceptions = exception_status(before);
+        let after_exceptions = exception_status(after);
+
+        let mut dates: Vec<NaiveDate> = before_exceptions
+            .keys()
+            .chain(after_exceptions.keys())
+            .filter(|date| before_exceptions.get(date) != after_exceptions.get(date))
+            .copied()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        dates.sort();
+
+        if !dates.is_empty() {
+            changes.push(CalendarChange::ExceptionsChanged { service_id, dates });
+        }
+    }
+
+    for (key, after) in &new_calendars {
+        if !old_calendars.contains_key(key) {
+            changes.push(CalendarChange::Added {
+                service_id: after.service_id.clone(),
+            });
+        }
+    }
+
+    changes
+}
+
+// ============================================================================
+// Report Formatting
+// ============================================================================
+
+fn review_marker(needs_review: bool) -> &'static str {
+    if needs_review {
+        "[review] "
+    } else {
+        ""
+    }
+}
+
+fn join(ids: &[StationIdentifier]) -> String {
+    ids.iter()
+        .map(|id| id.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl fmt::Display for StationChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", review_marker(self.needs_review()))?;
+
+        match self {
+            StationChange::Added { id, name } => write!(f, "+ station {} \"{}\"", id, name),
+            StationChange::Removed { id, name } => write!(f, "- station {} \"{}\"", id, name),
+            StationChange::Renamed { id, from, to, .. } => {
+                write!(f, "~ station {} renamed \"{}\" → \"{}\"", id, from, to)
+            }
+            StationChange::Moved { id, distance_m } => {
+                write!(f, "~ station {} moved {:.0} m", id, distance_m)
+            }
+            StationChange::ComplexChanged { id, from, to } => {
+                write!(f, "~ station {} complex {} → {}", id, from, to)
+            }
+        }
+    }
+}
+
+impl fmt::Display for RouteChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", review_marker(self.needs_review()))?;
+
+        match self {
+            RouteChange::Added { id, name } => write!(f, "+ route {} \"{}\"", id, name),
+            RouteChange::Removed { id, name } => write!(f, "- route {} \"{}\"", id, name),
+            RouteChange::Renamed { id, from, to } => {
+                write!(f, "~ route {} renamed \"{}\" → \"{}\"", id, from, to)
+            }
+            RouteChange::StopsChanged { id, added, removed } => {
+                write!(f, "~ route {} stops", id)?;
+                if !added.is_empty() {
+                    write!(f, " +[{}]", join(added))?;
+                }
+                if !removed.is_empty() {
+                    write!(f, " -[{}]", join(removed))?;
+                }
+                Ok(())
+            }
+            RouteChange::TripCountChanged { id, from, to } => {
+                write!(f, "~ route {} trips {} → {}", id, from, to)
+            }
+        }
+    }
+}
+
+impl fmt::Display for CalendarChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", review_marker(self.needs_review()))?;
+
+        match self {
+            CalendarChange::Added { service_id } => write!(f, "+ service {}", service_id),
+            CalendarChange::Removed { service_id } => write!(f, "- service {}", service_id),
+            CalendarChange::RangeChanged {
+                service_id,
+                from,
+                to,
+            } => write!(
+                f,
+                "~ service {} {}..{} → {}..{}",
+                service_id, from.0, from.1, to.0, to.1
+            ),
+            CalendarChange::WeekdaysChanged { service_id } => {
+                write!(f, "~ service {} weekdays changed", service_id)
+            }
+            CalendarChange::ExceptionsChanged { service_id, dates } => {
+                let dates: Vec<String> = dates.iter().map(|d| d.to_string()).collect();
+                write!(
+                    f,
+                    "~ service {} exceptions on {}",
+                    service_id,
+                    dates.join(", ")
+                )
+            }
+        }
+    }
+}
+
+impl fmt::Display for TransitDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No changes");
+        }
+
+        let sections: [(&str, Vec<String>); 3] = [
+            (
+                "Stations",
+                self.stations.iter().map(ToString::to_string).collect(),
+            ),
+            (
+                "Routes",
+                self.routes.iter().map(ToString::to_string).collect(),
+            ),
+            (
+                "Service calendars",
+                self.calendars.iter().map(ToString::to_string).collect(),
+            ),
+        ];
+
+        for (title, lines) in sections {
+            if lines.is_empty() {
+                continue;
+            }
+
+            writeln!(f, "{} ({})", title, lines.len())?;
+            for line in lines {
+                writeln!(f, "  {}", line)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::models::types::{RouteType, StopEvent};
+    use crate::provider::{RouteImpl, StaticTransitProvider, StationImpl};
+    use crate::test_support::{self, complex_of, route, weekdays};
+
+    fn station(id: &str, name: &str, lat: f64) -> StationImpl {
+        StationImpl {
+            name: name.into(),
+            ..test_support::station(id, -74.0, lat)
+        }
+    }
+
+    fn provider(
+        stations: Vec<StationImpl>,
+        stops: &[&str],
+        removed_dates: &[NaiveDate],
+    ) -> StaticTransitProvider {
+        let complexes = stations.iter().map(complex_of).collect();
+
+        let calendar = Arc::new(ServiceCalendar {
+            removed_dates: Arc::new(removed_dates.iter().copied().collect()),
+            ..weekdays(chrono_tz::America::New_York)
+        });
+
+        let stop_events = stops
+            .iter()
+            .enumerate()
+            .map(|(i, s)| {
+                StopEvent::new(
+                    StationIdentifier::new(*s),
+                    i as u32 * 60,
+                    i as u32 * 60,
+                    i as u32,
+                )
+            })
+            .collect();
+        let trip = Arc::new(test_support::trip("t1", "1", &calendar, stop_events));
+
+        let route = RouteImpl {
+            long_name: "Broadway Local".into(),
+            ..route("1", RouteType::Subway, vec![trip])
+        };
+
+        StaticTransitProvider::from_data(stations, complexes, vec![route])
+    }
+
+    #[test]
+    fn test_identical_providers() {
+        let old = provider(vec![station("a", "Alpha", 40.70)], &["a"], &[]);
+        let diff = diff_providers(&old, &old.clone(), &DiffOptions::default());
+
+        assert!(diff.is_empty());
+        assert!(!diff.needs_review());
+        assert_eq!(diff.to_string(), "No changes\n");
+    }
+
+    #[test]
+    fn test_diff() {
+        let holiday = NaiveDate::from_ymd_opt(2024, 7, 4).unwrap();
+
+        let old = provider(
+            vec![
+                station("a", "Alpha", 40.70),
+                station("b", "Bravo", 40.71),
+                station("c", "Charlie", 40.72),
+            ],
+            &["a", "b", "c"],
+            &[],
+        );
+        let new = provider(
+            vec![
+                // Renamed, same question length
+                station("a", "Alpha Station", 40.70),
+                // Moved ~110m north
+                station("b", "Bravo", 40.711),
+                station("d", "Delta", 40.73),
+            ],
+            &["a", "b", "d"],
+            &[holiday],
+        );
+
+        let diff = diff_providers(&old, &new, &DiffOptions::default());
+
+        assert_eq!(diff.stations.len(), 4);
+        assert!(matches!(
+            &diff.stations[0],
+            StationChange::Renamed {
+                length_changed: false,
+                ..
+            }
+        ));
+        assert!(!diff.stations[0].needs_review());
+        assert!(
+            matches!(&diff.stations[1], StationChange::Moved { distance_m, .. } if (*distance_m - 111.0).abs() < 2.0)
+        );
+        assert!(
+            matches!(&diff.stations[2], StationChange::Removed { id, .. } if id.as_str() == "c")
+        );
+        assert!(matches!(&diff.stations[3], StationChange::Added { id, .. } if id.as_str() == "d"));
+
+        assert_eq!(
+            diff.routes,
+            vec![RouteChange::StopsChanged {
+                id: RouteIdentifier::new("1"),
+                added: vec![StationIdentifier::new("d")],
+                removed: vec![StationIdentifier::new("c")],
+            }]
+        );
+
+        assert_eq!(
+            diff.calendars,
+            vec![CalendarChange::ExceptionsChanged {
+                service_id: ServiceIdentifier::new("weekday"),
+                dates: vec![holiday],
+            }]
+        );
+
+        assert!(diff.needs_review());
+
+        // Moving less than the threshold isn't reported
+        let lenient = diff_providers(
+            &old,
+            &new,
+            &DiffOptions::default().with_move_threshold(200.0),
+        );
+        assert_eq!(lenient.stations.len(), 3);
+
+        let report = diff.to_string();
+        assert!(report.contains("[review] ~ route 1 stops +[d] -[c]"));
+        assert!(report.contains("  ~ station a renamed \"Alpha\" → \"Alpha Station\""));
+    }
+}
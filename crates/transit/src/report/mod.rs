@@ -0,0 +1,13 @@
+//! Feed diffs and validation.
+//!
+//! When a new feed arrives mid-season, [`diff_providers`] lists what changed
+//! against the data players have been using, and [`validate`] flags
+//! structural problems in the new data. Changes that can alter a question's
+//! answer (a station moving, a stop being dropped from a line, a renamed
+//! station whose name length differs) are marked as needing review.
+
+pub mod diff;
+pub mod validate;
+
+pub use diff::{diff_providers, CalendarChange, DiffOptions, RouteChange, StationChange, TransitDiff};
+pub use validate::{validate, Issue, Severity};
@@ -0,0 +1,284 @@
+//! Structural checks on a provider's data.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::identifiers::*;
+use crate::models::traits::TransitProvider;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Suspicious but usable
+    Warning,
+    /// Breaks queries or question answers
+    Error,
+}
+
+/// A structural problem in transit data
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Issue {
+    /// The station's `complex_id` doesn't resolve to a complex
+    StationWithoutComplex {
+        station_id: StationIdentifier,
+        complex_id: ComplexIdentifier,
+    },
+    /// A complex lists a station that doesn't exist or belongs elsewhere
+    OrphanComplexMember {
+        complex_id: ComplexIdentifier,
+        station_id: StationIdentifier,
+    },
+    /// A complex with no stations
+    EmptyComplex { complex_id: ComplexIdentifier },
+    /// A trip stops at a station that doesn't exist
+    UnknownStation {
+        trip_id: TripIdentifier,
+        station_id: StationIdentifier,
+    },
+    /// A trip's times go backwards (or departs before arriving) at a stop
+    NonMonotonicStopTimes {
+        trip_id: TripIdentifier,
+        stop_sequence: u32,
+    },
+    /// A trip's stop sequence numbers don't strictly increase
+    NonMonotonicStopSequence {
+        trip_id: TripIdentifier,
+        stop_sequence: u32,
+    },
+}
+
+impl Issue {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Issue::EmptyComplex { .. } | Issue::NonMonotonicStopSequence { .. } => {
+                Severity::Warning
+            }
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity() {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: ", severity)?;
+
+        match self {
+            Issue::StationWithoutComplex {
+                station_id,
+                complex_id,
+            } => {
+                write!(
+                    f,
+                    "station {} references missing complex {}",
+                    station_id, complex_id
+                )
+            }
+            Issue::OrphanComplexMember {
+                complex_id,
+                station_id,
+            } => write!(
+                f,
+                "complex {} lists station {}, which doesn't belong to it",
+                complex_id, station_id
+            ),
+            Issue::EmptyComplex { complex_id } => {
+                write!(f, "complex {} has no stations", complex_id)
+            }
+            Issue::UnknownStation {
+                trip_id,
+                station_id,
+            } => {
+                write!(
+                    f,
+                    "trip {} stops at unknown station {}",
+                    trip_id, station_id
+                )
+            }
+            Issue::NonMonotonicStopTimes {
+                trip_id,
+                stop_sequence,
+            } => write!(
+                f,
+                "trip {} goes back in time at stop sequence {}",
+                trip_id, stop_sequence
+            ),
+            Issue::NonMonotonicStopSequence {
+                trip_id,
+                stop_sequence,
+            } => write!(
+                f,
+                "trip {} has out-of-order stop sequence {}",
+                trip_id, stop_sequence
+            ),
+        }
+    }
+}
+
+/// Check a provider for structural problems, errors first
+pub fn validate(provider: &dyn TransitProvider) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    let stations = provider.all_stations();
+    let station_ids: HashSet<&StationIdentifier> = stations.iter().map(|s| s.id()).collect();
+
+    for station in &stations {
+        if provider.get_complex(station.complex_id()).is_none() {
+            issues.push(Issue::StationWithoutComplex {
+                station_id: station.id().clone(),
+                complex_id: station.complex_id().clone(),
+            });
+        }
+    }
+
+    for complex in provider.all_complexes() {
+        if complex.station_ids().is_empty() {
+            issues.push(Issue::EmptyComplex {
+                complex_id: complex.id().clone(),
+            });
+        }
+
+        for station_id in complex.station_ids() {
+            let belongs = provider
+                .get_station(station_id)
+                .is_some_and(|s| s.complex_id() == complex.id());
+
+            if !belongs {
+                issues.push(Issue::OrphanComplexMember {
+                    complex_id: complex.id().clone(),
+                    station_id: station_id.clone(),
+                });
+            }
+        }
+    }
+
+    for route in provider.all_routes() {
+        for trip in route.trips() {
+            let mut reported_unknown = HashSet::new();
+
+            for event in trip.stop_events() {
+                if !station_ids.contains(&event.station_id)
+                    && reported_unknown.insert(event.station_id.clone())
+                {
+                    issues.push(Issue::UnknownStation {
+                        trip_id: trip.id().clone(),
+                        station_id: event.station_id.clone(),
+                    });
+                }
+            }
+
+            for (i, event) in trip.stop_events().iter().enumerate() {
+                let previous = i.checked_sub(1).map(|p| &trip.stop_events()[p]);
+
+                let goes_back = event.departure < event.arrival
+                    || previous.is_some_and(|p| event.arrival < p.departure);
+
+                if goes_back {
+                    issues.push(Issue::NonMonotonicStopTimes {
+                        trip_id: trip.id().clone(),
+                        stop_sequence: event.stop_sequence,
+                    });
+                }
+
+                if previous.is_some_and(|p| event.stop_sequence <= p.stop_sequence) {
+                    issues.push(Issue::NonMonotonicStopSequence {
+                        trip_id: trip.id().clone(),
+                        stop_sequence: event.stop_sequence,
+                    });
+                }
+            }
+        }
+    }
+
+    issues.sort_by_key(|issue| std::cmp::Reverse(issue.severity()));
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use geo::Point;
+
+    use crate::models::types::{RouteType, StopEvent};
+    use crate::provider::{ComplexImpl, StaticTransitProvider, StationImpl};
+    use crate::test_support::{complex_of, daily, route, station, trip};
+
+    #[test]
+    fn test_validate() {
+        let stations = vec![
+            StationImpl {
+                complex_id: ComplexIdentifier::new("ca"),
+                ..station("a", -74.0, 40.70)
+            },
+            StationImpl {
+                complex_id: ComplexIdentifier::new("missing"),
+                ..station("b", -74.0, 40.71)
+            },
+        ];
+
+        let complexes = vec![
+            ComplexImpl {
+                station_ids: vec![StationIdentifier::new("a"), StationIdentifier::new("b")],
+                ..complex_of(&stations[0])
+            },
+            ComplexImpl {
+                id: ComplexIdentifier::new("empty"),
+                name: "Empty".into(),
+                station_ids: vec![],
+                center: Point::new(-74.0, 40.70),
+            },
+        ];
+
+        let calendar = Arc::new(daily(chrono_tz::America::New_York));
+
+        let trip = trip(
+            "t",
+            "1",
+            &calendar,
+            vec![
+                StopEvent::new(StationIdentifier::new("a"), 600, 660, 1),
+                StopEvent::new(StationIdentifier::new("b"), 600, 600, 2),
+                StopEvent::new(StationIdentifier::new("ghost"), 700, 700, 2),
+            ],
+        );
+
+        let route = route("1", RouteType::Subway, vec![Arc::new(trip)]);
+
+        let provider = StaticTransitProvider::from_data(stations, complexes, vec![route]);
+        let issues = validate(&provider);
+
+        let t = || TripIdentifier::new("t");
+        assert_eq!(
+            issues,
+            vec![
+                Issue::StationWithoutComplex {
+                    station_id: StationIdentifier::new("b"),
+                    complex_id: ComplexIdentifier::new("missing"),
+                },
+                Issue::OrphanComplexMember {
+                    complex_id: ComplexIdentifier::new("ca"),
+                    station_id: StationIdentifier::new("b"),
+                },
+                Issue::UnknownStation {
+                    trip_id: t(),
+                    station_id: StationIdentifier::new("ghost"),
+                },
+                Issue::NonMonotonicStopTimes {
+                    trip_id: t(),
+                    stop_sequence: 2
+                },
+                Issue::EmptyComplex {
+                    complex_id: ComplexIdentifier::new("empty"),
+                },
+                Issue::NonMonotonicStopSequence {
+                    trip_id: t(),
+                    stop_sequence: 2
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,7 @@
+//! Journey planning over the static schedule.
+
+pub mod reachability;
+pub mod trajectory;
+
+pub use reachability::{reachable_from, Reachability, ReachabilityQuery, ReachableStation};
+pub use trajectory::{check_trajectory, Claim, ImpossibleLeg, Infeasibility, TrajectoryOptions};
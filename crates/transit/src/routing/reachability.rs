@@ -0,0 +1,544 @@
+//! Reachability ("isochrone") search.
+//!
+//! Answers "where could someone be `budget` after leaving `origin` at
+//! `departure`?" by combining walking with scheduled trips. The search is a
+//! connection scan: every stop-to-stop hop of every trip running in the time
+//! window is sorted by departure and relaxed once, following the provider's
+//! transfers (see [`crate::transfers`]) after each improvement. Transfers
+//! chain: a walk to one station can continue as a walk to the next.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use chrono::{DateTime, Duration, Utc};
+use geo::Point;
+
+use crate::identifiers::*;
+use crate::models::traits::TransitProvider;
+use crate::schedule::runs::{runs_between, TripRun};
+use crate::spatial::queries::haversine_distance;
+use crate::transfers::TransferKind;
+
+pub use crate::transfers::DEFAULT_WALKING_SPEED_MPS;
+
+/// Longest walk considered between two stations when transferring
+///
+/// Walking transfers come from the provider, so this can only narrow the
+/// ones it generates, not add longer walks.
+pub const DEFAULT_MAX_TRANSFER_WALK_M: f64 = crate::transfers::DEFAULT_MAX_WALKING_DISTANCE_M;
+
+// ============================================================================
+// Query / Result Types
+// ============================================================================
+
+/// Parameters for a reachability search
+#[derive(Clone, Debug)]
+pub struct ReachabilityQuery {
+    pub origin: Point,
+    pub departure: DateTime<Utc>,
+    pub budget: Duration,
+
+    /// Pace for walking from the origin, after the last stop and along
+    /// street-level transfers; other transfers use the provider's times
+    pub walking_speed_mps: f64,
+    pub max_transfer_walk_m: f64,
+}
+
+impl ReachabilityQuery {
+    pub fn new(origin: Point, departure: DateTime<Utc>, budget: Duration) -> Self {
+        Self {
+            origin,
+            departure,
+            budget,
+            walking_speed_mps: DEFAULT_WALKING_SPEED_MPS,
+            max_transfer_walk_m: DEFAULT_MAX_TRANSFER_WALK_M,
+        }
+    }
+
+    pub fn with_walking_speed(mut self, walking_speed_mps: f64) -> Self {
+        self.walking_speed_mps = walking_speed_mps;
+        self
+    }
+
+    pub fn with_max_transfer_walk(mut self, max_transfer_walk_m: f64) -> Self {
+        self.max_transfer_walk_m = max_transfer_walk_m;
+        self
+    }
+
+    /// Latest instant covered by the query
+    pub fn deadline(&self) -> DateTime<Utc> {
+        self.departure + self.budget
+    }
+
+    fn walking_time(&self, distance_m: f64) -> Duration {
+        Duration::milliseconds((distance_m / self.walking_speed_mps * 1000.0).ceil() as i64)
+    }
+
+    fn walking_distance(&self, time: Duration) -> f64 {
+        (time.num_milliseconds().max(0) as f64 / 1000.0) * self.walking_speed_mps
+    }
+}
+
+/// A station that can be reached within the query budget
+#[derive(Clone, Debug)]
+pub struct ReachableStation {
+    pub station_id: StationIdentifier,
+    pub location: Point,
+    pub arrival: DateTime<Utc>,
+
+    /// How far one can still walk from this station before the budget runs out
+    pub remaining_walk_m: f64,
+}
+
+/// Result of a reachability search
+#[derive(Clone, Debug)]
+pub struct Reachability {
+    pub origin: Point,
+
+    /// How far one can walk from the origin without using transit
+    pub origin_walk_m: f64,
+
+    /// Every reachable station, sorted by arrival time
+    pub stations: Vec<ReachableStation>,
+}
+
+impl Reachability {
+    pub fn get(&self, station_id: &StationIdentifier) -> Option<&ReachableStation> {
+        self.stations.iter().find(|s| &s.station_id == station_id)
+    }
+}
+
+// ============================================================================
+// Search
+// ============================================================================
+
+/// One stop-to-stop hop of a single trip run
+struct Connection {
+    trip: (TripIdentifier, TripRun),
+    from: StationIdentifier,
+    to: StationIdentifier,
+    departure: DateTime<Utc>,
+    arrival: DateTime<Utc>,
+}
+
+/// Earliest known arrival at a station
+struct Label {
+    location: Point,
+    arrival: DateTime<Utc>,
+
+    /// When a different trip can be boarded here; `None` if changing trips
+    /// at this station is not possible
+    ready: Option<DateTime<Utc>>,
+}
+
+impl Label {
+    fn improve(&mut self, arrival: DateTime<Utc>, ready: Option<DateTime<Utc>>) -> bool {
+        let mut improved = false;
+
+        if arrival < self.arrival {
+            self.arrival = arrival;
+            improved = true;
+        }
+
+        if let Some(ready) = ready {
+            if self.ready.is_none_or(|at| ready < at) {
+                self.ready = Some(ready);
+                improved = true;
+            }
+        }
+
+        improved
+    }
+}
+
+/// Find every station reachable from `query.origin` within the query budget
+pub fn reachable_from(provider: &dyn TransitProvider, query: &ReachabilityQuery) -> Reachability {
+    let deadline = query.deadline();
+    let origin_walk_m = query.walking_distance(query.budget);
+
+    if query.budget <= Duration::zero() || query.walking_speed_mps <= 0.0 {
+        return Reachability {
+            origin: query.origin,
+            origin_walk_m: 0.0,
+            stations: Vec::new(),
+        };
+    }
+
+    let mut earliest: HashMap<StationIdentifier, Label> = HashMap::new();
+
+    // Walk from the origin to every station within range
+    for station in provider.stations_near(query.origin, origin_walk_m) {
+        let distance = haversine_distance(query.origin, station.location());
+        let arrival = query.departure + query.walking_time(distance);
+
+        if arrival <= deadline {
+            let label = Label {
+                location: station.location(),
+                arrival,
+                ready: Some(arrival),
+            };
+            earliest.insert(station.id().clone(), label);
+        }
+    }
+
+    let mut boarded: HashSet<(TripIdentifier, TripRun)> = HashSet::new();
+
+    for connection in connections_between(provider, query.departure, deadline) {
+        let on_board = boarded.contains(&connection.trip);
+        let can_board = earliest
+            .get(&connection.from)
+            .and_then(|label| label.ready)
+            .is_some_and(|at| at <= connection.departure);
+
+        if !on_board && !can_board {
+            continue;
+        }
+
+        if !on_board {
+            boarded.insert(connection.trip.clone());
+        }
+
+        // Changing to another trip at the arrival station takes the
+        // station's own minimum transfer time
+        let ready = provider
+            .min_transfer_secs(&connection.to, &connection.to)
+            .map(|secs| connection.arrival + Duration::seconds(secs as i64));
+
+        let improved = match earliest.get_mut(&connection.to) {
+            Some(label) => label.improve(connection.arrival, ready),
+            None => {
+                let Some(station) = provider.get_station(&connection.to) else {
+                    continue;
+                };
+
+                let label = Label {
+                    location: station.location(),
+                    arrival: connection.arrival,
+                    ready,
+                };
+                earliest.insert(connection.to.clone(), label);
+                true
+            }
+        };
+
+        if !improved {
+            continue;
+        }
+
+        follow_transfers(provider, query, &mut earliest, &connection.to);
+    }
+
+    let mut stations: Vec<ReachableStation> = earliest
+        .into_iter()
+        .map(|(station_id, label)| ReachableStation {
+            station_id,
+            location: label.location,
+            arrival: label.arrival,
+            remaining_walk_m: query.walking_distance(deadline - label.arrival),
+        })
+        .collect();
+
+    stations.sort_by(|a, b| {
+        a.arrival
+            .cmp(&b.arrival)
+            .then_with(|| a.station_id.as_str().cmp(b.station_id.as_str()))
+    });
+
+    Reachability {
+        origin: query.origin,
+        origin_walk_m,
+        stations,
+    }
+}
+
+/// Improve every station reachable from `start` by a chain of transfers
+///
+/// Transfers from a station leave at its earliest arrival, nearest stations
+/// first, so each station is expanded once per improvement.
+fn follow_transfers(
+    provider: &dyn TransitProvider,
+    query: &ReachabilityQuery,
+    earliest: &mut HashMap<StationIdentifier, Label>,
+    start: &StationIdentifier,
+) {
+    let deadline = query.deadline();
+
+    // Identifiers aren't ordered, so the queue holds indices into `queued`
+    let mut queued = vec![start.clone()];
+    let mut queue = BinaryHeap::new();
+    queue.push(Reverse((earliest[start].arrival, 0)));
+
+    while let Some(Reverse((departure, index))) = queue.pop() {
+        let station_id = queued[index].clone();
+        let label = &earliest[&station_id];
+        if label.arrival < departure {
+            continue;
+        }
+        let from_location = label.location;
+
+        for transfer in provider.transfers_from(&station_id) {
+            if transfer.to == station_id {
+                continue;
+            }
+
+            let Some(station) = provider.get_station(&transfer.to) else {
+                continue;
+            };
+
+            // Street walks were generated from distance at the provider's
+            // pace, so redo them at the query's
+            let transfer_time = match transfer.kind {
+                TransferKind::Walking => {
+                    let distance = haversine_distance(from_location, station.location());
+                    if distance > query.max_transfer_walk_m {
+                        continue;
+                    }
+
+                    query.walking_time(distance)
+                }
+                _ => Duration::seconds(transfer.min_transfer_secs as i64),
+            };
+            let arrival = departure + transfer_time;
+
+            if arrival > deadline {
+                continue;
+            }
+
+            // Only an earlier arrival can lead anywhere new; a later one may
+            // still make the station boardable
+            let earlier = match earliest.get_mut(&transfer.to) {
+                Some(label) => {
+                    let earlier = arrival < label.arrival;
+                    label.improve(arrival, Some(arrival));
+                    earlier
+                }
+                None => {
+                    let label = Label {
+                        location: station.location(),
+                        arrival,
+                        ready: Some(arrival),
+                    };
+                    earliest.insert(transfer.to.clone(), label);
+                    true
+                }
+            };
+
+            if earlier {
+                queue.push(Reverse((arrival, queued.len())));
+                queued.push(transfer.to);
+            }
+        }
+    }
+}
+
+/// Every hop departing and arriving within `[from, until]`, sorted by departure
+fn connections_between(
+    provider: &dyn TransitProvider,
+    from: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> Vec<Connection> {
+    let mut connections = Vec::new();
+
+    for route in provider.all_routes() {
+        for trip in route.trips() {
+            for run in runs_between(trip.as_ref(), from, until) {
+                for hop in trip.stop_events().windows(2) {
+                    let departure = run.resolve(trip.as_ref(), hop[0].departure);
+                    let arrival = run.resolve(trip.as_ref(), hop[1].arrival);
+
+                    if departure < from || arrival > until {
+                        continue;
+                    }
+
+                    connections.push(Connection {
+                        trip: (trip.id().clone(), run),
+                        from: hop[0].station_id.clone(),
+                        to: hop[1].station_id.clone(),
+                        departure,
+                        arrival,
+                    });
+                }
+            }
+        }
+    }
+
+    connections.sort_by_key(|c| (c.departure, c.arrival));
+    connections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::models::traits::Trip;
+    use crate::models::types::{RouteType, StopEvent};
+    use crate::provider::StaticTransitProvider;
+    use crate::test_support::{complex_of, daily, route, station, trip};
+
+    fn test_provider() -> StaticTransitProvider {
+        // A north-south line along roughly -73.99, one stop every ~1.1km
+        let stations = vec![
+            station("a", -73.99, 40.70),
+            station("b", -73.99, 40.71),
+            station("c", -73.99, 40.72),
+            // ~250m east of "c"
+            station("c_east", -73.987, 40.72),
+            // ~250m east of "c_east", too far to walk to from "c" directly
+            station("c_far_east", -73.984, 40.72),
+            // Far away, not served
+            station("far", -73.80, 40.90),
+        ];
+        let complexes = stations.iter().map(complex_of).collect();
+
+        let calendar = Arc::new(daily(chrono_tz::America::New_York));
+
+        let trip_at = |id: &str, start: u32| {
+            let stop_events = vec![
+                StopEvent::new(StationIdentifier::new("a"), start, start + 30, 1),
+                StopEvent::new(StationIdentifier::new("b"), start + 120, start + 150, 2),
+                StopEvent::new(StationIdentifier::new("c"), start + 240, start + 270, 3),
+            ];
+
+            Arc::new(trip(id, "1", &calendar, stop_events)) as Arc<dyn Trip>
+        };
+
+        let route = route(
+            "1",
+            RouteType::Subway,
+            vec![trip_at("t_0800", 8 * 3600), trip_at("t_late", 24 * 3600 + 10 * 60)],
+        );
+
+        StaticTransitProvider::from_data(stations, complexes, vec![route])
+    }
+
+    /// Local New York time on a day in March 2024
+    fn at(day: u32, h: u32, m: u32) -> DateTime<Utc> {
+        use chrono::TimeZone;
+
+        chrono_tz::America::New_York
+            .with_ymd_and_hms(2024, 3, day, h, m, 0)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_walking_only() {
+        let provider = test_provider();
+        let query = ReachabilityQuery::new(
+            Point::new(-73.99, 40.70),
+            at(4, 12, 0),
+            Duration::minutes(5),
+        );
+
+        let result = reachable_from(&provider, &query);
+
+        assert!((result.origin_walk_m - 420.0).abs() < 1.0);
+        assert!(result.get(&StationIdentifier::new("a")).is_some());
+        assert!(result.get(&StationIdentifier::new("b")).is_none());
+    }
+
+    #[test]
+    fn test_ride_and_transfer_walk() {
+        let provider = test_provider();
+        let query = ReachabilityQuery::new(
+            Point::new(-73.99, 40.70),
+            at(4, 7, 55),
+            Duration::minutes(20),
+        );
+
+        let result = reachable_from(&provider, &query);
+
+        let c = result.get(&StationIdentifier::new("c")).unwrap();
+        assert_eq!(c.arrival, at(4, 8, 4));
+        assert!((c.remaining_walk_m - 11.0 * 60.0 * DEFAULT_WALKING_SPEED_MPS).abs() < 1.0);
+
+        let c_east = result.get(&StationIdentifier::new("c_east")).unwrap();
+        assert!(c_east.arrival > c.arrival);
+
+        assert!(result.get(&StationIdentifier::new("far")).is_none());
+    }
+
+    #[test]
+    fn test_transfer_walk_follows_query() {
+        let provider = test_provider();
+        let query = ReachabilityQuery::new(
+            Point::new(-73.99, 40.70),
+            at(4, 7, 55),
+            Duration::minutes(20),
+        );
+
+        let c_east = StationIdentifier::new("c_east");
+        let distance = haversine_distance(Point::new(-73.99, 40.72), Point::new(-73.987, 40.72));
+
+        for speed in [DEFAULT_WALKING_SPEED_MPS, 0.7] {
+            let query = query.clone().with_walking_speed(speed);
+            let result = reachable_from(&provider, &query);
+
+            let arrival = result.get(&c_east).unwrap().arrival;
+            assert_eq!(arrival, at(4, 8, 4) + query.walking_time(distance));
+        }
+
+        let result = reachable_from(&provider, &query.with_max_transfer_walk(100.0));
+        assert!(result.get(&c_east).is_none());
+    }
+
+    #[test]
+    fn test_transfer_walks_chain() {
+        let provider = test_provider();
+        let query = ReachabilityQuery::new(
+            Point::new(-73.99, 40.70),
+            at(4, 7, 55),
+            Duration::minutes(20),
+        );
+
+        let c_far_east = StationIdentifier::new("c_far_east");
+        let c_east = Point::new(-73.987, 40.72);
+
+        // Only reachable as a walk to "c_east" and on from there
+        assert!(!provider
+            .transfers_from(&StationIdentifier::new("c"))
+            .iter()
+            .any(|t| t.to == c_far_east));
+
+        let result = reachable_from(&provider, &query);
+        let walk = |from, to| query.walking_time(haversine_distance(from, to));
+
+        let arrival = result.get(&c_far_east).unwrap().arrival;
+        assert_eq!(
+            arrival,
+            at(4, 8, 4)
+                + walk(Point::new(-73.99, 40.72), c_east)
+                + walk(c_east, Point::new(-73.984, 40.72))
+        );
+    }
+
+    #[test]
+    fn test_missed_departure() {
+        let provider = test_provider();
+        let query = ReachabilityQuery::new(
+            Point::new(-73.99, 40.70),
+            at(4, 8, 1),
+            Duration::minutes(20),
+        );
+
+        let result = reachable_from(&provider, &query);
+        assert!(result.get(&StationIdentifier::new("c")).is_none());
+    }
+
+    #[test]
+    fn test_trip_past_midnight() {
+        let provider = test_provider();
+
+        // "t_late" runs at 24:10 on the 4th, i.e. 00:10 on the 5th
+        let query = ReachabilityQuery::new(
+            Point::new(-73.99, 40.70),
+            at(5, 0, 5),
+            Duration::minutes(15),
+        );
+
+        let result = reachable_from(&provider, &query);
+        let c = result.get(&StationIdentifier::new("c")).unwrap();
+        assert_eq!(c.arrival, at(5, 0, 14));
+    }
+}
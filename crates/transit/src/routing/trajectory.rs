@@ -0,0 +1,554 @@
+//! Hider trajectory auditing.
+//!
+//! A hider's claimed movements are a time-stamped sequence of positions and
+//! rides. Each leg between consecutive claims is checked against walking
+//! speed and the schedule: a ride must be on a trip that actually left the
+//! boarding station at the claimed time, and the hider must have been able to
+//! get there from their previous claim. Between claims the hider may also
+//! have used transit they didn't mention, unless that is turned off.
+
+use std::fmt;
+
+use chrono::{DateTime, Duration, Utc};
+use geo::Point;
+
+use crate::identifiers::*;
+use crate::models::traits::TransitProvider;
+use crate::routing::reachability::{reachable_from, ReachabilityQuery, DEFAULT_WALKING_SPEED_MPS};
+use crate::schedule::positions::MAX_REALTIME_DELAY_SECS;
+use crate::schedule::runs::runs_between;
+use crate::spatial::queries::haversine_distance;
+
+/// Default slack for clock differences between claims and the schedule
+pub const DEFAULT_TIME_TOLERANCE_SECS: i64 = 60;
+
+/// Default slack for GPS error in claimed positions
+pub const DEFAULT_POSITION_TOLERANCE_M: f64 = 50.0;
+
+// ============================================================================
+// Claims
+// ============================================================================
+
+/// Something a hider says they did
+#[derive(Clone, Debug, PartialEq)]
+pub enum Claim {
+    /// Was at `location` at `at`
+    Position { location: Point, at: DateTime<Utc> },
+    /// Boarded `trip_id` at `from` at `boarded_at` and rode it to `to`
+    Ride {
+        trip_id: TripIdentifier,
+        from: StationIdentifier,
+        to: StationIdentifier,
+        boarded_at: DateTime<Utc>,
+    },
+}
+
+/// Parameters for [`check_trajectory`]
+#[derive(Clone, Copy, Debug)]
+pub struct TrajectoryOptions {
+    pub walking_speed_mps: f64,
+    pub time_tolerance: Duration,
+    pub position_tolerance_m: f64,
+
+    /// May the hider have used transit between claims without claiming it?
+    pub allow_unclaimed_transit: bool,
+}
+
+impl Default for TrajectoryOptions {
+    fn default() -> Self {
+        Self {
+            walking_speed_mps: DEFAULT_WALKING_SPEED_MPS,
+            time_tolerance: Duration::seconds(DEFAULT_TIME_TOLERANCE_SECS),
+            position_tolerance_m: DEFAULT_POSITION_TOLERANCE_M,
+            allow_unclaimed_transit: true,
+        }
+    }
+}
+
+impl TrajectoryOptions {
+    pub fn with_walking_speed(mut self, walking_speed_mps: f64) -> Self {
+        self.walking_speed_mps = walking_speed_mps;
+        self
+    }
+
+    pub fn with_time_tolerance(mut self, time_tolerance: Duration) -> Self {
+        self.time_tolerance = time_tolerance;
+        self
+    }
+
+    pub fn with_position_tolerance(mut self, position_tolerance_m: f64) -> Self {
+        self.position_tolerance_m = position_tolerance_m;
+        self
+    }
+
+    pub fn with_unclaimed_transit(mut self, allow_unclaimed_transit: bool) -> Self {
+        self.allow_unclaimed_transit = allow_unclaimed_transit;
+        self
+    }
+}
+
+// ============================================================================
+// Results
+// ============================================================================
+
+/// Why a claim can't follow the one before it
+#[derive(Clone, Debug, PartialEq)]
+pub enum Infeasibility {
+    UnknownTrip {
+        trip_id: TripIdentifier,
+    },
+    /// The trip doesn't stop at the station (after the boarding station,
+    /// for the alighting one)
+    NotOnTrip {
+        trip_id: TripIdentifier,
+        station_id: StationIdentifier,
+    },
+    /// No run of the trip leaves the station around the claimed time
+    NotRunning {
+        trip_id: TripIdentifier,
+        station_id: StationIdentifier,
+        at: DateTime<Utc>,
+    },
+    /// Claimed before the previous leg could have finished
+    TooEarly {
+        claimed: DateTime<Utc>,
+        earliest: DateTime<Utc>,
+    },
+    /// Too far from the previous claim to get there in time
+    TooFar {
+        distance_m: f64,
+        available: Duration,
+    },
+}
+
+/// The first claim that can't follow the one before it
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImpossibleLeg {
+    /// Index of the claim in the checked sequence
+    pub index: usize,
+    pub reason: Infeasibility,
+}
+
+impl fmt::Display for Infeasibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Infeasibility::UnknownTrip { trip_id } => write!(f, "trip {} doesn't exist", trip_id),
+            Infeasibility::NotOnTrip {
+                trip_id,
+                station_id,
+            } => {
+                write!(
+                    f,
+                    "trip {} doesn't stop at {} in that direction",
+                    trip_id, station_id
+                )
+            }
+            Infeasibility::NotRunning {
+                trip_id,
+                station_id,
+                at,
+            } => {
+                write!(
+                    f,
+                    "trip {} doesn't leave {} around {}",
+                    trip_id, station_id, at
+                )
+            }
+            Infeasibility::TooEarly { claimed, earliest } => write!(
+                f,
+                "claimed at {}, but the previous leg can't end before {}",
+                claimed, earliest
+            ),
+            Infeasibility::TooFar {
+                distance_m,
+                available,
+            } => write!(
+                f,
+                "{:.0} m from the previous claim, which can't be covered in {} s",
+                distance_m,
+                available.num_seconds()
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ImpossibleLeg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "claim {}: {}", self.index, self.reason)
+    }
+}
+
+// ============================================================================
+// Checking
+// ============================================================================
+
+/// Where the hider is known to be, and from when they're free to move on
+struct Waypoint {
+    location: Point,
+    ready: DateTime<Utc>,
+}
+
+/// Check that each claim can follow the one before it
+///
+/// Returns the first claim that can't, or `None` if the whole sequence is
+/// consistent. The first claim is taken as given (apart from a ride having
+/// to exist as claimed).
+pub fn check_trajectory(
+    provider: &dyn TransitProvider,
+    claims: &[Claim],
+    options: &TrajectoryOptions,
+) -> Option<ImpossibleLeg> {
+    let mut previous: Option<Waypoint> = None;
+
+    for (index, claim) in claims.iter().enumerate() {
+        let next = match claim {
+            Claim::Position { location, at } => {
+                check_arrival(provider, previous.as_ref(), *location, *at, options).map(|_| {
+                    Waypoint {
+                        location: *location,
+                        ready: *at,
+                    }
+                })
+            }
+            Claim::Ride {
+                trip_id,
+                from,
+                to,
+                boarded_at,
+            } => check_ride(
+                provider,
+                previous.as_ref(),
+                trip_id,
+                from,
+                to,
+                *boarded_at,
+                options,
+            ),
+        };
+
+        match next {
+            Ok(waypoint) => previous = Some(waypoint),
+            Err(reason) => return Some(ImpossibleLeg { index, reason }),
+        }
+    }
+
+    None
+}
+
+/// Could the hider get from `previous` to `location` by `at`?
+fn check_arrival(
+    provider: &dyn TransitProvider,
+    previous: Option<&Waypoint>,
+    location: Point,
+    at: DateTime<Utc>,
+    options: &TrajectoryOptions,
+) -> Result<(), Infeasibility> {
+    let Some(previous) = previous else {
+        return Ok(());
+    };
+
+    let deadline = at + options.time_tolerance;
+    if deadline < previous.ready {
+        return Err(Infeasibility::TooEarly {
+            claimed: at,
+            earliest: previous.ready,
+        });
+    }
+
+    if earliest_arrival(provider, previous, location, deadline, options).is_some() {
+        return Ok(());
+    }
+
+    Err(Infeasibility::TooFar {
+        distance_m: haversine_distance(previous.location, location),
+        available: at - previous.ready,
+    })
+}
+
+fn check_ride(
+    provider: &dyn TransitProvider,
+    previous: Option<&Waypoint>,
+    trip_id: &TripIdentifier,
+    from: &StationIdentifier,
+    to: &StationIdentifier,
+    boarded_at: DateTime<Utc>,
+    options: &TrajectoryOptions,
+) -> Result<Waypoint, Infeasibility> {
+    let trip = provider
+        .get_trip(trip_id)
+        .ok_or_else(|| Infeasibility::UnknownTrip {
+            trip_id: trip_id.clone(),
+        })?;
+
+    let not_on_trip = |station_id: &StationIdentifier| Infeasibility::NotOnTrip {
+        trip_id: trip_id.clone(),
+        station_id: station_id.clone(),
+    };
+
+    let events = trip.stop_events();
+    let board = events
+        .iter()
+        .position(|e| provider.is_same_station(&e.station_id, from))
+        .ok_or_else(|| not_on_trip(from))?;
+    let alight = events[board + 1..]
+        .iter()
+        .position(|e| provider.is_same_station(&e.station_id, to))
+        .map(|i| board + 1 + i)
+        .ok_or_else(|| not_on_trip(to))?;
+
+    // The run leaving the boarding station closest to the claimed time, with
+    // realtime delays if there are any; delayed runs may leave outside their
+    // scheduled window
+    let tolerance = options.time_tolerance;
+    let slack = tolerance + Duration::seconds(MAX_REALTIME_DELAY_SECS);
+    let (run, events, departure) = runs_between(
+        trip.as_ref(),
+        boarded_at - slack,
+        boarded_at + slack,
+    )
+    .into_iter()
+    .map(|run| {
+        let events = match provider.trip_delays(trip_id, &run) {
+            Some(delays) => delays.apply(events),
+            None => events.to_vec(),
+        };
+        let departure = run.resolve(trip.as_ref(), events[board].departure);
+        (run, events, departure)
+    })
+    .filter(|(_, _, departure)| (*departure - boarded_at).abs() <= tolerance)
+    .min_by_key(|(_, _, departure)| (*departure - boarded_at).abs())
+    .ok_or_else(|| Infeasibility::NotRunning {
+        trip_id: trip_id.clone(),
+        station_id: from.clone(),
+        at: boarded_at,
+    })?;
+
+    let station = provider
+        .get_station(&events[board].station_id)
+        .ok_or_else(|| not_on_trip(from))?;
+    check_arrival(provider, previous, station.location(), departure, options)?;
+
+    let alighting = provider
+        .get_station(&events[alight].station_id)
+        .ok_or_else(|| not_on_trip(to))?;
+
+    Ok(Waypoint {
+        location: alighting.location(),
+        ready: run.resolve(trip.as_ref(), events[alight].arrival),
+    })
+}
+
+/// Earliest the hider could be within the position tolerance of `target`,
+/// if it's no later than `deadline`
+fn earliest_arrival(
+    provider: &dyn TransitProvider,
+    from: &Waypoint,
+    target: Point,
+    deadline: DateTime<Utc>,
+    options: &TrajectoryOptions,
+) -> Option<DateTime<Utc>> {
+    let walk_m =
+        |point: Point| (haversine_distance(point, target) - options.position_tolerance_m).max(0.0);
+    let arrive_after = |ready: DateTime<Utc>, distance_m: f64| {
+        let millis = (distance_m / options.walking_speed_mps * 1000.0).ceil();
+        ready.checked_add_signed(Duration::try_milliseconds(millis as i64)?)
+    };
+
+    let direct = arrive_after(from.ready, walk_m(from.location)).filter(|at| *at <= deadline);
+    if direct.is_some() || !options.allow_unclaimed_transit {
+        return direct;
+    }
+
+    let query = ReachabilityQuery::new(from.location, from.ready, deadline - from.ready)
+        .with_walking_speed(options.walking_speed_mps);
+
+    reachable_from(provider, &query)
+        .stations
+        .iter()
+        .filter(|station| walk_m(station.location) <= station.remaining_walk_m)
+        .filter_map(|station| arrive_after(station.arrival, walk_m(station.location)))
+        .min()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use chrono::{NaiveDate, TimeZone};
+
+    use crate::models::types::{RouteType, StopEvent};
+    use crate::provider::{RouteImpl, StaticTransitProvider, StationImpl, TripImpl};
+    use crate::test_support::{self, complex_of, daily, route, station};
+
+    fn test_provider() -> StaticTransitProvider {
+        // "a" and "c" are ~2.2 km apart; the 08:00 covers it in four minutes
+        let stations: Vec<StationImpl> = [("a", 40.70), ("c", 40.72)]
+            .into_iter()
+            .map(|(id, lat)| station(id, -73.99, lat))
+            .collect();
+
+        let complexes = stations.iter().map(complex_of).collect();
+
+        let calendar = Arc::new(daily(chrono_tz::America::New_York));
+
+        let trip = TripImpl {
+            headsign: "Uptown".into(),
+            ..test_support::trip(
+                "t_0800",
+                "1",
+                &calendar,
+                vec![
+                    StopEvent::new(StationIdentifier::new("a"), 8 * 3600, 8 * 3600, 1),
+                    StopEvent::new(
+                        StationIdentifier::new("c"),
+                        8 * 3600 + 240,
+                        8 * 3600 + 240,
+                        2,
+                    ),
+                ],
+            )
+        };
+
+        let route = RouteImpl {
+            long_name: "Test Line".into(),
+            ..route("1", RouteType::Subway, vec![Arc::new(trip)])
+        };
+
+        StaticTransitProvider::from_data(stations, complexes, vec![route])
+    }
+
+    /// Local New York time on Monday 4 March 2024
+    fn at(h: u32, m: u32) -> DateTime<Utc> {
+        chrono_tz::America::New_York
+            .with_ymd_and_hms(2024, 3, 4, h, m, 0)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn position(lat: f64, at: DateTime<Utc>) -> Claim {
+        Claim::Position {
+            location: Point::new(-73.99, lat),
+            at,
+        }
+    }
+
+    fn ride(from: &str, to: &str, boarded_at: DateTime<Utc>) -> Claim {
+        Claim::Ride {
+            trip_id: TripIdentifier::new("t_0800"),
+            from: StationIdentifier::new(from),
+            to: StationIdentifier::new(to),
+            boarded_at,
+        }
+    }
+
+    #[test]
+    fn test_consistent_trajectory() {
+        let provider = test_provider();
+        let claims = [
+            position(40.701, at(7, 55)),
+            ride("a", "c", at(8, 0)),
+            position(40.721, at(8, 6)),
+        ];
+
+        assert_eq!(
+            check_trajectory(&provider, &claims, &TrajectoryOptions::default()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_unclaimed_transit() {
+        let provider = test_provider();
+        let claims = [position(40.70, at(7, 58)), position(40.72, at(8, 5))];
+
+        // Only possible on the 08:00
+        assert_eq!(
+            check_trajectory(&provider, &claims, &TrajectoryOptions::default()),
+            None
+        );
+
+        let walking_only = TrajectoryOptions::default().with_unclaimed_transit(false);
+        let leg = check_trajectory(&provider, &claims, &walking_only).unwrap();
+        assert_eq!(leg.index, 1);
+        assert!(
+            matches!(leg.reason, Infeasibility::TooFar { distance_m, .. } if (distance_m - 2224.0).abs() < 5.0)
+        );
+    }
+
+    #[test]
+    fn test_impossible_legs() {
+        let provider = test_provider();
+        let options = TrajectoryOptions::default();
+        let check = |claims: &[Claim]| check_trajectory(&provider, claims, &options).unwrap();
+
+        // Can't walk to "a" from 2 km away in five minutes
+        let leg = check(&[position(40.72, at(7, 55)), ride("a", "c", at(8, 0))]);
+        assert_eq!(leg.index, 1);
+        assert!(matches!(leg.reason, Infeasibility::TooFar { .. }));
+
+        // No 08:30 run
+        let leg = check(&[ride("a", "c", at(8, 30))]);
+        assert!(matches!(leg.reason, Infeasibility::NotRunning { .. }));
+
+        // Wrong direction
+        let leg = check(&[ride("c", "a", at(8, 4))]);
+        assert_eq!(
+            leg.reason,
+            Infeasibility::NotOnTrip {
+                trip_id: TripIdentifier::new("t_0800"),
+                station_id: StationIdentifier::new("a"),
+            }
+        );
+
+        // Seen off the train before it arrived
+        let leg = check(&[ride("a", "c", at(8, 0)), position(40.72, at(8, 1))]);
+        assert_eq!(leg.index, 1);
+        assert_eq!(
+            leg.reason,
+            Infeasibility::TooEarly {
+                claimed: at(8, 1),
+                earliest: at(8, 4),
+            }
+        );
+        assert!(leg.to_string().starts_with("claim 1: claimed at"));
+    }
+
+    #[test]
+    fn test_delayed_ride() {
+        use crate::schedule::{TripDelays, TripRun};
+
+        let run = TripRun {
+            service_date: NaiveDate::from_ymd_opt(2024, 3, 4).unwrap(),
+            offset: 0,
+            exact: true,
+        };
+        let provider = test_provider().with_trip_delays([(
+            TripIdentifier::new("t_0800"),
+            run,
+            TripDelays::new().with_delay(1, 600),
+        )]);
+        let options = TrajectoryOptions::default();
+
+        // Ten minutes late: boarded at 08:10 and off at "c" by 08:14
+        let claims = [ride("a", "c", at(8, 10)), position(40.721, at(8, 15))];
+        assert_eq!(check_trajectory(&provider, &claims, &options), None);
+
+        // Its scheduled time no longer matches the run
+        let leg = check_trajectory(&provider, &[ride("a", "c", at(8, 0))], &options).unwrap();
+        assert!(matches!(leg.reason, Infeasibility::NotRunning { .. }));
+
+        // Seen at "c" when it would have been on time, but it wasn't
+        let leg = check_trajectory(
+            &provider,
+            &[ride("a", "c", at(8, 10)), position(40.72, at(8, 5))],
+            &options,
+        )
+        .unwrap();
+        assert_eq!(leg.index, 1);
+        assert!(matches!(
+            leg.reason,
+            Infeasibility::TooEarly { earliest, .. } if earliest == at(8, 14)
+        ));
+    }
+}
@@ -0,0 +1,21 @@
+//! Time-based queries over the static schedule.
+//!
+//! All queries take absolute (UTC) instants and resolve GTFS stop times
+//! (which may exceed 24:00) against the service day and agency timezone they
+//! belong to, so trips that run past midnight or across a DST change are
+//! found on either side of it.
+
+pub mod positions;
+pub mod queries;
+pub mod realtime;
+pub mod runs;
+pub mod service_day;
+
+pub use positions::{vehicle_position, vehicles_in, VehiclePosition};
+pub use queries::{
+    departures_from, next_stop_of, stations_served_between, stations_served_by, trips_active_at,
+    ActiveTrip, ScheduledStop,
+};
+pub use realtime::TripDelays;
+pub use runs::{runs_between, TripRun};
+pub use service_day::{resolve_time, service_dates_covering};
@@ -0,0 +1,384 @@
+//! Interpolated vehicle positions.
+//!
+//! A vehicle dwells at each stop from its arrival to its departure, then
+//! moves along the trip's shape at constant speed until it arrives at the
+//! next. Where a trip has no shape, or a stop is too far from it, the vehicle
+//! follows the great circle between stops instead. Realtime delays from the
+//! provider are applied before interpolating.
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use geo::{Contains, HaversineBearing, HaversineIntermediate, Point, Rect};
+
+use crate::identifiers::*;
+use crate::models::traits::{TransitProvider, Trip};
+use crate::schedule::queries::ScheduledStop;
+use crate::schedule::runs::{runs_between, TripRun};
+use crate::shapes::{normalize_bearing, MeasuredShape};
+
+/// Furthest a stop may be from a trip's shape for the shape to be followed
+/// to and from it
+pub const MAX_SHAPE_OFFSET_M: f64 = 200.0;
+
+/// Most a realtime run may be delayed (or early) and still be found
+pub const MAX_REALTIME_DELAY_SECS: i64 = 3600;
+
+/// Where a vehicle is at some instant
+#[derive(Clone, Debug, PartialEq)]
+pub struct VehiclePosition {
+    pub trip_id: TripIdentifier,
+    pub route_id: RouteIdentifier,
+    pub run: TripRun,
+    pub location: Point,
+
+    /// Direction of travel in degrees clockwise from north, `[0, 360)`
+    pub bearing: f64,
+
+    /// The stop the vehicle is at, or the one it's heading to
+    pub next_stop: ScheduledStop,
+
+    /// Is the vehicle stopped at `next_stop`?
+    pub dwelling: bool,
+}
+
+// ============================================================================
+// Queries
+// ============================================================================
+
+/// Where `trip_id`'s vehicle is at `at`
+///
+/// For a frequency-based trip with several vehicles underway, the one
+/// arriving at its next stop soonest is returned. Returns `None` if the trip
+/// is unknown, not running at `at`, or stops at an unknown station.
+pub fn vehicle_position<P: TransitProvider + ?Sized>(
+    provider: &P,
+    trip_id: &TripIdentifier,
+    at: DateTime<Utc>,
+) -> Option<VehiclePosition> {
+    let trip = provider.get_trip(trip_id)?;
+
+    positions_of(provider, &trip, at)
+        .into_iter()
+        .min_by_key(|position| position.next_stop.arrival)
+}
+
+/// Every vehicle inside `bounds` at `at`, sorted by trip
+pub fn vehicles_in<P: TransitProvider + ?Sized>(
+    provider: &P,
+    bounds: Rect,
+    at: DateTime<Utc>,
+) -> Vec<VehiclePosition> {
+    let mut vehicles: Vec<VehiclePosition> = provider
+        .all_routes()
+        .iter()
+        .flat_map(|route| route.trips().iter())
+        .flat_map(|trip| positions_of(provider, trip, at))
+        .filter(|position| bounds.contains(&position.location))
+        .collect();
+
+    vehicles.sort_by(|a, b| {
+        a.trip_id
+            .as_str()
+            .cmp(b.trip_id.as_str())
+            .then_with(|| a.run.service_date.cmp(&b.run.service_date))
+            .then_with(|| a.run.offset.cmp(&b.run.offset))
+    });
+    vehicles
+}
+
+/// Positions of every run of `trip` underway at `at`
+fn positions_of<P: TransitProvider + ?Sized>(
+    provider: &P,
+    trip: &Arc<dyn Trip>,
+    at: DateTime<Utc>,
+) -> Vec<VehiclePosition> {
+    // Delayed runs may be underway outside their scheduled window
+    let slack = Duration::seconds(MAX_REALTIME_DELAY_SECS);
+    let mut track: Option<Option<Track>> = None;
+    let mut positions = Vec::new();
+
+    for run in runs_between(trip.as_ref(), at - slack, at + slack) {
+        let events = match provider.trip_delays(trip.id(), &run) {
+            Some(delays) => delays.apply(trip.stop_events()),
+            None => trip.stop_events().to_vec(),
+        };
+
+        let arrivals: Vec<DateTime<Utc>> = events
+            .iter()
+            .map(|e| run.resolve(trip.as_ref(), e.arrival))
+            .collect();
+
+        let (Some(first), Some(last)) = (arrivals.first(), arrivals.last()) else {
+            continue;
+        };
+        if at < *first || at > *last {
+            continue;
+        }
+
+        let Some(track) = track
+            .get_or_insert_with(|| Track::new(provider, trip.as_ref()))
+            .as_ref()
+        else {
+            break;
+        };
+
+        // The last stop arrived at, and whether the vehicle is still there
+        let i = arrivals.partition_point(|arrival| *arrival <= at) - 1;
+        let departure = run.resolve(trip.as_ref(), events[i].departure);
+        let dwelling = at <= departure || i + 1 == events.len();
+
+        let (location, bearing, next) = if dwelling {
+            let (location, bearing) = track.at_stop(i);
+            (location, bearing, &events[i])
+        } else {
+            let hop_secs = (arrivals[i + 1] - departure).num_milliseconds() as f64;
+            let fraction = if hop_secs > 0.0 {
+                (at - departure).num_milliseconds() as f64 / hop_secs
+            } else {
+                1.0
+            };
+
+            let (location, bearing) = track.between(i, fraction);
+            (location, bearing, &events[i + 1])
+        };
+
+        positions.push(VehiclePosition {
+            trip_id: trip.id().clone(),
+            route_id: trip.route_id().clone(),
+            run,
+            location,
+            bearing,
+            next_stop: ScheduledStop::new(trip.as_ref(), run, next),
+            dwelling,
+        });
+    }
+
+    positions
+}
+
+// ============================================================================
+// Track
+// ============================================================================
+
+/// Where a trip's stops lie along its path
+struct Track {
+    stops: Vec<Point>,
+    shape: Option<MeasuredShape>,
+
+    /// Distance of each stop along `shape`, `None` where it's too far off it
+    along_m: Vec<Option<f64>>,
+}
+
+impl Track {
+    fn new<P: TransitProvider + ?Sized>(provider: &P, trip: &dyn Trip) -> Option<Self> {
+        let stops: Vec<Point> = trip
+            .stop_events()
+            .iter()
+            .map(|e| provider.get_station(&e.station_id).map(|s| s.location()))
+            .collect::<Option<_>>()?;
+
+        let shape = provider
+            .trip_shape(trip.id())
+            .map(|shape| MeasuredShape::new(&shape));
+
+        let mut along_m = Vec::with_capacity(stops.len());
+        if let Some(shape) = &shape {
+            let mut from_m = 0.0;
+
+            for stop in &stops {
+                let projected = shape
+                    .project(*stop, from_m)
+                    .filter(|(_, offset_m)| *offset_m <= MAX_SHAPE_OFFSET_M)
+                    .map(|(m, _)| m);
+
+                if let Some(m) = projected {
+                    from_m = m;
+                }
+                along_m.push(projected);
+            }
+        }
+
+        Some(Self {
+            stops,
+            shape,
+            along_m,
+        })
+    }
+
+    /// Location and bearing `fraction` of the way from stop `i` to stop `i + 1`
+    fn between(&self, i: usize, fraction: f64) -> (Point, f64) {
+        let fraction = fraction.clamp(0.0, 1.0);
+
+        if let (Some(shape), Some(Some(from_m)), Some(Some(to_m))) =
+            (&self.shape, self.along_m.get(i), self.along_m.get(i + 1))
+        {
+            if to_m > from_m {
+                if let Some(position) = shape.point_at(from_m + (to_m - from_m) * fraction) {
+                    return position;
+                }
+            }
+        }
+
+        let (from, to) = (self.stops[i], self.stops[i + 1]);
+        (
+            from.haversine_intermediate(&to, fraction),
+            normalize_bearing(from.haversine_bearing(to)),
+        )
+    }
+
+    /// Location and bearing of a vehicle stopped at stop `i`, facing the way
+    /// it will leave (or arrived, at the last stop)
+    fn at_stop(&self, i: usize) -> (Point, f64) {
+        let bearing = if i + 1 < self.stops.len() {
+            self.between(i, 0.0).1
+        } else if i > 0 {
+            self.between(i - 1, 1.0).1
+        } else {
+            0.0
+        };
+
+        (self.stops[i], bearing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{NaiveDate, TimeZone};
+    use geo::{coord, LineString};
+
+    use crate::models::types::{RouteType, StopEvent};
+    use crate::provider::{StaticTransitProvider, StationImpl, TripImpl};
+    use crate::schedule::TripDelays;
+    use crate::spatial::queries::haversine_distance;
+    use crate::test_support::{self, complex_of, daily, route, station};
+
+    const MONDAY: (i32, u32, u32) = (2024, 1, 8);
+
+    fn provider() -> StaticTransitProvider {
+        let stations: Vec<StationImpl> = [("a", 40.70), ("b", 40.72), ("c", 40.74)]
+            .into_iter()
+            .map(|(id, lat)| station(id, -74.0, lat))
+            .collect();
+
+        let complexes = stations.iter().map(complex_of).collect();
+
+        let calendar = Arc::new(daily(chrono_tz::America::New_York));
+
+        // 08:00 from a, 08:10 at b, 08:20 at c
+        let trip = TripImpl {
+            headsign: "c".into(),
+            shape_id: Some(ShapeIdentifier::new("jog")),
+            ..test_support::trip(
+                "t1",
+                "A",
+                &calendar,
+                vec![
+                    StopEvent::new(StationIdentifier::new("a"), 28_800, 28_830, 1),
+                    StopEvent::new(StationIdentifier::new("b"), 29_400, 29_430, 2),
+                    StopEvent::new(StationIdentifier::new("c"), 30_000, 30_000, 3),
+                ],
+            )
+        };
+
+        let route = route("A", RouteType::Subway, vec![Arc::new(trip)]);
+
+        // Jogs east between a and b, then straight north to c
+        let jog = LineString::new(vec![
+            coord! { x: -74.0, y: 40.70 },
+            coord! { x: -73.99, y: 40.71 },
+            coord! { x: -74.0, y: 40.72 },
+            coord! { x: -74.0, y: 40.74 },
+        ]);
+
+        StaticTransitProvider::from_data(stations, complexes, vec![route])
+            .with_shapes([(ShapeIdentifier::new("jog"), jog)])
+    }
+
+    fn local(seconds: i64) -> DateTime<Utc> {
+        let (y, m, d) = MONDAY;
+        chrono_tz::America::New_York
+            .with_ymd_and_hms(y, m, d, 0, 0, 0)
+            .unwrap()
+            .with_timezone(&Utc)
+            + Duration::milliseconds(seconds * 1000)
+    }
+
+    #[test]
+    fn test_vehicle_follows_shape() {
+        let provider = provider();
+        let t1 = TripIdentifier::new("t1");
+
+        // A quarter of the way from a to b: halfway along the jog's first leg
+        let at = local(28_830) + Duration::milliseconds(142_500);
+        let position = provider.vehicle_position(&t1, at).unwrap();
+
+        assert!(!position.dwelling);
+        assert_eq!(position.next_stop.station_id, StationIdentifier::new("b"));
+        assert!(haversine_distance(position.location, Point::new(-73.995, 40.705)) < 5.0);
+        assert!(
+            (30.0..45.0).contains(&position.bearing),
+            "{}",
+            position.bearing
+        );
+
+        // Dwelling at b, then at the terminus facing the way it arrived
+        let position = provider.vehicle_position(&t1, local(29_410)).unwrap();
+        assert!(position.dwelling);
+        assert_eq!(position.location, Point::new(-74.0, 40.72));
+
+        let position = provider.vehicle_position(&t1, local(30_000)).unwrap();
+        assert!(position.dwelling);
+        assert_eq!(position.next_stop.station_id, StationIdentifier::new("c"));
+        assert!(position.bearing.min(360.0 - position.bearing) < 1e-6);
+
+        // Before and after the run
+        assert!(provider.vehicle_position(&t1, local(28_700)).is_none());
+        assert!(provider.vehicle_position(&t1, local(30_100)).is_none());
+    }
+
+    #[test]
+    fn test_delayed_vehicle() {
+        let run = TripRun {
+            service_date: NaiveDate::from_ymd_opt(MONDAY.0, MONDAY.1, MONDAY.2).unwrap(),
+            offset: 0,
+            exact: true,
+        };
+        let t1 = TripIdentifier::new("t1");
+
+        let provider =
+            provider().with_trip_delays([(t1.clone(), run, TripDelays::new().with_delay(1, 300))]);
+
+        // Five minutes late: still waiting at a when it should be halfway to b
+        let position = provider.vehicle_position(&t1, local(29_115)).unwrap();
+        assert!(position.dwelling);
+        assert_eq!(position.next_stop.station_id, StationIdentifier::new("a"));
+        assert_eq!(position.next_stop.departure, local(29_130));
+
+        // And still running after its scheduled arrival at c
+        assert!(provider.vehicle_position(&t1, local(30_100)).is_some());
+    }
+
+    #[test]
+    fn test_vehicles_in_bounds() {
+        let provider = provider();
+        let at = local(29_115);
+
+        let south = Rect::new(
+            coord! { x: -74.01, y: 40.69 },
+            coord! { x: -73.98, y: 40.715 },
+        );
+        let north = Rect::new(
+            coord! { x: -74.01, y: 40.73 },
+            coord! { x: -73.98, y: 40.75 },
+        );
+
+        let vehicles = provider.vehicles_in(south, at);
+        assert_eq!(vehicles.len(), 1);
+        assert_eq!(vehicles[0].trip_id, TripIdentifier::new("t1"));
+
+        assert!(provider.vehicles_in(north, at).is_empty());
+    }
+}
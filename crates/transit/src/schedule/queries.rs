@@ -0,0 +1,409 @@
+//! Departure boards and trip position queries.
+//!
+//! These are generic over any [`TransitProvider`] and back the provided
+//! schedule methods on that trait.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+
+use crate::identifiers::*;
+use crate::models::traits::{TransitProvider, Trip};
+use crate::models::types::StopEvent;
+use crate::schedule::runs::{runs_between, TripRun};
+
+// ============================================================================
+// Result Types
+// ============================================================================
+
+/// A stop event resolved to absolute time on a specific service day
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScheduledStop {
+    pub trip_id: TripIdentifier,
+    pub route_id: RouteIdentifier,
+    pub service_date: NaiveDate,
+    pub station_id: StationIdentifier,
+    pub stop_sequence: u32,
+    pub arrival: DateTime<Utc>,
+    pub departure: DateTime<Utc>,
+
+    /// `false` when the times are estimated from a headway (see [`TripRun::exact`])
+    pub exact: bool,
+}
+
+impl ScheduledStop {
+    pub fn new(trip: &dyn Trip, run: TripRun, event: &StopEvent) -> Self {
+        Self {
+            trip_id: trip.id().clone(),
+            route_id: trip.route_id().clone(),
+            service_date: run.service_date,
+            station_id: event.station_id.clone(),
+            stop_sequence: event.stop_sequence,
+            arrival: run.resolve(trip, event.arrival),
+            departure: run.resolve(trip, event.departure),
+            exact: run.exact,
+        }
+    }
+}
+
+/// A single run of a trip
+#[derive(Clone)]
+pub struct ActiveTrip {
+    pub trip: Arc<dyn Trip>,
+    pub run: TripRun,
+}
+
+impl ActiveTrip {
+    /// When the trip leaves its first stop
+    pub fn start(&self) -> Option<DateTime<Utc>> {
+        let first = self.trip.stop_events().first()?;
+        Some(self.run.resolve(self.trip.as_ref(), first.departure))
+    }
+
+    /// When the trip reaches its last stop
+    pub fn end(&self) -> Option<DateTime<Utc>> {
+        let last = self.trip.stop_events().last()?;
+        Some(self.run.resolve(self.trip.as_ref(), last.arrival))
+    }
+
+    /// Is the vehicle between its first departure and last arrival at `at`?
+    pub fn is_running_at(&self, at: DateTime<Utc>) -> bool {
+        match (self.start(), self.end()) {
+            (Some(start), Some(end)) => start <= at && at <= end,
+            _ => false,
+        }
+    }
+}
+
+// ============================================================================
+// Queries
+// ============================================================================
+
+/// Every departure from `station_id` within `[at, at + window]`, sorted by time
+///
+/// Arrivals at a trip's final stop are not departures and are excluded.
+pub fn departures_from<P: TransitProvider + ?Sized>(
+    provider: &P,
+    station_id: &StationIdentifier,
+    at: DateTime<Utc>,
+    window: Duration,
+) -> Vec<ScheduledStop> {
+    let until = at + window;
+    let mut departures = Vec::new();
+
+    for route in provider.all_routes() {
+        for trip in route.trips() {
+            let events = trip.stop_events();
+            let Some((_, boardable)) = events.split_last() else {
+                continue;
+            };
+
+            if !boardable.iter().any(|e| &e.station_id == station_id) {
+                continue;
+            }
+
+            for run in runs_between(trip.as_ref(), at, until) {
+                for event in boardable.iter().filter(|e| &e.station_id == station_id) {
+                    let departure = run.resolve(trip.as_ref(), event.departure);
+
+                    if at <= departure && departure <= until {
+                        departures.push(ScheduledStop::new(trip.as_ref(), run, event));
+                    }
+                }
+            }
+        }
+    }
+
+    departures.sort_by(|a, b| {
+        a.departure
+            .cmp(&b.departure)
+            .then_with(|| a.trip_id.as_str().cmp(b.trip_id.as_str()))
+    });
+    departures
+}
+
+/// Every trip whose vehicle is between its first and last stop at `at`
+pub fn trips_active_at<P: TransitProvider + ?Sized>(
+    provider: &P,
+    at: DateTime<Utc>,
+) -> Vec<ActiveTrip> {
+    let mut active = Vec::new();
+
+    for route in provider.all_routes() {
+        for trip in route.trips() {
+            active.extend(active_runs_of(trip, at));
+        }
+    }
+
+    active
+}
+
+/// The next stop `trip_id` will make at or after `at`
+///
+/// If the vehicle is currently dwelling at a station, that station is
+/// returned. Returns `None` if the trip is unknown or not running at `at`.
+pub fn next_stop_of<P: TransitProvider + ?Sized>(
+    provider: &P,
+    trip_id: &TripIdentifier,
+    at: DateTime<Utc>,
+) -> Option<ScheduledStop> {
+    let trip = provider.get_trip(trip_id)?;
+
+    active_runs_of(&trip, at)
+        .filter_map(|run| {
+            let event = trip
+                .stop_events()
+                .iter()
+                .find(|e| run.run.resolve(trip.as_ref(), e.departure) >= at)
+                .or_else(|| trip.stop_events().last())?;
+
+            Some(ScheduledStop::new(trip.as_ref(), run.run, event))
+        })
+        .min_by_key(|stop| stop.arrival)
+}
+
+/// Stations served by trips of `route_id` that are running at `at`
+///
+/// This reflects the service pattern in effect at that moment (e.g. express
+/// vs. local, late-night short turns), rather than every station the route
+/// ever visits. Stations are returned in the order first visited.
+pub fn stations_served_by<P: TransitProvider + ?Sized>(
+    provider: &P,
+    route_id: &RouteIdentifier,
+    at: DateTime<Utc>,
+) -> Vec<StationIdentifier> {
+    stations_served_between(provider, route_id, at, at)
+}
+
+/// Stations served by trips of `route_id` that are running at some point
+/// within `[from, until]`
+///
+/// Unlike [`stations_served_by`], a moment between two trips on a sparse
+/// route still sees the service pattern either side of it.
+pub fn stations_served_between<P: TransitProvider + ?Sized>(
+    provider: &P,
+    route_id: &RouteIdentifier,
+    from: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> Vec<StationIdentifier> {
+    let Some(route) = provider.get_route(route_id) else {
+        return Vec::new();
+    };
+
+    let mut seen = HashSet::new();
+    let mut stations = Vec::new();
+
+    for trip in route.trips() {
+        if runs_between(trip.as_ref(), from, until).is_empty() {
+            continue;
+        }
+
+        for event in trip.stop_events() {
+            if seen.insert(event.station_id.clone()) {
+                stations.push(event.station_id.clone());
+            }
+        }
+    }
+
+    stations
+}
+
+/// Runs of `trip` that are underway at `at`
+fn active_runs_of(trip: &Arc<dyn Trip>, at: DateTime<Utc>) -> impl Iterator<Item = ActiveTrip> + '_ {
+    runs_between(trip.as_ref(), at, at)
+        .into_iter()
+        .map(|run| ActiveTrip {
+            trip: trip.clone(),
+            run,
+        })
+        .filter(move |active| active.is_running_at(at))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::models::types::RouteType;
+    use crate::provider::{RouteImpl, StaticTransitProvider, TripImpl};
+    use crate::test_support::{self, route, station, weekdays};
+
+    fn trip(id: &str, stops: &[(&str, u32)]) -> Arc<dyn Trip> {
+        let stop_events = stops
+            .iter()
+            .enumerate()
+            .map(|(i, (station, time))| {
+                StopEvent::new(StationIdentifier::new(*station), *time, *time + 30, i as u32 + 1)
+            })
+            .collect();
+
+        let calendar = Arc::new(weekdays(chrono_tz::America::New_York));
+        Arc::new(test_support::trip(id, "A", &calendar, stop_events))
+    }
+
+    fn test_provider() -> StaticTransitProvider {
+        let stations = ["x", "y", "z", "express"]
+            .iter()
+            .map(|id| station(id, -73.9, 40.7))
+            .collect();
+
+        let route = RouteImpl {
+            long_name: "8 Avenue Express".into(),
+            ..route(
+                "A",
+                RouteType::Subway,
+                vec![
+                    // Daytime express: skips "y"
+                    trip("day", &[("x", 9 * 3600), ("express", 9 * 3600 + 300), ("z", 9 * 3600 + 600)]),
+                    // Late night local crossing midnight
+                    trip("night", &[("x", 23 * 3600 + 50 * 60), ("y", 24 * 3600 + 5 * 60), ("z", 24 * 3600 + 20 * 60)]),
+                ],
+            )
+        };
+
+        StaticTransitProvider::from_data(stations, vec![], vec![route])
+    }
+
+    /// Local New York time on a day in March 2024
+    fn at(day: u32, h: u32, m: u32) -> DateTime<Utc> {
+        use chrono::TimeZone;
+
+        chrono_tz::America::New_York
+            .with_ymd_and_hms(2024, 3, day, h, m, 0)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_departures_from() {
+        let provider = test_provider();
+
+        // Monday 4th
+        let board = departures_from(&provider, &StationIdentifier::new("x"), at(4, 8, 0), Duration::hours(16));
+        let trips: Vec<_> = board.iter().map(|d| d.trip_id.as_str()).collect();
+        assert_eq!(trips, vec!["day", "night"]);
+        assert_eq!(board[0].departure, at(4, 9, 0) + Duration::seconds(30));
+
+        // Terminal arrivals aren't departures
+        let board = departures_from(&provider, &StationIdentifier::new("z"), at(4, 0, 0), Duration::hours(24));
+        assert!(board.is_empty());
+
+        // Saturday: no service
+        let board = departures_from(&provider, &StationIdentifier::new("x"), at(9, 0, 0), Duration::hours(24));
+        assert!(board.is_empty());
+    }
+
+    #[test]
+    fn test_departures_past_midnight() {
+        let provider = test_provider();
+
+        // Friday night's service still departs "y" early Saturday morning
+        let board = departures_from(&provider, &StationIdentifier::new("y"), at(9, 0, 0), Duration::minutes(30));
+        assert_eq!(board.len(), 1);
+        assert_eq!(board[0].service_date, NaiveDate::from_ymd_opt(2024, 3, 8).unwrap());
+        assert_eq!(board[0].arrival, at(9, 0, 5));
+    }
+
+    #[test]
+    fn test_trips_active_at() {
+        let provider = test_provider();
+
+        let active = trips_active_at(&provider, at(4, 9, 2));
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].trip.id().as_str(), "day");
+
+        let active = trips_active_at(&provider, at(5, 0, 10));
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].trip.id().as_str(), "night");
+        assert_eq!(active[0].run.service_date, NaiveDate::from_ymd_opt(2024, 3, 4).unwrap());
+
+        assert!(trips_active_at(&provider, at(4, 12, 0)).is_empty());
+    }
+
+    #[test]
+    fn test_next_stop_of() {
+        let provider = test_provider();
+        let night = TripIdentifier::new("night");
+
+        let next = next_stop_of(&provider, &night, at(5, 0, 0)).unwrap();
+        assert_eq!(next.station_id.as_str(), "y");
+
+        // Dwelling at "y"
+        let next = next_stop_of(&provider, &night, at(5, 0, 5)).unwrap();
+        assert_eq!(next.station_id.as_str(), "y");
+
+        assert!(next_stop_of(&provider, &night, at(5, 12, 0)).is_none());
+    }
+
+    #[test]
+    fn test_frequency_departures() {
+        use crate::models::types::Frequency;
+
+        let calendar = Arc::new(weekdays(chrono_tz::America::New_York));
+        let stop_events = vec![
+            StopEvent::new(StationIdentifier::new("x"), 0, 0, 1),
+            StopEvent::new(StationIdentifier::new("y"), 120, 120, 2),
+        ];
+        let mut shuttle = TripImpl {
+            frequencies: vec![Frequency::new(6 * 3600, 24 * 3600, 300, true)],
+            ..test_support::trip("shuttle", "S", &calendar, stop_events)
+        };
+
+        let provider = |trip: &TripImpl| {
+            let route = route("S", RouteType::Subway, vec![Arc::new(trip.clone())]);
+            StaticTransitProvider::from_data(vec![], vec![], vec![route])
+        };
+
+        let board = departures_from(&provider(&shuttle), &StationIdentifier::new("x"), at(4, 8, 1), Duration::minutes(15));
+        let times: Vec<_> = board.iter().map(|d| d.departure).collect();
+        assert_eq!(times, vec![at(4, 8, 5), at(4, 8, 10), at(4, 8, 15)]);
+
+        let next = next_stop_of(&provider(&shuttle), &TripIdentifier::new("shuttle"), at(4, 8, 1)).unwrap();
+        assert_eq!(next.station_id.as_str(), "y");
+        assert_eq!(next.arrival, at(4, 8, 2));
+
+        // Outside the headway window
+        shuttle.frequencies = vec![Frequency::new(6 * 3600, 7 * 3600, 300, false)];
+        assert!(trips_active_at(&provider(&shuttle), at(4, 8, 1)).is_empty());
+    }
+
+    #[test]
+    fn test_stations_served_by() {
+        let provider = test_provider();
+        let route = RouteIdentifier::new("A");
+
+        let ids = |stations: Vec<StationIdentifier>| {
+            stations.iter().map(|s| s.as_str().to_string()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(ids(stations_served_by(&provider, &route, at(4, 9, 1))), vec!["x", "express", "z"]);
+        assert_eq!(ids(stations_served_by(&provider, &route, at(5, 0, 1))), vec!["x", "y", "z"]);
+        assert!(stations_served_by(&provider, &route, at(4, 15, 0)).is_empty());
+    }
+
+    #[test]
+    fn test_stations_served_between() {
+        let provider = test_provider();
+        let route = RouteIdentifier::new("A");
+
+        let ids = |stations: Vec<StationIdentifier>| {
+            stations.iter().map(|s| s.as_str().to_string()).collect::<Vec<_>>()
+        };
+
+        // Between trips, nothing is underway but the express is still the
+        // pattern a few minutes either side
+        assert!(stations_served_by(&provider, &route, at(4, 9, 20)).is_empty());
+        assert_eq!(
+            ids(stations_served_between(&provider, &route, at(4, 8, 50), at(4, 9, 50))),
+            vec!["x", "express", "z"]
+        );
+
+        // A window reaching both trips sees both patterns
+        assert_eq!(
+            ids(stations_served_between(&provider, &route, at(4, 9, 0), at(4, 23, 55))),
+            vec!["x", "express", "z", "y"]
+        );
+
+        assert!(stations_served_between(&provider, &route, at(4, 14, 0), at(4, 16, 0)).is_empty());
+    }
+}
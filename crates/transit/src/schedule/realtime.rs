@@ -0,0 +1,89 @@
+//! Realtime delays over the static schedule.
+
+use std::collections::BTreeMap;
+
+use crate::models::types::StopEvent;
+
+/// Realtime delays for one run of a trip (a GTFS-RT `TripUpdate`)
+///
+/// Delays are keyed by stop sequence. As in GTFS-RT, a delay carries forward
+/// to later stops until the next update; stops before the first update keep
+/// their scheduled times.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TripDelays {
+    updates: BTreeMap<u32, i32>,
+}
+
+impl TripDelays {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the delay from `stop_sequence` onwards
+    pub fn with_delay(mut self, stop_sequence: u32, delay_secs: i32) -> Self {
+        self.updates.insert(stop_sequence, delay_secs);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.updates.is_empty()
+    }
+
+    /// Delay in effect at `stop_sequence`
+    pub fn delay_at(&self, stop_sequence: u32) -> i32 {
+        self.updates
+            .range(..=stop_sequence)
+            .next_back()
+            .map_or(0, |(_, delay)| *delay)
+    }
+
+    /// `events` with delays applied
+    ///
+    /// A vehicle can't leave a stop before it arrives, so where a smaller
+    /// delay follows a larger one the later times are held back until the
+    /// vehicle could have got there.
+    pub fn apply(&self, events: &[StopEvent]) -> Vec<StopEvent> {
+        let mut delayed: Vec<StopEvent> = Vec::with_capacity(events.len());
+
+        for event in events {
+            let mut event = event
+                .with_delay(self.delay_at(event.stop_sequence))
+                .unwrap_or_else(|_| event.clone());
+
+            if let Some(previous) = delayed.last() {
+                event.arrival = event.arrival.max(previous.departure);
+            }
+            event.departure = event.departure.max(event.arrival);
+
+            delayed.push(event);
+        }
+
+        delayed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::identifiers::StationIdentifier;
+
+    #[test]
+    fn test_delays_carry_forward() {
+        let events: Vec<StopEvent> = (0..4)
+            .map(|i| {
+                StopEvent::new(
+                    StationIdentifier::new(format!("s{}", i)),
+                    i * 300,
+                    i * 300 + 30,
+                    i + 1,
+                )
+            })
+            .collect();
+
+        let delays = TripDelays::new().with_delay(2, 240).with_delay(4, -120);
+        let delayed = delays.apply(&events);
+
+        let times: Vec<(u32, u32)> = delayed.iter().map(|e| (e.arrival, e.departure)).collect();
+        assert_eq!(times, vec![(0, 30), (540, 570), (840, 870), (870, 870)]);
+    }
+}
@@ -0,0 +1,209 @@
+//! Expansion of trips into individual vehicle runs.
+//!
+//! A scheduled trip makes one run per service day. A frequency-based trip
+//! makes many, one per headway; rather than materialising them, queries ask
+//! for just the runs overlapping the time window they care about.
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+use crate::models::traits::Trip;
+use crate::models::types::Frequency;
+use crate::schedule::service_day::service_dates_covering;
+
+/// One run of a trip: a service day plus a shift applied to its stop times
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TripRun {
+    pub service_date: NaiveDate,
+
+    /// Seconds added to the trip's template stop times (0 for scheduled trips)
+    pub offset: i64,
+
+    /// The feed promises this run's times. Runs of a headway-only frequency
+    /// (`exact_times=0`) are placed on an estimated grid, so only the spacing
+    /// between them is reliable.
+    pub exact: bool,
+}
+
+impl TripRun {
+    /// Service time of a template stop time on this run
+    pub fn service_time(&self, seconds: u32) -> u32 {
+        (seconds as i64 + self.offset).clamp(0, u32::MAX as i64) as u32
+    }
+
+    /// Absolute time of a template stop time on this run
+    pub fn resolve(&self, trip: &dyn Trip, seconds: u32) -> DateTime<Utc> {
+        trip.service_calendar()
+            .to_instant(self.service_date, self.service_time(seconds))
+    }
+}
+
+/// Every run of `trip` that is underway at some point within `[from, until]`
+///
+/// A run is underway from its first stop's arrival to its last stop's
+/// arrival. Runs are returned in service date order, then start time.
+pub fn runs_between(trip: &dyn Trip, from: DateTime<Utc>, until: DateTime<Utc>) -> Vec<TripRun> {
+    let events = trip.stop_events();
+    let (Some(first), Some(last)) = (events.first(), events.last()) else {
+        return Vec::new();
+    };
+
+    let template_start = first.arrival;
+    let duration = last.arrival.saturating_sub(template_start) as i64;
+    let calendar = trip.service_calendar();
+
+    let mut runs = Vec::new();
+
+    for service_date in service_dates_covering(from, until) {
+        if !trip.runs_on(service_date) {
+            continue;
+        }
+
+        // The window in service time for this day
+        let origin = calendar.service_day_origin(service_date);
+        let window_start = (from - origin).num_seconds();
+        let window_end = (until - origin).num_seconds();
+
+        if trip.frequencies().is_empty() {
+            let start = template_start as i64;
+
+            if start <= window_end && start + duration >= window_start {
+                runs.push(TripRun {
+                    service_date,
+                    offset: 0,
+                    exact: true,
+                });
+            }
+
+            continue;
+        }
+
+        let mut starts: Vec<(u32, bool)> = trip
+            .frequencies()
+            .iter()
+            .flat_map(|frequency| {
+                starts_overlapping(frequency, duration, window_start, window_end)
+                    .map(|start| (start, frequency.exact_times))
+            })
+            .collect();
+
+        // Where windows overlap on the same start, an exact run wins
+        starts.sort_unstable_by_key(|&(start, exact)| (start, !exact));
+        starts.dedup_by_key(|&mut (start, _)| start);
+
+        runs.extend(starts.into_iter().map(|(start, exact)| TripRun {
+            service_date,
+            offset: start as i64 - template_start as i64,
+            exact,
+        }));
+    }
+
+    runs
+}
+
+/// Run start times of `frequency` whose run overlaps `[window_start, window_end]`
+fn starts_overlapping(
+    frequency: &Frequency,
+    duration: i64,
+    window_start: i64,
+    window_end: i64,
+) -> impl Iterator<Item = u32> + '_ {
+    // Skip straight to the first run that could still be underway
+    let earliest_start = window_start - duration;
+    let first_index = match frequency.headway_secs {
+        0 => 0,
+        headway => {
+            let behind = earliest_start - frequency.start_time as i64;
+            (behind.max(0) as u64).div_ceil(headway as u64).min(u32::MAX as u64) as u32
+        }
+    };
+
+    (first_index..)
+        .map_while(|index| frequency.run_start(index))
+        .take_while(move |start| (*start as i64) <= window_end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use chrono::{Duration, TimeZone};
+
+    use crate::identifiers::*;
+    use crate::models::types::StopEvent;
+    use crate::provider::TripImpl;
+    use crate::test_support::{self, daily};
+
+    fn trip(frequencies: Vec<Frequency>) -> TripImpl {
+        // Template: 0:00 at "a", 0:05 at "b"
+        let stop_events = vec![
+            StopEvent::new(StationIdentifier::new("a"), 0, 30, 1),
+            StopEvent::new(StationIdentifier::new("b"), 300, 300, 2),
+        ];
+        TripImpl {
+            frequencies,
+            headsign: "Grand Central".into(),
+            ..test_support::trip("shuttle", "S", &Arc::new(daily(chrono_tz::UTC)), stop_events)
+        }
+    }
+
+    fn at(h: u32, m: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 4, h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn test_scheduled_trip_runs_once_per_day() {
+        let trip = trip(vec![]);
+
+        let runs = runs_between(&trip, at(0, 2), at(0, 2));
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].offset, 0);
+        assert!(runs[0].exact);
+        assert_eq!(runs[0].resolve(&trip, 300), at(0, 5));
+
+        assert!(runs_between(&trip, at(0, 6), at(23, 0)).is_empty());
+    }
+
+    #[test]
+    fn test_frequency_trip_expands_lazily() {
+        // Every 4 minutes from 07:00 until 09:00, i.e. 30 runs
+        let trip = trip(vec![Frequency::new(7 * 3600, 9 * 3600, 240, true)]);
+
+        // At 08:00 the 07:56 and 08:00 runs are underway
+        let runs = runs_between(&trip, at(8, 0), at(8, 0));
+        let starts: Vec<_> = runs.iter().map(|r| r.resolve(&trip, 0)).collect();
+        assert_eq!(starts, vec![at(7, 56), at(8, 0)]);
+
+        // Arrival at "b" is shifted along with the run
+        assert_eq!(runs[1].resolve(&trip, 300), at(8, 5));
+
+        // Whole window
+        let runs = runs_between(&trip, at(6, 0), at(10, 0));
+        assert_eq!(runs.len(), 30);
+
+        // The last run starts at 08:56 and is done by 09:01
+        let runs = runs_between(&trip, at(9, 2), at(9, 2) + Duration::hours(1));
+        assert!(runs.is_empty());
+    }
+
+    #[test]
+    fn test_headway_only_runs_are_not_exact() {
+        // Headway-only in the morning, timetabled from 08:00
+        let trip = trip(vec![
+            Frequency::new(7 * 3600, 8 * 3600 + 1, 600, false),
+            Frequency::new(8 * 3600, 9 * 3600, 600, true),
+        ]);
+
+        let runs = runs_between(&trip, at(7, 45), at(8, 10));
+        let flags: Vec<_> = runs.iter().map(|r| (r.resolve(&trip, 0), r.exact)).collect();
+        assert_eq!(
+            flags,
+            vec![
+                (at(7, 40), false),
+                (at(7, 50), false),
+                (at(8, 0), true),
+                (at(8, 10), true),
+            ]
+        );
+    }
+}
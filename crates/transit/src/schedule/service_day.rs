@@ -0,0 +1,58 @@
+//! Mapping between service days and absolute time.
+
+use chrono::{DateTime, Days, NaiveDate, Utc};
+
+use crate::models::traits::Trip;
+
+/// How many days a trip may run past the end of its service day.
+///
+/// GTFS allows stop times beyond 24:00; a value of 1 covers times up to
+/// 47:59:59, which is the practical maximum in published feeds.
+pub const MAX_SERVICE_DAY_OVERRUN_DAYS: u64 = 1;
+
+/// Absolute time of a stop time (seconds since the service day origin)
+pub fn resolve_time(trip: &dyn Trip, service_date: NaiveDate, seconds: u32) -> DateTime<Utc> {
+    trip.service_calendar().to_instant(service_date, seconds)
+}
+
+/// Every service date whose trips may be running between `from` and `until`
+///
+/// Service dates are local to each agency, so the range is padded by a day on
+/// either side to cover every UTC offset; callers filter on resolved times.
+pub fn service_dates_covering(
+    from: DateTime<Utc>,
+    until: DateTime<Utc>,
+) -> impl Iterator<Item = NaiveDate> {
+    let first = from
+        .date_naive()
+        .checked_sub_days(Days::new(MAX_SERVICE_DAY_OVERRUN_DAYS + 1))
+        .unwrap_or(from.date_naive());
+    let last = until
+        .date_naive()
+        .checked_add_days(Days::new(1))
+        .unwrap_or(until.date_naive());
+
+    first.iter_days().take_while(move |day| *day <= last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    fn test_service_dates_covering() {
+        let at = Utc.with_ymd_and_hms(2024, 3, 5, 0, 30, 0).unwrap();
+        let dates: Vec<_> = service_dates_covering(at, at + Duration::hours(1)).collect();
+
+        assert_eq!(
+            dates,
+            vec![
+                NaiveDate::from_ymd_opt(2024, 3, 3).unwrap(),
+                NaiveDate::from_ymd_opt(2024, 3, 4).unwrap(),
+                NaiveDate::from_ymd_opt(2024, 3, 5).unwrap(),
+                NaiveDate::from_ymd_opt(2024, 3, 6).unwrap(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! Distance along a shape.
+
+use geo::{
+    Closest, Coord, HaversineBearing, HaversineClosestPoint, HaversineIntermediate, Line,
+    LineString, Point,
+};
+
+use crate::spatial::queries::haversine_distance;
+
+/// A shape with the cumulative distance to each of its vertices
+///
+/// Lets positions be expressed as metres along the shape, which is how stops
+/// are placed on a shape and vehicles interpolated between them.
+#[derive(Clone, Debug)]
+pub struct MeasuredShape {
+    coords: Vec<Coord>,
+    cumulative_m: Vec<f64>,
+}
+
+impl MeasuredShape {
+    pub fn new(shape: &LineString) -> Self {
+        let mut coords = shape.0.clone();
+        coords.dedup();
+
+        let mut cumulative_m = Vec::with_capacity(coords.len());
+        let mut total = 0.0;
+        for (i, coord) in coords.iter().enumerate() {
+            if i > 0 {
+                total += haversine_distance(Point::from(coords[i - 1]), Point::from(*coord));
+            }
+            cumulative_m.push(total);
+        }
+
+        Self {
+            coords,
+            cumulative_m,
+        }
+    }
+
+    /// Total length in metres
+    pub fn length_m(&self) -> f64 {
+        self.cumulative_m.last().copied().unwrap_or(0.0)
+    }
+
+    /// Project `point` onto the shape, considering only the part at or after
+    /// `from_m`
+    ///
+    /// Returns the distance along the shape of the closest point and how far
+    /// `point` is from it. Restricting the search lets consecutive stops be
+    /// placed in order on shapes that double back on themselves.
+    pub fn project(&self, point: Point, from_m: f64) -> Option<(f64, f64)> {
+        let mut best: Option<(f64, f64)> = None;
+
+        for i in 0..self.coords.len().saturating_sub(1) {
+            if self.cumulative_m[i + 1] < from_m {
+                continue;
+            }
+
+            let (start, end) = (self.coords[i], self.coords[i + 1]);
+            let closest = match Line::new(start, end).haversine_closest_point(&point) {
+                Closest::Intersection(p) | Closest::SinglePoint(p) => p,
+                Closest::Indeterminate => continue,
+            };
+
+            let mut along_m =
+                self.cumulative_m[i] + haversine_distance(Point::from(start), closest);
+            let mut closest = closest;
+
+            // The segment starts before `from_m`: only its tail is eligible
+            if along_m < from_m {
+                let segment_m = self.cumulative_m[i + 1] - self.cumulative_m[i];
+                let fraction = (from_m - self.cumulative_m[i]) / segment_m;
+
+                along_m = from_m;
+                closest = Point::from(start).haversine_intermediate(&Point::from(end), fraction);
+            }
+
+            let offset_m = haversine_distance(point, closest);
+
+            if best.is_none_or(|(_, best_offset)| offset_m < best_offset) {
+                best = Some((along_m, offset_m));
+            }
+        }
+
+        best
+    }
+
+    /// The point `along_m` metres along the shape and the bearing of travel
+    /// there, in degrees clockwise from north
+    pub fn point_at(&self, along_m: f64) -> Option<(Point, f64)> {
+        let last = self.coords.len().checked_sub(1)?;
+        if last == 0 {
+            return Some((Point::from(self.coords[0]), 0.0));
+        }
+
+        let along_m = along_m.clamp(0.0, self.length_m());
+        let segment = self
+            .cumulative_m
+            .partition_point(|&m| m <= along_m)
+            .clamp(1, last)
+            - 1;
+
+        let start = Point::from(self.coords[segment]);
+        let end = Point::from(self.coords[segment + 1]);
+        let segment_m = self.cumulative_m[segment + 1] - self.cumulative_m[segment];
+        let fraction = if segment_m > 0.0 {
+            (along_m - self.cumulative_m[segment]) / segment_m
+        } else {
+            0.0
+        };
+
+        Some((
+            start.haversine_intermediate(&end, fraction),
+            normalize_bearing(start.haversine_bearing(end)),
+        ))
+    }
+}
+
+/// Map a bearing in degrees to `[0, 360)`
+pub fn normalize_bearing(bearing: f64) -> f64 {
+    bearing.rem_euclid(360.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_and_point_at() {
+        // North, then back south a little east of the outbound leg
+        let shape = MeasuredShape::new(&LineString::from(vec![
+            (-74.0, 40.70),
+            (-74.0, 40.72),
+            (-73.999, 40.72),
+            (-73.999, 40.70),
+        ]));
+
+        let north_leg = haversine_distance(Point::new(-74.0, 40.70), Point::new(-74.0, 40.72));
+        assert!((shape.length_m() - 2.0 * north_leg - 84.0).abs() < 2.0);
+
+        // From the start, a point near the outbound leg lands on it...
+        let (outbound, offset) = shape.project(Point::new(-73.9998, 40.71), 0.0).unwrap();
+        assert!((outbound - north_leg / 2.0).abs() < 1.0);
+        assert!(offset < 50.0);
+
+        // ...but once past the turn, it lands on the return leg
+        let (inbound, _) = shape
+            .project(Point::new(-73.9998, 40.71), north_leg)
+            .unwrap();
+        assert!(inbound > north_leg);
+
+        let (point, bearing) = shape.point_at(north_leg / 2.0).unwrap();
+        assert!((point.y() - 40.71).abs() < 1e-6);
+        assert!(bearing.abs() < 1e-6);
+
+        let (_, bearing) = shape.point_at(inbound).unwrap();
+        assert!((bearing - 180.0).abs() < 1e-3);
+    }
+}
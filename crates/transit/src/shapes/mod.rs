@@ -0,0 +1,19 @@
+//! Route shapes and stop patterns.
+//!
+//! A route usually runs several patterns (local vs. express, branches,
+//! short turns), each with its own physical path. Trips point at a shape by
+//! [`ShapeIdentifier`]; when a feed has no shapes.txt, shapes can be
+//! reconstructed from stop sequences by snapping each hop to a
+//! [`RailNetwork`], falling back to great-circle arcs between stops.
+//!
+//! [`ShapeIdentifier`]: crate::identifiers::ShapeIdentifier
+
+pub mod measure;
+pub mod patterns;
+pub mod rail;
+pub mod reconstruct;
+
+pub use measure::{normalize_bearing, MeasuredShape};
+pub use patterns::{patterns_of, RoutePattern};
+pub use rail::RailGraph;
+pub use reconstruct::{reconstruct_shape, RailNetwork, GEODESIC_STEP_M};
@@ -0,0 +1,68 @@
+//! Deduplication of trips into stop patterns.
+
+use std::collections::HashMap;
+
+use crate::identifiers::*;
+use crate::models::traits::Route;
+use crate::models::types::DirectionId;
+
+/// A distinct stop sequence run by one or more trips of a route
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RoutePattern {
+    pub route_id: RouteIdentifier,
+    pub direction_id: DirectionId,
+    pub station_ids: Vec<StationIdentifier>,
+
+    /// Shape shared by the pattern's trips, if the feed specifies one
+    pub shape_id: Option<ShapeIdentifier>,
+
+    /// Trips running this pattern, in route order
+    pub trip_ids: Vec<TripIdentifier>,
+}
+
+impl RoutePattern {
+    /// Does this pattern stop at `station_id`?
+    pub fn serves(&self, station_id: &StationIdentifier) -> bool {
+        self.station_ids.contains(station_id)
+    }
+}
+
+/// Group a route's trips into patterns
+///
+/// Trips share a pattern when they run in the same direction, stop at the
+/// same stations in the same order and use the same shape. Patterns are
+/// sorted by trip count (most common first), then by first appearance.
+pub fn patterns_of(route: &dyn Route) -> Vec<RoutePattern> {
+    let mut patterns: Vec<RoutePattern> = Vec::new();
+    let mut index: HashMap<(DirectionId, Vec<StationIdentifier>, Option<ShapeIdentifier>), usize> =
+        HashMap::new();
+
+    for trip in route.trips() {
+        let station_ids: Vec<_> = trip
+            .stop_events()
+            .iter()
+            .map(|e| e.station_id.clone())
+            .collect();
+        let key = (trip.direction_id(), station_ids, trip.shape_id().cloned());
+
+        match index.get(&key) {
+            Some(&i) => patterns[i].trip_ids.push(trip.id().clone()),
+            None => {
+                index.insert(key.clone(), patterns.len());
+
+                let (direction_id, station_ids, shape_id) = key;
+                patterns.push(RoutePattern {
+                    route_id: route.id().clone(),
+                    direction_id,
+                    station_ids,
+                    shape_id,
+                    trip_ids: vec![trip.id().clone()],
+                });
+            }
+        }
+    }
+
+    // Stable, so ties keep first-appearance order
+    patterns.sort_by_key(|p| std::cmp::Reverse(p.trip_ids.len()));
+    patterns
+}
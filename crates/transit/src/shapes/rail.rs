@@ -0,0 +1,266 @@
+//! Rail network graph for snapping hops to track geometry.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use geo::{Coord, LineString, Point};
+use rstar::primitives::GeomWithData;
+use rstar::RTree;
+
+use crate::shapes::reconstruct::RailNetwork;
+use crate::spatial::index::{chord_for_distance, to_ecef};
+use crate::spatial::queries::haversine_distance;
+
+/// Default furthest a stop may be from the track to snap to it
+pub const DEFAULT_MAX_SNAP_DISTANCE_M: f64 = 150.0;
+
+/// Default longest track path accepted, relative to the straight-line hop
+pub const DEFAULT_MAX_DETOUR_FACTOR: f64 = 3.0;
+
+/// Vertices closer than this (in degrees, ~1cm) are merged into one node
+const NODE_PRECISION: f64 = 1e-7;
+
+type NodeEntry = GeomWithData<[f64; 3], usize>;
+
+/// Track geometry as a graph, with shortest-path snapping between stops
+///
+/// Lines that share a vertex are connected there; lines that merely cross
+/// are not, so level crossings and viaducts don't create false junctions.
+pub struct RailGraph {
+    nodes: Vec<Coord>,
+    edges: Vec<Vec<(usize, f64)>>,
+    tree: RTree<NodeEntry>,
+    max_snap_distance_m: f64,
+    max_detour_factor: f64,
+}
+
+impl RailGraph {
+    pub fn from_lines(lines: impl IntoIterator<Item = LineString>) -> Self {
+        let mut nodes = Vec::new();
+        let mut edges: Vec<Vec<(usize, f64)>> = Vec::new();
+        let mut by_key: HashMap<(i64, i64), usize> = HashMap::new();
+
+        for line in lines {
+            let mut previous: Option<usize> = None;
+
+            for coord in line.0 {
+                let key = (
+                    (coord.x / NODE_PRECISION).round() as i64,
+                    (coord.y / NODE_PRECISION).round() as i64,
+                );
+                let node = *by_key.entry(key).or_insert_with(|| {
+                    nodes.push(coord);
+                    edges.push(Vec::new());
+                    nodes.len() - 1
+                });
+
+                if let Some(previous) = previous.filter(|&p| p != node) {
+                    let length = haversine_distance(nodes[previous].into(), nodes[node].into());
+                    edges[previous].push((node, length));
+                    edges[node].push((previous, length));
+                }
+
+                previous = Some(node);
+            }
+        }
+
+        let tree = RTree::bulk_load(
+            nodes
+                .iter()
+                .enumerate()
+                .map(|(i, coord)| NodeEntry::new(to_ecef((*coord).into()), i))
+                .collect(),
+        );
+
+        Self {
+            nodes,
+            edges,
+            tree,
+            max_snap_distance_m: DEFAULT_MAX_SNAP_DISTANCE_M,
+            max_detour_factor: DEFAULT_MAX_DETOUR_FACTOR,
+        }
+    }
+
+    pub fn with_max_snap_distance(mut self, max_snap_distance_m: f64) -> Self {
+        self.max_snap_distance_m = max_snap_distance_m;
+        self
+    }
+
+    pub fn with_max_detour_factor(mut self, max_detour_factor: f64) -> Self {
+        self.max_detour_factor = max_detour_factor;
+        self
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn snap(&self, point: Point) -> Option<usize> {
+        let chord = chord_for_distance(self.max_snap_distance_m);
+        let nearest = self.tree.nearest_neighbor(&to_ecef(point))?;
+
+        let distance_2: f64 = to_ecef(point)
+            .iter()
+            .zip(nearest.geom())
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum();
+
+        (distance_2 <= chord * chord).then_some(nearest.data)
+    }
+
+    /// Dijkstra from `from` to `to`, giving up beyond `limit_m`
+    fn shortest_path(&self, from: usize, to: usize, limit_m: f64) -> Option<Vec<usize>> {
+        let mut distance = vec![f64::INFINITY; self.nodes.len()];
+        let mut previous = vec![usize::MAX; self.nodes.len()];
+        let mut queue = BinaryHeap::new();
+
+        distance[from] = 0.0;
+        queue.push(QueueEntry { distance: 0.0, node: from });
+
+        while let Some(QueueEntry { distance: d, node }) = queue.pop() {
+            if node == to {
+                break;
+            }
+
+            if d > distance[node] {
+                continue;
+            }
+
+            for &(next, length) in &self.edges[node] {
+                let candidate = d + length;
+
+                if candidate < distance[next] && candidate <= limit_m {
+                    distance[next] = candidate;
+                    previous[next] = node;
+                    queue.push(QueueEntry { distance: candidate, node: next });
+                }
+            }
+        }
+
+        if !distance[to].is_finite() {
+            return None;
+        }
+
+        let mut path = vec![to];
+        while let Some(&last) = path.last().filter(|&&n| n != from) {
+            path.push(previous[last]);
+        }
+        path.reverse();
+
+        Some(path)
+    }
+}
+
+impl RailNetwork for RailGraph {
+    fn path_between(&self, from: Point, to: Point) -> Option<LineString> {
+        let start = self.snap(from)?;
+        let end = self.snap(to)?;
+
+        let straight = haversine_distance(from, to);
+        let limit = straight * self.max_detour_factor + 2.0 * self.max_snap_distance_m;
+
+        let path = self.shortest_path(start, end, limit)?;
+
+        let mut coords = vec![from.0];
+        coords.extend(path.into_iter().map(|node| self.nodes[node]));
+        coords.push(to.0);
+        coords.dedup();
+
+        Some(LineString::new(coords))
+    }
+}
+
+/// Min-heap entry for Dijkstra
+struct QueueEntry {
+    distance: f64,
+    node: usize,
+}
+
+impl PartialEq for QueueEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for QueueEntry {}
+
+impl PartialOrd for QueueEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueueEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed so the smallest distance pops first
+        other.distance.total_cmp(&self.distance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph() -> RailGraph {
+        // A main line north along -74.0 with a branch curving east at 40.71,
+        // and an unconnected line crossing it at 40.705
+        RailGraph::from_lines(vec![
+            LineString::from(vec![(-74.0, 40.70), (-74.0, 40.705), (-74.0, 40.71), (-74.0, 40.72)]),
+            LineString::from(vec![(-74.0, 40.71), (-73.995, 40.712), (-73.99, 40.712)]),
+            LineString::from(vec![(-74.01, 40.705), (-73.99, 40.705)]),
+        ])
+    }
+
+    #[test]
+    fn test_snaps_along_branch() {
+        let graph = graph();
+        assert_eq!(graph.node_count(), 8);
+
+        let path = graph
+            .path_between(Point::new(-74.0001, 40.70), Point::new(-73.99, 40.7121))
+            .unwrap();
+
+        assert_eq!(
+            path.0,
+            vec![
+                Coord { x: -74.0001, y: 40.70 },
+                Coord { x: -74.0, y: 40.70 },
+                Coord { x: -74.0, y: 40.705 },
+                Coord { x: -74.0, y: 40.71 },
+                Coord { x: -73.995, y: 40.712 },
+                Coord { x: -73.99, y: 40.712 },
+                Coord { x: -73.99, y: 40.7121 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rejects_unsnappable_and_disconnected() {
+        let graph = graph();
+
+        // Too far from any track
+        assert!(graph
+            .path_between(Point::new(-74.0, 40.70), Point::new(-73.95, 40.75))
+            .is_none());
+
+        // On a line that only crosses the main line without a junction
+        assert!(graph
+            .path_between(Point::new(-74.0, 40.70), Point::new(-74.01, 40.705))
+            .is_none());
+    }
+
+    #[test]
+    fn test_rejects_long_detours() {
+        let graph = graph().with_max_detour_factor(1.0).with_max_snap_distance(1.0);
+
+        // Straight along the main line is fine
+        assert!(graph
+            .path_between(Point::new(-74.0, 40.70), Point::new(-74.0, 40.72))
+            .is_some());
+
+        // Going round the branch is longer than the straight hop allows
+        assert!(graph
+            .path_between(Point::new(-74.0, 40.71), Point::new(-73.99, 40.712))
+            .is_none());
+    }
+}
@@ -0,0 +1,101 @@
+//! Shape reconstruction from stop sequences.
+
+use geo::{Coord, HaversineIntermediate, LineString, Point};
+
+/// Spacing of interpolated points on fallback great-circle arcs
+pub const GEODESIC_STEP_M: f64 = 1_000.0;
+
+/// A network that stop-to-stop hops can be snapped to (e.g. OSM railways)
+pub trait RailNetwork: Send + Sync {
+    /// Path along the network from `from` to `to`, or `None` if the two
+    /// points can't be joined plausibly
+    fn path_between(&self, from: Point, to: Point) -> Option<LineString>;
+}
+
+/// Build a shape through `stops` in order
+///
+/// Each hop is snapped to `network` when possible; otherwise (or with no
+/// network) it becomes a great-circle arc densified every
+/// [`GEODESIC_STEP_M`] so it renders and indexes correctly.
+pub fn reconstruct_shape(stops: &[Point], network: Option<&dyn RailNetwork>) -> LineString {
+    let mut coords: Vec<Coord> = Vec::new();
+
+    let mut push = |coord: Coord| {
+        if coords.last() != Some(&coord) {
+            coords.push(coord);
+        }
+    };
+
+    if let [only] = stops {
+        push(only.0);
+    }
+
+    for hop in stops.windows(2) {
+        let (from, to) = (hop[0], hop[1]);
+
+        let snapped = network.and_then(|network| network.path_between(from, to));
+
+        match snapped {
+            Some(path) => path.0.into_iter().for_each(&mut push),
+            None => from
+                .haversine_intermediate_fill(&to, GEODESIC_STEP_M, true)
+                .into_iter()
+                .for_each(|point| push(point.0)),
+        }
+    }
+
+    LineString::new(coords)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo::HaversineDistance;
+
+    struct Detour;
+
+    impl RailNetwork for Detour {
+        fn path_between(&self, from: Point, to: Point) -> Option<LineString> {
+            // Only knows hops heading north
+            (to.y() > from.y()).then(|| {
+                LineString::from(vec![
+                    (from.x(), from.y()),
+                    (from.x() + 0.01, (from.y() + to.y()) / 2.0),
+                    (to.x(), to.y()),
+                ])
+            })
+        }
+    }
+
+    #[test]
+    fn test_geodesic_fallback() {
+        let stops = [Point::new(-74.0, 40.70), Point::new(-74.0, 40.75)];
+        let shape = reconstruct_shape(&stops, None);
+
+        // ~5.6km, so six 1km steps
+        assert_eq!(shape.0.len(), 7);
+        assert_eq!(shape.0.first(), Some(&stops[0].0));
+        assert_eq!(shape.0.last(), Some(&stops[1].0));
+
+        for pair in shape.0.windows(2) {
+            let step = Point::from(pair[0]).haversine_distance(&Point::from(pair[1]));
+            assert!(step <= GEODESIC_STEP_M);
+        }
+    }
+
+    #[test]
+    fn test_snapped_hops_are_joined() {
+        let stops = [
+            Point::new(-74.0, 40.70),
+            Point::new(-74.0, 40.71),
+            Point::new(-74.0, 40.705),
+        ];
+        let shape = reconstruct_shape(&stops, Some(&Detour));
+
+        // Snapped north hop (3 points), then a short straight south hop that
+        // shares its first point
+        assert_eq!(shape.0.len(), 4);
+        assert_eq!(shape.0[1], Coord { x: -73.99, y: 40.705 });
+        assert_eq!(shape.0.last(), Some(&stops[2].0));
+    }
+}
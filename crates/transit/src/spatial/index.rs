@@ -2,16 +2,22 @@
 //!
 //! Wraps transit entities with geometric data for efficient spatial queries.
 //!
-//! ## Two-Stage Filtering
+//! ## Coordinate Frame
+//!
+//! Nodes are indexed in Earth-centred, Earth-fixed (ECEF) coordinates on a
+//! sphere of [`EARTH_RADIUS_M`], so every R-tree distance is a straight-line
+//! chord in metres. Chord length grows monotonically with great-circle
+//! distance, which gives us:
 //!
-//! The spatial queries use a two-stage filtering approach:
-//! 1. **R-tree filter**: Uses Euclidean distance for fast approximate filtering
-//! 2. **Haversine filter**: Applies accurate geodesic distance on filtered results
+//! - **Nearest-k**: ordering by chord is ordering by haversine distance
+//! - **Within-radius**: a great-circle radius maps to an exact chord radius
+//!   via [`chord_for_distance`]
 //!
-//! This approach balances performance (fast Euclidean checks in the R-tree) with
-//! accuracy (precise Haversine distance for final results), which is especially
-//! important for geographic coordinates where Euclidean distance becomes
-//! increasingly inaccurate over larger distances.
+//! ## Two-Stage Filtering
+//!
+//! The R-tree stage is a conservative prefilter (it may accept a few extra
+//! candidates but never drops a match); the Haversine stage then applies the
+//! exact distance test to what remains.
 
 use std::sync::Arc;
 use geo::{Point, Line};
@@ -21,6 +27,61 @@ use rstar::{RTreeObject, AABB, PointDistance};
 // This allows us to compile spatial independently
 use crate::provider::static_provider::{StationImpl, RouteImpl};
 
+/// Sphere radius used for indexing; matches `geo`'s Haversine implementation
+pub const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+/// Relative slack added to chord radii to absorb floating-point error
+const CHORD_EPSILON: f64 = 1e-9;
+
+// ============================================================================
+// Frame Conversions
+// ============================================================================
+
+/// Project a lon/lat point onto the indexing sphere
+pub fn to_ecef(point: Point) -> [f64; 3] {
+    let (lon, lat) = (point.x().to_radians(), point.y().to_radians());
+
+    [
+        EARTH_RADIUS_M * lat.cos() * lon.cos(),
+        EARTH_RADIUS_M * lat.cos() * lon.sin(),
+        EARTH_RADIUS_M * lat.sin(),
+    ]
+}
+
+/// Chord length spanning a great-circle distance of `distance_m`
+pub fn chord_for_distance(distance_m: f64) -> f64 {
+    let half_angle = (distance_m / (2.0 * EARTH_RADIUS_M)).min(std::f64::consts::FRAC_PI_2);
+    let chord = 2.0 * EARTH_RADIUS_M * half_angle.sin();
+
+    chord + chord * CHORD_EPSILON + CHORD_EPSILON
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+/// Squared distance from `p` to the straight segment `a`-`b`
+fn segment_distance_2(p: [f64; 3], a: [f64; 3], b: [f64; 3]) -> f64 {
+    let ab = sub(b, a);
+    let ap = sub(p, a);
+
+    let ab_ab = dot(ab, ab);
+
+    if ab_ab == 0.0 {
+        // Segment is actually a point
+        return dot(ap, ap);
+    }
+
+    let t = (dot(ab, ap) / ab_ab).clamp(0.0, 1.0);
+    let offset = sub(ap, [ab[0] * t, ab[1] * t, ab[2] * t]);
+
+    dot(offset, offset)
+}
+
 // ============================================================================
 // Station Spatial Node
 // ============================================================================
@@ -28,20 +89,20 @@ use crate::provider::static_provider::{StationImpl, RouteImpl};
 #[derive(Clone)]
 pub struct StationNode {
     pub station: Arc<StationImpl>,
-    point: [f64; 2],
+    point: [f64; 3],
 }
 
 impl StationNode {
     pub fn new(location: Point, station: Arc<StationImpl>) -> Self {
         Self {
             station,
-            point: [location.x(), location.y()],
+            point: to_ecef(location),
         }
     }
 }
 
 impl RTreeObject for StationNode {
-    type Envelope = AABB<[f64; 2]>;
+    type Envelope = AABB<[f64; 3]>;
 
     fn envelope(&self) -> Self::Envelope {
         AABB::from_point(self.point)
@@ -49,10 +110,9 @@ impl RTreeObject for StationNode {
 }
 
 impl PointDistance for StationNode {
-    fn distance_2(&self, point: &[f64; 2]) -> f64 {
-        let dx = self.point[0] - point[0];
-        let dy = self.point[1] - point[1];
-        dx * dx + dy * dy
+    fn distance_2(&self, point: &[f64; 3]) -> f64 {
+        let d = sub(self.point, *point);
+        dot(d, d)
     }
 }
 
@@ -60,30 +120,59 @@ impl PointDistance for StationNode {
 // Route Segment Spatial Node
 // ============================================================================
 
+/// A single segment of a route's geometry.
+///
+/// The segment is stored as its straight chord in ECEF, padded by `slack`:
+/// the real path (straight in lon/lat) bows away from the chord, and the
+/// padding keeps the node's envelope and distance conservative.
 #[derive(Clone)]
 pub struct RouteSegmentNode {
     pub route: Arc<RouteImpl>,
     pub segment: Line,
-    aabb: AABB<[f64; 2]>,
+    start: [f64; 3],
+    end: [f64; 3],
+    slack: f64,
+    aabb: AABB<[f64; 3]>,
 }
 
 impl RouteSegmentNode {
     pub fn new(segment: Line, route: Arc<RouteImpl>) -> Self {
-        let start = [segment.start.x, segment.start.y];
-        let end = [segment.end.x, segment.end.y];
-
-        let aabb = AABB::from_corners(start, end);
+        let start = to_ecef(segment.start.into());
+        let end = to_ecef(segment.end.into());
+
+        // The path deviates from the chord roughly parabolically, peaking
+        // near the middle; double the midpoint deviation to stay on the safe
+        // side of that approximation.
+        let midpoint = to_ecef(Point::new(
+            (segment.start.x + segment.end.x) / 2.0,
+            (segment.start.y + segment.end.y) / 2.0,
+        ));
+        let slack = 2.0 * segment_distance_2(midpoint, start, end).sqrt() + CHORD_EPSILON;
+
+        let lower = [
+            start[0].min(end[0]) - slack,
+            start[1].min(end[1]) - slack,
+            start[2].min(end[2]) - slack,
+        ];
+        let upper = [
+            start[0].max(end[0]) + slack,
+            start[1].max(end[1]) + slack,
+            start[2].max(end[2]) + slack,
+        ];
 
         Self {
             route,
             segment,
-            aabb,
+            start,
+            end,
+            slack,
+            aabb: AABB::from_corners(lower, upper),
         }
     }
 }
 
 impl RTreeObject for RouteSegmentNode {
-    type Envelope = AABB<[f64; 2]>;
+    type Envelope = AABB<[f64; 3]>;
 
     fn envelope(&self) -> Self::Envelope {
         self.aabb
@@ -91,29 +180,11 @@ impl RTreeObject for RouteSegmentNode {
 }
 
 impl PointDistance for RouteSegmentNode {
-    fn distance_2(&self, point: &[f64; 2]) -> f64 {
-        // Distance from point to line segment (squared Euclidean distance)
-        let p = [point[0], point[1]];
-        let a = [self.segment.start.x, self.segment.start.y];
-        let b = [self.segment.end.x, self.segment.end.y];
-
-        let ab = [b[0] - a[0], b[1] - a[1]];
-        let ap = [p[0] - a[0], p[1] - a[1]];
-
-        let ab_ab = ab[0] * ab[0] + ab[1] * ab[1];
-
-        if ab_ab == 0.0 {
-            // Segment is actually a point
-            return ap[0] * ap[0] + ap[1] * ap[1];
-        }
-
-        let ab_ap = ab[0] * ap[0] + ab[1] * ap[1];
-        let t = (ab_ap / ab_ab).clamp(0.0, 1.0);
-
-        let closest = [a[0] + t * ab[0], a[1] + t * ab[1]];
-        let dx = p[0] - closest[0];
-        let dy = p[1] - closest[1];
+    fn distance_2(&self, point: &[f64; 3]) -> f64 {
+        // Lower bound on the chord distance to any point of the path
+        let distance = segment_distance_2(*point, self.start, self.end).sqrt();
+        let distance = (distance - self.slack).max(0.0);
 
-        dx * dx + dy * dy
+        distance * distance
     }
 }
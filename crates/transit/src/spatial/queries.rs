@@ -2,7 +2,7 @@
 //!
 //! Uses Haversine formula for accurate distances on Earth's surface.
 
-use geo::{Point, Line, HaversineDistance, ClosestPoint, LineString};
+use geo::{Coord, Point, Line, HaversineDistance, ClosestPoint, LineString};
 
 /// Calculate Haversine distance between two points in meters
 pub fn haversine_distance(p1: Point, p2: Point) -> f64 {
@@ -10,13 +10,21 @@ pub fn haversine_distance(p1: Point, p2: Point) -> f64 {
 }
 
 /// Calculate distance from point to line segment in meters
+///
+/// The closest point is found in a local equirectangular frame centred on
+/// `point` (longitude scaled by the cosine of its latitude), so the search
+/// isn't skewed away from the equator.
 pub fn haversine_distance_to_line(point: Point, line: Line) -> f64 {
+    let scale = point.y().to_radians().cos().max(f64::EPSILON);
+    let to_local = |c: Coord| Coord { x: c.x * scale, y: c.y };
+
     // Convert line to LineString for ClosestPoint trait
-    let line_string = LineString::from(vec![line.start, line.end]);
+    let line_string = LineString::from(vec![to_local(line.start), to_local(line.end)]);
+    let local_point = Point::from(to_local(point.0));
 
-    match line_string.closest_point(&point) {
+    match line_string.closest_point(&local_point) {
         geo::Closest::Intersection(p) | geo::Closest::SinglePoint(p) => {
-            haversine_distance(point, p)
+            haversine_distance(point, Point::new(p.x() / scale, p.y()))
         }
         geo::Closest::Indeterminate => f64::INFINITY,
     }
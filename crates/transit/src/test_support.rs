@@ -0,0 +1,92 @@
+//! Fixtures shared by the unit tests.
+//!
+//! Everything here has the plainest possible defaults; tests override the
+//! fields they care about with struct update syntax.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+use geo::Point;
+
+use crate::identifiers::*;
+use crate::models::calendar::{ServiceCalendar, WeekdayFlags};
+use crate::models::traits::Trip;
+use crate::models::types::{DirectionId, RouteType, StopEvent};
+use crate::provider::{ComplexImpl, RouteImpl, StationImpl, TripImpl};
+
+/// Service every day of 2024
+pub fn daily(timezone: Tz) -> ServiceCalendar {
+    ServiceCalendar {
+        service_id: ServiceIdentifier::new("daily"),
+        start_date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+        end_date: NaiveDate::from_ymd_opt(2024, 12, 31).unwrap(),
+        weekdays: WeekdayFlags::from_bools(true, true, true, true, true, true, true),
+        added_dates: Arc::new(HashSet::new()),
+        removed_dates: Arc::new(HashSet::new()),
+        timezone,
+    }
+}
+
+/// Service Monday to Friday in 2024
+pub fn weekdays(timezone: Tz) -> ServiceCalendar {
+    ServiceCalendar {
+        service_id: ServiceIdentifier::new("weekday"),
+        weekdays: WeekdayFlags::from_bools(true, true, true, true, true, false, false),
+        ..daily(timezone)
+    }
+}
+
+/// A station in a complex of its own, named after its id
+pub fn station(id: &str, lon: f64, lat: f64) -> StationImpl {
+    StationImpl {
+        id: StationIdentifier::new(id),
+        name: id.into(),
+        location: Point::new(lon, lat),
+        complex_id: ComplexIdentifier::new(id),
+    }
+}
+
+/// The single-station complex `station` belongs to
+pub fn complex_of(station: &StationImpl) -> ComplexImpl {
+    ComplexImpl {
+        id: station.complex_id.clone(),
+        name: station.name.clone(),
+        station_ids: vec![station.id.clone()],
+        center: station.location,
+    }
+}
+
+/// An outbound trip with no frequencies, headsign or shape
+pub fn trip(
+    id: &str,
+    route_id: &str,
+    calendar: &Arc<ServiceCalendar>,
+    stop_events: Vec<StopEvent>,
+) -> TripImpl {
+    TripImpl {
+        id: TripIdentifier::new(id),
+        route_id: RouteIdentifier::new(route_id),
+        stop_events,
+        frequencies: vec![],
+        service_calendar: calendar.clone(),
+        direction_id: DirectionId::Outbound,
+        headsign: "".into(),
+        shape_id: None,
+    }
+}
+
+/// A route named after its id, with no colours or geometry
+pub fn route(id: &str, route_type: RouteType, trips: Vec<Arc<dyn Trip>>) -> RouteImpl {
+    RouteImpl {
+        id: RouteIdentifier::new(id),
+        route_type,
+        short_name: id.into(),
+        long_name: "".into(),
+        color: None,
+        text_color: None,
+        geometry: None,
+        trips,
+    }
+}
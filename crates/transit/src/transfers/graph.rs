@@ -0,0 +1,566 @@
+//! Precomputed transfer graph.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use crate::identifiers::*;
+use crate::models::traits::TransitProvider;
+use crate::spatial::queries::haversine_distance;
+use crate::transfers::{
+    ComplexLink, FeedTransfer, FeedTransferType, Pathway, PathwayEdge, Transfer, TransferConfig,
+    TransferKind,
+};
+
+// ============================================================================
+// Graph
+// ============================================================================
+
+/// All transfers of a network, indexed by origin station
+#[derive(Clone, Debug, Default)]
+pub struct TransferGraph {
+    by_station: HashMap<StationIdentifier, Vec<Transfer>>,
+    complex_links: HashMap<ComplexIdentifier, Vec<ComplexIdentifier>>,
+
+    /// Stations where the feed forbids changing between trips
+    no_same_station_transfer: HashSet<StationIdentifier>,
+}
+
+impl TransferGraph {
+    pub fn builder() -> TransferGraphBuilder {
+        TransferGraphBuilder::default()
+    }
+
+    /// Transfers leaving `station_id`, including a same-station entry if one
+    /// was specified
+    pub fn transfers_from(&self, station_id: &StationIdentifier) -> &[Transfer] {
+        self.by_station
+            .get(station_id)
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    /// Minimum seconds to transfer from `from` to `to`, or `None` if there is
+    /// no (permitted) transfer between them
+    ///
+    /// Transfers at the same station default to 0 unless the feed says otherwise.
+    pub fn min_transfer_secs(&self, from: &StationIdentifier, to: &StationIdentifier) -> Option<u32> {
+        let transfer = self.transfers_from(from).iter().find(|t| &t.to == to);
+
+        match transfer {
+            Some(transfer) => Some(transfer.min_transfer_secs),
+            None if from == to && !self.no_same_station_transfer.contains(from) => Some(0),
+            None => None,
+        }
+    }
+
+    /// Complexes joined to `complex_id` by in-system links (not including itself)
+    pub fn linked_complexes(&self, complex_id: &ComplexIdentifier) -> &[ComplexIdentifier] {
+        self.complex_links
+            .get(complex_id)
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    fn insert(&mut self, transfer: Transfer) {
+        let transfers = self.by_station.entry(transfer.from.clone()).or_default();
+
+        match transfers.iter_mut().find(|t| t.to == transfer.to) {
+            Some(existing) => *existing = transfer,
+            None => transfers.push(transfer),
+        }
+    }
+
+    fn get_mut(&mut self, from: &StationIdentifier, to: &StationIdentifier) -> Option<&mut Transfer> {
+        self.by_station.get_mut(from)?.iter_mut().find(|t| &t.to == to)
+    }
+
+    fn remove(&mut self, from: &StationIdentifier, to: &StationIdentifier) {
+        if let Some(transfers) = self.by_station.get_mut(from) {
+            transfers.retain(|t| &t.to != to);
+        }
+    }
+}
+
+// ============================================================================
+// Builder
+// ============================================================================
+
+/// Collects feed data and settings, then builds a [`TransferGraph`]
+#[derive(Clone, Debug, Default)]
+pub struct TransferGraphBuilder {
+    config: TransferConfig,
+    feed_transfers: Vec<FeedTransfer>,
+    pathways: Vec<Pathway>,
+    complex_links: Vec<ComplexLink>,
+}
+
+impl TransferGraphBuilder {
+    pub fn with_config(mut self, config: TransferConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn with_feed_transfers(mut self, transfers: impl IntoIterator<Item = FeedTransfer>) -> Self {
+        self.feed_transfers.extend(transfers);
+        self
+    }
+
+    pub fn with_pathways(mut self, pathways: impl IntoIterator<Item = Pathway>) -> Self {
+        self.pathways.extend(pathways);
+        self
+    }
+
+    pub fn with_complex_links(mut self, links: impl IntoIterator<Item = ComplexLink>) -> Self {
+        self.complex_links.extend(links);
+        self
+    }
+
+    pub fn build<P: TransitProvider + ?Sized>(&self, provider: &P) -> TransferGraph {
+        let mut graph = TransferGraph::default();
+
+        // Generated walking and in-complex links
+        for station in provider.all_stations() {
+            for transfer in generate_transfers_from(provider, station.id(), &self.config) {
+                graph.insert(transfer);
+            }
+        }
+
+        // Complex links: every station pair across the two complexes
+        for link in &self.complex_links {
+            let (Some(a), Some(b)) = (provider.get_complex(&link.a), provider.get_complex(&link.b)) else {
+                continue;
+            };
+
+            let walk = self
+                .config
+                .walking_secs(haversine_distance(a.center(), b.center()));
+            let secs = link
+                .transfer_secs
+                .unwrap_or(walk.max(self.config.in_complex_transfer_secs));
+
+            for (from, to) in [(&a, &b), (&b, &a)] {
+                for from_station in from.station_ids() {
+                    for to_station in to.station_ids() {
+                        graph.insert(Transfer {
+                            from: from_station.clone(),
+                            to: to_station.clone(),
+                            kind: TransferKind::InterComplex,
+                            min_transfer_secs: secs,
+                        });
+                    }
+                }
+
+                let links = graph.complex_links.entry(from.id().clone()).or_default();
+                if !links.contains(to.id()) {
+                    links.push(to.id().clone());
+                }
+            }
+        }
+
+        // Pathways override generated times
+        for pathway in &self.pathways {
+            let mut directions = vec![(&pathway.from, &pathway.to)];
+            if pathway.bidirectional {
+                directions.push((&pathway.to, &pathway.from));
+            }
+
+            for (from, to) in directions {
+                let kind = kind_between(provider, from, to);
+                graph.insert(Transfer {
+                    from: from.clone(),
+                    to: to.clone(),
+                    kind,
+                    min_transfer_secs: pathway.traversal_secs,
+                });
+            }
+        }
+
+        // Feed transfers have the final word
+        for feed in &self.feed_transfers {
+            match feed.transfer_type {
+                FeedTransferType::NotPossible => {
+                    graph.remove(&feed.from, &feed.to);
+
+                    if feed.from == feed.to {
+                        graph.no_same_station_transfer.insert(feed.from.clone());
+                    }
+                }
+                FeedTransferType::MinimumTime(secs) => {
+                    match graph.get_mut(&feed.from, &feed.to) {
+                        Some(existing) => existing.min_transfer_secs = secs,
+                        None => graph.insert(Transfer {
+                            from: feed.from.clone(),
+                            to: feed.to.clone(),
+                            kind: kind_between(provider, &feed.from, &feed.to),
+                            min_transfer_secs: secs,
+                        }),
+                    }
+                }
+                FeedTransferType::Recommended | FeedTransferType::Timed => {
+                    // Timed transfers are held for each other; otherwise keep
+                    // any time we already know about.
+                    let timed = feed.transfer_type == FeedTransferType::Timed;
+
+                    match graph.get_mut(&feed.from, &feed.to) {
+                        Some(existing) if timed => existing.min_transfer_secs = 0,
+                        Some(_) => {}
+                        None => {
+                            let secs = match timed {
+                                true => 0,
+                                false => estimate_secs(provider, &feed.from, &feed.to, &self.config),
+                            };
+
+                            graph.insert(Transfer {
+                                from: feed.from.clone(),
+                                to: feed.to.clone(),
+                                kind: kind_between(provider, &feed.from, &feed.to),
+                                min_transfer_secs: secs,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        graph
+    }
+}
+
+// ============================================================================
+// Generation
+// ============================================================================
+
+/// Walking and in-complex transfers from `station_id`, generated from
+/// station locations alone
+///
+/// This is what providers without feed transfer data fall back to.
+pub fn generate_transfers_from<P: TransitProvider + ?Sized>(
+    provider: &P,
+    station_id: &StationIdentifier,
+    config: &TransferConfig,
+) -> Vec<Transfer> {
+    let Some(station) = provider.get_station(station_id) else {
+        return Vec::new();
+    };
+
+    let mut transfers = Vec::new();
+    let mut seen = HashSet::new();
+
+    if let Some(complex) = provider.get_complex(station.complex_id()) {
+        for other_id in complex.station_ids() {
+            if other_id == station_id || !seen.insert(other_id.clone()) {
+                continue;
+            }
+
+            transfers.push(Transfer {
+                from: station_id.clone(),
+                to: other_id.clone(),
+                kind: TransferKind::InComplex,
+                min_transfer_secs: estimate_secs(provider, station_id, other_id, config),
+            });
+        }
+    }
+
+    for nearby in provider.stations_near(station.location(), config.max_walking_distance_m) {
+        if nearby.id() == station_id || !seen.insert(nearby.id().clone()) {
+            continue;
+        }
+
+        let kind = match nearby.complex_id() == station.complex_id() {
+            true => TransferKind::InComplex,
+            false => TransferKind::Walking,
+        };
+
+        transfers.push(Transfer {
+            from: station_id.clone(),
+            to: nearby.id().clone(),
+            kind,
+            min_transfer_secs: estimate_secs(provider, station_id, nearby.id(), config),
+        });
+    }
+
+    transfers
+}
+
+/// Collapse pathway networks to links between the stations they join
+///
+/// `station_of` maps a node to the station it boards (a platform to itself,
+/// a boarding area to its platform), or `None` for entrances and other
+/// nodes. Each station gets a link to every station it can reach, timed by
+/// the quickest route through non-boarding nodes.
+pub fn collapse_pathways<'a>(
+    edges: impl IntoIterator<Item = PathwayEdge<'a>>,
+    station_of: impl Fn(&str) -> Option<StationIdentifier>,
+) -> Vec<Pathway> {
+    let mut adjacent: HashMap<&str, Vec<(&str, u32)>> = HashMap::new();
+    for edge in edges {
+        adjacent.entry(edge.from).or_default().push((edge.to, edge.traversal_secs));
+        if edge.bidirectional {
+            adjacent.entry(edge.to).or_default().push((edge.from, edge.traversal_secs));
+        }
+    }
+
+    let mut starts: HashMap<StationIdentifier, Vec<&str>> = HashMap::new();
+    for &node in adjacent.keys() {
+        if let Some(station) = station_of(node) {
+            starts.entry(station).or_default().push(node);
+        }
+    }
+
+    let mut pathways = Vec::new();
+
+    for (station, nodes) in starts {
+        let mut best: HashMap<&str, u32> = nodes.iter().map(|&node| (node, 0)).collect();
+        let mut queue: BinaryHeap<_> = nodes.iter().map(|&node| Reverse((0, node))).collect();
+        let mut reached: HashMap<StationIdentifier, u32> = HashMap::new();
+
+        while let Some(Reverse((secs, node))) = queue.pop() {
+            if best.get(node).is_some_and(|&known| known < secs) {
+                continue;
+            }
+
+            // Stop at other stations rather than walking through their platforms
+            if let Some(other) = station_of(node).filter(|other| other != &station) {
+                let known = reached.entry(other).or_insert(secs);
+                *known = (*known).min(secs);
+                continue;
+            }
+
+            for &(next, traversal) in adjacent.get(node).into_iter().flatten() {
+                let secs = secs.saturating_add(traversal);
+                if best.get(next).is_none_or(|&known| secs < known) {
+                    best.insert(next, secs);
+                    queue.push(Reverse((secs, next)));
+                }
+            }
+        }
+
+        pathways.extend(reached.into_iter().map(|(to, traversal_secs)| Pathway {
+            from: station.clone(),
+            to,
+            traversal_secs,
+            bidirectional: false,
+        }));
+    }
+
+    pathways.sort_by(|a, b| (a.from.as_str(), a.to.as_str()).cmp(&(b.from.as_str(), b.to.as_str())));
+    pathways
+}
+
+fn kind_between<P: TransitProvider + ?Sized>(
+    provider: &P,
+    from: &StationIdentifier,
+    to: &StationIdentifier,
+) -> TransferKind {
+    if from == to {
+        return TransferKind::SameStation;
+    }
+
+    let complex_of = |id: &StationIdentifier| provider.get_station(id).map(|s| s.complex_id().clone());
+
+    match (complex_of(from), complex_of(to)) {
+        (Some(a), Some(b)) if a == b => TransferKind::InComplex,
+        _ => TransferKind::InterComplex,
+    }
+}
+
+/// Walking time between two stations, floored at the in-complex minimum for
+/// stations of the same complex
+fn estimate_secs<P: TransitProvider + ?Sized>(
+    provider: &P,
+    from: &StationIdentifier,
+    to: &StationIdentifier,
+    config: &TransferConfig,
+) -> u32 {
+    let (Some(a), Some(b)) = (provider.get_station(from), provider.get_station(to)) else {
+        return config.in_complex_transfer_secs;
+    };
+
+    let walk = config.walking_secs(haversine_distance(a.location(), b.location()));
+
+    match a.complex_id() == b.complex_id() {
+        true => walk.max(config.in_complex_transfer_secs),
+        false => walk,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::provider::{ComplexImpl, StaticTransitProvider, StationImpl};
+    use crate::test_support;
+
+    fn station(id: &str, complex: &str, lon: f64, lat: f64) -> StationImpl {
+        StationImpl {
+            complex_id: ComplexIdentifier::new(complex),
+            ..test_support::station(id, lon, lat)
+        }
+    }
+
+    fn complex(id: &str, stations: &[&StationImpl]) -> ComplexImpl {
+        ComplexImpl {
+            id: ComplexIdentifier::new(id),
+            name: id.into(),
+            station_ids: stations.iter().map(|s| s.id.clone()).collect(),
+            center: stations[0].location,
+        }
+    }
+
+    /// Times Sq (two platforms), Port Authority ~300m west, Bryant Park
+    /// ~350m east, and Grand Central ~1km east
+    fn provider() -> StaticTransitProvider {
+        let ts_7 = station("ts_7", "times_sq", -73.9870, 40.7553);
+        let ts_123 = station("ts_123", "times_sq", -73.9873, 40.7552);
+        let pa = station("port_authority", "port_authority", -73.9903, 40.7573);
+        let bp = station("bryant_park", "bryant_park", -73.9840, 40.7540);
+        let gc = station("grand_central", "grand_central", -73.9772, 40.7527);
+
+        let complexes = vec![
+            complex("times_sq", &[&ts_7, &ts_123]),
+            complex("port_authority", &[&pa]),
+            complex("bryant_park", &[&bp]),
+            complex("grand_central", &[&gc]),
+        ];
+
+        StaticTransitProvider::from_data(vec![ts_7, ts_123, pa, bp, gc], complexes, vec![])
+    }
+
+    fn id(s: &str) -> StationIdentifier {
+        StationIdentifier::new(s)
+    }
+
+    #[test]
+    fn test_generated_transfers() {
+        let provider = provider();
+        let graph = TransferGraph::builder().build(&provider);
+
+        let in_complex = graph
+            .transfers_from(&id("ts_7"))
+            .iter()
+            .find(|t| t.to == id("ts_123"))
+            .unwrap();
+        assert_eq!(in_complex.kind, TransferKind::InComplex);
+        assert_eq!(in_complex.min_transfer_secs, 120);
+
+        let walk = graph
+            .transfers_from(&id("ts_7"))
+            .iter()
+            .find(|t| t.to == id("bryant_park"))
+            .unwrap();
+        assert_eq!(walk.kind, TransferKind::Walking);
+
+        // Too far to walk with the default limit
+        assert_eq!(graph.min_transfer_secs(&id("ts_7"), &id("grand_central")), None);
+
+        // Faster walkers cover more ground
+        let config = TransferConfig::default()
+            .with_walking_speed(2.0)
+            .with_max_walking_distance(1_500.0);
+        let graph = TransferGraph::builder().with_config(config).build(&provider);
+        let secs = graph.min_transfer_secs(&id("ts_7"), &id("grand_central")).unwrap();
+        assert!(secs < 1_000);
+    }
+
+    #[test]
+    fn test_complex_links() {
+        let provider = provider();
+        let graph = TransferGraph::builder()
+            .with_complex_links([ComplexLink {
+                a: ComplexIdentifier::new("times_sq"),
+                b: ComplexIdentifier::new("port_authority"),
+                transfer_secs: Some(300),
+            }])
+            .build(&provider);
+
+        for from in ["ts_7", "ts_123"] {
+            let transfer = graph
+                .transfers_from(&id(from))
+                .iter()
+                .find(|t| t.to == id("port_authority"))
+                .unwrap();
+            assert_eq!(transfer.kind, TransferKind::InterComplex);
+            assert_eq!(transfer.min_transfer_secs, 300);
+        }
+
+        assert_eq!(graph.min_transfer_secs(&id("port_authority"), &id("ts_7")), Some(300));
+        assert_eq!(
+            graph.linked_complexes(&ComplexIdentifier::new("port_authority")),
+            &[ComplexIdentifier::new("times_sq")]
+        );
+    }
+
+    #[test]
+    fn test_feed_precedence() {
+        let provider = provider();
+        let graph = TransferGraph::builder()
+            .with_pathways([Pathway {
+                from: id("ts_7"),
+                to: id("ts_123"),
+                traversal_secs: 240,
+                bidirectional: false,
+            }])
+            .with_feed_transfers([
+                FeedTransfer {
+                    from: id("ts_7"),
+                    to: id("ts_7"),
+                    transfer_type: FeedTransferType::MinimumTime(60),
+                },
+                FeedTransfer {
+                    from: id("ts_123"),
+                    to: id("ts_7"),
+                    transfer_type: FeedTransferType::NotPossible,
+                },
+                FeedTransfer {
+                    from: id("ts_7"),
+                    to: id("grand_central"),
+                    transfer_type: FeedTransferType::Timed,
+                },
+            ])
+            .build(&provider);
+
+        // Pathway beats the generated estimate, one way only
+        assert_eq!(graph.min_transfer_secs(&id("ts_7"), &id("ts_123")), Some(240));
+
+        // Forbidden by the feed
+        assert_eq!(graph.min_transfer_secs(&id("ts_123"), &id("ts_7")), None);
+
+        // Same-station minimum, and the default elsewhere
+        assert_eq!(graph.min_transfer_secs(&id("ts_7"), &id("ts_7")), Some(60));
+        assert_eq!(graph.min_transfer_secs(&id("bryant_park"), &id("bryant_park")), Some(0));
+
+        // Timed transfers are guaranteed, however far apart
+        assert_eq!(graph.min_transfer_secs(&id("ts_7"), &id("grand_central")), Some(0));
+    }
+
+    #[test]
+    fn test_collapse_pathways() {
+        let edge = |from, to, traversal_secs, bidirectional| PathwayEdge {
+            from,
+            to,
+            traversal_secs,
+            bidirectional,
+        };
+
+        // ts_7's boarding area and ts_123 both reach the mezzanine; the
+        // entrance only leads in
+        let edges = [
+            edge("ts_7_area", "mezzanine", 60, true),
+            edge("ts_123", "mezzanine", 90, true),
+            edge("mezzanine", "ts_123", 30, false),
+            edge("entrance", "mezzanine", 45, false),
+        ];
+        let station_of = |node: &str| match node {
+            "ts_7" | "ts_7_area" => Some(id("ts_7")),
+            "ts_123" => Some(id("ts_123")),
+            _ => None,
+        };
+
+        let pathways = collapse_pathways(edges, station_of);
+        let links: Vec<_> = pathways
+            .iter()
+            .map(|p| (p.from.as_str(), p.to.as_str(), p.traversal_secs))
+            .collect();
+        assert_eq!(links, vec![("ts_123", "ts_7", 150), ("ts_7", "ts_123", 90)]);
+    }
+}
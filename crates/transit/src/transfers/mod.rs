@@ -0,0 +1,145 @@
+//! Transfers between stations.
+//!
+//! A transfer is a directed link from one station to another (or to itself,
+//! for a platform change) with the minimum time needed to make it. Links come
+//! from several sources, in increasing order of precedence:
+//!
+//! 1. **Walking**: generated between nearby stations by distance and speed
+//! 2. **In-complex**: every pair of stations in the same [`TransitComplex`]
+//! 3. **Complex links**: explicit in-system connections between complexes
+//!    (e.g. Times Sq ↔ Port Authority)
+//! 4. **Pathways**: GTFS pathways.txt traversal times
+//! 5. **Feed transfers**: GTFS transfers.txt, which can also forbid a transfer
+//!
+//! [`TransitComplex`]: crate::models::traits::TransitComplex
+
+pub mod graph;
+
+pub use graph::{collapse_pathways, generate_transfers_from, TransferGraph, TransferGraphBuilder};
+
+use crate::identifiers::*;
+
+/// Default walking pace (~5 km/h)
+pub const DEFAULT_WALKING_SPEED_MPS: f64 = 1.4;
+
+/// Default longest street-level walk considered a transfer
+pub const DEFAULT_MAX_WALKING_DISTANCE_M: f64 = 400.0;
+
+/// Default minimum time to change between stations of one complex
+pub const DEFAULT_IN_COMPLEX_TRANSFER_SECS: u32 = 120;
+
+// ============================================================================
+// Transfer Types
+// ============================================================================
+
+/// Where a transfer link comes from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TransferKind {
+    /// Changing platforms at the same station
+    SameStation,
+    /// Between stations of the same complex
+    InComplex,
+    /// Between linked complexes, inside the system
+    InterComplex,
+    /// Street-level walk between nearby stations
+    Walking,
+}
+
+/// A directed transfer between two stations
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Transfer {
+    pub from: StationIdentifier,
+    pub to: StationIdentifier,
+    pub kind: TransferKind,
+    pub min_transfer_secs: u32,
+}
+
+/// GTFS transfers.txt `transfer_type`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FeedTransferType {
+    Recommended,
+    Timed,
+    MinimumTime(u32),
+    NotPossible,
+}
+
+/// A row of GTFS transfers.txt
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FeedTransfer {
+    pub from: StationIdentifier,
+    pub to: StationIdentifier,
+    pub transfer_type: FeedTransferType,
+}
+
+/// A GTFS pathways.txt link, collapsed to the stations it connects
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pathway {
+    pub from: StationIdentifier,
+    pub to: StationIdentifier,
+    pub traversal_secs: u32,
+    pub bidirectional: bool,
+}
+
+/// A raw GTFS pathways.txt link between two nodes of a station, which may
+/// be platforms, boarding areas, entrances or generic nodes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PathwayEdge<'a> {
+    pub from: &'a str,
+    pub to: &'a str,
+    pub traversal_secs: u32,
+    pub bidirectional: bool,
+}
+
+/// An in-system connection between two complexes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ComplexLink {
+    pub a: ComplexIdentifier,
+    pub b: ComplexIdentifier,
+
+    /// Time to make the connection; estimated from distance if `None`
+    pub transfer_secs: Option<u32>,
+}
+
+/// Parameters for generated transfers
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TransferConfig {
+    pub walking_speed_mps: f64,
+    pub max_walking_distance_m: f64,
+    pub in_complex_transfer_secs: u32,
+}
+
+impl Default for TransferConfig {
+    fn default() -> Self {
+        Self {
+            walking_speed_mps: DEFAULT_WALKING_SPEED_MPS,
+            max_walking_distance_m: DEFAULT_MAX_WALKING_DISTANCE_M,
+            in_complex_transfer_secs: DEFAULT_IN_COMPLEX_TRANSFER_SECS,
+        }
+    }
+}
+
+impl TransferConfig {
+    pub fn with_walking_speed(mut self, walking_speed_mps: f64) -> Self {
+        self.walking_speed_mps = walking_speed_mps;
+        self
+    }
+
+    pub fn with_max_walking_distance(mut self, max_walking_distance_m: f64) -> Self {
+        self.max_walking_distance_m = max_walking_distance_m;
+        self
+    }
+
+    pub fn with_in_complex_transfer(mut self, in_complex_transfer_secs: u32) -> Self {
+        self.in_complex_transfer_secs = in_complex_transfer_secs;
+        self
+    }
+
+    /// Seconds needed to walk `distance_m`
+    pub fn walking_secs(&self, distance_m: f64) -> u32 {
+        if self.walking_speed_mps <= 0.0 {
+            return u32::MAX;
+        }
+
+        (distance_m / self.walking_speed_mps).ceil().min(u32::MAX as f64) as u32
+    }
+}
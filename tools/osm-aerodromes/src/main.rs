@@ -0,0 +1,80 @@
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use std::path::PathBuf;
+
+mod output;
+mod pbf;
+
+use output::write_airports_geojson;
+use pbf::{extract_aerodromes, AirportFilter};
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "osm-aerodromes",
+    author,
+    version,
+    about = "Extract commercial airports from OpenStreetMap PBF data",
+    long_about = "Finds aeroway=aerodrome nodes, ways and multipolygons in an OSM PBF file \
+                  and outputs commercial airports as GeoJSON points with ICAO and IATA codes.\n\n\
+                  The output is the \"airport\" POI category used by matching questions: \
+                  each feature's `id` is the ICAO code."
+)]
+struct Args {
+    /// Input OSM PBF file
+    #[arg(short, long)]
+    input: PathBuf,
+
+    /// Output GeoJSON file for airport points
+    #[arg(short, long)]
+    output: PathBuf,
+
+    /// Keep aerodromes without an IATA code if tagged as international or
+    /// regional (by default an IATA code is required)
+    #[arg(long)]
+    allow_missing_iata: bool,
+
+    /// Verbose output (show debug messages)
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    env_logger::Builder::from_env(
+        env_logger::Env::default().default_filter_or(if args.verbose { "debug" } else { "info" }),
+    )
+    .format_timestamp(None)
+    .init();
+
+    log::info!("=== OSM Aerodrome Extractor ===");
+    log::info!("Input: {}", args.input.display());
+    log::info!("Output: {}", args.output.display());
+
+    if !args.input.exists() {
+        bail!("Input file does not exist: {}", args.input.display());
+    }
+
+    let filter = AirportFilter {
+        allow_missing_iata: args.allow_missing_iata,
+    };
+
+    let airports = extract_aerodromes(&args.input, &filter)
+        .context("Failed to extract aerodromes from PBF")?;
+
+    log::info!("");
+    log::info!("Found {} commercial airports", airports.len());
+    for airport in &airports {
+        log::debug!(
+            "  {} {} {}",
+            airport.icao,
+            airport.iata.as_deref().unwrap_or("---"),
+            airport.name.as_deref().unwrap_or("(unnamed)")
+        );
+    }
+
+    write_airports_geojson(&airports, &args.output).context("Failed to write output")?;
+    log::info!("Wrote {}", args.output.display());
+
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use anyhow::{Context, Result};
+use geojson::{Feature, FeatureCollection, GeoJson, Geometry, Value};
+use std::path::Path;
+
+use crate::pbf::Airport;
+
+/// Create a GeoJSON point Feature for an airport
+///
+/// Properties mirror the `Poi` the game reads (`id`, `name`) plus the codes
+/// needed for `MatchingTarget::CommercialAirport`.
+fn airport_to_feature(airport: &Airport) -> Feature {
+    let mut properties = serde_json::Map::new();
+    properties.insert("id".to_string(), serde_json::json!(airport.icao));
+    properties.insert("icao".to_string(), serde_json::json!(airport.icao));
+    properties.insert("iata".to_string(), serde_json::json!(airport.iata));
+    properties.insert("name".to_string(), serde_json::json!(airport.name));
+    properties.insert("osm_type".to_string(), serde_json::json!(airport.source.osm_type()));
+    properties.insert("osm_id".to_string(), serde_json::json!(airport.osm_id));
+
+    Feature {
+        bbox: None,
+        geometry: Some(Geometry::new(Value::Point(vec![
+            airport.position.x,
+            airport.position.y,
+        ]))),
+        id: Some(geojson::feature::Id::String(airport.icao.clone())),
+        properties: Some(properties),
+        foreign_members: None,
+    }
+}
+
+/// Write airports to a GeoJSON FeatureCollection
+pub fn write_airports_geojson(airports: &[Airport], path: &Path) -> Result<()> {
+    let collection = FeatureCollection {
+        bbox: None,
+        features: airports.iter().map(airport_to_feature).collect(),
+        foreign_members: None,
+    };
+
+    let json = GeoJson::FeatureCollection(collection).to_string();
+    std::fs::write(path, json)
+        .with_context(|| format!("Failed to write output file: {}", path.display()))?;
+
+    Ok(())
+}
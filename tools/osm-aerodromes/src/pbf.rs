@@ -0,0 +1,370 @@
+use anyhow::{Context, Result};
+use hashbrown::{HashMap, HashSet};
+use osmpbf::{Element, ElementReader};
+use std::path::Path;
+
+/// Tag storage type
+pub type Tags = HashMap<String, String>;
+
+/// Longitude/latitude in degrees
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Position {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Which kind of OSM element an airport came from
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Source {
+    Node,
+    Way,
+    Relation,
+}
+
+impl Source {
+    pub fn osm_type(self) -> &'static str {
+        match self {
+            Source::Node => "node",
+            Source::Way => "way",
+            Source::Relation => "relation",
+        }
+    }
+}
+
+/// A commercial airport
+#[derive(Clone, Debug)]
+pub struct Airport {
+    pub icao: String,
+    pub iata: Option<String>,
+    pub name: Option<String>,
+    pub position: Position,
+    pub source: Source,
+    pub osm_id: i64,
+}
+
+/// Which aerodromes count as commercial airports
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AirportFilter {
+    pub allow_missing_iata: bool,
+}
+
+/// Aerodrome types that never have scheduled passenger service
+const EXCLUDED_AERODROME_TYPES: &[&str] = &[
+    "military",
+    "private",
+    "airfield",
+    "gliding",
+    "glider",
+    "ultralight",
+    "heliport",
+];
+
+/// Check if tags indicate an aerodrome
+pub fn is_aerodrome(tags: &Tags) -> bool {
+    tags.get("aeroway").map(|v| v == "aerodrome").unwrap_or(false)
+}
+
+/// Normalised ICAO code: exactly four ASCII letters or digits
+pub fn icao_code(tags: &Tags) -> Option<String> {
+    let code = tags.get("icao")?.trim().to_ascii_uppercase();
+    (code.len() == 4 && code.chars().all(|c| c.is_ascii_alphanumeric())).then_some(code)
+}
+
+/// Normalised IATA code: exactly three ASCII letters
+pub fn iata_code(tags: &Tags) -> Option<String> {
+    let code = tags.get("iata")?.trim().to_ascii_uppercase();
+    (code.len() == 3 && code.chars().all(|c| c.is_ascii_alphabetic())).then_some(code)
+}
+
+/// Check if an aerodrome's tags describe a commercial airport
+///
+/// Requires an ICAO code. Military, private and general-aviation fields are
+/// excluded; of the rest, those with an IATA code (i.e. scheduled service)
+/// are kept, plus, if allowed, ones tagged international or regional.
+pub fn is_commercial(tags: &Tags, filter: &AirportFilter) -> bool {
+    if !is_aerodrome(tags) || icao_code(tags).is_none() {
+        return false;
+    }
+
+    let aerodrome_type = tags
+        .get("aerodrome:type")
+        .or_else(|| tags.get("aerodrome"))
+        .map(|v| v.to_ascii_lowercase());
+
+    if let Some(aerodrome_type) = &aerodrome_type {
+        if EXCLUDED_AERODROME_TYPES.contains(&aerodrome_type.as_str()) {
+            return false;
+        }
+    }
+
+    // `military=no` is sometimes added to civil airports to say just that
+    if tags
+        .get("military")
+        .is_some_and(|v| !v.eq_ignore_ascii_case("no"))
+    {
+        return false;
+    }
+
+    if let Some(access) = tags.get("access") {
+        if access == "private" || access == "no" {
+            return false;
+        }
+    }
+
+    if iata_code(tags).is_some() {
+        return true;
+    }
+
+    filter.allow_missing_iata
+        && matches!(aerodrome_type.as_deref(), Some("international" | "regional"))
+}
+
+fn collect_tags<'a>(tags: impl Iterator<Item = (&'a str, &'a str)>) -> Tags {
+    tags.map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+/// An aerodrome found in the first pass, before its position is known
+struct Candidate {
+    tags: Tags,
+    source: Source,
+    osm_id: i64,
+    /// Node IDs (for ways) or member way IDs (for relations)
+    refs: Vec<i64>,
+    position: Option<Position>,
+}
+
+/// Extract commercial airports from a PBF file
+///
+/// Areas (ways and multipolygons) are placed at the mean of their nodes.
+/// Reads the file three times: aerodromes, then the member ways of
+/// aerodrome relations, then the nodes of all aerodrome ways.
+pub fn extract_aerodromes(path: &Path, filter: &AirportFilter) -> Result<Vec<Airport>> {
+    let open = || {
+        ElementReader::from_path(path)
+            .with_context(|| format!("Failed to open PBF file: {}", path.display()))
+    };
+
+    // Pass 1: aerodrome elements
+    log::info!("Pass 1: Finding aerodromes...");
+    let mut candidates = Vec::new();
+
+    open()?.for_each(|element| {
+        let (tags, source, osm_id, refs, position) = match element {
+            Element::Node(node) => (
+                collect_tags(node.tags()),
+                Source::Node,
+                node.id(),
+                Vec::new(),
+                Some(Position { x: node.lon(), y: node.lat() }),
+            ),
+            Element::DenseNode(node) => (
+                collect_tags(node.tags()),
+                Source::Node,
+                node.id(),
+                Vec::new(),
+                Some(Position { x: node.lon(), y: node.lat() }),
+            ),
+            Element::Way(way) => (
+                collect_tags(way.tags()),
+                Source::Way,
+                way.id(),
+                way.refs().collect(),
+                None,
+            ),
+            Element::Relation(rel) => (
+                collect_tags(rel.tags()),
+                Source::Relation,
+                rel.id(),
+                rel.members()
+                    .filter(|m| m.member_type == osmpbf::RelMemberType::Way)
+                    .map(|m| m.member_id)
+                    .collect(),
+                None,
+            ),
+        };
+
+        if is_commercial(&tags, filter) {
+            candidates.push(Candidate { tags, source, osm_id, refs, position });
+        }
+    })?;
+    log::info!("  {} candidate aerodromes", candidates.len());
+
+    // Pass 2: node refs of relation member ways
+    log::info!("Pass 2: Resolving relation members...");
+    let member_ways: HashSet<i64> = candidates
+        .iter()
+        .filter(|c| c.source == Source::Relation)
+        .flat_map(|c| c.refs.iter().copied())
+        .collect();
+    let mut way_refs: HashMap<i64, Vec<i64>> = HashMap::new();
+
+    if !member_ways.is_empty() {
+        open()?.for_each(|element| {
+            if let Element::Way(way) = element {
+                if member_ways.contains(&way.id()) {
+                    way_refs.insert(way.id(), way.refs().collect());
+                }
+            }
+        })?;
+    }
+
+    for candidate in &mut candidates {
+        if candidate.source == Source::Relation {
+            candidate.refs = candidate
+                .refs
+                .iter()
+                .filter_map(|way_id| way_refs.get(way_id))
+                .flatten()
+                .copied()
+                .collect();
+        }
+    }
+
+    // Pass 3: node positions
+    log::info!("Pass 3: Resolving node positions...");
+    let needed: HashSet<i64> = candidates
+        .iter()
+        .flat_map(|c| c.refs.iter().copied())
+        .collect();
+    let mut nodes: HashMap<i64, Position> = HashMap::new();
+
+    if !needed.is_empty() {
+        open()?.for_each(|element| {
+            let (id, position) = match element {
+                Element::Node(node) => (node.id(), Position { x: node.lon(), y: node.lat() }),
+                Element::DenseNode(node) => (node.id(), Position { x: node.lon(), y: node.lat() }),
+                _ => return,
+            };
+
+            if needed.contains(&id) {
+                nodes.insert(id, position);
+            }
+        })?;
+    }
+
+    let mut missing = 0;
+    let mut by_icao: HashMap<String, Airport> = HashMap::new();
+
+    for candidate in candidates {
+        let position = candidate
+            .position
+            .or_else(|| mean_position(candidate.refs.iter().filter_map(|id| nodes.get(id))));
+
+        let Some(position) = position else {
+            missing += 1;
+            continue;
+        };
+
+        let airport = Airport {
+            icao: icao_code(&candidate.tags).expect("commercial airports have an ICAO code"),
+            iata: iata_code(&candidate.tags),
+            name: candidate.tags.get("name").cloned(),
+            position,
+            source: candidate.source,
+            osm_id: candidate.osm_id,
+        };
+
+        // Airports are often mapped both as a node and as an area; prefer the area
+        match by_icao.get(&airport.icao) {
+            Some(existing) if existing.source >= airport.source => {}
+            _ => {
+                by_icao.insert(airport.icao.clone(), airport);
+            }
+        }
+    }
+
+    if missing > 0 {
+        log::warn!("{} aerodromes could not be resolved (missing nodes)", missing);
+    }
+
+    let mut airports: Vec<Airport> = by_icao.into_values().collect();
+    airports.sort_by(|a, b| a.icao.cmp(&b.icao));
+
+    Ok(airports)
+}
+
+/// Mean of a set of positions (fine at airport scale)
+fn mean_position<'a>(positions: impl Iterator<Item = &'a Position>) -> Option<Position> {
+    let (sum, count) = positions.fold((Position { x: 0.0, y: 0.0 }, 0usize), |(sum, n), p| {
+        (Position { x: sum.x + p.x, y: sum.y + p.y }, n + 1)
+    });
+
+    (count > 0).then(|| Position {
+        x: sum.x / count as f64,
+        y: sum.y / count as f64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(pairs: &[(&str, &str)]) -> Tags {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_codes() {
+        assert_eq!(icao_code(&tags(&[("icao", " kjfk ")])), Some("KJFK".to_string()));
+        assert_eq!(icao_code(&tags(&[("icao", "JFK")])), None);
+        assert_eq!(iata_code(&tags(&[("iata", "jfk")])), Some("JFK".to_string()));
+        assert_eq!(iata_code(&tags(&[("iata", "J2K")])), None);
+    }
+
+    #[test]
+    fn test_is_commercial() {
+        let filter = AirportFilter::default();
+
+        let jfk = tags(&[("aeroway", "aerodrome"), ("icao", "KJFK"), ("iata", "JFK")]);
+        assert!(is_commercial(&jfk, &filter));
+
+        // No ICAO code
+        let no_icao = tags(&[("aeroway", "aerodrome"), ("iata", "JFK")]);
+        assert!(!is_commercial(&no_icao, &filter));
+
+        let military = tags(&[
+            ("aeroway", "aerodrome"),
+            ("icao", "KNHK"),
+            ("iata", "NHK"),
+            ("aerodrome:type", "military"),
+        ]);
+        assert!(!is_commercial(&military, &filter));
+
+        let airbase = tags(&[
+            ("aeroway", "aerodrome"),
+            ("icao", "KNHK"),
+            ("iata", "NHK"),
+            ("military", "airfield"),
+        ]);
+        assert!(!is_commercial(&airbase, &filter));
+
+        let civil = tags(&[
+            ("aeroway", "aerodrome"),
+            ("icao", "KJFK"),
+            ("iata", "JFK"),
+            ("military", "no"),
+        ]);
+        assert!(is_commercial(&civil, &filter));
+
+        let regional = tags(&[
+            ("aeroway", "aerodrome"),
+            ("icao", "KFRG"),
+            ("aerodrome:type", "regional"),
+        ]);
+        assert!(!is_commercial(&regional, &filter));
+        assert!(is_commercial(&regional, &AirportFilter { allow_missing_iata: true }));
+    }
+
+    #[test]
+    fn test_mean_position() {
+        let positions = [Position { x: 0.0, y: 0.0 }, Position { x: 2.0, y: 4.0 }];
+        assert_eq!(
+            mean_position(positions.iter()),
+            Some(Position { x: 1.0, y: 2.0 })
+        );
+        assert_eq!(mean_position([].iter()), None);
+    }
+}